@@ -0,0 +1,206 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::Serde;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::sqrt;
+
+use crate::{add_count_noise, charge_jointly, laplace, laplace_scale, BooleanStatsStore, Release, RunningStatsStore};
+
+/// Privately releases the difference between the averages of two running stats stores,
+/// mean(A) - mean(B), e.g. the lift of variant A over variant B in an experiment. Each average
+/// is released as in `fuzzy_average`, at a cost of 2 * epsilon of its own store plus its
+/// minimum-count check, and the two costs are charged together: if either store cannot pay, nothing is charged or released.
+/// If either minimum-count check refuses the release, both checks are charged and
+/// `Release::Refused` is returned.
+/// Averages of stores declared `with_transform` are transformed back before they are
+/// subtracted, e.g. to the geometric means for `Transform::Log1p`.
+pub fn fuzzy_difference_of_means<Ser: Serde>(
+    store_a: &RunningStatsStore<Ser>,
+    store_b: &RunningStatsStore<Ser>,
+    storage: &mut dyn Storage,
+    rng: &mut ChaChaRng,
+) -> StdResult<Release<I32F32>> {
+    store_a.check_ready_for_release(storage)?;
+    store_b.check_ready_for_release(storage)?;
+    let epsilon_a = store_a.get_epsilon(storage)?;
+    let epsilon_b = store_b.get_epsilon(storage)?;
+    store_a.check_privacy_policy(storage, epsilon_a)?;
+    store_b.check_privacy_policy(storage, epsilon_b)?;
+    // sequential queries for sum + count in each store, after its minimum-count check
+    let cost_a = 2 * epsilon_a + store_a.release_gate_cost(storage, epsilon_a)?;
+    let cost_b = 2 * epsilon_b + store_b.release_gate_cost(storage, epsilon_b)?;
+    if store_a.get_privacy_budget(storage)? < cost_a || store_b.get_privacy_budget(storage)? < cost_b {
+        return Err(StdError::generic_err("Privacy budget exhausted"));
+    }
+    let passed_a = store_a.check_release_gate(storage, rng, epsilon_a)?;
+    let passed_b = store_b.check_release_gate(storage, rng, epsilon_b)?;
+    if !(passed_a && passed_b) {
+        // a check that passed was drawn all the same, so it is charged with the one that failed
+        if passed_a {
+            store_a.charge_release_gate(storage, epsilon_a)?;
+        }
+        if passed_b {
+            store_b.charge_release_gate(storage, epsilon_b)?;
+        }
+        return Ok(Release::Refused);
+    }
+
+    let dp_average_a = store_a.uncharged_noisy_average(storage, rng, epsilon_a)?;
+    let dp_average_b = store_b.uncharged_noisy_average(storage, rng, epsilon_b)?;
+    // saturate rather than fail, as the noise has already been drawn
+    let difference = I32F32::saturating_from_num(I64F64::from_num(dp_average_a) - I64F64::from_num(dp_average_b));
+
+    charge_jointly(storage, store_a, cost_a, store_b, cost_b)?;
+    store_a.record_query(storage)?;
+    store_b.record_query(storage)?;
+    Ok(Release::Released(difference))
+}
+
+/// Two-proportion z statistic, (p_a - p_b) / sqrt(p (1 - p) (1 / n_a + 1 / n_b)) with the
+/// pooled proportion p, for counts that may already have noise. Proportions are clamped to
+/// [0, 1]. Returns None if either total is below 1 or the standard error is zero, i.e. every
+/// observation is a success or none is.
+pub fn two_proportion_z(
+    successes_a: I32F32,
+    total_a: I32F32,
+    successes_b: I32F32,
+    total_b: I32F32,
+) -> Option<I32F32> {
+    let zero = I64F64::from_num(0);
+    let one = I64F64::from_num(1);
+    let (successes_a, total_a) = (I64F64::from_num(successes_a), I64F64::from_num(total_a));
+    let (successes_b, total_b) = (I64F64::from_num(successes_b), I64F64::from_num(total_b));
+    if total_a < one || total_b < one {
+        return None;
+    }
+
+    let proportion_a = (successes_a / total_a).clamp(zero, one);
+    let proportion_b = (successes_b / total_b).clamp(zero, one);
+    let pooled = ((successes_a + successes_b) / (total_a + total_b)).clamp(zero, one);
+    let variance = pooled * (one - pooled) * (one / total_a + one / total_b);
+    let standard_error = sqrt::<I32F32, I32F32>(I32F32::checked_from_num(variance)?).ok()?;
+    if standard_error <= I32F32::from_num(0) {
+        return None;
+    }
+    I32F32::checked_from_num(proportion_a - proportion_b)?.checked_div(standard_error)
+}
+
+/// Privately releases the two-proportion z statistic comparing the success rates of two
+/// boolean stores, e.g. conversions under variants A and B. A z above about 1.96 suggests that A
+/// outperforms B at the 5% level, though the noise widens the true spread for small samples.
+/// The successes and totals of each store are noised as in `fuzzy_proportion`, at a cost of
+/// 2 * epsilon of each store plus its minimum-count check, and the two costs are charged
+/// together, or both checks alone if either refuses the release. Returns zero if the
+/// noisy counts show no difference at all.
+pub fn fuzzy_two_proportion_z<Ser: Serde>(
+    store_a: &BooleanStatsStore<Ser>,
+    store_b: &BooleanStatsStore<Ser>,
+    storage: &mut dyn Storage,
+    rng: &mut ChaChaRng,
+) -> StdResult<Release<I32F32>> {
+    store_a.check_ready_for_release(storage)?;
+    store_b.check_ready_for_release(storage)?;
+    let epsilon_a = store_a.get_epsilon(storage)?;
+    let epsilon_b = store_b.get_epsilon(storage)?;
+    let cost_a = 2 * epsilon_a + store_a.release_gate_cost(storage, epsilon_a)?;
+    let cost_b = 2 * epsilon_b + store_b.release_gate_cost(storage, epsilon_b)?;
+    if store_a.get_privacy_budget(storage)? < cost_a || store_b.get_privacy_budget(storage)? < cost_b {
+        return Err(StdError::generic_err("Privacy budget exhausted"));
+    }
+    let passed_a = store_a.check_release_gate(storage, rng, epsilon_a)?;
+    let passed_b = store_b.check_release_gate(storage, rng, epsilon_b)?;
+    if !(passed_a && passed_b) {
+        // a check that passed was drawn all the same, so it is charged with the one that failed
+        if passed_a {
+            store_a.charge_release_gate(storage, epsilon_a)?;
+        }
+        if passed_b {
+            store_b.charge_release_gate(storage, epsilon_b)?;
+        }
+        return Ok(Release::Refused);
+    }
+
+    // sensitivity is 1 for every count
+    let scale_a = laplace_scale(I32F32::from_num(1_u32), epsilon_a)?;
+    let scale_b = laplace_scale(I32F32::from_num(1_u32), epsilon_b)?;
+    let dp_successes_a = add_count_noise(store_a.get_successes(storage)?, laplace(rng, scale_a)?)?;
+    let dp_total_a = add_count_noise(store_a.get_total(storage)?, laplace(rng, scale_a)?)?;
+    let dp_successes_b = add_count_noise(store_b.get_successes(storage)?, laplace(rng, scale_b)?)?;
+    let dp_total_b = add_count_noise(store_b.get_total(storage)?, laplace(rng, scale_b)?)?;
+
+    // floor the noisy totals at 1, so the statistic only fails to exist when every noisy
+    // observation is a success or none is, and then neither variant is ahead
+    let one = I32F32::from_num(1);
+    let z = two_proportion_z(dp_successes_a, dp_total_a.max(one), dp_successes_b, dp_total_b.max(one))
+        .unwrap_or(I32F32::from_num(0));
+
+    charge_jointly(storage, store_a, cost_a, store_b, cost_b)?;
+    store_a.record_query(storage)?;
+    store_b.record_query(storage)?;
+    Ok(Release::Released(z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+    use crate::{ResetPolicy, RunningStatsStatus, Transform};
+
+    #[test]
+    fn z_statistic_matches_textbook_example() {
+        // 60 / 100 against 45 / 100: pooled p = 0.525, z = 0.15 / sqrt(0.525 * 0.475 * 0.02)
+        let z = two_proportion_z(
+            I32F32::from_num(60),
+            I32F32::from_num(100),
+            I32F32::from_num(45),
+            I32F32::from_num(100),
+        ).unwrap();
+        assert!((z - I32F32::from_num(2.1240)).abs() < I32F32::from_num(0.001));
+
+        let all = I32F32::from_num(10);
+        assert_eq!(two_proportion_z(all, all, all, all), None);
+        assert_eq!(two_proportion_z(I32F32::from_num(0), I32F32::from_num(0.5), all, all), None);
+    }
+
+    #[test]
+    fn transformed_means_are_compared_in_the_original_domain() {
+        let mut storage = MockStorage::new();
+        let stores: [RunningStatsStore; 2] = [
+            RunningStatsStore::new(b"a").with_transform(Transform::Log1p).with_clipping(I32F32::from_num(0), I32F32::from_num(7)),
+            RunningStatsStore::new(b"b").with_transform(Transform::Log1p).with_clipping(I32F32::from_num(0), I32F32::from_num(7)),
+        ];
+        for (store, x) in stores.iter().zip([99, 9]) {
+            store.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(10)), None, Some(I32F32::from_num(100)))).unwrap();
+            for _ in 0..200 {
+                store.add_observation(&mut storage, I32F32::from_num(x)).unwrap();
+            }
+            store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([5; 32]);
+        let difference = fuzzy_difference_of_means(&stores[0], &stores[1], &mut storage, &mut rng).unwrap().released().unwrap();
+        // 99 - 9, not ln(100) - ln(10)
+        assert!((difference - I32F32::from_num(90)).abs() < I32F32::from_num(2), "{difference}");
+    }
+
+    #[test]
+    fn a_refused_check_charges_both_checks() {
+        let mut storage = MockStorage::new();
+        let stores: [RunningStatsStore; 2] = [RunningStatsStore::new(b"a"), RunningStatsStore::new(b"b")];
+        for (store, n) in stores.iter().zip([200, 3]) {
+            store.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), None, Some(I32F32::from_num(10)))).unwrap();
+            store.set_min_count_for_release(&mut storage, 20).unwrap();
+            for _ in 0..n {
+                store.add_observation(&mut storage, I32F32::from_num(5)).unwrap();
+            }
+            store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([6; 32]);
+        let refused = fuzzy_difference_of_means(&stores[0], &stores[1], &mut storage, &mut rng).unwrap();
+        assert_eq!(refused, Release::Refused);
+        for store in &stores {
+            assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(9));
+            assert_eq!(store.get_total_queries(&storage).unwrap(), 1);
+        }
+    }
+}
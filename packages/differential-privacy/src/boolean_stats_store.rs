@@ -0,0 +1,544 @@
+use std::marker::PhantomData;
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::{I32F32, I64F64};
+
+#[cfg(feature = "constant_time")]
+use crate::charged_cost;
+use crate::{add_count_noise, laplace, laplace_scale, min_count_gate_cost, noisy_count_reaches, CachedField, PrivacyPolicy, Release, RunningStatsStatus};
+
+const TOTAL_KEY: &[u8] = b"total";
+const SUCCESSES_KEY: &[u8] = b"successes";
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const MIN_COUNT_KEY: &[u8] = b"min-count";
+const STATUS_KEY: &[u8] = b"status";
+const MAX_QUERIES_KEY: &[u8] = b"max-queries";
+const QUERY_COUNT_KEY: &[u8] = b"queries";
+const PRIVACY_POLICY_KEY: &[u8] = b"policy";
+
+/// Counts yes/no observations, keeping the number of successes (yes answers) and the total
+/// number of observations.
+pub struct BooleanStatsStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    total: CachedField<u32>,
+    successes: CachedField<u32>,
+    epsilon: CachedField<I32F32>,
+    privacy_budget: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    status: CachedField<RunningStatsStatus>,
+    max_total_queries: CachedField<u32>,
+    total_queries: CachedField<u32>,
+    privacy_policy: CachedField<PrivacyPolicy>,
+    serialization_type: PhantomData<Ser>,
+}
+
+impl<'a, Ser: Serde> BooleanStatsStore<'a, Ser> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            total: CachedField::new(TOTAL_KEY),
+            successes: CachedField::new(SUCCESSES_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: PhantomData,
+        }
+    }
+
+    /// This is used to produce a new BooleanStatsStore. It can be used when you want to associate
+    /// a BooleanStatsStore to multiple suffixes and you still want to define the BooleanStatsStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            total: CachedField::new(TOTAL_KEY),
+            successes: CachedField::new(SUCCESSES_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: self.serialization_type,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> BooleanStatsStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub(crate) fn get_total(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn set_total(
+        &self,
+        storage: &mut dyn Storage,
+        total: u32,
+    ) {
+        self.total.save(storage, self.as_slice(), total);
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_total(storage)? == 0)
+    }
+
+    pub(crate) fn get_successes(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.successes.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn set_successes(
+        &self,
+        storage: &mut dyn Storage,
+        successes: u32,
+    ) {
+        self.successes.save(storage, self.as_slice(), successes);
+    }
+
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default epsilon = 1
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the epsilon. Returns an error if it is not positive or is outside the privacy policy,
+    /// if there is one.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    pub fn get_privacy_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the privacy budget. Returns an error if it is above the privacy policy, if there is
+    /// one.
+    pub fn set_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(budget)?;
+        }
+        self.save_privacy_budget(storage, budget);
+        Ok(())
+    }
+
+    /// Writes the privacy budget after a release is charged
+    fn save_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) {
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        self.privacy_policy.may_load(storage, self.as_slice())
+    }
+
+    /// Sets limits on epsilon and the privacy budget, as for
+    /// `RunningStatsStore::set_privacy_policy`: they are checked whenever either is set and
+    /// on every release, and the policy can only be tightened. The current epsilon and budget,
+    /// or their defaults of 1, must be within it. The delta limit does not apply, since the
+    /// store's releases spend no delta.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        policy.validate()?;
+        if let Some(current) = self.get_privacy_policy(storage)? {
+            current.check_tightened_by(&policy)?;
+        }
+        policy.check_epsilon(self.get_epsilon(storage)?)?;
+        policy.check_privacy_budget(self.get_privacy_budget(storage)?)?;
+        self.privacy_policy.save(storage, self.as_slice(), policy);
+        Ok(())
+    }
+
+    fn check_privacy_policy(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the minimum number of observations needed before any statistic is released
+    pub fn get_min_count_for_release(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of observations needed before any statistic is released, even
+    /// with noise. Each release first compares a noisy total with the minimum, at an extra cost
+    /// of epsilon; if it falls short, only that cost is charged and the release is refused.
+    /// This is kept when the store is cleared.
+    pub fn set_min_count_for_release(
+        &self,
+        storage: &mut dyn Storage,
+        min_count: u32,
+    ) {
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u32>> {
+        self.max_total_queries.may_load(storage, self.as_slice())
+    }
+
+    /// Limit the number of releases from the store, whatever epsilon they use, as for
+    /// `RunningStatsStore::set_max_total_queries`. A release refused by the minimum-count check
+    /// counts too, since it was charged.
+    pub fn set_max_total_queries(
+        &self,
+        storage: &mut dyn Storage,
+        max_queries: u32,
+    ) {
+        self.max_total_queries.save(storage, self.as_slice(), max_queries);
+    }
+
+    /// Returns the number of releases made from the store
+    pub fn get_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn check_query_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if let Some(max_queries) = self.get_max_total_queries(storage)? {
+            if self.get_total_queries(storage)? >= max_queries {
+                return Err(StdError::generic_err("Query limit reached"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a release towards the query limit
+    pub(crate) fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let total_queries = self.get_total_queries(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Query count overflow")
+        )?;
+        self.total_queries.save(storage, self.as_slice(), total_queries);
+        Ok(())
+    }
+
+    /// Privacy cost of the noisy minimum-count check made before each release
+    pub(crate) fn release_gate_cost(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<I32F32> {
+        Ok(min_count_gate_cost(self.get_min_count_for_release(storage)?, epsilon))
+    }
+
+    /// Draws the noisy minimum-count check of a release and returns whether it passed. If the
+    /// noisy total falls short, the check alone is charged and counted towards the query limit,
+    /// and the caller must refuse the release with `Release::Refused`, so that the charge is
+    /// kept.
+    pub(crate) fn check_release_gate(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<bool> {
+        let min_count = self.get_min_count_for_release(storage)?;
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < gate_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if noisy_count_reaches(rng, self.get_total(storage)?, min_count, epsilon)? {
+            return Ok(true);
+        }
+        self.charge_release_gate(storage, epsilon)?;
+        Ok(false)
+    }
+
+    /// Charges the minimum-count check of a release alone and counts it towards the query
+    /// limit, e.g. for a joint release refused by the check of another store after this one
+    /// passed, since the check was drawn all the same
+    pub(crate) fn charge_release_gate(&self, storage: &mut dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        let gate_cost = self.release_gate_cost(storage, epsilon)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        self.save_privacy_budget(storage, privacy_budget - gate_cost);
+        self.record_query(storage)
+    }
+
+    /// Checks the conditions of `fuzzy_proportion` for a release made outside of it, e.g. by
+    /// `fuzzy_two_proportion_z`. The caller must also budget for and draw `check_release_gate`.
+    pub(crate) fn check_ready_for_release(&self, storage: &dyn Storage) -> StdResult<()> {
+        self.check_query_limit(storage)?;
+        self.check_privacy_policy(storage, self.get_epsilon(storage)?)?;
+        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
+            return Err(StdError::generic_err("Status not set to calculating stats") );
+        }
+        if self.get_total(storage)? == 0 {
+            return Err(StdError::generic_err("No data to count"));
+        }
+        Ok(())
+    }
+
+    pub fn get_status(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<RunningStatsStatus> {
+        self.status.load_or(storage, self.as_slice(), RunningStatsStatus::CollectingData)
+    }
+
+    pub fn set_status(
+        &self,
+        storage: &mut dyn Storage,
+        status: RunningStatsStatus,
+    ) -> StdResult<()> {
+        self.get_status(storage)?.check_transition(status)?;
+        if status == RunningStatsStatus::CalculatingStats && self.get_total(storage)? == 0 {
+            return Err(StdError::generic_err("No data in boolean stats store"));
+        }
+        self.status.save(storage, self.as_slice(), status);
+        Ok(())
+    }
+
+    pub fn add_observation(&self, storage: &mut dyn Storage, x: bool) -> StdResult<()> {
+        if self.get_status(storage)? != RunningStatsStatus::CollectingData {
+            return Err(StdError::generic_err("Status is not set to collecting data") );
+        }
+
+        let new_total = self.get_total(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        self.set_total(storage, new_total);
+
+        if x {
+            let new_successes = self.get_successes(storage)?.checked_add(1).ok_or(
+                StdError::generic_err("Count overflow")
+            )?;
+            self.set_successes(storage, new_successes);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a fuzzy proportion of successes, calculated from a noisy count of successes and a
+    /// noisy total count. The result is clamped to [0, 1]. The privacy cost is 2 * epsilon,
+    /// plus epsilon for the minimum-count check if one is set. A release refused by the check
+    /// is charged for it alone and returns `Release::Refused`.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_proportion(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.check_ready_for_release(storage)?;
+
+        // sequential queries for successes + total, after the minimum-count check
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_cost: I32F32 = 2 * epsilon + self.release_gate_cost(storage, epsilon)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        // sensitivity is 1 for both counts
+        let sensitivity = I32F32::from_num(1_u32);
+        let scale = laplace_scale(sensitivity, epsilon)?;
+
+        let successes_noise = laplace(rng, scale)?;
+        let dp_successes = add_count_noise(self.get_successes(storage)?, successes_noise)?;
+
+        let total_noise = laplace(rng, scale)?;
+        let dp_total = add_count_noise(self.get_total(storage)?, total_noise)?;
+
+        let zero = I32F32::from_num(0);
+        let one = I32F32::from_num(1);
+        let dp_proportion = if dp_successes <= zero {
+            zero
+        } else if dp_successes >= dp_total {
+            one
+        } else {
+            I32F32::from_num(I64F64::from_num(dp_successes) / I64F64::from_num(dp_total))
+        };
+
+        // update the remaining privacy budget
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+
+        Ok(Release::Released(dp_proportion))
+    }
+
+    /// Returns a fuzzy proportion of successes, calculated from a noisy count of successes and a
+    /// noisy total count. The result is clamped to [0, 1]. The privacy cost is 2 * epsilon,
+    /// plus epsilon for the minimum-count check if one is set.
+    /// This is the constant-work version: every field is read and both noise values are drawn
+    /// before anything is checked, and every refusal returns `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_proportion(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        // read every field in a fixed order, whatever the state of the store
+        let status = self.get_status(storage)?;
+        let total = self.get_total(storage)?;
+        let successes = self.get_successes(storage)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let policy = self.get_privacy_policy(storage)?;
+
+        // sequential queries for successes + total, after the minimum-count check
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost: I32F32 = 2 * epsilon + gate_cost;
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let dp_successes = add_count_noise(successes, laplace(rng, scale)?)?;
+        let dp_total = add_count_noise(total, laplace(rng, scale)?)?;
+        let gate_passed = noisy_count_reaches(rng, total, min_count, epsilon)?;
+
+        let zero = I32F32::from_num(0);
+        let one = I32F32::from_num(1);
+        // branching on the noisy counts reveals nothing beyond the released proportion
+        let dp_proportion = if dp_successes <= zero {
+            zero
+        } else if dp_successes >= dp_total {
+            one
+        } else {
+            I32F32::from_num(I64F64::from_num(dp_successes) / I64F64::from_num(dp_total))
+        };
+
+        // evaluate every condition without short-circuiting
+        let eligible = (status == RunningStatsStatus::CalculatingStats)
+            & (total > 0)
+            & max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            })
+            & (privacy_budget >= privacy_cost);
+        let allowed = eligible & gate_passed;
+        let gate_charged = eligible & !gate_passed;
+
+        // the budget and query count are written back either way
+        let charged = charged_cost(privacy_cost, allowed) + charged_cost(gate_cost, gate_charged);
+        self.save_privacy_budget(storage, privacy_budget - charged);
+        let counted = allowed | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        Ok(Release::Released(dp_proportion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn releases_stop_at_the_query_limit() {
+        let mut storage = MockStorage::new();
+        let store: BooleanStatsStore = BooleanStatsStore::new(b"votes");
+        store.set_privacy_budget(&mut storage, I32F32::from_num(100)).unwrap();
+        store.set_max_total_queries(&mut storage, 2);
+        for x in [true, false, true] {
+            store.add_observation(&mut storage, x).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        store.fuzzy_proportion(&mut storage, &mut rng).unwrap().released().unwrap();
+        store.fuzzy_proportion(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.get_total_queries(&storage).unwrap(), 2);
+        assert!(store.fuzzy_proportion(&mut storage, &mut rng).is_err());
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(96));
+    }
+
+    #[test]
+    fn tiny_epsilons_are_an_error_instead_of_a_panic() {
+        let mut storage = MockStorage::new();
+        let store: BooleanStatsStore = BooleanStatsStore::new(b"votes");
+        store.set_epsilon(&mut storage, I32F32::from_bits(1)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(1)).unwrap();
+        store.add_observation(&mut storage, true).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        // 1 / epsilon does not fit in I32F32
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        assert!(store.fuzzy_proportion(&mut storage, &mut rng).is_err());
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+    }
+
+    #[test]
+    fn privacy_policy_bounds_epsilon_and_budget() {
+        let mut storage = MockStorage::new();
+        let store: BooleanStatsStore = BooleanStatsStore::new(b"votes");
+        let policy = PrivacyPolicy {
+            min_epsilon_per_query: I32F32::from_num(1),
+            max_epsilon_per_query: I32F32::from_num(2),
+            max_delta_total: I32F32::from_num(0),
+            max_privacy_budget: I32F32::from_num(10),
+        };
+        store.set_privacy_budget(&mut storage, I32F32::from_num(20)).unwrap();
+        assert!(store.set_privacy_policy(&mut storage, policy).is_err());
+        store.set_privacy_budget(&mut storage, I32F32::from_num(10)).unwrap();
+        store.set_privacy_policy(&mut storage, policy).unwrap();
+
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(3)).is_err());
+        assert!(store.set_privacy_budget(&mut storage, I32F32::from_num(11)).is_err());
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(0)).is_err());
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(-1)).is_err());
+        store.set_epsilon(&mut storage, I32F32::from_num(2)).unwrap();
+        assert!(store.set_privacy_policy(&mut storage, PrivacyPolicy { max_epsilon_per_query: I32F32::from_num(3), ..policy }).is_err());
+
+        store.add_observation(&mut storage, true).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        store.fuzzy_proportion(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(6));
+    }
+}
@@ -0,0 +1,220 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::I32F32;
+
+const LEN_KEY: &[u8] = b"len";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeapOrder {
+    /// smallest value at the root
+    Min,
+    /// largest value at the root
+    Max,
+}
+
+/// Binary heap of fixed point values kept in storage, one key per element. It is used to
+/// recompute the upper and lower bounds of a store after an observation has been removed.
+pub struct BoundHeap {
+    prefix: Vec<u8>,
+    order: HeapOrder,
+}
+
+impl BoundHeap {
+    pub fn new(prefix: Vec<u8>, order: HeapOrder) -> Self {
+        Self { prefix, order }
+    }
+
+    fn element_key(&self, pos: u32) -> Vec<u8> {
+        [self.prefix.as_slice(), &pos.to_be_bytes()].concat()
+    }
+
+    /// true if `a` belongs above `b` in the heap
+    fn precedes(&self, a: I32F32, b: I32F32) -> bool {
+        match self.order {
+            HeapOrder::Min => a < b,
+            HeapOrder::Max => a > b,
+        }
+    }
+
+    pub fn len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        let len_key = [self.prefix.as_slice(), LEN_KEY].concat();
+        if let Some(len_vec) = storage.get(&len_key) {
+            let len_bytes = len_vec
+                .as_slice()
+                .try_into()
+                .map_err(|err| StdError::parse_err("u32", err))?;
+            Ok(u32::from_be_bytes(len_bytes))
+        } else {
+            Ok(0)
+        }
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.len(storage)? == 0)
+    }
+
+    fn set_len(&self, storage: &mut dyn Storage, len: u32) {
+        let len_key = [self.prefix.as_slice(), LEN_KEY].concat();
+        storage.set(&len_key, &len.to_be_bytes());
+    }
+
+    fn get_at(&self, storage: &dyn Storage, pos: u32) -> StdResult<I32F32> {
+        if let Some(value_vec) = storage.get(&self.element_key(pos)) {
+            I32F32::from_stored_bytes(&value_vec)
+        } else {
+            Err(StdError::generic_err("Heap element missing from storage"))
+        }
+    }
+
+    fn set_at(&self, storage: &mut dyn Storage, pos: u32, value: I32F32) {
+        storage.set(&self.element_key(pos), &value.to_stored_bytes());
+    }
+
+    /// Returns the root of the heap, i.e. the minimum or maximum value
+    pub fn peek(&self, storage: &dyn Storage) -> StdResult<Option<I32F32>> {
+        if self.is_empty(storage)? {
+            Ok(None)
+        } else {
+            Ok(Some(self.get_at(storage, 0)?))
+        }
+    }
+
+    pub fn push(&self, storage: &mut dyn Storage, value: I32F32) -> StdResult<()> {
+        let len = self.len(storage)?;
+        let new_len = len.checked_add(1).ok_or(
+            StdError::generic_err("Heap overflow")
+        )?;
+        self.set_at(storage, len, value);
+        self.set_len(storage, new_len);
+        self.sift_up(storage, len, value)
+    }
+
+    /// Removes one occurrence of `value` from the heap. Returns false if the value is not present.
+    pub fn remove(&self, storage: &mut dyn Storage, value: I32F32) -> StdResult<bool> {
+        let len = self.len(storage)?;
+        let mut found: Option<u32> = None;
+        for pos in 0..len {
+            if self.get_at(storage, pos)? == value {
+                found = Some(pos);
+                break;
+            }
+        }
+        let pos = match found {
+            Some(pos) => pos,
+            None => { return Ok(false); }
+        };
+
+        let last_pos = len - 1;
+        let last = self.get_at(storage, last_pos)?;
+        storage.remove(&self.element_key(last_pos));
+        self.set_len(storage, last_pos);
+        if pos < last_pos {
+            self.set_at(storage, pos, last);
+            if pos > 0 && self.precedes(last, self.get_at(storage, (pos - 1) / 2)?) {
+                self.sift_up(storage, pos, last)?;
+            } else {
+                self.sift_down(storage, pos, last, last_pos)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Removes every element in the heap
+    pub fn clear(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let len = self.len(storage)?;
+        for pos in 0..len {
+            storage.remove(&self.element_key(pos));
+        }
+        self.set_len(storage, 0);
+        Ok(())
+    }
+
+    fn sift_up(&self, storage: &mut dyn Storage, mut pos: u32, value: I32F32) -> StdResult<()> {
+        while pos > 0 {
+            let parent_pos = (pos - 1) / 2;
+            let parent = self.get_at(storage, parent_pos)?;
+            if !self.precedes(value, parent) {
+                break;
+            }
+            self.set_at(storage, pos, parent);
+            pos = parent_pos;
+        }
+        self.set_at(storage, pos, value);
+        Ok(())
+    }
+
+    fn sift_down(&self, storage: &mut dyn Storage, mut pos: u32, value: I32F32, len: u32) -> StdResult<()> {
+        loop {
+            let left_pos = 2 * pos + 1;
+            if left_pos >= len {
+                break;
+            }
+            let mut child_pos = left_pos;
+            let mut child = self.get_at(storage, left_pos)?;
+            let right_pos = left_pos + 1;
+            if right_pos < len {
+                let right = self.get_at(storage, right_pos)?;
+                if self.precedes(right, child) {
+                    child_pos = right_pos;
+                    child = right;
+                }
+            }
+            if !self.precedes(child, value) {
+                break;
+            }
+            self.set_at(storage, pos, child);
+            pos = child_pos;
+        }
+        self.set_at(storage, pos, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn roots_stay_the_extremes_of_the_remaining_values() {
+        let mut storage = MockStorage::new();
+        let min_heap = BoundHeap::new(b"min".to_vec(), HeapOrder::Min);
+        let max_heap = BoundHeap::new(b"max".to_vec(), HeapOrder::Max);
+        let mut values: Vec<I32F32> = [5, -3, 8, 0, 8, 2, -7, 4].iter().map(|x| I32F32::from_num(*x)).collect();
+        for value in &values {
+            min_heap.push(&mut storage, *value).unwrap();
+            max_heap.push(&mut storage, *value).unwrap();
+        }
+        assert_eq!(min_heap.len(&storage).unwrap(), 8);
+
+        // remove from the root, a leaf and the middle, and one of two equal values
+        for value in [-7, 4, 8, 0, 5, 8, -3] {
+            let value = I32F32::from_num(value);
+            assert!(min_heap.remove(&mut storage, value).unwrap());
+            assert!(max_heap.remove(&mut storage, value).unwrap());
+            let idx = values.iter().position(|x| *x == value).unwrap();
+            values.remove(idx);
+            assert_eq!(min_heap.peek(&storage).unwrap(), values.iter().min().copied());
+            assert_eq!(max_heap.peek(&storage).unwrap(), values.iter().max().copied());
+        }
+        assert!(!min_heap.remove(&mut storage, I32F32::from_num(8)).unwrap());
+        assert_eq!(min_heap.len(&storage).unwrap(), 1);
+    }
+
+    #[test]
+    fn cleared_heaps_are_empty() {
+        let mut storage = MockStorage::new();
+        let heap = BoundHeap::new(b"heap".to_vec(), HeapOrder::Max);
+        assert_eq!(heap.peek(&storage).unwrap(), None);
+        for x in 0..5 {
+            heap.push(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        heap.clear(&mut storage).unwrap();
+        assert!(heap.is_empty(&storage).unwrap());
+        assert_eq!(heap.peek(&storage).unwrap(), None);
+        assert!(!heap.remove(&mut storage, I32F32::from_num(3)).unwrap());
+        // elements pushed after clearing do not see the old ones
+        heap.push(&mut storage, I32F32::from_num(-1)).unwrap();
+        assert_eq!(heap.peek(&storage).unwrap(), Some(I32F32::from_num(-1)));
+    }
+}
@@ -0,0 +1,186 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use substrate_fixed::types::{I32F32, I64F64};
+
+/// Layout of a coarse histogram: `bins` equal-width bins covering [lower, upper). Values
+/// below `lower` fall in the first bin and values at or above `upper` fall in the last bin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistogramBins {
+    pub lower: I32F32,
+    pub upper: I32F32,
+    pub bins: u16,
+}
+
+impl HistogramBins {
+    /// Returns the index of the bin that `x` falls in
+    pub fn bin_index(&self, x: I32F32) -> u16 {
+        if x < self.lower {
+            return 0;
+        }
+        if x >= self.upper {
+            return self.bins - 1;
+        }
+        let offset = I64F64::from_num(x) - I64F64::from_num(self.lower);
+        let idx: u32 = (offset * I64F64::from_num(self.bins) / self.range()).to_num();
+        idx.min(self.bins as u32 - 1) as u16
+    }
+
+    /// Lower edge of a bin, or None for the first bin which is open below
+    pub fn bin_lower_edge(&self, idx: u16) -> Option<I32F32> {
+        if idx == 0 {
+            return None;
+        }
+        Some(self.edge(idx))
+    }
+
+    /// Upper edge of a bin, or None for the last bin which is open above
+    pub fn bin_upper_edge(&self, idx: u16) -> Option<I32F32> {
+        if idx + 1 >= self.bins {
+            return None;
+        }
+        Some(self.edge(idx + 1))
+    }
+
+    fn range(&self) -> I64F64 {
+        I64F64::from_num(self.upper) - I64F64::from_num(self.lower)
+    }
+
+    fn edge(&self, idx: u16) -> I32F32 {
+        let offset = self.range() * I64F64::from_num(idx) / I64F64::from_num(self.bins);
+        I32F32::from_num(I64F64::from_num(self.lower) + offset)
+    }
+}
+
+/// Coarse histogram of observation counts kept in storage. It lets a store recompute
+/// approximate upper and lower bounds after the extreme observation has been removed, without
+/// keeping the raw observations. Recomputed bounds never move past the remaining data.
+pub struct BoundHistogram {
+    prefix: Vec<u8>,
+    layout: HistogramBins,
+}
+
+impl BoundHistogram {
+    pub fn new(prefix: Vec<u8>, layout: HistogramBins) -> Self {
+        Self { prefix, layout }
+    }
+
+    fn bin_key(&self, idx: u16) -> Vec<u8> {
+        [self.prefix.as_slice(), &idx.to_be_bytes()].concat()
+    }
+
+    pub fn get_bin_count(&self, storage: &dyn Storage, idx: u16) -> StdResult<u32> {
+        if let Some(count_vec) = storage.get(&self.bin_key(idx)) {
+            let count_bytes = count_vec
+                .as_slice()
+                .try_into()
+                .map_err(|err| StdError::parse_err("u32", err))?;
+            Ok(u32::from_be_bytes(count_bytes))
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn set_bin_count(&self, storage: &mut dyn Storage, idx: u16, count: u32) {
+        if count == 0 {
+            storage.remove(&self.bin_key(idx));
+        } else {
+            storage.set(&self.bin_key(idx), &count.to_be_bytes());
+        }
+    }
+
+    pub fn add(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        let idx = self.layout.bin_index(x);
+        let count = self.get_bin_count(storage, idx)?.checked_add(1).ok_or(
+            StdError::generic_err("Histogram bin overflow")
+        )?;
+        self.set_bin_count(storage, idx, count);
+        Ok(())
+    }
+
+    pub fn remove(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        let idx = self.layout.bin_index(x);
+        let count = self.get_bin_count(storage, idx)?.checked_sub(1).ok_or(
+            StdError::generic_err("Observation not found in histogram")
+        )?;
+        self.set_bin_count(storage, idx, count);
+        Ok(())
+    }
+
+    /// Returns an upper bound for the remaining data, no larger than `current`
+    pub fn recompute_upper_bound(&self, storage: &dyn Storage, current: I32F32) -> StdResult<I32F32> {
+        let top = self.layout.bin_index(current);
+        for idx in (0..=top).rev() {
+            if self.get_bin_count(storage, idx)? > 0 {
+                return Ok(match self.layout.bin_upper_edge(idx) {
+                    Some(edge) => edge.min(current),
+                    None => current,
+                });
+            }
+        }
+        Ok(current)
+    }
+
+    /// Returns a lower bound for the remaining data, no smaller than `current`
+    pub fn recompute_lower_bound(&self, storage: &dyn Storage, current: I32F32) -> StdResult<I32F32> {
+        let bottom = self.layout.bin_index(current);
+        for idx in bottom..self.layout.bins {
+            if self.get_bin_count(storage, idx)? > 0 {
+                return Ok(match self.layout.bin_lower_edge(idx) {
+                    Some(edge) => edge.max(current),
+                    None => current,
+                });
+            }
+        }
+        Ok(current)
+    }
+
+    /// Resets every bin count to zero
+    pub fn clear(&self, storage: &mut dyn Storage) {
+        for idx in 0..self.layout.bins {
+            storage.remove(&self.bin_key(idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    fn layout() -> HistogramBins {
+        HistogramBins { lower: I32F32::from_num(0), upper: I32F32::from_num(100), bins: 10 }
+    }
+
+    #[test]
+    fn values_fall_in_equal_width_bins_with_open_ends() {
+        let layout = layout();
+        for (x, idx) in [(-5.0, 0), (0.0, 0), (9.99, 0), (10.0, 1), (99.0, 9), (100.0, 9), (1000.0, 9)] {
+            assert_eq!(layout.bin_index(I32F32::from_num(x)), idx);
+        }
+        assert_eq!(layout.bin_lower_edge(0), None);
+        assert_eq!(layout.bin_lower_edge(3), Some(I32F32::from_num(30)));
+        assert_eq!(layout.bin_upper_edge(3), Some(I32F32::from_num(40)));
+        assert_eq!(layout.bin_upper_edge(9), None);
+    }
+
+    #[test]
+    fn recomputed_bounds_cover_the_remaining_data() {
+        let mut storage = MockStorage::new();
+        let histogram = BoundHistogram::new(b"histogram".to_vec(), layout());
+        for x in [5, 15, 55, 95] {
+            histogram.add(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        histogram.remove(&mut storage, I32F32::from_num(95)).unwrap();
+        histogram.remove(&mut storage, I32F32::from_num(5)).unwrap();
+        // the bounds move in to the edges of the nearest non-empty bins
+        assert_eq!(histogram.recompute_upper_bound(&storage, I32F32::from_num(95)).unwrap(), I32F32::from_num(60));
+        assert_eq!(histogram.recompute_lower_bound(&storage, I32F32::from_num(5)).unwrap(), I32F32::from_num(10));
+        // but never past the current bound
+        assert_eq!(histogram.recompute_upper_bound(&storage, I32F32::from_num(57)).unwrap(), I32F32::from_num(57));
+
+        assert!(histogram.remove(&mut storage, I32F32::from_num(35)).is_err());
+        histogram.clear(&mut storage);
+        for idx in 0..10 {
+            assert_eq!(histogram.get_bin_count(&storage, idx).unwrap(), 0);
+        }
+    }
+}
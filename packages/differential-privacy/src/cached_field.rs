@@ -0,0 +1,396 @@
+#[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+use std::cell::{Cell, RefCell};
+#[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use cosmwasm_std::{Storage, StdResult, StdError};
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::{I32F32, I64F64};
+
+/// A value that can be kept in a `CachedField`, stored as fixed-width big-endian bytes. Fixed-point
+/// values use the versioned `FixedBytes` encoding.
+pub trait FieldValue: Copy {
+    fn to_stored(&self) -> Vec<u8>;
+    fn from_stored(bytes: &[u8]) -> StdResult<Self>;
+}
+
+impl FieldValue for u8 {
+    fn to_stored(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        let value_bytes = bytes
+            .try_into()
+            .map_err(|err| StdError::parse_err("u8", err))?;
+        Ok(u8::from_be_bytes(value_bytes))
+    }
+}
+
+impl FieldValue for u32 {
+    fn to_stored(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        let value_bytes = bytes
+            .try_into()
+            .map_err(|err| StdError::parse_err("u32", err))?;
+        Ok(u32::from_be_bytes(value_bytes))
+    }
+}
+
+impl FieldValue for u64 {
+    fn to_stored(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        let value_bytes = bytes
+            .try_into()
+            .map_err(|err| StdError::parse_err("u64", err))?;
+        Ok(u64::from_be_bytes(value_bytes))
+    }
+}
+
+impl FieldValue for I32F32 {
+    fn to_stored(&self) -> Vec<u8> {
+        self.to_stored_bytes()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        I32F32::from_stored_bytes(bytes)
+    }
+}
+
+impl FieldValue for I64F64 {
+    fn to_stored(&self) -> Vec<u8> {
+        self.to_stored_bytes()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        I64F64::from_stored_bytes(bytes)
+    }
+}
+
+/// In-memory cache slot for a copyable value. Contracts execute single-threaded, so on wasm32
+/// a `Cell` is used without any locking. Native builds, e.g. contract unit tests or off-chain
+/// tools that share a static store between threads, use a `Mutex` instead, as does wasm32 with
+/// the `sync` feature. The
+/// cached value is a plain copy that is only ever replaced whole, so it cannot be left
+/// half-written by a panicking thread and a poisoned lock is recovered instead of propagating
+/// the panic.
+pub struct CacheCell<T: Copy> {
+    #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+    value: Cell<Option<T>>,
+    #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+    value: Mutex<Option<T>>,
+}
+
+// SAFETY: the cell is only used on wasm32 without the `sync` feature, where contract execution
+// is single-threaded, so it is never accessed from more than one thread. This lets contracts
+// declare stores as statics. Native builds use the `Mutex`, which is `Sync` already.
+#[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+unsafe impl<T: Copy + Send> Sync for CacheCell<T> {}
+
+impl<T: Copy> CacheCell<T> {
+    /// constructor
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+            value: Cell::new(None),
+            #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+            value: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached value, if any
+    #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+    pub fn get(&self) -> Option<T> {
+        self.value.get()
+    }
+
+    /// Returns the cached value, if any
+    #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+    pub fn get(&self) -> Option<T> {
+        *self.value.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the cached value
+    #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+    pub fn set(&self, value: Option<T>) {
+        self.value.set(value);
+    }
+
+    /// Replaces the cached value
+    #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+    pub fn set(&self, value: Option<T>) {
+        *self.value.lock().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
+
+/// With the `zeroize` feature, the cached value is overwritten with zeros when the cache is
+/// dropped, so sums, bounds and other sensitive values do not linger in enclave memory.
+#[cfg(feature = "zeroize")]
+impl<T: Copy> Drop for CacheCell<T> {
+    fn drop(&mut self) {
+        #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+        let slot: &mut Option<T> = self.value.get_mut();
+        #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+        let slot: &mut Option<T> = self.value.get_mut().unwrap_or_else(PoisonError::into_inner);
+
+        let ptr = slot as *mut Option<T> as *mut u8;
+        for i in 0..std::mem::size_of::<Option<T>>() {
+            // SAFETY: `ptr` points to the cached value, which is valid for writes of its size. T
+            // is Copy and so has no drop glue, and the value is never read again after this.
+            unsafe { std::ptr::write_volatile(ptr.add(i), 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl<T: Copy> Default for CacheCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Full storage key of a `CachedField`, behind the same kind of lock as `CacheCell`. It is
+/// concatenated on first use, and again whenever the field is used with another prefix.
+struct KeyCell {
+    #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+    full_key: RefCell<Vec<u8>>,
+    #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+    full_key: Mutex<Vec<u8>>,
+}
+
+// SAFETY: as for `CacheCell`, the `RefCell` is only used on wasm32 without the `sync` feature,
+// where contract execution is single-threaded.
+#[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+unsafe impl Sync for KeyCell {}
+
+impl KeyCell {
+    const fn new() -> Self {
+        Self {
+            #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+            full_key: RefCell::new(Vec::new()),
+            #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+            full_key: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Calls `f` with `[prefix, key]`, holding the cell for the whole call. `on_change` is
+    /// called first if the prefix differs from the one the key was last built with.
+    fn with<R>(&self, prefix: &[u8], key: &[u8], on_change: impl FnOnce(), f: impl FnOnce(&[u8]) -> R) -> R {
+        #[cfg(all(not(feature = "sync"), target_arch = "wasm32"))]
+        let mut full_key = self.full_key.borrow_mut();
+        #[cfg(any(feature = "sync", not(target_arch = "wasm32")))]
+        let mut full_key = self.full_key.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if full_key.len() != prefix.len() + key.len() || !full_key.starts_with(prefix) {
+            *full_key = [prefix, key].concat();
+            on_change();
+        }
+        f(&full_key)
+    }
+}
+
+/// Value read from or written to storage, or None if the key held no value, together with the
+/// storage it belongs to and the write generation it was read at
+#[derive(Clone, Copy)]
+struct Cached<T: Copy> {
+    value: Option<T>,
+    storage: usize,
+    generation: u64,
+}
+
+/// Counts the writes made through every `CachedField`. A cached value, or a cached absence, is
+/// only used while no field has written since it was cached, so a value saved through another
+/// `CachedField`, e.g. a second store declared with the same namespace, is never hidden by a
+/// stale copy.
+static WRITE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn current_generation() -> u64 {
+    WRITE_GENERATION.load(Ordering::Relaxed)
+}
+
+fn next_generation() -> u64 {
+    WRITE_GENERATION.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+}
+
+/// Identifies a `Storage` by its address, so that a value cached from one storage is not
+/// returned for another
+fn storage_id(storage: &dyn Storage) -> usize {
+    storage as *const dyn Storage as *const () as usize
+}
+
+/// A single value kept in storage under `[prefix, key]`, with an in-memory cache so that
+/// repeated reads during one contract call only hit storage once. Stores hold one
+/// `CachedField` per value and pass in their current prefix. The full storage key is
+/// concatenated once and reused while the prefix stays the same, so gets and sets do not
+/// allocate a new key each time. When the prefix changes, the key is rebuilt and the cached
+/// value, which belongs to the old key, is dropped.
+///
+/// The cache is keyed by the storage it was read from, so a store used with another storage
+/// reads it afresh, and it is dropped whenever any `CachedField` writes to storage. Writes made
+/// directly to storage, bypassing every `CachedField`, are not seen until `reload`. A storage
+/// dropped and replaced by a new one at the same address, as can happen between native tests,
+/// looks the same: call `invalidate` (or the store's `preload`) when switching to a new storage
+/// there.
+pub struct CachedField<T: FieldValue> {
+    key: &'static [u8],
+    full_key: KeyCell,
+    cache: CacheCell<Cached<T>>,
+}
+
+impl<T: FieldValue> CachedField<T> {
+    /// constructor
+    pub const fn new(key: &'static [u8]) -> Self {
+        Self {
+            key,
+            full_key: KeyCell::new(),
+            cache: CacheCell::new(),
+        }
+    }
+
+    /// Calls `f` with the full storage key for `prefix`, dropping the cached value first if it
+    /// was read or written under another prefix
+    fn with_storage_key<R>(&self, prefix: &[u8], f: impl FnOnce(&[u8]) -> R) -> R {
+        self.full_key.with(prefix, self.key, || self.cache.set(None), f)
+    }
+
+    /// Returns the stored value, or None if nothing has been stored
+    pub fn may_load(&self, storage: &dyn Storage, prefix: &[u8]) -> StdResult<Option<T>> {
+        self.with_storage_key(prefix, |key| {
+            if let Some(cached) = self.cache.get() {
+                if cached.storage == storage_id(storage) && cached.generation == current_generation() {
+                    return Ok(cached.value);
+                }
+            }
+            self.read(storage, key)
+        })
+    }
+
+    /// Reads the value from storage, whatever is cached, and caches it
+    pub fn reload(&self, storage: &dyn Storage, prefix: &[u8]) -> StdResult<Option<T>> {
+        self.with_storage_key(prefix, |key| self.read(storage, key))
+    }
+
+    fn read(&self, storage: &dyn Storage, key: &[u8]) -> StdResult<Option<T>> {
+        let value = match storage.get(key) {
+            Some(value_vec) => Some(T::from_stored(&value_vec)?),
+            None => None,
+        };
+        self.cache.set(Some(Cached { value, storage: storage_id(storage), generation: current_generation() }));
+        Ok(value)
+    }
+
+    /// Returns the stored value, or `default` if nothing has been stored
+    pub fn load_or(&self, storage: &dyn Storage, prefix: &[u8], default: T) -> StdResult<T> {
+        Ok(self.may_load(storage, prefix)?.unwrap_or(default))
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage, prefix: &[u8], value: T) {
+        self.with_storage_key(prefix, |key| {
+            storage.set(key, &value.to_stored());
+            let generation = next_generation();
+            self.cache.set(Some(Cached { value: Some(value), storage: storage_id(storage), generation }));
+        })
+    }
+
+    pub fn remove(&self, storage: &mut dyn Storage, prefix: &[u8]) {
+        self.with_storage_key(prefix, |key| {
+            storage.remove(key);
+            let generation = next_generation();
+            self.cache.set(Some(Cached { value: None, storage: storage_id(storage), generation }));
+        })
+    }
+
+    /// Drops the cached value, so the next read goes to storage
+    pub fn invalidate(&self) {
+        self.cache.set(None);
+    }
+
+    /// Same as `may_load`
+    pub fn get(&self, storage: &dyn Storage, prefix: &[u8]) -> StdResult<Option<T>> {
+        self.may_load(storage, prefix)
+    }
+
+    /// Same as `save`
+    pub fn set(&self, storage: &mut dyn Storage, prefix: &[u8], value: T) {
+        self.save(storage, prefix, value)
+    }
+
+    /// Returns the stored value, or `T::default()` if nothing has been stored
+    pub fn get_or_default(&self, storage: &dyn Storage, prefix: &[u8]) -> StdResult<T>
+    where
+        T: Default,
+    {
+        self.load_or(storage, prefix, T::default())
+    }
+}
+
+/// Name of `CachedField` for new stores that only need get/set access to a stored value
+pub type StoredField<T> = CachedField<T>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn cache_is_kept_per_storage() {
+        let field: CachedField<u32> = CachedField::new(b"count");
+        let mut first = MockStorage::new();
+        let mut second = MockStorage::new();
+        field.save(&mut first, b"stats", 3);
+        assert_eq!(field.may_load(&second, b"stats").unwrap(), None);
+        field.save(&mut second, b"stats", 5);
+        assert_eq!(field.load_or(&first, b"stats", 0).unwrap(), 3);
+        assert_eq!(field.load_or(&second, b"stats", 0).unwrap(), 5);
+
+        // a write that bypasses the field is seen after a reload
+        first.set(b"statscount", &7_u32.to_stored());
+        assert_eq!(field.reload(&first, b"stats").unwrap(), Some(7));
+    }
+
+    #[test]
+    fn writes_through_another_field_are_seen() {
+        let mut storage = MockStorage::new();
+        let field: CachedField<u32> = CachedField::new(b"count");
+        let other: CachedField<u32> = CachedField::new(b"count");
+        // absent, and cached as absent
+        assert_eq!(field.may_load(&storage, b"stats").unwrap(), None);
+        other.save(&mut storage, b"stats", 4);
+        assert_eq!(field.may_load(&storage, b"stats").unwrap(), Some(4));
+        other.remove(&mut storage, b"stats");
+        assert_eq!(field.may_load(&storage, b"stats").unwrap(), None);
+    }
+
+    #[test]
+    fn stored_field_defaults_until_set() {
+        let mut storage = MockStorage::new();
+        let field: StoredField<I32F32> = StoredField::new(b"epsilon");
+        assert_eq!(field.get(&storage, b"stats").unwrap(), None);
+        assert_eq!(field.get_or_default(&storage, b"stats").unwrap(), I32F32::from_num(0));
+        field.set(&mut storage, b"stats", I32F32::from_num(2));
+        assert_eq!(field.get_or_default(&storage, b"stats").unwrap(), I32F32::from_num(2));
+    }
+
+    #[test]
+    fn one_field_can_be_used_with_several_prefixes() {
+        let mut storage = MockStorage::new();
+        let field: StoredField<u32> = StoredField::new(b"count");
+        field.set(&mut storage, b"first", 3);
+        assert_eq!(field.get(&storage, b"second").unwrap(), None);
+        field.set(&mut storage, b"second", 5);
+        assert_eq!(field.get_or_default(&storage, b"first").unwrap(), 3);
+        assert_eq!(field.get_or_default(&storage, b"second").unwrap(), 5);
+        assert_eq!(storage.get(b"firstcount"), Some(3_u32.to_stored()));
+        assert_eq!(storage.get(b"secondcount"), Some(5_u32.to_stored()));
+
+        field.remove(&mut storage, b"first");
+        assert_eq!(field.get(&storage, b"first").unwrap(), None);
+        assert_eq!(field.get(&storage, b"second").unwrap(), Some(5));
+    }
+}
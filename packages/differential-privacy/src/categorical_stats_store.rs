@@ -0,0 +1,628 @@
+use std::marker::PhantomData;
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::I32F32;
+
+#[cfg(feature = "constant_time")]
+use crate::{charged_cost, NoiseResult};
+use crate::{add_count_noise, laplace_scale, laplace_vec, min_count_gate_cost, noisy_count_reaches, synthetic_categories, CachedField, PrivacyPolicy, Release, RunningStatsStatus};
+
+const TOTAL_KEY: &[u8] = b"total";
+const BIN_KEY: &[u8] = b"bin";
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const MIN_COUNT_KEY: &[u8] = b"min-count";
+const STATUS_KEY: &[u8] = b"status";
+const MAX_QUERIES_KEY: &[u8] = b"max-queries";
+const QUERY_COUNT_KEY: &[u8] = b"queries";
+const PRIVACY_POLICY_KEY: &[u8] = b"policy";
+
+/// Counts observations of a categorical variable (e.g. answers to a multiple choice survey
+/// question), where each category is identified by its index in `0..categories`.
+pub struct CategoricalStatsStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// number of categories
+    categories: u16,
+    total: CachedField<u32>,
+    epsilon: CachedField<I32F32>,
+    privacy_budget: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    status: CachedField<RunningStatsStatus>,
+    max_total_queries: CachedField<u32>,
+    total_queries: CachedField<u32>,
+    privacy_policy: CachedField<PrivacyPolicy>,
+    serialization_type: PhantomData<Ser>,
+}
+
+impl<'a, Ser: Serde> CategoricalStatsStore<'a, Ser> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        categories: u16,
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            categories,
+            total: CachedField::new(TOTAL_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: PhantomData,
+        }
+    }
+
+    /// This is used to produce a new CategoricalStatsStore. It can be used when you want to associate
+    /// a CategoricalStatsStore to multiple suffixes and you still want to define the CategoricalStatsStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            categories: self.categories,
+            total: CachedField::new(TOTAL_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: self.serialization_type,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> CategoricalStatsStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn bin_key(&self, category: u16) -> Vec<u8> {
+        [self.as_slice(), BIN_KEY, &category.to_be_bytes()].concat()
+    }
+
+    pub fn get_categories(&self) -> u16 {
+        self.categories
+    }
+
+    fn get_bin_count(
+        &self,
+        storage: &dyn Storage,
+        category: u16,
+    ) -> StdResult<u32> {
+        if let Some(count_vec) = storage.get(&self.bin_key(category)) {
+            let count_bytes = count_vec
+                .as_slice()
+                .try_into()
+                .map_err(|err| StdError::parse_err("u32", err))?;
+            Ok(u32::from_be_bytes(count_bytes))
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn set_bin_count(
+        &self,
+        storage: &mut dyn Storage,
+        category: u16,
+        count: u32,
+    ) {
+        storage.set(&self.bin_key(category), &count.to_be_bytes());
+    }
+
+    fn get_total(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total.load_or(storage, self.as_slice(), 0)
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_total(storage)? == 0)
+    }
+
+    fn set_total(
+        &self,
+        storage: &mut dyn Storage,
+        total: u32,
+    ) {
+        self.total.save(storage, self.as_slice(), total);
+    }
+
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default epsilon = 1
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the epsilon. Returns an error if it is not positive or is outside the privacy policy,
+    /// if there is one.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    pub fn get_privacy_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the privacy budget. Returns an error if it is above the privacy policy, if there is
+    /// one.
+    pub fn set_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(budget)?;
+        }
+        self.save_privacy_budget(storage, budget);
+        Ok(())
+    }
+
+    /// Writes the privacy budget after a release is charged
+    fn save_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) {
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        self.privacy_policy.may_load(storage, self.as_slice())
+    }
+
+    /// Sets limits on epsilon and the privacy budget, as for
+    /// `RunningStatsStore::set_privacy_policy`: they are checked whenever either is set and
+    /// on every release, and the policy can only be tightened. The current epsilon and budget,
+    /// or their defaults of 1, must be within it. The delta limit does not apply, since the
+    /// store's releases spend no delta.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        policy.validate()?;
+        if let Some(current) = self.get_privacy_policy(storage)? {
+            current.check_tightened_by(&policy)?;
+        }
+        policy.check_epsilon(self.get_epsilon(storage)?)?;
+        policy.check_privacy_budget(self.get_privacy_budget(storage)?)?;
+        self.privacy_policy.save(storage, self.as_slice(), policy);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_privacy_policy(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the minimum number of observations needed before any statistic is released
+    pub fn get_min_count_for_release(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of observations needed before any statistic is released, even
+    /// with noise. Each release first compares a noisy total with the minimum, at an extra cost
+    /// of epsilon; if it falls short, only that cost is charged and the release is refused.
+    /// This is kept when the store is cleared.
+    pub fn set_min_count_for_release(
+        &self,
+        storage: &mut dyn Storage,
+        min_count: u32,
+    ) {
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u32>> {
+        self.max_total_queries.may_load(storage, self.as_slice())
+    }
+
+    /// Limit the number of releases from the store, whatever epsilon they use, as for
+    /// `RunningStatsStore::set_max_total_queries`. A release refused by the minimum-count check
+    /// counts too, since it was charged.
+    pub fn set_max_total_queries(
+        &self,
+        storage: &mut dyn Storage,
+        max_queries: u32,
+    ) {
+        self.max_total_queries.save(storage, self.as_slice(), max_queries);
+    }
+
+    /// Returns the number of releases made from the store
+    pub fn get_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_query_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if let Some(max_queries) = self.get_max_total_queries(storage)? {
+            if self.get_total_queries(storage)? >= max_queries {
+                return Err(StdError::generic_err("Query limit reached"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a release towards the query limit
+    #[cfg(not(feature = "constant_time"))]
+    fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let total_queries = self.get_total_queries(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Query count overflow")
+        )?;
+        self.total_queries.save(storage, self.as_slice(), total_queries);
+        Ok(())
+    }
+
+    /// Checks the status, data and query limit of a release
+    #[cfg(not(feature = "constant_time"))]
+    fn check_ready_for_release(&self, storage: &dyn Storage) -> StdResult<()> {
+        self.check_query_limit(storage)?;
+        self.check_privacy_policy(storage, self.get_epsilon(storage)?)?;
+        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
+            return Err(StdError::generic_err("Status not set to calculating stats") );
+        }
+
+        if self.is_empty(storage)? {
+            return Err(StdError::generic_err("No data to count"));
+        }
+        Ok(())
+    }
+
+    /// Privacy cost of the noisy minimum-count check made before each release
+    #[cfg(not(feature = "constant_time"))]
+    fn release_gate_cost(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<I32F32> {
+        Ok(min_count_gate_cost(self.get_min_count_for_release(storage)?, epsilon))
+    }
+
+    /// Draws the noisy minimum-count check of a release and returns whether it passed. If the
+    /// noisy total falls short, the check alone is charged and counted towards the query limit,
+    /// and the release is refused with `Release::Refused`, so that the charge is kept.
+    #[cfg(not(feature = "constant_time"))]
+    fn check_release_gate(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<bool> {
+        let min_count = self.get_min_count_for_release(storage)?;
+        if noisy_count_reaches(rng, self.get_total(storage)?, min_count, epsilon)? {
+            return Ok(true);
+        }
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        self.save_privacy_budget(storage, privacy_budget - min_count_gate_cost(min_count, epsilon));
+        self.record_query(storage)?;
+        Ok(false)
+    }
+
+    pub fn get_status(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<RunningStatsStatus> {
+        self.status.load_or(storage, self.as_slice(), RunningStatsStatus::CollectingData)
+    }
+
+    pub fn set_status(
+        &self,
+        storage: &mut dyn Storage,
+        status: RunningStatsStatus,
+    ) -> StdResult<()> {
+        self.get_status(storage)?.check_transition(status)?;
+        if status == RunningStatsStatus::CalculatingStats && self.get_total(storage)? == 0 {
+            return Err(StdError::generic_err("No data in categorical stats store"));
+        }
+        self.status.save(storage, self.as_slice(), status);
+        Ok(())
+    }
+
+    pub fn add_observation(&self, storage: &mut dyn Storage, category: u16) -> StdResult<()> {
+        if self.get_status(storage)? != RunningStatsStatus::CollectingData {
+            return Err(StdError::generic_err("Status is not set to collecting data") );
+        }
+        if category >= self.categories {
+            return Err(StdError::generic_err("Invalid category"));
+        }
+
+        let new_total = self.get_total(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        self.set_total(storage, new_total);
+
+        let new_count = self.get_bin_count(storage, category)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        self.set_bin_count(storage, category, new_count);
+
+        Ok(())
+    }
+
+    /// Returns the most frequent category using report noisy max: Laplace noise with scale
+    /// 1 / epsilon is added to every category count and the index of the largest noisy count is
+    /// released. Only the index is released, so the privacy cost is a single epsilon, plus
+    /// epsilon for the minimum-count check if one is set. A release refused by the check is
+    /// charged for it alone and returns `Release::Refused`.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_mode(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<u16>> {
+        self.check_ready_for_release(storage)?;
+
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_cost = epsilon + self.release_gate_cost(storage, epsilon)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        // adding one observation changes one count by 1, and counts only increase
+        let sensitivity = I32F32::from_num(1_u32);
+        let scale = laplace_scale(sensitivity, epsilon)?;
+
+        let mut mode: u16 = 0;
+        let mut max_noisy_count = I32F32::min_value();
+        let noise_vec = laplace_vec(rng, scale, usize::from(self.categories))?;
+        for (category, noise) in (0..self.categories).zip(noise_vec) {
+            let noisy_count = add_count_noise(self.get_bin_count(storage, category)?, noise)?;
+            if noisy_count > max_noisy_count {
+                max_noisy_count = noisy_count;
+                mode = category;
+            }
+        }
+
+        // update the remaining privacy budget
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+
+        Ok(Release::Released(mode))
+    }
+
+    /// Returns the most frequent category using report noisy max, like the default version.
+    /// This is the constant-work version: every field and category count is read and every
+    /// noise value is drawn before anything is checked, and every refusal returns
+    /// `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_mode(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<u16>> {
+        // read every field in a fixed order, whatever the state of the store
+        let status = self.get_status(storage)?;
+        let total = self.get_total(storage)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let policy = self.get_privacy_policy(storage)?;
+        let mut counts = Vec::with_capacity(usize::from(self.categories));
+        for category in 0..self.categories {
+            counts.push(self.get_bin_count(storage, category)?);
+        }
+
+        // adding one observation changes one count by 1, and counts only increase
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let mut mode: u16 = 0;
+        let mut max_noisy_count = I32F32::min_value();
+        let noise_vec = laplace_vec(rng, scale, usize::from(self.categories))?;
+        for ((category, count), noise) in (0..self.categories).zip(counts).zip(noise_vec) {
+            let noisy_count = add_count_noise(count, noise)?;
+            if noisy_count > max_noisy_count {
+                max_noisy_count = noisy_count;
+                mode = category;
+            }
+        }
+        let gate_passed = noisy_count_reaches(rng, total, min_count, epsilon)?;
+
+        // evaluate every condition without short-circuiting
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let eligible = (status == RunningStatsStatus::CalculatingStats)
+            & (total > 0)
+            & max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            })
+            & (privacy_budget >= privacy_cost);
+        let allowed = eligible & gate_passed;
+        let gate_charged = eligible & !gate_passed;
+
+        // the budget and query count are written back either way
+        let charged = charged_cost(privacy_cost, allowed) + charged_cost(gate_cost, gate_charged);
+        self.save_privacy_budget(storage, privacy_budget - charged);
+        let counted = allowed | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        Ok(Release::Released(mode))
+    }
+
+    /// Returns a fuzzy count of every category: Laplace noise with scale 1 / epsilon is added
+    /// to each count. Adding one observation changes one count by 1, so releasing all of them
+    /// costs a single epsilon, plus epsilon for the minimum-count check if one is set. A release
+    /// refused by the check is charged for it alone and returns `Release::Refused`.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_counts(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<Vec<I32F32>>> {
+        self.check_ready_for_release(storage)?;
+
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_cost = epsilon + self.release_gate_cost(storage, epsilon)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let noise_vec = laplace_vec(rng, scale, usize::from(self.categories))?;
+        let mut noisy_counts = Vec::with_capacity(usize::from(self.categories));
+        for (category, noise) in (0..self.categories).zip(noise_vec) {
+            noisy_counts.push(add_count_noise(self.get_bin_count(storage, category)?, noise)?);
+        }
+
+        // update the remaining privacy budget
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+
+        Ok(Release::Released(noisy_counts))
+    }
+
+    /// Returns a fuzzy count of every category, like the default version. This is the
+    /// constant-work version: every field and category count is read and every noise value is
+    /// drawn before anything is checked, and every refusal returns `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_counts(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<Vec<I32F32>>> {
+        // read every field in a fixed order, whatever the state of the store
+        let status = self.get_status(storage)?;
+        let total = self.get_total(storage)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let policy = self.get_privacy_policy(storage)?;
+        let mut counts = Vec::with_capacity(usize::from(self.categories));
+        for category in 0..self.categories {
+            counts.push(self.get_bin_count(storage, category)?);
+        }
+
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let noise_vec = laplace_vec(rng, scale, usize::from(self.categories))?;
+        let noisy_counts: Vec<I32F32> = counts
+            .into_iter()
+            .zip(noise_vec)
+            .map(|(count, noise)| add_count_noise(count, noise))
+            .collect::<NoiseResult<_>>()?;
+        let gate_passed = noisy_count_reaches(rng, total, min_count, epsilon)?;
+
+        // evaluate every condition without short-circuiting
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let eligible = (status == RunningStatsStatus::CalculatingStats)
+            & (total > 0)
+            & max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            })
+            & (privacy_budget >= privacy_cost);
+        let allowed = eligible & gate_passed;
+        let gate_charged = eligible & !gate_passed;
+
+        // the budget and query count are written back either way
+        let charged = charged_cost(privacy_cost, allowed) + charged_cost(gate_cost, gate_charged);
+        self.save_privacy_budget(storage, privacy_budget - charged);
+        let counted = allowed | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        Ok(Release::Released(noisy_counts))
+    }
+
+    /// Returns `n` synthetic observations drawn from the fuzzy counts of every category, at a
+    /// privacy cost of epsilon. The records are post-processing of one `fuzzy_counts` release,
+    /// so they are epsilon-differentially private however many are drawn.
+    pub fn synthesize(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, n: usize) -> StdResult<Release<Vec<u16>>> {
+        self.fuzzy_counts(storage, rng)?.try_map(|noisy_counts| {
+            Ok(synthetic_categories(rng, &noisy_counts, n)?
+                .into_iter()
+                .map(|category| category as u16)
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn releases_the_mode_and_counts_of_each_category() {
+        let mut storage = MockStorage::new();
+        let store: CategoricalStatsStore = CategoricalStatsStore::new(b"answers", 3);
+        assert!(store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).is_err());
+        assert!(store.add_observation(&mut storage, 3).is_err());
+        for _ in 0..50 {
+            store.add_observation(&mut storage, 2).unwrap();
+        }
+        for _ in 0..5 {
+            store.add_observation(&mut storage, 0).unwrap();
+        }
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(0)).is_err());
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(-1)).is_err());
+        store.set_epsilon(&mut storage, I32F32::from_num(5)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(10)).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert!(store.add_observation(&mut storage, 1).is_err());
+
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        assert_eq!(store.fuzzy_mode(&mut storage, &mut rng).unwrap().released().unwrap(), 2);
+        let counts = store.fuzzy_counts(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(counts.len(), 3);
+        assert!((counts[2] - I32F32::from_num(50)).abs() < I32F32::from_num(5));
+        assert!((counts[0] - I32F32::from_num(5)).abs() < I32F32::from_num(5));
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+        assert_eq!(store.get_total_queries(&storage).unwrap(), 2);
+        assert!(store.fuzzy_mode(&mut storage, &mut rng).is_err());
+    }
+
+    #[test]
+    fn suffixed_stores_count_separately() {
+        let mut storage = MockStorage::new();
+        let store: CategoricalStatsStore = CategoricalStatsStore::new(b"answers", 2);
+        let first = store.add_suffix(b"q1");
+        let second = store.add_suffix(b"q2");
+        first.add_observation(&mut storage, 1).unwrap();
+        assert!(!first.is_empty(&storage).unwrap());
+        assert!(second.is_empty(&storage).unwrap());
+        assert!(store.is_empty(&storage).unwrap());
+        assert_eq!(second.get_categories(), 2);
+    }
+}
@@ -0,0 +1,14 @@
+use substrate_fixed::types::I32F32;
+
+// With the `constant_time` feature, the fuzzy queries of the stats stores do the same work
+// whatever the state of the store: every field is read in a fixed order, noise is drawn for
+// every statistic the store can release, the privacy budget is always written back, and every
+// refusal returns `Ok(Release::Refused)`, whatever the reason. The only difference between an
+// allowed and a refused query is the value written to the budget and whether results are
+// returned. A refusal is not an error, so the transaction commits and a charged refusal keeps
+// its charge.
+
+/// `cost` if the query is allowed and zero otherwise, without branching on `allowed`
+pub(crate) fn charged_cost(cost: I32F32, allowed: bool) -> I32F32 {
+    cost * I32F32::from_num(u8::from(allowed))
+}
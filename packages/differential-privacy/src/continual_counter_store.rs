@@ -0,0 +1,218 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::I32F32;
+
+use crate::{laplace, laplace_scale, CachedField};
+
+const STEP_KEY: &[u8] = b"step";
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PSUM_KEY: &[u8] = b"psum";
+const NOISY_PSUM_KEY: &[u8] = b"npsum";
+
+/// Counter that releases a noisy running count after every increment, using the binary
+/// mechanism. Each increment is part of at most `log2(horizon)` partial sums, and each partial
+/// sum is released once with Laplace noise of scale `levels / epsilon`, so the whole stream of
+/// releases up to `horizon` steps is epsilon-differentially private and the error of each
+/// release grows only polylogarithmically with the number of steps.
+pub struct ContinualCounterStore<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// maximum number of increments
+    horizon: u32,
+    step: CachedField<u32>,
+    epsilon: CachedField<I32F32>,
+}
+
+impl<'a> ContinualCounterStore<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        horizon: u32,
+    ) -> Self {
+        assert!(horizon > 0, "Horizon must be greater than zero");
+        Self {
+            namespace,
+            prefix: None,
+            horizon,
+            step: CachedField::new(STEP_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+
+    /// This is used to produce a new ContinualCounterStore. It can be used when you want to associate
+    /// a ContinualCounterStore to multiple suffixes and you still want to define the ContinualCounterStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            horizon: self.horizon,
+            step: CachedField::new(STEP_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+}
+
+impl<'a> ContinualCounterStore<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_horizon(&self) -> u32 {
+        self.horizon
+    }
+
+    /// number of binary levels (partial sums) needed to cover the horizon
+    fn levels(&self) -> u32 {
+        u32::BITS - self.horizon.leading_zeros()
+    }
+
+    /// Returns the number of increments so far
+    pub fn get_step(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.step.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn set_step(
+        &self,
+        storage: &mut dyn Storage,
+        step: u32,
+    ) {
+        self.step.save(storage, self.as_slice(), step);
+    }
+
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default epsilon = 1
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the epsilon for the whole stream, which must be positive. This must be done before
+    /// the first increment.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if self.get_step(storage)? > 0 {
+            return Err(StdError::generic_err("Cannot change epsilon after counting has started"));
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    fn get_level_value(&self, storage: &dyn Storage, key: &[u8], level: u32) -> StdResult<I32F32> {
+        let level_key = [self.as_slice(), key, &level.to_be_bytes()].concat();
+        if let Some(value_vec) = storage.get(&level_key) {
+            I32F32::from_stored_bytes(&value_vec)
+        } else {
+            Ok(I32F32::from_num(0))
+        }
+    }
+
+    fn set_level_value(&self, storage: &mut dyn Storage, key: &[u8], level: u32, value: I32F32) {
+        let level_key = [self.as_slice(), key, &level.to_be_bytes()].concat();
+        storage.set(&level_key, &value.to_stored_bytes());
+    }
+
+    /// Adds `x` to the counter and returns the new noisy running count. `x` must be in [0, 1],
+    /// since the noise of each partial sum is calibrated to a sensitivity of 1.
+    pub fn increment(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        x: I32F32,
+    ) -> StdResult<I32F32> {
+        if x < I32F32::from_num(0) || x > I32F32::from_num(1) {
+            return Err(StdError::generic_err("Increment must be between 0 and 1"));
+        }
+        let step = self.get_step(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Step overflow")
+        )?;
+        if step > self.horizon {
+            return Err(StdError::generic_err("Counter horizon reached"));
+        }
+
+        // the new partial sum at the lowest set bit of step absorbs every lower level
+        let level = step.trailing_zeros();
+        let mut psum = x;
+        for lower in 0..level {
+            psum = psum.checked_add(self.get_level_value(storage, PSUM_KEY, lower)?).ok_or(
+                StdError::generic_err("Count overflow")
+            )?;
+            self.set_level_value(storage, PSUM_KEY, lower, I32F32::from_num(0));
+            self.set_level_value(storage, NOISY_PSUM_KEY, lower, I32F32::from_num(0));
+        }
+        self.set_level_value(storage, PSUM_KEY, level, psum);
+
+        let epsilon = self.get_epsilon(storage)?;
+        let scale = laplace_scale(I32F32::from_num(self.levels()), epsilon)?;
+        let noisy_psum = psum.checked_add(laplace(rng, scale)?).ok_or(
+            StdError::generic_err("Noisy count overflow")
+        )?;
+        self.set_level_value(storage, NOISY_PSUM_KEY, level, noisy_psum);
+
+        self.set_step(storage, step);
+        self.get_noisy_count(storage)
+    }
+
+    /// Returns the latest noisy running count. This only combines already released noisy
+    /// partial sums, so it has no additional privacy cost.
+    pub fn get_noisy_count(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        let step = self.get_step(storage)?;
+        let mut count = I32F32::from_num(0);
+        for level in 0..self.levels() {
+            if step & (1 << level) != 0 {
+                count += self.get_level_value(storage, NOISY_PSUM_KEY, level)?;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn increments_must_be_between_zero_and_one() {
+        let mut storage = MockStorage::new();
+        let counter = ContinualCounterStore::new(b"counter", 64);
+        assert!(counter.set_epsilon(&mut storage, I32F32::from_num(0)).is_err());
+        counter.set_epsilon(&mut storage, I32F32::from_num(10)).unwrap();
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+
+        assert!(counter.increment(&mut storage, &mut rng, I32F32::from_num(2)).is_err());
+        assert!(counter.increment(&mut storage, &mut rng, I32F32::from_num(-1)).is_err());
+        assert_eq!(counter.get_step(&storage).unwrap(), 0);
+
+        for _ in 0..40 {
+            counter.increment(&mut storage, &mut rng, I32F32::from_num(1)).unwrap();
+        }
+        counter.increment(&mut storage, &mut rng, I32F32::from_num(0)).unwrap();
+        assert_eq!(counter.get_step(&storage).unwrap(), 41);
+        let count = counter.get_noisy_count(&storage).unwrap();
+        assert!((count - I32F32::from_num(40)).abs() < I32F32::from_num(10));
+        assert!(counter.set_epsilon(&mut storage, I32F32::from_num(1)).is_err());
+    }
+}
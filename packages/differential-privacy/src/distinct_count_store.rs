@@ -0,0 +1,481 @@
+use std::marker::PhantomData;
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::{serialization::{Serde, Bincode2}, crypto::sha_256};
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::ln;
+
+#[cfg(feature = "constant_time")]
+use crate::charged_cost;
+use crate::{add_count_noise, laplace, laplace_scale, min_count_gate_cost, noisy_count_reaches, CachedField, PrivacyPolicy, Release};
+
+const SET_BITS_KEY: &[u8] = b"set-bits";
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const MIN_COUNT_KEY: &[u8] = b"min-count";
+const MAX_QUERIES_KEY: &[u8] = b"max-queries";
+const QUERY_COUNT_KEY: &[u8] = b"queries";
+const PRIVACY_POLICY_KEY: &[u8] = b"policy";
+const BITMAP_KEY: &[u8] = b"bitmap";
+
+/// Bytes of the sketch stored under each key, so an insert reads and writes one page
+const PAGE_BYTES: u32 = 256;
+/// Largest sketch, which keeps the estimate within the range of I32F32
+pub const MAX_SKETCH_BITS: u32 = 1 << 24;
+
+/// Estimates the number of distinct contributors, e.g. unique addresses that interacted with a
+/// contract, from a linear counting sketch: a bitmap of `bits` bits in which each contributor
+/// sets the bit chosen by a hash of its id. Repeat contributions set the same bit, so the number
+/// of set bits depends only on the set of contributors, and no ids are stored.
+///
+/// Adding or removing one contributor changes the number of set bits by at most 1, so it is
+/// released with Laplace noise of scale 1 / epsilon, at a cost of epsilon, and turned into an
+/// estimate of the distinct count afterwards. The estimate is accurate while the number of
+/// contributors is up to a few times `bits`; choose `bits` above the largest expected count.
+pub struct DistinctCountStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    bits: u32,
+    set_bits: CachedField<u32>,
+    epsilon: CachedField<I32F32>,
+    privacy_budget: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    max_total_queries: CachedField<u32>,
+    total_queries: CachedField<u32>,
+    privacy_policy: CachedField<PrivacyPolicy>,
+    serialization_type: PhantomData<Ser>,
+}
+
+impl<'a, Ser: Serde> DistinctCountStore<'a, Ser> {
+    /// constructor, for a sketch of `bits` bits, a multiple of 8 up to `MAX_SKETCH_BITS`
+    pub const fn new(
+        namespace: &'a [u8],
+        bits: u32,
+    ) -> Self {
+        assert!(bits > 0 && bits % 8 == 0, "Sketch bits must be a positive multiple of 8");
+        assert!(bits <= MAX_SKETCH_BITS, "Sketch is too large");
+        Self {
+            namespace,
+            prefix: None,
+            bits,
+            set_bits: CachedField::new(SET_BITS_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: PhantomData,
+        }
+    }
+
+    /// This is used to produce a new DistinctCountStore. It can be used when you want to
+    /// associate a DistinctCountStore to multiple suffixes and you still want to define the
+    /// DistinctCountStore as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            bits: self.bits,
+            set_bits: CachedField::new(SET_BITS_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: self.serialization_type,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> DistinctCountStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns the number of set bits in the sketch. This is not private, use `fuzzy_distinct_count`
+    /// to release it.
+    pub(crate) fn get_set_bits(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.set_bits.load_or(storage, self.as_slice(), 0)
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_set_bits(storage)? == 0)
+    }
+
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default epsilon = 1
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the epsilon. Returns an error if it is not positive or is outside the privacy policy,
+    /// if there is one.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    pub fn get_privacy_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the privacy budget. Returns an error if it is above the privacy policy, if there is
+    /// one.
+    pub fn set_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(budget)?;
+        }
+        self.save_privacy_budget(storage, budget);
+        Ok(())
+    }
+
+    /// Writes the privacy budget after a release is charged
+    fn save_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) {
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        self.privacy_policy.may_load(storage, self.as_slice())
+    }
+
+    /// Sets limits on epsilon and the privacy budget, as for
+    /// `RunningStatsStore::set_privacy_policy`: they are checked whenever either is set and
+    /// on every release, and the policy can only be tightened. The current epsilon and budget,
+    /// or their defaults of 1, must be within it. The delta limit does not apply, since the
+    /// store's releases spend no delta.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        policy.validate()?;
+        if let Some(current) = self.get_privacy_policy(storage)? {
+            current.check_tightened_by(&policy)?;
+        }
+        policy.check_epsilon(self.get_epsilon(storage)?)?;
+        policy.check_privacy_budget(self.get_privacy_budget(storage)?)?;
+        self.privacy_policy.save(storage, self.as_slice(), policy);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_privacy_policy(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the minimum number of distinct contributors needed before the count is released
+    pub fn get_min_count_for_release(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of distinct contributors needed before the count is released,
+    /// even with noise. Each release first compares a noisy number of set bits, which is at most
+    /// the number of contributors, with the minimum, at an extra cost of epsilon; if it falls
+    /// short, only that cost is charged and the release is refused. This is kept when the
+    /// sketch is cleared.
+    pub fn set_min_count_for_release(
+        &self,
+        storage: &mut dyn Storage,
+        min_count: u32,
+    ) {
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u32>> {
+        self.max_total_queries.may_load(storage, self.as_slice())
+    }
+
+    /// Limit the number of releases from the store, whatever epsilon they use, as for
+    /// `RunningStatsStore::set_max_total_queries`. A release refused by the minimum-count check
+    /// counts too, since it was charged.
+    pub fn set_max_total_queries(
+        &self,
+        storage: &mut dyn Storage,
+        max_queries: u32,
+    ) {
+        self.max_total_queries.save(storage, self.as_slice(), max_queries);
+    }
+
+    /// Returns the number of releases made from the store
+    pub fn get_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_query_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if let Some(max_queries) = self.get_max_total_queries(storage)? {
+            if self.get_total_queries(storage)? >= max_queries {
+                return Err(StdError::generic_err("Query limit reached"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a release towards the query limit
+    #[cfg(not(feature = "constant_time"))]
+    fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let total_queries = self.get_total_queries(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Query count overflow")
+        )?;
+        self.total_queries.save(storage, self.as_slice(), total_queries);
+        Ok(())
+    }
+
+    fn page_key(&self, page: u32) -> Vec<u8> {
+        [self.as_slice(), BITMAP_KEY, &page.to_be_bytes()].concat()
+    }
+
+    /// Records a contributor, e.g. the sender address. Returns true if it set a new bit in the
+    /// sketch, which is always the case for a new contributor unless its bit is shared with an
+    /// earlier one.
+    pub fn add_contributor(&self, storage: &mut dyn Storage, contributor: &[u8]) -> StdResult<bool> {
+        let hash = sha_256(contributor);
+        let mut hash_bytes = [0u8; 8];
+        hash_bytes.copy_from_slice(&hash[..8]);
+        let bit = (u64::from_be_bytes(hash_bytes) % u64::from(self.bits)) as u32;
+
+        let byte = bit / 8;
+        let (page, offset) = (byte / PAGE_BYTES, (byte % PAGE_BYTES) as usize);
+        let page_key = self.page_key(page);
+        let mut bitmap = storage.get(&page_key).unwrap_or_else(|| {
+            let page_len = PAGE_BYTES.min(self.bits / 8 - page * PAGE_BYTES);
+            vec![0; page_len as usize]
+        });
+        let mask = 1u8 << (bit % 8);
+        if bitmap[offset] & mask != 0 {
+            return Ok(false);
+        }
+        bitmap[offset] |= mask;
+        storage.set(&page_key, &bitmap);
+
+        let set_bits = self.get_set_bits(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        self.set_bits.save(storage, self.as_slice(), set_bits);
+        Ok(true)
+    }
+
+    /// Empties the sketch, keeping epsilon, the privacy budget, the minimum count and the query count. This writes every page of
+    /// the sketch, so it costs gas in proportion to `bits`.
+    pub fn clear(&self, storage: &mut dyn Storage) {
+        for page in 0..self.bits.div_ceil(8 * PAGE_BYTES) {
+            storage.remove(&self.page_key(page));
+        }
+        self.set_bits.remove(storage, self.as_slice());
+    }
+
+    /// Returns a fuzzy number of distinct contributors, estimated from a noisy count of the set
+    /// bits in the sketch. The privacy cost is epsilon, plus epsilon for the minimum-count
+    /// check if one is set. If the check fails, its cost is charged and `Release::Refused` is
+    /// returned.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_distinct_count(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.check_query_limit(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        self.check_privacy_policy(storage, epsilon)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+
+        // sensitivity is 1, since one contributor sets at most one bit
+        let set_bits = self.get_set_bits(storage)?;
+        if !noisy_count_reaches(rng, set_bits, min_count, epsilon)? {
+            self.save_privacy_budget(storage, privacy_budget - gate_cost);
+            self.record_query(storage)?;
+            return Ok(Release::Refused);
+        }
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let dp_set_bits = add_count_noise(set_bits, laplace(rng, scale)?)?;
+        let estimate = linear_count_estimate(self.bits, dp_set_bits)?;
+
+        // update the remaining privacy budget
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+
+        Ok(Release::Released(estimate))
+    }
+
+    /// Returns a fuzzy number of distinct contributors, estimated from a noisy count of the set
+    /// bits in the sketch. The privacy cost is epsilon, plus epsilon for the minimum-count
+    /// check if one is set. This is the constant-work version: every field is read and the
+    /// noise is drawn before anything is checked, and every refusal returns
+    /// `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_distinct_count(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        // read every field in a fixed order, whatever the state of the store
+        let set_bits = self.get_set_bits(storage)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let policy = self.get_privacy_policy(storage)?;
+
+        let gate_passed = noisy_count_reaches(rng, set_bits, min_count, epsilon)?;
+        let scale = laplace_scale(I32F32::from_num(1_u32), epsilon)?;
+        let dp_set_bits = add_count_noise(set_bits, laplace(rng, scale)?)?;
+        let estimate = linear_count_estimate(self.bits, dp_set_bits);
+
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let eligible = max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            })
+            & (privacy_budget >= privacy_cost);
+        let allowed = eligible & gate_passed;
+        let gate_charged = eligible & !gate_passed;
+
+        // the budget and query count are written back either way
+        let charged = charged_cost(privacy_cost, allowed) + charged_cost(gate_cost, gate_charged);
+        self.save_privacy_budget(storage, privacy_budget - charged);
+        let counted = allowed | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        Ok(Release::Released(estimate?))
+    }
+}
+
+/// Linear counting estimate of the number of distinct items hashed into a bitmap of `bits`
+/// bits with `set_bits` of them set: -bits * ln(1 - set_bits / bits). `set_bits` may be noisy;
+/// it is clamped to [0, bits - 1], so a full bitmap gives the largest estimate,
+/// about bits * ln(bits).
+pub fn linear_count_estimate(bits: u32, set_bits: I32F32) -> StdResult<I32F32> {
+    if bits == 0 || bits > MAX_SKETCH_BITS {
+        return Err(StdError::generic_err("Sketch size out of range"));
+    }
+    let size = I32F32::from_num(bits);
+    let set_bits = set_bits.clamp(I32F32::from_num(0), size - I32F32::from_num(1));
+    let empty_fraction = (size - set_bits) / size;
+    let log_empty = ln::<I32F32, I32F32>(empty_fraction).map_err(|_|
+        StdError::generic_err("Distinct count estimate failed")
+    )?;
+    let estimate = -(I64F64::from_num(size) * I64F64::from_num(log_empty));
+    Ok(I32F32::saturating_from_num(estimate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn estimates_unique_contributors() {
+        let mut storage = MockStorage::new();
+        let store: DistinctCountStore = DistinctCountStore::new(b"distinct", 4096);
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(0)).is_err());
+        store.set_epsilon(&mut storage, I32F32::from_num(1)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(1)).unwrap();
+
+        // 3000 unique contributors, each of them seen three times
+        for _ in 0..3 {
+            for i in 0..3000_u32 {
+                store.add_contributor(&mut storage, &i.to_be_bytes()).unwrap();
+            }
+        }
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        let estimate = store.fuzzy_distinct_count(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert!((estimate - I32F32::from_num(3000)).abs() < I32F32::from_num(150));
+        assert!(store.fuzzy_distinct_count(&mut storage, &mut rng).is_err());
+
+        store.clear(&mut storage);
+        assert!(store.is_empty(&storage).unwrap());
+        assert!(store.add_contributor(&mut storage, b"again").unwrap());
+    }
+
+    #[test]
+    fn linear_count_estimate_is_exact_for_empty_and_bounded_when_full() {
+        assert_eq!(linear_count_estimate(64, I32F32::from_num(-3)).unwrap(), I32F32::from_num(0));
+        // ln(1 / 64) * -64
+        let full = linear_count_estimate(64, I32F32::from_num(80)).unwrap();
+        assert!((full - I32F32::from_num(266.17)).abs() < I32F32::from_num(0.01));
+    }
+
+    #[test]
+    fn too_few_contributors_are_refused_at_the_cost_of_the_check() {
+        let mut storage = MockStorage::new();
+        let store: DistinctCountStore = DistinctCountStore::new(b"distinct", 4096);
+        store.set_epsilon(&mut storage, I32F32::from_num(1)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(4)).unwrap();
+        store.set_min_count_for_release(&mut storage, 100);
+        for i in 0..5_u32 {
+            store.add_contributor(&mut storage, &i.to_be_bytes()).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([4; 32]);
+        // refused, but the check was charged and the refusal is not an error, so the charge
+        // commits
+        assert_eq!(store.fuzzy_distinct_count(&mut storage, &mut rng).unwrap(), Release::Refused);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+
+        for i in 5..500_u32 {
+            store.add_contributor(&mut storage, &i.to_be_bytes()).unwrap();
+        }
+        store.fuzzy_distinct_count(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+    }
+}
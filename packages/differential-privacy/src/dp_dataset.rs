@@ -0,0 +1,270 @@
+use cosmwasm_std::{Addr, Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::{serialization::{Serde, Bincode2}, crypto::sha_256};
+use substrate_fixed::types::I32F32;
+
+use crate::{PrivacyAccountant, Release, ResetPolicy, RunningStatsStore, RunningStatsStatus};
+
+const OWNER_KEY: &[u8] = b"owner";
+const READER_KEY: &[u8] = b"reader";
+
+/// Resets the shared privacy budget to `amount` at the start of every `period` time units
+/// (e.g. seconds or blocks). Unspent budget does not carry over. The last refilled period is
+/// kept by the accountant, so datasets sharing an accountant, which should all use the same
+/// schedule, refill it once per period between them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RefillSchedule {
+    pub amount: I32F32,
+    pub period: u64,
+}
+
+/// A running stats dataset wired to a shared `PrivacyAccountant`, with an optional clipping
+/// range, an optional budget refill schedule, and a list of addresses allowed to query it.
+///
+/// Every query checks, in order: that the caller is the owner or a reader, that the budget has
+/// been refilled for the current period, that the dataset has stopped collecting data, and that
+/// the shared budget covers the full cost of the query. Only then is noise drawn and the budget
+/// charged.
+pub struct DpDataset<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    stats: RunningStatsStore<'a, Ser>,
+    accountant: &'a PrivacyAccountant<'a>,
+    refill: Option<RefillSchedule>,
+}
+
+impl<'a, Ser: Serde> DpDataset<'a, Ser> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        stats_namespace: &'a [u8],
+        accountant: &'a PrivacyAccountant<'a>,
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            stats: RunningStatsStore::new(stats_namespace).with_shared_budget(accountant),
+            accountant,
+            refill: None,
+        }
+    }
+
+    /// Clamp every observation into the range [lower, upper], see `RunningStatsStore::with_clipping`
+    pub const fn with_clipping(
+        self,
+        lower: I32F32,
+        upper: I32F32,
+    ) -> Self {
+        Self {
+            namespace: self.namespace,
+            prefix: self.prefix,
+            stats: self.stats.with_clipping(lower, upper),
+            accountant: self.accountant,
+            refill: self.refill,
+        }
+    }
+
+    /// Reset the shared privacy budget to `amount` at the start of every `period` time units
+    pub const fn with_refill(
+        self,
+        amount: I32F32,
+        period: u64,
+    ) -> Self {
+        assert!(period > 0, "Refill period must be greater than zero");
+        Self {
+            namespace: self.namespace,
+            prefix: self.prefix,
+            stats: self.stats,
+            accountant: self.accountant,
+            refill: Some(RefillSchedule { amount, period }),
+        }
+    }
+
+    /// This is used to produce a new DpDataset. It can be used when you want to associate
+    /// a DpDataset to multiple suffixes and you still want to define the DpDataset
+    /// as a static constant. Suffixed datasets share the same accountant.
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let stats = self.stats.add_suffix(suffix);
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            stats,
+            accountant: self.accountant,
+            refill: self.refill,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> DpDataset<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn reader_key(&self, reader: &Addr) -> Vec<u8> {
+        [self.as_slice(), READER_KEY, &sha_256(reader.as_bytes())].concat()
+    }
+
+    /// Returns the underlying running stats store
+    pub fn stats(&self) -> &RunningStatsStore<'a, Ser> {
+        &self.stats
+    }
+
+    pub fn get_refill(&self) -> Option<RefillSchedule> {
+        self.refill
+    }
+
+    /// Sets up a new dataset owned by `owner`. The owner can grant and revoke read access and
+    /// decides when data collection ends. If a refill schedule is set, the shared budget is
+    /// filled for the period containing `timestamp`, unless another dataset sharing the
+    /// accountant already filled it for that period.
+    pub fn init(
+        &self,
+        storage: &mut dyn Storage,
+        owner: &Addr,
+        epsilon: I32F32,
+        avg_sensitivity: Option<I32F32>,
+        timestamp: u64,
+    ) -> StdResult<()> {
+        if storage.get(&[self.as_slice(), OWNER_KEY].concat()).is_some() {
+            return Err(StdError::generic_err("Dataset already initialized"));
+        }
+        self.stats.reset(storage, ResetPolicy::new(Some(epsilon), avg_sensitivity, None))?;
+        storage.set(&[self.as_slice(), OWNER_KEY].concat(), owner.as_bytes());
+        self.refill(storage, timestamp)
+    }
+
+    pub fn get_owner(&self, storage: &dyn Storage) -> StdResult<Addr> {
+        let owner_vec = storage.get(&[self.as_slice(), OWNER_KEY].concat()).ok_or(
+            StdError::generic_err("Dataset not initialized")
+        )?;
+        let owner = String::from_utf8(owner_vec)
+            .map_err(|err| StdError::generic_err(format!("{:?}", err)))?;
+        Ok(Addr::unchecked(owner))
+    }
+
+    fn assert_owner(&self, storage: &dyn Storage, sender: &Addr) -> StdResult<()> {
+        if self.get_owner(storage)? != *sender {
+            return Err(StdError::generic_err("Only the dataset owner can do this"));
+        }
+        Ok(())
+    }
+
+    /// Returns true if `address` is the owner or has been granted read access
+    pub fn can_read(&self, storage: &dyn Storage, address: &Addr) -> StdResult<bool> {
+        if self.get_owner(storage)? == *address {
+            return Ok(true);
+        }
+        Ok(storage.get(&self.reader_key(address)).is_some())
+    }
+
+    /// Allows `reader` to run queries. Only the owner can grant access.
+    pub fn grant_reader(&self, storage: &mut dyn Storage, sender: &Addr, reader: &Addr) -> StdResult<()> {
+        self.assert_owner(storage, sender)?;
+        storage.set(&self.reader_key(reader), &[1]);
+        Ok(())
+    }
+
+    /// Removes the read access of `reader`. Only the owner can revoke access.
+    pub fn revoke_reader(&self, storage: &mut dyn Storage, sender: &Addr, reader: &Addr) -> StdResult<()> {
+        self.assert_owner(storage, sender)?;
+        storage.remove(&self.reader_key(reader));
+        Ok(())
+    }
+
+    /// Refills the shared budget if `timestamp` is in a later period than the last refill of
+    /// the accountant, by this or any other dataset sharing it
+    pub fn refill(&self, storage: &mut dyn Storage, timestamp: u64) -> StdResult<()> {
+        if let Some(refill) = self.refill {
+            self.accountant.refill(storage, timestamp / refill.period, refill.amount)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_observation(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        self.get_owner(storage)?;
+        self.stats.add_observation(storage, x)
+    }
+
+    /// Ends data collection so the dataset can be queried. Only the owner can do this.
+    pub fn close(&self, storage: &mut dyn Storage, sender: &Addr) -> StdResult<()> {
+        self.assert_owner(storage, sender)?;
+        self.stats.set_status(storage, RunningStatsStatus::CalculatingStats)
+    }
+
+    fn before_query(&self, storage: &mut dyn Storage, sender: &Addr, timestamp: u64) -> StdResult<()> {
+        if !self.can_read(storage, sender)? {
+            return Err(StdError::generic_err("Not allowed to query this dataset"));
+        }
+        self.refill(storage, timestamp)
+    }
+
+    pub fn fuzzy_count(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        sender: &Addr,
+        timestamp: u64,
+    ) -> StdResult<Release<I32F32>> {
+        self.before_query(storage, sender, timestamp)?;
+        self.stats.fuzzy_count(storage, rng)
+    }
+
+    pub fn fuzzy_average(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        sender: &Addr,
+        timestamp: u64,
+    ) -> StdResult<Release<I32F32>> {
+        self.before_query(storage, sender, timestamp)?;
+        self.stats.fuzzy_average(storage, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn datasets_sharing_an_accountant_refill_it_once_per_period() {
+        let accountant = PrivacyAccountant::new(b"budget");
+        let amount = I32F32::from_num(10);
+        let visits: DpDataset = DpDataset::new(b"visits", b"stats-visits", &accountant).with_refill(amount, 100);
+        let sales: DpDataset = DpDataset::new(b"sales", b"stats-sales", &accountant).with_refill(amount, 100);
+        let owner = Addr::unchecked("owner");
+        let mut storage = MockStorage::new();
+        let mut rng = ChaChaRng::from_seed([4; 32]);
+
+        for dataset in [&visits, &sales] {
+            dataset.init(&mut storage, &owner, I32F32::from_num(1), None, 0).unwrap();
+            for _ in 0..20 {
+                dataset.add_observation(&mut storage, I32F32::from_num(1)).unwrap();
+            }
+            dataset.close(&mut storage, &owner).unwrap();
+        }
+        visits.fuzzy_count(&mut storage, &mut rng, &owner, 10).unwrap().released().unwrap();
+        // the second dataset does not refill the budget the first one spent in this period
+        sales.fuzzy_count(&mut storage, &mut rng, &owner, 20).unwrap().released().unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(8));
+
+        sales.fuzzy_count(&mut storage, &mut rng, &owner, 150).unwrap().released().unwrap();
+        visits.fuzzy_count(&mut storage, &mut rng, &owner, 160).unwrap().released().unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(8));
+        assert_eq!(accountant.get_last_refill(&storage).unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,170 @@
+use cosmwasm_std::{from_binary, to_binary, Binary, Storage, StdResult, StdError};
+use rand_chacha::ChaChaRng;
+use secret_data_tools_fixed::FixedBytes;
+use secret_toolkit::serialization::Serde;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use substrate_fixed::types::I32F32;
+
+use crate::{DpResult, QueryKind, Release, RunningStatsStore};
+
+/// Request for DP statistics from a dataset held by another contract, sent as a query message
+/// or as the data of an IBC packet. Fixed-point values are in the stored byte encoding of
+/// `FixedBytes`, so they cross contracts and chains without rounding.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DpQueryRequest {
+    /// id of the dataset on the serving contract
+    pub dataset_id: String,
+    pub queries: Vec<QueryKind>,
+    /// most epsilon the request may spend in total
+    pub epsilon: Vec<u8>,
+}
+
+impl DpQueryRequest {
+    pub fn new(dataset_id: impl Into<String>, queries: Vec<QueryKind>, epsilon: I32F32) -> Self {
+        Self {
+            dataset_id: dataset_id.into(),
+            queries,
+            epsilon: epsilon.to_stored_bytes(),
+        }
+    }
+
+    pub fn get_epsilon(&self) -> StdResult<I32F32> {
+        I32F32::from_stored_bytes(&self.epsilon)
+    }
+}
+
+/// Serializable form of a `DpResult`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoredDpResult {
+    Count(Vec<u8>),
+    Average(Vec<u8>),
+    Range(Vec<u8>, Vec<u8>),
+    WeightedAverage(Vec<u8>),
+}
+
+impl From<DpResult> for StoredDpResult {
+    fn from(result: DpResult) -> Self {
+        match result {
+            DpResult::Count(count) => StoredDpResult::Count(count.to_stored_bytes()),
+            DpResult::Average(average) => StoredDpResult::Average(average.to_stored_bytes()),
+            DpResult::Range(lower, upper) => {
+                StoredDpResult::Range(lower.to_stored_bytes(), upper.to_stored_bytes())
+            }
+            DpResult::WeightedAverage(average) => {
+                StoredDpResult::WeightedAverage(average.to_stored_bytes())
+            }
+        }
+    }
+}
+
+impl StoredDpResult {
+    pub fn into_humanized(&self) -> StdResult<DpResult> {
+        Ok(match self {
+            StoredDpResult::Count(count) => DpResult::Count(I32F32::from_stored_bytes(count)?),
+            StoredDpResult::Average(average) => DpResult::Average(I32F32::from_stored_bytes(average)?),
+            StoredDpResult::Range(lower, upper) => DpResult::Range(
+                I32F32::from_stored_bytes(lower)?,
+                I32F32::from_stored_bytes(upper)?,
+            ),
+            StoredDpResult::WeightedAverage(average) => {
+                DpResult::WeightedAverage(I32F32::from_stored_bytes(average)?)
+            }
+        })
+    }
+}
+
+/// Statistics released for a `DpQueryRequest`, in the order requested
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DpQueryResponse {
+    pub dataset_id: String,
+    /// epsilon charged for the release
+    pub epsilon_spent: Vec<u8>,
+    pub results: Vec<StoredDpResult>,
+}
+
+impl DpQueryResponse {
+    pub fn new(dataset_id: impl Into<String>, epsilon_spent: I32F32, results: &[DpResult]) -> Self {
+        Self {
+            dataset_id: dataset_id.into(),
+            epsilon_spent: epsilon_spent.to_stored_bytes(),
+            results: results.iter().map(|result| StoredDpResult::from(*result)).collect(),
+        }
+    }
+
+    pub fn get_epsilon_spent(&self) -> StdResult<I32F32> {
+        I32F32::from_stored_bytes(&self.epsilon_spent)
+    }
+
+    pub fn get_results(&self) -> StdResult<Vec<DpResult>> {
+        self.results.iter().map(StoredDpResult::into_humanized).collect()
+    }
+}
+
+/// Acknowledgement of a DP query sent over IBC: the response, or the reason it was refused.
+/// A request refused with an error charges nothing, since the error reverts the transaction.
+/// A request refused by the minimum-count check was charged for it: acknowledge it with
+/// `Error` and let the transaction commit.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DpQueryAck {
+    Result(DpQueryResponse),
+    Error(String),
+}
+
+/// Encodes a request, response or acknowledgement as JSON, e.g. for the data of an IBC packet
+pub fn encode_dp_packet<T: Serialize>(packet: &T) -> StdResult<Binary> {
+    to_binary(packet)
+}
+
+/// Decodes a request, response or acknowledgement encoded with `encode_dp_packet`
+pub fn decode_dp_packet<T: DeserializeOwned>(data: &Binary) -> StdResult<T> {
+    from_binary(data)
+}
+
+/// Answers a request on the serving side from the store of the requested dataset, which the
+/// contract looks up from `request.dataset_id`. The request is refused, without charging
+/// anything, if its statistics would cost more than its epsilon. The release itself is a
+/// `fuzzy_report`, with all of the store's usual checks, and returns `Release::Refused` if the
+/// minimum-count check refuses it.
+pub fn serve_dp_query_request<Ser: Serde>(
+    storage: &mut dyn Storage,
+    rng: &mut ChaChaRng,
+    store: &RunningStatsStore<Ser>,
+    request: &DpQueryRequest,
+) -> StdResult<Release<DpQueryResponse>> {
+    if request.queries.is_empty() {
+        return Err(StdError::generic_err("Request has no queries"));
+    }
+    let cost = store.report_cost(storage, &request.queries)?;
+    if cost > request.get_epsilon()? {
+        return Err(StdError::generic_err("Query costs more than its epsilon limit"));
+    }
+    let budget_before = store.get_privacy_budget(storage)?;
+    let results = store.fuzzy_report(storage, rng, &request.queries)?;
+    // cached statistics are not charged again, so report what was actually spent
+    let epsilon_spent = budget_before - store.get_privacy_budget(storage)?;
+    Ok(results.map(|results| DpQueryResponse::new(request.dataset_id.clone(), epsilon_spent, &results)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let request = DpQueryRequest::new("votes", vec![QueryKind::Count, QueryKind::Range], I32F32::from_num(2.5));
+        let encoded = encode_dp_packet(&request).unwrap();
+        let decoded: DpQueryRequest = decode_dp_packet(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.get_epsilon().unwrap(), I32F32::from_num(2.5));
+
+        let results = [DpResult::Count(I32F32::from_num(-1.25)), DpResult::Range(I32F32::from_num(3), I32F32::from_num(9))];
+        let ack = DpQueryAck::Result(DpQueryResponse::new("votes", I32F32::from_num(2), &results));
+        let decoded: DpQueryAck = decode_dp_packet(&encode_dp_packet(&ack).unwrap()).unwrap();
+        match decoded {
+            DpQueryAck::Result(response) => assert_eq!(response.get_results().unwrap(), results),
+            DpQueryAck::Error(err) => panic!("unexpected error {}", err),
+        }
+    }
+}
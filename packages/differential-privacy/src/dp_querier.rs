@@ -0,0 +1,144 @@
+use cosmwasm_std::{to_binary, Binary, QuerierWrapper, QueryRequest, Storage, StdResult, StdError, WasmQuery};
+use secret_data_tools_fixed::FixedBytes;
+use secret_toolkit::serialization::Serde;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use substrate_fixed::types::I32F32;
+
+use crate::{DpQueryRequest, DpQueryResponse, DpResult, QueryKind, RunningStatsStore};
+
+/// Standard query messages of a contract that serves DP statistics to other contracts. Smart
+/// queries cannot write to storage, so they cannot charge a privacy budget: they only return
+/// statistics that were already released, from the result cache, and the cost of new ones.
+/// New statistics are released by an execute message or IBC packet carrying a `DpQueryRequest`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DpQueryMsg {
+    /// statistics that were already released, answered with a `DpQueryResponse`
+    ReleasedStats { request: DpQueryRequest },
+    /// privacy cost of releasing the requested statistics, answered with a `DpCostResponse`
+    QueryCost { request: DpQueryRequest },
+}
+
+impl DpQueryMsg {
+    /// Returns the id of the dataset the message asks about, so the serving contract can find
+    /// its store
+    pub fn dataset_id(&self) -> &str {
+        match self {
+            DpQueryMsg::ReleasedStats { request } | DpQueryMsg::QueryCost { request } => &request.dataset_id,
+        }
+    }
+}
+
+/// Answer to `DpQueryMsg::QueryCost`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DpCostResponse {
+    pub epsilon: Vec<u8>,
+}
+
+impl DpCostResponse {
+    pub fn get_epsilon(&self) -> StdResult<I32F32> {
+        I32F32::from_stored_bytes(&self.epsilon)
+    }
+}
+
+/// Answers a `DpQueryMsg` on the serving side, from the store the contract picked for its
+/// dataset id. The store must be declared `with_result_cache` for released statistics to be
+/// served.
+pub fn answer_dp_query_msg<Ser: Serde>(
+    storage: &dyn Storage,
+    store: &RunningStatsStore<Ser>,
+    msg: &DpQueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        DpQueryMsg::ReleasedStats { request } => {
+            let results = store.get_released_report(storage, &request.queries)?.ok_or(
+                StdError::generic_err("Statistics have not been released")
+            )?;
+            to_binary(&DpQueryResponse::new(request.dataset_id.clone(), I32F32::from_num(0), &results))
+        }
+        DpQueryMsg::QueryCost { request } => {
+            let cost = store.report_cost(storage, &request.queries)?;
+            to_binary(&DpCostResponse { epsilon: cost.to_stored_bytes() })
+        }
+    }
+}
+
+/// Client for a contract that serves DP statistics with the `DpQueryMsg` messages, so that
+/// consumers do not have to build the wasm queries themselves
+pub struct DpQuerier<'a> {
+    querier: QuerierWrapper<'a>,
+    contract_addr: String,
+    code_hash: String,
+}
+
+impl<'a> DpQuerier<'a> {
+    pub fn new(
+        querier: QuerierWrapper<'a>,
+        contract_addr: impl Into<String>,
+        code_hash: impl Into<String>,
+    ) -> Self {
+        Self {
+            querier,
+            contract_addr: contract_addr.into(),
+            code_hash: code_hash.into(),
+        }
+    }
+
+    fn query<T: DeserializeOwned>(&self, msg: &DpQueryMsg) -> StdResult<T> {
+        self.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: self.contract_addr.clone(),
+            code_hash: self.code_hash.clone(),
+            msg: to_binary(msg)?,
+        }))
+    }
+
+    /// Returns statistics of a dataset that were already released, in the order requested.
+    /// Returns an error if any of them has not been released at the current data version.
+    pub fn released_stats(&self, dataset_id: &str, queries: &[QueryKind]) -> StdResult<Vec<DpResult>> {
+        let request = DpQueryRequest::new(dataset_id, queries.to_vec(), I32F32::from_num(0));
+        let response: DpQueryResponse = self.query(&DpQueryMsg::ReleasedStats { request })?;
+        response.get_results()
+    }
+
+    /// Returns the privacy cost of releasing the statistics of a dataset
+    pub fn query_cost(&self, dataset_id: &str, queries: &[QueryKind]) -> StdResult<I32F32> {
+        let request = DpQueryRequest::new(dataset_id, queries.to_vec(), I32F32::from_num(0));
+        let response: DpCostResponse = self.query(&DpQueryMsg::QueryCost { request })?;
+        response.get_epsilon()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_binary, testing::MockStorage};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use crate::{ResetPolicy, RunningStatsStatus};
+
+    #[test]
+    fn serves_released_stats_and_costs() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"served").with_result_cache();
+        store.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), Some(I32F32::from_num(5)), Some(I32F32::from_num(10)))).unwrap();
+        for x in 0..20 {
+            store.add_observation(&mut storage, I32F32::from_num(x % 5)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let request = DpQueryRequest::new("served", vec![QueryKind::Count, QueryKind::Average], I32F32::from_num(0));
+        let cost: DpCostResponse = from_binary(
+            &answer_dp_query_msg(&storage, &store, &DpQueryMsg::QueryCost { request: request.clone() }).unwrap()
+        ).unwrap();
+        assert_eq!(cost.get_epsilon().unwrap(), I32F32::from_num(3));
+
+        let released = DpQueryMsg::ReleasedStats { request: request.clone() };
+        assert_eq!(released.dataset_id(), "served");
+        assert!(answer_dp_query_msg(&storage, &store, &released).is_err());
+
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        let results = store.fuzzy_report(&mut storage, &mut rng, &request.queries).unwrap().released().unwrap();
+        let response: DpQueryResponse = from_binary(&answer_dp_query_msg(&storage, &store, &released).unwrap()).unwrap();
+        assert_eq!(response.get_results().unwrap(), results);
+    }
+}
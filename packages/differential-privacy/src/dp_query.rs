@@ -0,0 +1,350 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::Serde;
+use substrate_fixed::types::{I32F32, I64F64};
+
+use crate::{
+    BooleanStatsStore, CategoricalStatsStore, DistinctCountStore, DpResult, QueryKind,
+    Release, RunningStatsStore,
+};
+
+/// Aggregate requested by a `DpQuery`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    /// `COUNT`, from a running stats store
+    Count,
+    /// `AVG`, from a running stats store
+    Average,
+    /// `RANGE`, from a running stats store
+    Range,
+    /// `WEIGHTED_AVG`, from a running stats store
+    WeightedAverage,
+    /// `PROPORTION`, from a boolean stats store
+    Proportion,
+    /// `MODE`, from a categorical stats store
+    Mode,
+    /// `COUNT_DISTINCT`, from a distinct count store
+    DistinctCount,
+}
+
+impl Aggregate {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(Aggregate::Count),
+            "AVG" | "AVERAGE" => Some(Aggregate::Average),
+            "RANGE" => Some(Aggregate::Range),
+            "WEIGHTED_AVG" => Some(Aggregate::WeightedAverage),
+            "PROPORTION" => Some(Aggregate::Proportion),
+            "MODE" => Some(Aggregate::Mode),
+            "COUNT_DISTINCT" => Some(Aggregate::DistinctCount),
+            _ => None,
+        }
+    }
+
+    fn query_kind(self) -> Option<QueryKind> {
+        match self {
+            Aggregate::Count => Some(QueryKind::Count),
+            Aggregate::Average => Some(QueryKind::Average),
+            Aggregate::Range => Some(QueryKind::Range),
+            Aggregate::WeightedAverage => Some(QueryKind::WeightedAverage),
+            _ => None,
+        }
+    }
+}
+
+/// Statistic released by `run_dp_query`, in the same order as the requested aggregates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpAnswer {
+    Count(I32F32),
+    Average(I32F32),
+    Range(I32F32, I32F32),
+    WeightedAverage(I32F32),
+    Proportion(I32F32),
+    Mode(u16),
+    DistinctCount(I32F32),
+}
+
+impl From<DpResult> for DpAnswer {
+    fn from(result: DpResult) -> Self {
+        match result {
+            DpResult::Count(count) => DpAnswer::Count(count),
+            DpResult::Average(average) => DpAnswer::Average(average),
+            DpResult::Range(lower, upper) => DpAnswer::Range(lower, upper),
+            DpResult::WeightedAverage(average) => DpAnswer::WeightedAverage(average),
+        }
+    }
+}
+
+/// A query against one DP store: the aggregates to release, an optional group, which selects
+/// the store suffixed with that name, and the most epsilon the query may spend in total.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DpQuery {
+    pub aggregates: Vec<Aggregate>,
+    pub group: Option<Vec<u8>>,
+    pub epsilon: I32F32,
+}
+
+impl DpQuery {
+    /// Parses a query of the form
+    ///
+    /// `SELECT COUNT, AVG WHERE GROUP = north EPSILON 0.5`
+    ///
+    /// with one or more comma-separated aggregates (`COUNT`, `AVG`, `RANGE`, `WEIGHTED_AVG`,
+    /// `PROPORTION`, `MODE` or `COUNT_DISTINCT`), an optional group name without spaces, which
+    /// may be in single quotes, and the epsilon limit as a decimal number. Keywords are not
+    /// case sensitive.
+    pub fn parse(text: &str) -> StdResult<Self> {
+        let text = text.replace(',', " , ");
+        let mut tokens = text.split_whitespace().peekable();
+        if !tokens.next().is_some_and(|token| token.eq_ignore_ascii_case("SELECT")) {
+            return Err(StdError::generic_err("Query must start with SELECT"));
+        }
+
+        let mut aggregates = vec![];
+        loop {
+            let name = tokens.next().ok_or(StdError::generic_err("Expected an aggregate"))?;
+            let aggregate = Aggregate::from_name(name).ok_or_else(||
+                StdError::generic_err(format!("Unknown aggregate {}", name))
+            )?;
+            aggregates.push(aggregate);
+            if tokens.next_if_eq(&",").is_none() {
+                break;
+            }
+        }
+
+        let mut group = None;
+        if tokens.next_if(|token| token.eq_ignore_ascii_case("WHERE")).is_some() {
+            let is_group = tokens.next().is_some_and(|token| token.eq_ignore_ascii_case("GROUP"));
+            if !is_group || tokens.next() != Some("=") {
+                return Err(StdError::generic_err("Expected WHERE GROUP = <name>"));
+            }
+            let name = tokens.next().ok_or(StdError::generic_err("Expected a group name"))?;
+            let name = name.strip_prefix('\'').and_then(|name| name.strip_suffix('\'')).unwrap_or(name);
+            if name.is_empty() {
+                return Err(StdError::generic_err("Group name must not be empty"));
+            }
+            group = Some(name.as_bytes().to_vec());
+        }
+
+        if !tokens.next().is_some_and(|token| token.eq_ignore_ascii_case("EPSILON")) {
+            return Err(StdError::generic_err("Expected EPSILON <limit>"));
+        }
+        let epsilon = tokens.next().and_then(parse_decimal).ok_or(
+            StdError::generic_err("Expected a decimal epsilon")
+        )?;
+        if tokens.next().is_some() {
+            return Err(StdError::generic_err("Unexpected text after the epsilon"));
+        }
+
+        Ok(Self { aggregates, group, epsilon })
+    }
+}
+
+/// Parses a non-negative decimal number such as `2`, `0.25` or `.5`
+fn parse_decimal(text: &str) -> Option<I32F32> {
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !integer.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) || fraction.len() > 18 {
+        return None;
+    }
+    let integer: u32 = if integer.is_empty() { 0 } else { integer.parse().ok()? };
+    let fraction = if fraction.is_empty() {
+        I64F64::from_num(0)
+    } else {
+        I64F64::from_num(fraction.parse::<u64>().ok()?) / I64F64::from_num(10_u64.pow(fraction.len() as u32))
+    };
+    I32F32::checked_from_num(I64F64::from_num(integer) + fraction)
+}
+
+/// A store that `run_dp_query` can dispatch to
+pub enum DpSource<'s, 'a, Ser: Serde> {
+    Running(&'s RunningStatsStore<'a, Ser>),
+    Boolean(&'s BooleanStatsStore<'a, Ser>),
+    Categorical(&'s CategoricalStatsStore<'a, Ser>),
+    Distinct(&'s DistinctCountStore<'a, Ser>),
+}
+
+/// Runs `run` on the store of the group, or on the store itself if there is no group. The
+/// group comes from the query, so it must be one of `groups`; otherwise a query could name any
+/// suffix of the store's namespace.
+fn in_group<S, R>(
+    store: &S,
+    group: Option<&[u8]>,
+    groups: &[&[u8]],
+    add_suffix: impl Fn(&S, &[u8]) -> S,
+    run: impl FnOnce(&S) -> StdResult<R>,
+) -> StdResult<R> {
+    match group {
+        Some(group) if groups.contains(&group) => run(&add_suffix(store, group)),
+        Some(_) => Err(StdError::generic_err("Unknown group")),
+        None => run(store),
+    }
+}
+
+/// Boolean, categorical and distinct count stores release one aggregate each
+fn expect_single(aggregates: &[Aggregate], expected: Aggregate) -> StdResult<()> {
+    if aggregates != [expected] {
+        return Err(StdError::generic_err("Aggregate not supported by this store"));
+    }
+    Ok(())
+}
+
+/// Running stats queries are released together by `fuzzy_report`, so they are charged once
+fn running_query_kinds(aggregates: &[Aggregate]) -> StdResult<Vec<QueryKind>> {
+    if aggregates.is_empty() {
+        return Err(StdError::generic_err("Query has no aggregates"));
+    }
+    aggregates.iter().map(|aggregate| aggregate.query_kind().ok_or(
+        StdError::generic_err("Aggregate not supported by this store")
+    )).collect()
+}
+
+fn check_limit(cost: I32F32, query: &DpQuery) -> StdResult<()> {
+    if cost > query.epsilon {
+        return Err(StdError::generic_err("Query costs more than its epsilon limit"));
+    }
+    Ok(())
+}
+
+/// Returns the total privacy cost of a query against `source`, using the epsilon of the store
+/// selected by the group, which must be one of `groups`. Running stats stores with a result
+/// cache may charge less, for statistics that were already released.
+pub fn dp_query_cost<Ser: Serde>(
+    storage: &dyn Storage,
+    source: &DpSource<Ser>,
+    groups: &[&[u8]],
+    query: &DpQuery,
+) -> StdResult<I32F32> {
+    let group = query.group.as_deref();
+    match source {
+        DpSource::Running(store) => {
+            let kinds = running_query_kinds(&query.aggregates)?;
+            in_group(*store, group, groups, RunningStatsStore::add_suffix, |store| store.report_cost(storage, &kinds))
+        }
+        DpSource::Boolean(store) => {
+            expect_single(&query.aggregates, Aggregate::Proportion)?;
+            // sequential queries for successes + total
+            in_group(*store, group, groups, BooleanStatsStore::add_suffix, |store| Ok(2 * store.get_epsilon(storage)?))
+        }
+        DpSource::Categorical(store) => {
+            expect_single(&query.aggregates, Aggregate::Mode)?;
+            in_group(*store, group, groups, CategoricalStatsStore::add_suffix, |store| store.get_epsilon(storage))
+        }
+        DpSource::Distinct(store) => {
+            expect_single(&query.aggregates, Aggregate::DistinctCount)?;
+            in_group(*store, group, groups, DistinctCountStore::add_suffix, |store| store.get_epsilon(storage))
+        }
+    }
+}
+
+/// Single entry point for DP queries: checks that `source` supports every aggregate of the
+/// query and that their combined cost is within the query's epsilon limit, then releases them
+/// from the store selected by the group. Only the groups in `groups` can be selected, so a
+/// query cannot reach stores outside of them. Every aggregate is released and charged together, or
+/// none is. The store's own checks (status, minimum count, budget, query limit) apply as usual,
+/// and a query refused by the minimum-count check returns `Release::Refused`; running stats
+/// stores with a query cooldown must be queried with `fuzzy_report_at` instead.
+pub fn run_dp_query<Ser: Serde>(
+    storage: &mut dyn Storage,
+    rng: &mut ChaChaRng,
+    source: &DpSource<Ser>,
+    groups: &[&[u8]],
+    query: &DpQuery,
+) -> StdResult<Release<Vec<DpAnswer>>> {
+    check_limit(dp_query_cost(storage, source, groups, query)?, query)?;
+
+    let group = query.group.as_deref();
+    match source {
+        DpSource::Running(store) => {
+            let kinds = running_query_kinds(&query.aggregates)?;
+            in_group(*store, group, groups, RunningStatsStore::add_suffix, |store| {
+                let results = store.fuzzy_report(storage, rng, &kinds)?;
+                Ok(results.map(|results| results.into_iter().map(DpAnswer::from).collect()))
+            })
+        }
+        DpSource::Boolean(store) => in_group(*store, group, groups, BooleanStatsStore::add_suffix, |store| {
+            Ok(store.fuzzy_proportion(storage, rng)?.map(|proportion| vec![DpAnswer::Proportion(proportion)]))
+        }),
+        DpSource::Categorical(store) => in_group(*store, group, groups, CategoricalStatsStore::add_suffix, |store| {
+            Ok(store.fuzzy_mode(storage, rng)?.map(|mode| vec![DpAnswer::Mode(mode)]))
+        }),
+        DpSource::Distinct(store) => in_group(*store, group, groups, DistinctCountStore::add_suffix, |store| {
+            Ok(store.fuzzy_distinct_count(storage, rng)?.map(|count| vec![DpAnswer::DistinctCount(count)]))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+    use crate::{ResetPolicy, RunningStatsStatus};
+
+    #[test]
+    fn parses_queries() {
+        let query = DpQuery::parse("select count,AVG where group = 'north' epsilon 0.75").unwrap();
+        assert_eq!(query.aggregates, vec![Aggregate::Count, Aggregate::Average]);
+        assert_eq!(query.group, Some(b"north".to_vec()));
+        assert_eq!(query.epsilon, I32F32::from_num(0.75));
+
+        let query = DpQuery::parse("SELECT COUNT_DISTINCT EPSILON 2").unwrap();
+        assert_eq!(query.aggregates, vec![Aggregate::DistinctCount]);
+        assert_eq!(query.group, None);
+
+        assert!(DpQuery::parse("SELECT MEDIAN EPSILON 1").is_err());
+        assert!(DpQuery::parse("SELECT COUNT,").is_err());
+        assert!(DpQuery::parse("SELECT COUNT EPSILON -1").is_err());
+        assert!(DpQuery::parse("SELECT COUNT EPSILON 1 LIMIT 5").is_err());
+    }
+
+    #[test]
+    fn dispatches_to_group_within_epsilon_limit() {
+        let mut storage = MockStorage::new();
+        let stats: RunningStatsStore = RunningStatsStore::new(b"stats");
+        let north = stats.add_suffix(b"north");
+        north.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), Some(I32F32::from_num(10)), Some(I32F32::from_num(10)))).unwrap();
+        for x in 0..50 {
+            north.add_observation(&mut storage, I32F32::from_num(x % 10)).unwrap();
+        }
+        north.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let source = DpSource::Running(&stats);
+        let groups: &[&[u8]] = &[b"north", b"south"];
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        let too_costly = DpQuery::parse("SELECT COUNT, AVG WHERE GROUP = north EPSILON 2").unwrap();
+        assert_eq!(dp_query_cost(&storage, &source, groups, &too_costly).unwrap(), I32F32::from_num(3));
+        assert!(run_dp_query(&mut storage, &mut rng, &source, groups, &too_costly).is_err());
+        assert_eq!(north.get_privacy_budget(&storage).unwrap(), I32F32::from_num(10));
+
+        let query = DpQuery::parse("SELECT COUNT, AVG WHERE GROUP = north EPSILON 3").unwrap();
+        let answers = run_dp_query(&mut storage, &mut rng, &source, groups, &query).unwrap().released().unwrap();
+        assert!(matches!(answers[..], [DpAnswer::Count(_), DpAnswer::Average(_)]));
+        assert_eq!(north.get_privacy_budget(&storage).unwrap(), I32F32::from_num(7));
+
+        let wrong_store = DpQuery::parse("SELECT PROPORTION WHERE GROUP = north EPSILON 3").unwrap();
+        assert!(run_dp_query(&mut storage, &mut rng, &source, groups, &wrong_store).is_err());
+    }
+
+    #[test]
+    fn rejects_groups_outside_the_allowlist() {
+        let mut storage = MockStorage::new();
+        let stats: RunningStatsStore = RunningStatsStore::new(b"stats");
+        let hidden = stats.add_suffix(b"hidden");
+        hidden.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), Some(I32F32::from_num(10)), Some(I32F32::from_num(10)))).unwrap();
+        for x in 0..50 {
+            hidden.add_observation(&mut storage, I32F32::from_num(x % 10)).unwrap();
+        }
+        hidden.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let source = DpSource::Running(&stats);
+        let groups: &[&[u8]] = &[b"north"];
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        let query = DpQuery::parse("SELECT COUNT WHERE GROUP = hidden EPSILON 3").unwrap();
+        assert!(dp_query_cost(&storage, &source, groups, &query).is_err());
+        assert!(run_dp_query(&mut storage, &mut rng, &source, groups, &query).is_err());
+        assert_eq!(hidden.get_privacy_budget(&storage).unwrap(), I32F32::from_num(10));
+    }
+}
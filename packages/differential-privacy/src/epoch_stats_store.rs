@@ -0,0 +1,407 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::I32F32;
+
+use crate::{CachedField, DpResult, ExpiringPrivacyAccountant, QueryKind, Release, ResetPolicy, ResetValue, RunningStatsStore, RunningStatsStatus};
+
+const CURRENT_EPOCH_KEY: &[u8] = b"epoch";
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+
+/// Time-bucketed running statistics. Observations are added to a separate `RunningStatsStore`
+/// for each epoch of `epoch_length` time units (e.g. seconds or blocks). When an observation
+/// arrives in a new epoch, the store for the previous epoch is automatically switched to
+/// `CalculatingStats` so it can be queried. Each epoch's store is initialized, when the epoch
+/// starts, with the epsilon and per-epoch privacy budget set by `init`. Epochs are kept until
+/// they rotate out, see `with_retained_epochs`.
+pub struct EpochStatsStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    epoch_length: u64,
+    current_epoch: CachedField<u64>,
+    epsilon: CachedField<I32F32>,
+    /// privacy budget that each epoch starts with
+    privacy_budget: CachedField<I32F32>,
+    /// store that per-epoch stores are derived from
+    stats: RunningStatsStore<'a, Ser>,
+    /// number of finished epochs kept besides the current one, or None to keep every epoch
+    retained_epochs: Option<u64>,
+    /// budget shared by the epochs that have not rotated out
+    expiring_budget: Option<&'a ExpiringPrivacyAccountant<'a>>,
+}
+
+impl<'a, Ser: Serde> EpochStatsStore<'a, Ser> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        epoch_length: u64,
+    ) -> Self {
+        assert!(epoch_length > 0, "Epoch length must be greater than zero");
+        Self {
+            namespace,
+            prefix: None,
+            epoch_length,
+            current_epoch: CachedField::new(CURRENT_EPOCH_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            stats: RunningStatsStore::new(namespace),
+            retained_epochs: None,
+            expiring_budget: None,
+        }
+    }
+
+    /// Delete the data of an epoch once `epochs` later epochs have finished, e.g. 30 for daily
+    /// epochs of which the last month is kept. The current epoch and the `epochs` epochs before
+    /// it can be queried. The store of a deleted epoch is cleared and closed with a zero budget
+    /// when the store rotates, and its spending is forgotten by the `with_expiring_budget`
+    /// accountant. Each rotation reads up to `epochs + 1` epochs, so keep it small.
+    pub const fn with_retained_epochs(
+        mut self,
+        epochs: u64,
+    ) -> Self {
+        assert!(epochs > 0, "At least one finished epoch must be retained");
+        self.retained_epochs = Some(epochs);
+        self
+    }
+
+    /// Charge every release from an epoch to an `ExpiringPrivacyAccountant` as well as to the
+    /// budget of the epoch, so that the epochs still kept share one budget, e.g. for data from
+    /// users who contribute to every epoch. The spending on an epoch is only forgotten when its
+    /// data is deleted, see `with_retained_epochs`. Without retention nothing is deleted, so
+    /// nothing is forgotten and the accountant is a budget for the life of the store.
+    pub const fn with_expiring_budget(
+        mut self,
+        accountant: &'a ExpiringPrivacyAccountant<'a>,
+    ) -> Self {
+        self.expiring_budget = Some(accountant);
+        self
+    }
+
+    /// This is used to produce a new EpochStatsStore. It can be used when you want to associate
+    /// an EpochStatsStore to multiple suffixes and you still want to define the EpochStatsStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let stats = self.stats.add_suffix(suffix);
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            epoch_length: self.epoch_length,
+            current_epoch: CachedField::new(CURRENT_EPOCH_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            stats,
+            retained_epochs: self.retained_epochs,
+            expiring_budget: self.expiring_budget,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> EpochStatsStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_epoch_length(&self) -> u64 {
+        self.epoch_length
+    }
+
+    /// Returns the epoch that `timestamp` falls in
+    pub fn epoch_at(&self, timestamp: u64) -> u64 {
+        timestamp / self.epoch_length
+    }
+
+    /// Returns the running stats store for an epoch
+    pub fn store_for_epoch(&self, epoch: u64) -> RunningStatsStore<'a, Ser> {
+        self.stats.add_suffix(&epoch.to_be_bytes())
+    }
+
+    /// Sets the epsilon and the privacy budget that every epoch's store starts with. This must be
+    /// done before the first observation, and can be done again to change the settings of the
+    /// epochs that start afterwards.
+    pub fn init(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+        privacy_budget: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if privacy_budget < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy budget must not be negative"));
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        self.privacy_budget.save(storage, self.as_slice(), privacy_budget);
+        Ok(())
+    }
+
+    /// Returns the epsilon and privacy budget that each epoch starts with, or an error if `init`
+    /// was never called
+    pub fn get_epoch_params(&self, storage: &dyn Storage) -> StdResult<(I32F32, I32F32)> {
+        let not_initialized = || StdError::generic_err("Epoch store not initialized");
+        let epsilon = self.epsilon.may_load(storage, self.as_slice())?.ok_or_else(not_initialized)?;
+        let privacy_budget = self.privacy_budget.may_load(storage, self.as_slice())?.ok_or_else(not_initialized)?;
+        Ok((epsilon, privacy_budget))
+    }
+
+    fn start_epoch(&self, storage: &mut dyn Storage, epoch: u64) -> StdResult<()> {
+        let (epsilon, privacy_budget) = self.get_epoch_params(storage)?;
+        self.store_for_epoch(epoch).reset(storage, ResetPolicy::new(Some(epsilon), None, Some(privacy_budget)))?;
+        self.set_current_epoch(storage, epoch);
+        Ok(())
+    }
+
+    /// Returns the epoch that observations are currently being added to, if any
+    pub fn get_current_epoch(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u64>> {
+        self.current_epoch.may_load(storage, self.as_slice())
+    }
+
+    fn set_current_epoch(
+        &self,
+        storage: &mut dyn Storage,
+        epoch: u64,
+    ) {
+        self.current_epoch.save(storage, self.as_slice(), epoch);
+    }
+
+    /// Moves to the epoch containing `timestamp`. If that is a new epoch, the store for the
+    /// previous epoch is switched to calculating stats (if it has any data), the new epoch's
+    /// store starts with the settings from `init`, and the epochs that rotate out are deleted,
+    /// see `with_retained_epochs`. Returns the epoch, or an error if the current epoch's store
+    /// was closed.
+    pub fn rotate(&self, storage: &mut dyn Storage, timestamp: u64) -> StdResult<u64> {
+        let epoch = self.epoch_at(timestamp);
+        match self.get_current_epoch(storage)? {
+            Some(current_epoch) if current_epoch == epoch => {}
+            Some(current_epoch) if current_epoch > epoch => {
+                return Err(StdError::generic_err("Timestamp is in a past epoch"));
+            }
+            Some(current_epoch) => {
+                let previous = self.store_for_epoch(current_epoch);
+                // closing the current epoch closes the store, so no new epoch is started
+                if previous.get_status(storage)? == RunningStatsStatus::Closed {
+                    return Err(StdError::generic_err("Store is closed"));
+                }
+                if previous.get_status(storage)? == RunningStatsStatus::CollectingData
+                    && !previous.is_empty(storage)? {
+                    previous.set_status(storage, RunningStatsStatus::CalculatingStats)?;
+                }
+                self.start_epoch(storage, epoch)?;
+                self.delete_expired_epochs(storage, current_epoch, epoch)?;
+            }
+            None => {
+                self.start_epoch(storage, epoch)?;
+            }
+        }
+        Ok(epoch)
+    }
+
+    /// Adds an observation to the store for the epoch containing `timestamp`, rotating epochs
+    /// if needed
+    pub fn add_observation(
+        &self,
+        storage: &mut dyn Storage,
+        timestamp: u64,
+        x: I32F32,
+    ) -> StdResult<()> {
+        let epoch = self.rotate(storage, timestamp)?;
+        self.store_for_epoch(epoch).add_observation(storage, x)
+    }
+
+    /// Deletes the data of the epochs that rotate out when the store moves from epoch
+    /// `previous` to `epoch`, and expires them in the `with_expiring_budget` accountant. Epochs
+    /// older than `previous - retained` were deleted by earlier rotations, and epochs after
+    /// `previous` were never started. An epoch that was closed keeps its data, so its spending
+    /// is never forgotten.
+    fn delete_expired_epochs(&self, storage: &mut dyn Storage, previous: u64, epoch: u64) -> StdResult<()> {
+        let retained = match self.retained_epochs {
+            Some(retained) => retained,
+            None => return Ok(()),
+        };
+        let oldest_kept = epoch.saturating_sub(retained);
+        for expired in previous.saturating_sub(retained)..oldest_kept.min(previous + 1) {
+            let store = self.store_for_epoch(expired);
+            if !store.is_initialized(storage) || store.get_status(storage)? == RunningStatsStatus::Closed {
+                continue;
+            }
+            let policy = ResetPolicy { privacy_budget: ResetValue::Set(I32F32::from_num(0)), ..ResetPolicy::KEEP_ALL };
+            store.reset(storage, policy)?;
+            store.set_status(storage, RunningStatsStatus::Closed)?;
+            if let Some(accountant) = self.expiring_budget {
+                accountant.expire(storage, self.as_slice(), expired)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Privately releases statistics of `epoch`, as `RunningStatsStore::fuzzy_report`. The
+    /// epoch must have been rotated out, and the release is charged to that epoch's budget and
+    /// to the `with_expiring_budget` accountant, which must be able to pay the full cost before
+    /// any noise is drawn. Releases made directly on `store_for_epoch` bypass the accountant.
+    pub fn fuzzy_report_for_epoch(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        epoch: u64,
+        queries: &[QueryKind],
+    ) -> StdResult<Release<Vec<DpResult>>> {
+        let store = self.store_for_epoch(epoch);
+        let accountant = match self.expiring_budget {
+            Some(accountant) => accountant,
+            None => return store.fuzzy_report(storage, rng, queries),
+        };
+        accountant.check_charge(storage, self.as_slice(), epoch, store.report_cost(storage, queries)?)?;
+        let budget_before = store.get_privacy_budget(storage)?;
+        let results = store.fuzzy_report(storage, rng, queries);
+        // whatever the epoch was charged, including a refused minimum-count check
+        let charged = budget_before - store.get_privacy_budget(storage)?;
+        accountant.charge(storage, self.as_slice(), epoch, charged)?;
+        results
+    }
+
+    /// Privately releases the number of observations in `epoch`, e.g. `epoch_at(env.block.time)`
+    /// minus one for the epoch that just ended, see `fuzzy_report_for_epoch`.
+    pub fn fuzzy_count_for_epoch(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        epoch: u64,
+    ) -> StdResult<Release<I32F32>> {
+        self.fuzzy_report_for_epoch(storage, rng, epoch, &[QueryKind::Count])?.try_map(|results| match results[..] {
+            [DpResult::Count(fuzzy_count)] => Ok(fuzzy_count),
+            _ => Err(StdError::generic_err("Unexpected query result")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn counts_each_epoch_after_it_ends() {
+        let mut storage = MockStorage::new();
+        let store: EpochStatsStore = EpochStatsStore::new(b"daily", 100);
+        assert!(store.add_observation(&mut storage, 0, I32F32::from_num(1)).is_err());
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(3)).unwrap();
+        for timestamp in 0..40 {
+            store.add_observation(&mut storage, timestamp, I32F32::from_num(1)).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([5; 32]);
+        // epoch 0 is still collecting data
+        assert!(store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).is_err());
+
+        store.add_observation(&mut storage, 150, I32F32::from_num(1)).unwrap();
+        assert_eq!(store.get_current_epoch(&storage).unwrap(), Some(1));
+        let count = store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).unwrap().released().unwrap();
+        assert!((count - I32F32::from_num(40)).abs() < I32F32::from_num(8));
+        assert!(store.add_observation(&mut storage, 50, I32F32::from_num(1)).is_err());
+    }
+
+    #[test]
+    fn new_epochs_start_with_the_configured_settings() {
+        let mut storage = MockStorage::new();
+        let store: EpochStatsStore = EpochStatsStore::new(b"daily", 100);
+        store.init(&mut storage, I32F32::from_num(2), I32F32::from_num(6)).unwrap();
+        store.add_observation(&mut storage, 10, I32F32::from_num(1)).unwrap();
+        store.add_observation(&mut storage, 110, I32F32::from_num(1)).unwrap();
+        let mut rng = ChaChaRng::from_seed([6; 32]);
+        store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).unwrap().released().unwrap();
+        assert_eq!(store.store_for_epoch(0).get_privacy_budget(&storage).unwrap(), I32F32::from_num(4));
+
+        // a spent budget is not carried into the next epoch
+        store.add_observation(&mut storage, 210, I32F32::from_num(1)).unwrap();
+        let next = store.store_for_epoch(2);
+        assert_eq!(next.get_epsilon(&storage).unwrap(), I32F32::from_num(2));
+        assert_eq!(next.get_privacy_budget(&storage).unwrap(), I32F32::from_num(6));
+    }
+
+    #[test]
+    fn rotated_out_epochs_are_deleted_and_their_spending_forgotten() {
+        let mut storage = MockStorage::new();
+        let accountant = ExpiringPrivacyAccountant::new(b"window");
+        let store: EpochStatsStore = EpochStatsStore::new(b"daily", 100)
+            .with_retained_epochs(1)
+            .with_expiring_budget(&accountant);
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(10)).unwrap();
+        accountant.set_window_budget(&mut storage, I32F32::from_num(3)).unwrap();
+        store.add_observation(&mut storage, 10, I32F32::from_num(1)).unwrap();
+        store.add_observation(&mut storage, 110, I32F32::from_num(1)).unwrap();
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).unwrap().released().unwrap();
+        store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).unwrap().released().unwrap();
+        assert_eq!(accountant.get_spent(&storage).unwrap(), I32F32::from_num(2));
+
+        // epoch 1 is still kept, so epoch 0 is only deleted when epoch 2 starts
+        store.add_observation(&mut storage, 210, I32F32::from_num(1)).unwrap();
+        assert_eq!(accountant.get_spent(&storage).unwrap(), I32F32::from_num(0));
+        let deleted = store.store_for_epoch(0);
+        assert!(deleted.is_empty(&storage).unwrap());
+        assert_eq!(deleted.get_status(&storage).unwrap(), RunningStatsStatus::Closed);
+        assert_eq!(deleted.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+        assert!(store.fuzzy_count_for_epoch(&mut storage, &mut rng, 0).is_err());
+
+        // the kept epochs share the window budget, which is checked before anything is charged
+        for _ in 0..3 {
+            store.fuzzy_count_for_epoch(&mut storage, &mut rng, 1).unwrap().released().unwrap();
+        }
+        assert!(store.fuzzy_count_for_epoch(&mut storage, &mut rng, 1).is_err());
+        assert_eq!(store.store_for_epoch(1).get_privacy_budget(&storage).unwrap(), I32F32::from_num(7));
+
+        // a jump over several epochs deletes every epoch that rotates out
+        store.add_observation(&mut storage, 1_000, I32F32::from_num(1)).unwrap();
+        assert_eq!(store.store_for_epoch(1).get_status(&storage).unwrap(), RunningStatsStatus::Closed);
+        assert_eq!(store.store_for_epoch(2).get_status(&storage).unwrap(), RunningStatsStatus::Closed);
+        assert_eq!(accountant.get_spent(&storage).unwrap(), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn without_retention_spending_is_never_forgotten() {
+        let mut storage = MockStorage::new();
+        let accountant = ExpiringPrivacyAccountant::new(b"window");
+        let store: EpochStatsStore = EpochStatsStore::new(b"daily", 100).with_expiring_budget(&accountant);
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(10)).unwrap();
+        accountant.set_window_budget(&mut storage, I32F32::from_num(2)).unwrap();
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        for epoch in 0..2 {
+            store.add_observation(&mut storage, epoch * 100, I32F32::from_num(1)).unwrap();
+            store.add_observation(&mut storage, epoch * 100 + 100, I32F32::from_num(1)).unwrap();
+            store.fuzzy_count_for_epoch(&mut storage, &mut rng, epoch).unwrap().released().unwrap();
+        }
+        store.add_observation(&mut storage, 1_000, I32F32::from_num(1)).unwrap();
+        assert_eq!(store.store_for_epoch(0).get_count(&storage).unwrap(), 1);
+        assert_eq!(accountant.get_spent(&storage).unwrap(), I32F32::from_num(2));
+        assert!(store.fuzzy_count_for_epoch(&mut storage, &mut rng, 2).is_err());
+    }
+
+    #[test]
+    fn closing_the_current_epoch_stops_rotation() {
+        let mut storage = MockStorage::new();
+        let store: EpochStatsStore = EpochStatsStore::new(b"daily", 100);
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(3)).unwrap();
+        store.add_observation(&mut storage, 10, I32F32::from_num(1)).unwrap();
+        store.store_for_epoch(0).set_status(&mut storage, RunningStatsStatus::Closed).unwrap();
+
+        assert!(store.rotate(&mut storage, 110).is_err());
+        assert_eq!(store.get_current_epoch(&storage).unwrap(), Some(0));
+    }
+}
@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::{exp, ln, sqrt};
+use crate::{random_unit_interval, NoiseError, NoiseResult};
+
+// Gamma, Beta and Dirichlet samplers, for Thompson sampling and Bayesian mechanisms. They are
+// built on the same ChaChaRng and uniform draws as the noise samplers. Intermediate values are
+// kept in I64F64, since the polar method and the small shape case divide by numbers that can
+// be as small as 2^-32.
+
+// the Marsaglia-Tsang and polar loops accept a draw with probability above 0.75, so this many
+// rejections in a row does not happen in practice
+const MAX_REJECTIONS: u32 = 64;
+
+fn unit(rng: &mut ChaChaRng) -> I64F64 {
+    I64F64::from_num(random_unit_interval(rng))
+}
+
+fn ln_i64f64(x: I64F64) -> NoiseResult<I64F64> {
+    ln::<I64F64, I64F64>(x).map_err(|_| NoiseError("Logarithm is undefined"))
+}
+
+pub(crate) fn standard_normal_i64f64(rng: &mut ChaChaRng) -> NoiseResult<I64F64> {
+    let one = I64F64::from_num(1);
+    for _ in 0..MAX_REJECTIONS {
+        // Marsaglia's polar method, a point drawn uniformly from the unit disk
+        let u = 2 * unit(rng) - one;
+        let v = 2 * unit(rng) - one;
+        let s = u * u + v * v;
+        if s > 0 && s < one {
+            let factor = sqrt::<I64F64, I64F64>(-2 * ln_i64f64(s)? / s)
+                .map_err(|_| NoiseError("Square root is undefined"))?;
+            return Ok(u * factor);
+        }
+    }
+    Err(NoiseError("Normal sampler did not accept a draw"))
+}
+
+/// Draws a sample from the standard normal distribution, with mean 0 and variance 1
+pub fn standard_normal(rng: &mut ChaChaRng) -> NoiseResult<I32F32> {
+    I32F32::checked_from_num(standard_normal_i64f64(rng)?).ok_or(NoiseError("Normal sample overflowed"))
+}
+
+fn gamma_i64f64(rng: &mut ChaChaRng, shape: I64F64) -> NoiseResult<I64F64> {
+    if shape <= 0 {
+        return Err(NoiseError("Shape must be greater than zero"));
+    }
+    let one = I64F64::from_num(1);
+    if shape < one {
+        // Gamma(shape) = Gamma(shape + 1) * U^(1 / shape)
+        let sample = gamma_i64f64(rng, shape + one)?;
+        let exponent = ln_i64f64(unit(rng))? / shape;
+        // e^-45 is below 2^-64, the resolution of I64F64
+        if exponent < -45 {
+            return Ok(I64F64::from_num(0));
+        }
+        let power = exp::<I64F64, I64F64>(exponent).map_err(|_| NoiseError("Exponential overflowed"))?;
+        return Ok(sample * power);
+    }
+
+    // Marsaglia and Tsang, 2000
+    let d = shape - one / 3;
+    let c = one / sqrt::<I64F64, I64F64>(9 * d).map_err(|_| NoiseError("Square root is undefined"))?;
+    for _ in 0..MAX_REJECTIONS {
+        let x = standard_normal_i64f64(rng)?;
+        let t = one + c * x;
+        if t <= 0 {
+            continue;
+        }
+        let v = t * t * t;
+        let log_u = ln_i64f64(unit(rng))?;
+        if log_u < x * x / 2 + d - d * v + d * ln_i64f64(v)? {
+            return Ok(d * v);
+        }
+    }
+    Err(NoiseError("Gamma sampler did not accept a draw"))
+}
+
+/// Draws a sample from the Gamma distribution with the given shape and scale 1, whose mean and
+/// variance both equal the shape. Returns an error if the shape is not positive.
+pub fn gamma(rng: &mut ChaChaRng, shape: I32F32) -> NoiseResult<I32F32> {
+    I32F32::checked_from_num(gamma_i64f64(rng, I64F64::from_num(shape))?).ok_or(NoiseError("Gamma sample overflowed"))
+}
+
+/// Draws a sample in [0, 1] from the Beta distribution with shapes `alpha` and `beta`, e.g. a
+/// posterior over a success rate after alpha - 1 successes and beta - 1 failures
+pub fn beta(rng: &mut ChaChaRng, alpha: I32F32, beta: I32F32) -> NoiseResult<I32F32> {
+    let samples = dirichlet(rng, &[alpha, beta])?;
+    Ok(samples[0])
+}
+
+/// Draws a point of the probability simplex from the Dirichlet distribution with the given
+/// concentrations, one entry per category, summing to 1 up to rounding. Returns an error if
+/// any concentration is not positive.
+pub fn dirichlet(rng: &mut ChaChaRng, concentrations: &[I32F32]) -> NoiseResult<Vec<I32F32>> {
+    let mut samples = Vec::with_capacity(concentrations.len());
+    for concentration in concentrations {
+        samples.push(gamma_i64f64(rng, I64F64::from_num(*concentration))?);
+    }
+    let total = samples.iter().fold(I64F64::from_num(0), |total, sample| total.saturating_add(*sample));
+    if total == 0 {
+        return Err(NoiseError("Dirichlet samples underflowed"));
+    }
+    Ok(samples.into_iter().map(|sample| I32F32::from_num(sample / total)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn mean_and_variance(draws: &[f64]) -> (f64, f64) {
+        let n = draws.len() as f64;
+        let mean = draws.iter().sum::<f64>() / n;
+        (mean, draws.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n)
+    }
+
+    #[test]
+    fn gamma_and_beta_moments() {
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        let n = 5000;
+        for shape in [0.5, 1.0, 4.5] {
+            let draws: Vec<f64> = (0..n)
+                .map(|_| gamma(&mut rng, I32F32::from_num(shape)).unwrap().to_num::<f64>())
+                .collect();
+            let (mean, variance) = mean_and_variance(&draws);
+            assert!((mean - shape).abs() < 0.08 * shape.max(1.0), "mean {} for shape {}", mean, shape);
+            assert!((variance / shape - 1.0).abs() < 0.2, "variance {} for shape {}", variance, shape);
+        }
+
+        // Beta(2, 5) has mean 2/7 and variance 10/392
+        let draws: Vec<f64> = (0..n)
+            .map(|_| beta(&mut rng, I32F32::from_num(2), I32F32::from_num(5)).unwrap().to_num::<f64>())
+            .collect();
+        let (mean, variance) = mean_and_variance(&draws);
+        assert!((mean - 2.0 / 7.0).abs() < 0.01, "mean {}", mean);
+        assert!((variance - 10.0 / 392.0).abs() < 0.003, "variance {}", variance);
+        assert!(gamma(&mut rng, I32F32::from_num(0)).is_err());
+    }
+
+    #[test]
+    fn dirichlet_is_on_the_simplex() {
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        let concentrations = [I32F32::from_num(1), I32F32::from_num(2), I32F32::from_num(3)];
+        let n = 3000;
+        let mut totals = [0.0; 3];
+        for _ in 0..n {
+            let sample = dirichlet(&mut rng, &concentrations).unwrap();
+            let sum: f64 = sample.iter().map(|x| x.to_num::<f64>()).sum();
+            assert!((sum - 1.0).abs() < 1e-6);
+            for (total, x) in totals.iter_mut().zip(&sample) {
+                *total += x.to_num::<f64>();
+            }
+        }
+        for (total, expected) in totals.iter().zip([1.0 / 6.0, 1.0 / 3.0, 0.5]) {
+            assert!((total / n as f64 - expected).abs() < 0.015, "mean {}", total / n as f64);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::{exp, ln};
+use crate::{random_unit_interval, sample_distribution, standard_normal_i64f64, NoiseError, NoiseResult};
+
+// Heavy-tailed samplers, for generating synthetic data that looks like real contributions,
+// e.g. transaction sizes (Pareto) or item popularity (Zipf), and Cauchy noise for smooth
+// sensitivity.
+
+/// Draws a sample from the Cauchy distribution centered on 0 with the given scale, as the
+/// ratio of two standard normal samples times the scale. The tails are heavy enough that a
+/// sample can fall outside the I32F32 range, in which case it saturates rather than failing.
+pub fn cauchy(rng: &mut ChaChaRng, scale: I32F32) -> NoiseResult<I32F32> {
+    if scale <= 0 {
+        return Err(NoiseError("Scale must be greater than zero"));
+    }
+    let numerator = standard_normal_i64f64(rng)?;
+    let denominator = standard_normal_i64f64(rng)?;
+    let ratio = numerator.checked_div(denominator).unwrap_or(
+        if numerator < 0 { I64F64::MIN } else { I64F64::MAX }
+    );
+    Ok(I32F32::saturating_from_num(ratio.saturating_mul(I64F64::from_num(scale))))
+}
+
+/// Draws a sample from the Pareto distribution with minimum `scale` and tail index `shape`,
+/// scale * U^(-1 / shape). Smaller shapes give heavier tails; the mean is only finite for
+/// shape > 1. Returns an error if either parameter is not positive or the sample overflows.
+pub fn pareto(rng: &mut ChaChaRng, scale: I32F32, shape: I32F32) -> NoiseResult<I32F32> {
+    if scale <= 0 || shape <= 0 {
+        return Err(NoiseError("Scale and shape must be greater than zero"));
+    }
+    let log_u = ln::<I64F64, I64F64>(I64F64::from_num(random_unit_interval(rng)))
+        .map_err(|_| NoiseError("Logarithm of uniform draw is undefined"))?;
+    let power = exp::<I64F64, I64F64>(-log_u / I64F64::from_num(shape))
+        .map_err(|_| NoiseError("Pareto sample overflowed"))?;
+    power
+        .checked_mul(I64F64::from_num(scale))
+        .and_then(I32F32::checked_from_num)
+        .ok_or(NoiseError("Pareto sample overflowed"))
+}
+
+/// Returns the probabilities of ranks 1 to n under the Zipf distribution with the given
+/// exponent, proportional to k^-exponent, as used by `zipf`
+pub fn zipf_probabilities(n: u32, exponent: I32F32) -> NoiseResult<Vec<I64F64>> {
+    if n == 0 {
+        return Err(NoiseError("Zipf needs at least one rank"));
+    }
+    let exponent = I64F64::from_num(exponent);
+    let mut weights = Vec::with_capacity(n as usize);
+    for rank in 1..=n {
+        let log_rank = ln::<I64F64, I64F64>(I64F64::from_num(rank))
+            .map_err(|_| NoiseError("Logarithm is undefined"))?;
+        let weight = exp::<I64F64, I64F64>(-exponent * log_rank)
+            .map_err(|_| NoiseError("Zipf weight overflowed"))?;
+        weights.push(weight);
+    }
+    let total = weights.iter().fold(I64F64::from_num(0), |total, weight| total.saturating_add(*weight));
+    Ok(weights.into_iter().map(|weight| weight / total).collect())
+}
+
+/// Draws a rank in 1..=n from the Zipf distribution, where rank k has probability
+/// proportional to k^-exponent. Takes O(n) time, so it suits up to a few thousand ranks; draw
+/// from `zipf_probabilities` directly to reuse the table for many samples.
+pub fn zipf(rng: &mut ChaChaRng, n: u32, exponent: I32F32) -> NoiseResult<u32> {
+    let probabilities = zipf_probabilities(n, exponent)?;
+    Ok(sample_distribution(rng, &probabilities) as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn pareto_has_expected_median_and_minimum() {
+        let mut rng = ChaChaRng::from_seed([13; 32]);
+        let mut draws: Vec<f64> = (0..4001)
+            .map(|_| pareto(&mut rng, I32F32::from_num(2), I32F32::from_num(3)).unwrap().to_num::<f64>())
+            .collect();
+        draws.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(draws[0] >= 2.0);
+        // the median is scale * 2^(1 / shape)
+        assert!((draws[2000] - 2.0 * 2f64.powf(1.0 / 3.0)).abs() < 0.05, "median {}", draws[2000]);
+        assert!(pareto(&mut rng, I32F32::from_num(0), I32F32::from_num(1)).is_err());
+    }
+
+    #[test]
+    fn cauchy_has_expected_quartiles() {
+        let mut rng = ChaChaRng::from_seed([15; 32]);
+        let mut draws: Vec<f64> = (0..4001)
+            .map(|_| cauchy(&mut rng, I32F32::from_num(2)).unwrap().to_num::<f64>())
+            .collect();
+        draws.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // the quartiles are -scale and +scale around a median of 0
+        assert!(draws[2000].abs() < 0.15, "median {}", draws[2000]);
+        assert!((draws[1000] + 2.0).abs() < 0.25, "lower quartile {}", draws[1000]);
+        assert!((draws[3000] - 2.0).abs() < 0.25, "upper quartile {}", draws[3000]);
+        assert!(cauchy(&mut rng, I32F32::from_num(0)).is_err());
+    }
+
+    #[test]
+    fn zipf_ranks_follow_power_law() {
+        let mut rng = ChaChaRng::from_seed([14; 32]);
+        let mut counts = [0u32; 4];
+        for _ in 0..6000 {
+            counts[zipf(&mut rng, 4, I32F32::from_num(1)).unwrap() as usize - 1] += 1;
+        }
+        // probabilities 12/25, 6/25, 4/25 and 3/25
+        for (count, expected) in counts.iter().zip([2880.0, 1440.0, 960.0, 720.0]) {
+            assert!((f64::from(*count) - expected).abs() < 150.0, "{:?}", counts);
+        }
+    }
+}
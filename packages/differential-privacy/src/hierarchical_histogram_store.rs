@@ -0,0 +1,576 @@
+use std::marker::PhantomData;
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::{I32F32, I64F64};
+
+#[cfg(feature = "constant_time")]
+use crate::charged_cost;
+use crate::{add_count_noise, laplace_scale, laplace_vec, min_count_gate_cost, noisy_count_reaches, synthetic_values, CachedField, PrivacyPolicy, NoiseResult, Release};
+
+const EPSILON_KEY: &[u8] = b"epsilon";
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const MIN_COUNT_KEY: &[u8] = b"min-count";
+const MAX_QUERIES_KEY: &[u8] = b"max-queries";
+const QUERY_COUNT_KEY: &[u8] = b"queries";
+const PRIVACY_POLICY_KEY: &[u8] = b"policy";
+const NODE_KEY: &[u8] = b"node";
+
+/// Deepest tree, with 1024 leaves, which keeps the number of keys read by a release bounded
+pub const MAX_HISTOGRAM_DEPTH: u8 = 10;
+
+/// Histogram over [lower, upper] that answers "how many values between a and b" queries. The
+/// range is split into 2^depth equal bins, and counts are kept for every node of a binary tree
+/// over the bins: the root covers the whole range and each node covers the two halves below it.
+/// An observation adds 1 to the one node of each level that contains it.
+///
+/// A release adds Laplace noise of scale (depth + 1) / epsilon to every node, at a cost of
+/// epsilon, and then makes the noisy counts consistent, so that every node is the sum of its
+/// children, using the constrained inference of Hay et al. (2010). The noise in a range count
+/// then grows with the logarithm of the number of bins instead of with the width of the range,
+/// which makes wide range queries far more accurate than summing a flat noisy histogram.
+pub struct HierarchicalHistogramStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    depth: u8,
+    lower: I32F32,
+    upper: I32F32,
+    epsilon: CachedField<I32F32>,
+    privacy_budget: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    max_total_queries: CachedField<u32>,
+    total_queries: CachedField<u32>,
+    privacy_policy: CachedField<PrivacyPolicy>,
+    serialization_type: PhantomData<Ser>,
+}
+
+impl<'a, Ser: Serde> HierarchicalHistogramStore<'a, Ser> {
+    /// constructor, for 2^depth bins over [lower, upper]
+    pub const fn new(
+        namespace: &'a [u8],
+        depth: u8,
+        lower: I32F32,
+        upper: I32F32,
+    ) -> Self {
+        assert!(depth > 0 && depth <= MAX_HISTOGRAM_DEPTH, "Histogram depth must be between 1 and 10");
+        assert!(lower.to_bits() < upper.to_bits(), "Lower bound must be less than upper bound");
+        Self {
+            namespace,
+            prefix: None,
+            depth,
+            lower,
+            upper,
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: PhantomData,
+        }
+    }
+
+    /// This is used to produce a new HierarchicalHistogramStore. It can be used when you want
+    /// to associate a HierarchicalHistogramStore to multiple suffixes and you still want to
+    /// define the HierarchicalHistogramStore as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            depth: self.depth,
+            lower: self.lower,
+            upper: self.upper,
+            epsilon: CachedField::new(EPSILON_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            privacy_policy: CachedField::new(PRIVACY_POLICY_KEY),
+            serialization_type: self.serialization_type,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> HierarchicalHistogramStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Returns the number of bins, 2^depth
+    pub fn get_bins(&self) -> u16 {
+        1 << self.depth
+    }
+
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default epsilon = 1
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the epsilon. Returns an error if it is not positive or is outside the privacy policy,
+    /// if there is one.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    pub fn get_privacy_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the privacy budget. Returns an error if it is above the privacy policy, if there is
+    /// one.
+    pub fn set_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(budget)?;
+        }
+        self.save_privacy_budget(storage, budget);
+        Ok(())
+    }
+
+    /// Writes the privacy budget after a release is charged
+    fn save_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) {
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        self.privacy_policy.may_load(storage, self.as_slice())
+    }
+
+    /// Sets limits on epsilon and the privacy budget, as for
+    /// `RunningStatsStore::set_privacy_policy`: they are checked whenever either is set and
+    /// on every release, and the policy can only be tightened. The current epsilon and budget,
+    /// or their defaults of 1, must be within it. The delta limit does not apply, since the
+    /// store's releases spend no delta.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        policy.validate()?;
+        if let Some(current) = self.get_privacy_policy(storage)? {
+            current.check_tightened_by(&policy)?;
+        }
+        policy.check_epsilon(self.get_epsilon(storage)?)?;
+        policy.check_privacy_budget(self.get_privacy_budget(storage)?)?;
+        self.privacy_policy.save(storage, self.as_slice(), policy);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_privacy_policy(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the minimum number of observations needed before the histogram is released
+    pub fn get_min_count_for_release(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of observations needed before the histogram is released, even
+    /// with noise. Each release first compares a noisy total with the minimum, at an extra cost
+    /// of epsilon; if it falls short, only that cost is charged and the release is refused.
+    /// This is kept when the store is cleared.
+    pub fn set_min_count_for_release(
+        &self,
+        storage: &mut dyn Storage,
+        min_count: u32,
+    ) {
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u32>> {
+        self.max_total_queries.may_load(storage, self.as_slice())
+    }
+
+    /// Limit the number of releases from the store, whatever epsilon they use, as for
+    /// `RunningStatsStore::set_max_total_queries`. A release refused by the minimum-count check
+    /// counts too, since it was charged.
+    pub fn set_max_total_queries(
+        &self,
+        storage: &mut dyn Storage,
+        max_queries: u32,
+    ) {
+        self.max_total_queries.save(storage, self.as_slice(), max_queries);
+    }
+
+    /// Returns the number of releases made from the store
+    pub fn get_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_query_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if let Some(max_queries) = self.get_max_total_queries(storage)? {
+            if self.get_total_queries(storage)? >= max_queries {
+                return Err(StdError::generic_err("Query limit reached"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a release towards the query limit
+    #[cfg(not(feature = "constant_time"))]
+    fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let total_queries = self.get_total_queries(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Query count overflow")
+        )?;
+        self.total_queries.save(storage, self.as_slice(), total_queries);
+        Ok(())
+    }
+
+    fn node_key(&self, level: u8, index: u16) -> Vec<u8> {
+        [self.as_slice(), NODE_KEY, &[level], &index.to_be_bytes()].concat()
+    }
+
+    fn get_node(&self, storage: &dyn Storage, level: u8, index: u16) -> u32 {
+        storage.get(&self.node_key(level, index))
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_be_bytes)
+            .unwrap_or(0)
+    }
+
+    /// Returns the bin that `x` falls in, after clamping it into [lower, upper]
+    pub fn bin_of(&self, x: I32F32) -> u16 {
+        let bins = I64F64::from_num(self.get_bins());
+        let offset = I64F64::from_num(x.clamp(self.lower, self.upper)) - I64F64::from_num(self.lower);
+        let width = I64F64::from_num(self.upper) - I64F64::from_num(self.lower);
+        let bin: u16 = (offset * bins / width).to_num();
+        bin.min(self.get_bins() - 1)
+    }
+
+    /// Returns true if there are no observations. This is not private.
+    pub fn is_empty(&self, storage: &dyn Storage) -> bool {
+        self.get_node(storage, 0, 0) == 0
+    }
+
+    /// Adds an observation, clamped into [lower, upper]
+    pub fn add_observation(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        let bin = self.bin_of(x);
+        for level in 0..=self.depth {
+            let index = bin >> (self.depth - level);
+            let count = self.get_node(storage, level, index).checked_add(1).ok_or(
+                StdError::generic_err("Count overflow")
+            )?;
+            storage.set(&self.node_key(level, index), &count.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Removes every observation, keeping epsilon, the privacy budget, the minimum count and the
+    /// query count
+    pub fn clear(&self, storage: &mut dyn Storage) {
+        for level in 0..=self.depth {
+            for index in 0..(1_u16 << level) {
+                storage.remove(&self.node_key(level, index));
+            }
+        }
+    }
+
+    /// Reads the count of every node, level by level from the root
+    fn read_tree(&self, storage: &dyn Storage) -> Vec<Vec<u32>> {
+        (0..=self.depth)
+            .map(|level| (0..(1_u16 << level)).map(|index| self.get_node(storage, level, index)).collect())
+            .collect()
+    }
+
+    fn noisy_tree(&self, tree: &[Vec<u32>], rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<Vec<Vec<I32F32>>> {
+        // each observation is counted once per level
+        let scale = laplace_scale(I32F32::from_num(self.depth + 1), epsilon)?;
+        let mut noisy = Vec::with_capacity(tree.len());
+        for level in tree {
+            let noise = laplace_vec(rng, scale, level.len())?;
+            noisy.push(level.iter().zip(noise).map(|(count, noise)| add_count_noise(*count, noise)).collect::<NoiseResult<_>>()?);
+        }
+        Ok(noisy)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn release_bins(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<Vec<I32F32>>> {
+        self.check_query_limit(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        self.check_privacy_policy(storage, epsilon)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+
+        let tree = self.read_tree(storage);
+        if !noisy_count_reaches(rng, tree[0][0], min_count, epsilon)? {
+            self.save_privacy_budget(storage, privacy_budget - gate_cost);
+            self.record_query(storage)?;
+            return Ok(Release::Refused);
+        }
+        let noisy = self.noisy_tree(&tree, rng, epsilon)?;
+        let bins = consistent_leaf_counts(&noisy)?;
+
+        // update the remaining privacy budget
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+
+        Ok(Release::Released(bins))
+    }
+
+    /// This is the constant-work version: every field is read and every node is noised before
+    /// anything is checked, and every refusal returns `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    fn release_bins(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<Vec<I32F32>>> {
+        // read every field in a fixed order, whatever the state of the store
+        let tree = self.read_tree(storage);
+        let min_count = self.get_min_count_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let policy = self.get_privacy_policy(storage)?;
+
+        let gate_passed = noisy_count_reaches(rng, tree[0][0], min_count, epsilon)?;
+        let noisy = self.noisy_tree(&tree, rng, epsilon)?;
+        let bins = consistent_leaf_counts(&noisy);
+
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = epsilon + gate_cost;
+        let eligible = max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            })
+            & (privacy_budget >= privacy_cost);
+        let allowed = eligible & gate_passed;
+        let gate_charged = eligible & !gate_passed;
+
+        // the budget and query count are written back either way
+        let charged = charged_cost(privacy_cost, allowed) + charged_cost(gate_cost, gate_charged);
+        self.save_privacy_budget(storage, privacy_budget - charged);
+        let counted = allowed | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        Ok(Release::Released(bins?))
+    }
+
+    /// Returns fuzzy counts of every bin, consistent with the noisy counts of every range in
+    /// the tree. The privacy cost is epsilon, plus epsilon for the minimum-count check if one
+    /// is set. A release refused by the check is charged for it alone and returns
+    /// `Release::Refused`.
+    pub fn fuzzy_histogram(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<Vec<I32F32>>> {
+        self.release_bins(storage, rng)
+    }
+
+    /// Returns `n` synthetic observations drawn from the fuzzy histogram, each uniform within
+    /// its bin, at a privacy cost of epsilon. The records are post-processing of one release,
+    /// so they are epsilon-differentially private however many are drawn.
+    pub fn synthesize(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, n: usize) -> StdResult<Release<Vec<I32F32>>> {
+        self.release_bins(storage, rng)?
+            .try_map(|bins| Ok(synthetic_values(rng, &bins, self.lower, self.upper, n)?))
+    }
+
+    /// Returns a fuzzy count of the observations in each range [a, b], all from one release at
+    /// a privacy cost of epsilon. Ranges are widened to whole bins: a range counts every bin
+    /// from the one containing a to the one containing b.
+    pub fn fuzzy_range_counts(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        ranges: &[(I32F32, I32F32)],
+    ) -> StdResult<Release<Vec<I32F32>>> {
+        if ranges.iter().any(|(a, b)| a > b) {
+            return Err(StdError::generic_err("Range start must not be after its end"));
+        }
+        Ok(self.release_bins(storage, rng)?.map(|bins| {
+            ranges
+                .iter()
+                .map(|(a, b)| {
+                    let (first, last) = (usize::from(self.bin_of(*a)), usize::from(self.bin_of(*b)));
+                    bins[first..=last].iter().fold(I32F32::from_num(0), |sum, count| sum.saturating_add(*count))
+                })
+                .collect()
+        }))
+    }
+}
+
+/// Makes noisy counts of a complete binary tree consistent, so that each node is the sum of its
+/// children, and returns the leaves. `levels` holds the counts level by level from the root,
+/// with 2^l nodes on level l. This is the least squares fit of Hay et al. (2010), "Boosting the
+/// accuracy of differentially private histograms through consistency": a bottom-up pass
+/// combines each node with the sum of its children, weighted by their variances, and a
+/// top-down pass splits the difference between each parent and its children evenly.
+pub fn consistent_leaf_counts(levels: &[Vec<I32F32>]) -> StdResult<Vec<I32F32>> {
+    if levels.is_empty() || levels.iter().enumerate().any(|(level, counts)| level >= 16 || counts.len() != 1 << level) {
+        return Err(StdError::generic_err("Counts do not form a complete binary tree"));
+    }
+    let depth = levels.len() - 1;
+
+    // bottom-up: z = a * count + (1 - a) * sum of children z, for a = 2^(h-1) / (2^h - 1) at
+    // height h, where leaves have height 1
+    let mut z: Vec<Vec<I64F64>> = levels
+        .iter()
+        .map(|counts| counts.iter().map(|count| I64F64::from_num(*count)).collect())
+        .collect();
+    for level in (0..depth).rev() {
+        let height = (depth - level + 1) as u32;
+        let all = I64F64::from_num((1_u64 << height) - 1);
+        let own = I64F64::from_num(1_u64 << (height - 1)) / all;
+        let children = I64F64::from_num((1_u64 << (height - 1)) - 1) / all;
+        for index in 0..z[level].len() {
+            let child_sum = z[level + 1][2 * index] + z[level + 1][2 * index + 1];
+            z[level][index] = own * z[level][index] + children * child_sum;
+        }
+    }
+
+    // top-down: each child gets half of the difference between its parent and their sum
+    let mut consistent = vec![z[0][0]];
+    for level in 1..=depth {
+        consistent = consistent
+            .iter()
+            .enumerate()
+            .flat_map(|(index, parent)| {
+                let (left, right) = (z[level][2 * index], z[level][2 * index + 1]);
+                let correction = (*parent - left - right) / 2;
+                [left + correction, right + correction]
+            })
+            .collect();
+    }
+    Ok(consistent.into_iter().map(I32F32::saturating_from_num).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn consistent_counts_add_up() {
+        // a root of 10 over children 3 and 5 (sum 8) gets pulled towards its own count
+        let leaves = consistent_leaf_counts(&[
+            vec![I32F32::from_num(10)],
+            vec![I32F32::from_num(3), I32F32::from_num(5)],
+        ]).unwrap();
+        // z(root) = 2/3 * 10 + 1/3 * 8 = 28 / 3, split evenly over the children
+        let third = I32F32::from_num(1) / 3;
+        assert!((leaves[0] - (I32F32::from_num(3) + third * 2)).abs() < I32F32::from_num(0.0001));
+        assert!((leaves[1] - (I32F32::from_num(5) + third * 2)).abs() < I32F32::from_num(0.0001));
+        assert!(consistent_leaf_counts(&[vec![I32F32::from_num(1)], vec![I32F32::from_num(1)]]).is_err());
+    }
+
+    #[test]
+    fn answers_range_counts() {
+        let mut storage = MockStorage::new();
+        let store: HierarchicalHistogramStore =
+            HierarchicalHistogramStore::new(b"tree", 4, I32F32::from_num(0), I32F32::from_num(16));
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(0)).is_err());
+        store.set_epsilon(&mut storage, I32F32::from_num(5)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(5)).unwrap();
+        for x in 0..160 {
+            store.add_observation(&mut storage, I32F32::from_num(x % 16)).unwrap();
+        }
+        assert_eq!(store.bin_of(I32F32::from_num(100)), 15);
+
+        let mut rng = ChaChaRng::from_seed([5; 32]);
+        let ranges = [(I32F32::from_num(0), I32F32::from_num(15)), (I32F32::from_num(4), I32F32::from_num(7.5))];
+        let counts = store.fuzzy_range_counts(&mut storage, &mut rng, &ranges).unwrap().released().unwrap();
+        assert!((counts[0] - I32F32::from_num(160)).abs() < I32F32::from_num(10));
+        assert!((counts[1] - I32F32::from_num(40)).abs() < I32F32::from_num(10));
+        assert!(store.fuzzy_histogram(&mut storage, &mut rng).is_err());
+    }
+
+    #[test]
+    fn synthesizes_from_one_release() {
+        let mut storage = MockStorage::new();
+        let store: HierarchicalHistogramStore =
+            HierarchicalHistogramStore::new(b"synth", 2, I32F32::from_num(0), I32F32::from_num(4));
+        store.set_epsilon(&mut storage, I32F32::from_num(2)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(2)).unwrap();
+        // all of the data is in [3, 4)
+        for _ in 0..200 {
+            store.add_observation(&mut storage, I32F32::from_num(3.5)).unwrap();
+        }
+
+        let mut rng = ChaChaRng::from_seed([6; 32]);
+        let records = store.synthesize(&mut storage, &mut rng, 500).unwrap().released().unwrap();
+        assert_eq!(records.len(), 500);
+        assert!(records.iter().all(|x| *x > 0 && *x <= 4));
+        assert!(records.iter().filter(|x| **x > 3).count() > 450);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn small_histograms_are_refused_at_the_cost_of_the_check() {
+        let mut storage = MockStorage::new();
+        let store: HierarchicalHistogramStore =
+            HierarchicalHistogramStore::new(b"small", 2, I32F32::from_num(0), I32F32::from_num(4));
+        store.set_epsilon(&mut storage, I32F32::from_num(1)).unwrap();
+        store.set_privacy_budget(&mut storage, I32F32::from_num(4)).unwrap();
+        store.set_min_count_for_release(&mut storage, 50);
+        for _ in 0..3 {
+            store.add_observation(&mut storage, I32F32::from_num(1)).unwrap();
+        }
+
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        assert_eq!(store.fuzzy_histogram(&mut storage, &mut rng).unwrap(), Release::Refused);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+
+        for _ in 0..100 {
+            store.add_observation(&mut storage, I32F32::from_num(1)).unwrap();
+        }
+        store.fuzzy_histogram(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+    }
+}
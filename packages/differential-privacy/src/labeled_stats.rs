@@ -0,0 +1,160 @@
+use cosmwasm_std::{Addr, Storage, StdResult, StdError};
+use secret_toolkit::{serialization::{Serde, Bincode2}, storage::Keymap};
+use serde::{Serialize, Deserialize};
+use substrate_fixed::types::I32F32;
+
+use crate::{ResetPolicy, RunningStatsStore};
+
+/// Self-describing metadata kept for each labeled dataset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DatasetMetadata {
+    pub label: String,
+    pub owner: Addr,
+    /// time the dataset was created, e.g. `env.block.time.seconds()`
+    pub created: u64,
+    /// time an observation was last added
+    pub updated: u64,
+}
+
+/// Initial parameters for a labeled dataset, with the same meaning as in `RunningStatsStore::clear`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DatasetParams {
+    pub epsilon: Option<I32F32>,
+    pub avg_sensitivity: Option<I32F32>,
+    pub privacy_budget: Option<I32F32>,
+}
+
+/// Pairs each suffixed `RunningStatsStore` with an entry in a secret-toolkit `Keymap` keyed by
+/// label, holding the dataset's owner and created/updated timestamps. This makes datasets
+/// discoverable by paging through the keymap.
+pub struct LabeledStatsRegistry<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    stats: RunningStatsStore<'a, Ser>,
+    metadata: Keymap<'a, String, DatasetMetadata, Ser>,
+}
+
+impl<'a, Ser: Serde> LabeledStatsRegistry<'a, Ser> {
+    /// constructor
+    pub const fn new(
+        stats_namespace: &'a [u8],
+        metadata_namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            stats: RunningStatsStore::new(stats_namespace),
+            metadata: Keymap::new(metadata_namespace),
+        }
+    }
+}
+
+impl<'a, Ser: Serde> LabeledStatsRegistry<'a, Ser> {
+    /// Creates a new dataset with its own running stats store, initialized with `params`
+    pub fn create_labeled(
+        &self,
+        storage: &mut dyn Storage,
+        label: &str,
+        params: DatasetParams,
+        owner: &Addr,
+        timestamp: u64,
+    ) -> StdResult<RunningStatsStore<'a, Ser>> {
+        let key = label.to_string();
+        if self.metadata.contains(storage, &key) {
+            return Err(StdError::generic_err("Dataset label already exists"));
+        }
+        let store = self.stats.add_suffix(label.as_bytes());
+        store.reset(storage, ResetPolicy::new(params.epsilon, params.avg_sensitivity, params.privacy_budget))?;
+        let metadata = DatasetMetadata {
+            label: key.clone(),
+            owner: owner.clone(),
+            created: timestamp,
+            updated: timestamp,
+        };
+        self.metadata.insert(storage, &key, &metadata)?;
+        Ok(store)
+    }
+
+    /// Returns the running stats store for a labeled dataset
+    pub fn get_labeled(
+        &self,
+        storage: &dyn Storage,
+        label: &str,
+    ) -> StdResult<RunningStatsStore<'a, Ser>> {
+        if !self.metadata.contains(storage, &label.to_string()) {
+            return Err(StdError::not_found("Dataset"));
+        }
+        Ok(self.stats.add_suffix(label.as_bytes()))
+    }
+
+    pub fn get_metadata(&self, storage: &dyn Storage, label: &str) -> Option<DatasetMetadata> {
+        self.metadata.get(storage, &label.to_string())
+    }
+
+    /// Adds an observation to a labeled dataset and updates its `updated` timestamp
+    pub fn add_observation(
+        &self,
+        storage: &mut dyn Storage,
+        label: &str,
+        x: I32F32,
+        timestamp: u64,
+    ) -> StdResult<()> {
+        let key = label.to_string();
+        let mut metadata = self.metadata.get(storage, &key).ok_or(
+            StdError::not_found("Dataset")
+        )?;
+        self.stats.add_suffix(label.as_bytes()).add_observation(storage, x)?;
+        metadata.updated = timestamp;
+        self.metadata.insert(storage, &key, &metadata)
+    }
+
+    /// Returns the number of labeled datasets
+    pub fn get_len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.metadata.get_len(storage)
+    }
+
+    /// Returns one page of dataset metadata, with pages numbered from 0
+    pub fn paging(
+        &self,
+        storage: &dyn Storage,
+        start_page: u32,
+        size: u32,
+    ) -> StdResult<Vec<DatasetMetadata>> {
+        Ok(self.metadata
+            .paging(storage, start_page, size)?
+            .into_iter()
+            .map(|(_, metadata)| metadata)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn datasets_are_created_once_and_keep_their_metadata() {
+        let mut storage = MockStorage::new();
+        let registry: LabeledStatsRegistry = LabeledStatsRegistry::new(b"stats", b"metadata");
+        let owner = Addr::unchecked("owner");
+        let params = DatasetParams { epsilon: Some(I32F32::from_num(2)), ..Default::default() };
+        registry.create_labeled(&mut storage, "heights", params, &owner, 10).unwrap();
+        registry.create_labeled(&mut storage, "weights", DatasetParams::default(), &owner, 11).unwrap();
+        assert!(registry.create_labeled(&mut storage, "heights", params, &owner, 12).is_err());
+        assert!(registry.get_labeled(&storage, "ages").is_err());
+        assert!(registry.add_observation(&mut storage, "ages", I32F32::from_num(1), 13).is_err());
+
+        registry.add_observation(&mut storage, "heights", I32F32::from_num(170), 14).unwrap();
+        let metadata = registry.get_metadata(&storage, "heights").unwrap();
+        assert_eq!((metadata.owner, metadata.created, metadata.updated), (owner, 10, 14));
+        let heights = registry.get_labeled(&storage, "heights").unwrap();
+        assert_eq!(heights.get_epsilon(&storage).unwrap(), I32F32::from_num(2));
+        assert!(!heights.is_empty(&storage).unwrap());
+        assert!(registry.get_labeled(&storage, "weights").unwrap().is_empty(&storage).unwrap());
+
+        assert_eq!(registry.get_len(&storage).unwrap(), 2);
+        let labels: Vec<String> = registry.paging(&storage, 0, 10).unwrap().into_iter().map(|metadata| metadata.label).collect();
+        assert_eq!(labels, vec!["heights".to_string(), "weights".to_string()]);
+    }
+}
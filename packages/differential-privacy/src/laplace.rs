@@ -1,13 +1,194 @@
+use alloc::vec::Vec;
 use rand_chacha::ChaChaRng;
-use substrate_fixed::types::I32F32;
+use substrate_fixed::types::{I32F32, I64F64};
 use substrate_fixed::transcendental::ln;
 use crate::random_unit_interval;
-  
+
+/// Error from a noise sampler, e.g. a sample that does not fit in I32F32. It converts to a
+/// `StdError` with the `std` feature, so store methods can return it with `?`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoiseError(pub &'static str);
+
+#[cfg(feature = "std")]
+impl From<NoiseError> for cosmwasm_std::StdError {
+    fn from(err: NoiseError) -> Self {
+        cosmwasm_std::StdError::generic_err(err.0)
+    }
+}
+
+pub type NoiseResult<T> = Result<T, NoiseError>;
+
+// The logarithms and products are computed in I64F64 and only the final sample is rounded
+// to I32F32, so the quantization error of ln near 0 and 1 does not bias the noise. Returns an
+// error instead of panicking if the sample overflows.
 pub fn laplace(
     rng: &mut ChaChaRng,
     scale: I32F32,
-) -> I32F32 {
-    let e1: I32F32 = (-scale) * ln::<I32F32, I32F32>(random_unit_interval(rng)).unwrap();
-    let e2: I32F32 = (-scale) * ln::<I32F32, I32F32>(random_unit_interval(rng)).unwrap();
-    e1 - e2
+) -> NoiseResult<I32F32> {
+    let scale = I64F64::from_num(scale);
+    let e1 = exponential_log(rng)?.checked_mul(-scale);
+    let e2 = exponential_log(rng)?.checked_mul(-scale);
+    e1.zip(e2)
+        .and_then(|(e1, e2)| e1.checked_sub(e2))
+        .and_then(I32F32::checked_from_num)
+        .ok_or(NoiseError("Laplace noise overflowed"))
+}
+
+// ln of a uniform draw from (0, 1], which is never undefined
+fn exponential_log(rng: &mut ChaChaRng) -> NoiseResult<I64F64> {
+    ln::<I64F64, I64F64>(I64F64::from_num(random_unit_interval(rng)))
+        .map_err(|_| NoiseError("Logarithm of uniform draw is undefined"))
+}
+
+/// Scale of the Laplace noise for a query with the given sensitivity and epsilon,
+/// sensitivity / epsilon. Returns an error instead of panicking if epsilon is not positive or
+/// the scale does not fit in I32F32, e.g. for a large sensitivity and a tiny epsilon.
+pub fn laplace_scale(sensitivity: I32F32, epsilon: I32F32) -> NoiseResult<I32F32> {
+    if epsilon <= I32F32::from_num(0) {
+        return Err(NoiseError("Epsilon must be greater than zero"));
+    }
+    sensitivity.checked_div(epsilon).ok_or(NoiseError("Laplace scale overflowed"))
+}
+
+/// Draws `n` independent Laplace samples with the same scale, e.g. one per histogram bin.
+/// Every sample uses fresh draws from `rng`, so no randomness is shared between entries.
+pub fn laplace_vec(
+    rng: &mut ChaChaRng,
+    scale: I32F32,
+    n: usize,
+) -> NoiseResult<Vec<I32F32>> {
+    (0..n).map(|_| laplace(rng, scale)).collect()
+}
+
+/// Noisy test of whether `count` reaches `min_count`, e.g. a minimum number of observations
+/// before any statistic is released. The count gets Laplace noise of scale 1 / epsilon, so
+/// unlike a test of the exact count the answer is epsilon-differentially private, at the cost
+/// given by `min_count_gate_cost`. A `min_count` of zero always passes without drawing noise.
+pub fn noisy_count_reaches(
+    rng: &mut ChaChaRng,
+    count: u32,
+    min_count: u32,
+    epsilon: I32F32,
+) -> NoiseResult<bool> {
+    if min_count == 0 {
+        return Ok(true);
+    }
+    let noise = laplace(rng, laplace_scale(I32F32::from_num(1_u32), epsilon)?)?;
+    Ok(I64F64::from_num(count) + I64F64::from_num(noise) >= I64F64::from_num(min_count))
+}
+
+/// Adds noise to a count, e.g. a number of observations. The sum is taken in I64F64, so it
+/// returns an error instead of panicking if the noisy count does not fit in I32F32.
+pub fn add_count_noise(count: u32, noise: I32F32) -> NoiseResult<I32F32> {
+    I32F32::checked_from_num(I64F64::from_num(count) + I64F64::from_num(noise))
+        .ok_or(NoiseError("Noisy count overflowed"))
+}
+
+/// Privacy cost of `noisy_count_reaches`: epsilon, or zero when there is no minimum
+pub fn min_count_gate_cost(min_count: u32, epsilon: I32F32) -> I32F32 {
+    if min_count == 0 {
+        I32F32::from_num(0)
+    } else {
+        epsilon
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn correlation(xs: &[f64], ys: &[f64]) -> f64 {
+        let n = xs.len() as f64;
+        let mean_x = xs.iter().sum::<f64>() / n;
+        let mean_y = ys.iter().sum::<f64>() / n;
+        let mut cov = 0.0;
+        let mut var_x = 0.0;
+        let mut var_y = 0.0;
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            cov += (x - mean_x) * (y - mean_y);
+            var_x += (x - mean_x) * (x - mean_x);
+            var_y += (y - mean_y) * (y - mean_y);
+        }
+        cov / (var_x.sqrt() * var_y.sqrt())
+    }
+
+    #[test]
+    fn laplace_overflow_is_an_error() {
+        let mut rng = ChaChaRng::from_seed([5; 32]);
+        let draws: Vec<NoiseResult<I32F32>> = (0..50).map(|_| laplace(&mut rng, I32F32::MAX)).collect();
+        assert!(draws.iter().any(|draw| *draw == Err(NoiseError("Laplace noise overflowed"))));
+    }
+
+    #[test]
+    fn laplace_scale_rejects_bad_epsilons() {
+        assert_eq!(laplace_scale(I32F32::from_num(2), I32F32::from_num(4)), Ok(I32F32::from_num(0.5)));
+        assert_eq!(laplace_scale(I32F32::from_num(1), I32F32::from_num(0)), Err(NoiseError("Epsilon must be greater than zero")));
+        assert!(laplace_scale(I32F32::from_num(1), I32F32::from_num(-1)).is_err());
+        assert_eq!(laplace_scale(I32F32::from_num(1_000_000), I32F32::from_bits(1)), Err(NoiseError("Laplace scale overflowed")));
+    }
+
+    #[test]
+    fn laplace_mean_and_variance_are_unbiased() {
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        let n = 20000;
+        for scale in [0.5, 2.0] {
+            let draws: Vec<f64> = laplace_vec(&mut rng, I32F32::from_num(scale), n)
+                .unwrap()
+                .iter()
+                .map(|x| x.to_num::<f64>())
+                .collect();
+            let mean = draws.iter().sum::<f64>() / n as f64;
+            let variance = draws.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n as f64;
+            // Laplace(b) has mean 0 and variance 2 b^2
+            let expected_variance = 2.0 * scale * scale;
+            assert!(mean.abs() < 0.05 * scale, "mean {}", mean);
+            assert!((variance / expected_variance - 1.0).abs() < 0.08, "variance {}", variance);
+        }
+    }
+
+    #[test]
+    fn laplace_vec_draws_are_independent() {
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        let n = 4000;
+        let draws: Vec<f64> = laplace_vec(&mut rng, I32F32::from_num(1), n)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_num::<f64>())
+            .collect();
+        assert_eq!(draws.len(), n);
+
+        // neighbouring entries of one vector are uncorrelated
+        let lag = correlation(&draws[..n - 1], &draws[1..]);
+        assert!(lag.abs() < 0.1, "lag-1 correlation {}", lag);
+
+        // a second vector from the same rng does not repeat or track the first
+        let next: Vec<f64> = laplace_vec(&mut rng, I32F32::from_num(1), n)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_num::<f64>())
+            .collect();
+        assert_ne!(draws, next);
+        let across = correlation(&draws, &next);
+        assert!(across.abs() < 0.1, "correlation across draws {}", across);
+    }
+
+    #[test]
+    fn noisy_count_gate_passes_about_half_the_time_at_the_minimum() {
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        let epsilon = I32F32::from_num(1);
+        let passes = (0..2000)
+            .filter(|_| noisy_count_reaches(&mut rng, 20, 20, epsilon).unwrap())
+            .count();
+        assert!((900..1100).contains(&passes), "passes {}", passes);
+        assert!((0..200).all(|_| noisy_count_reaches(&mut rng, 40, 20, epsilon).unwrap()));
+        assert!((0..200).all(|_| !noisy_count_reaches(&mut rng, 0, 20, epsilon).unwrap()));
+
+        // no minimum passes without drawing noise
+        let before = rng.clone();
+        assert!(noisy_count_reaches(&mut rng, 0, 0, epsilon).unwrap());
+        assert_eq!(rng, before);
+        assert_eq!(min_count_gate_cost(0, epsilon), I32F32::from_num(0));
+        assert_eq!(min_count_gate_cost(20, epsilon), epsilon);
+    }
 }
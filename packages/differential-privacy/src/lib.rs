@@ -1,9 +1,140 @@
 #![doc = include_str!("../Readme.md")]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod ab_test;
+#[cfg(feature = "std")]
+pub mod boolean_stats_store;
+#[cfg(feature = "std")]
+pub mod bound_heap;
+#[cfg(feature = "std")]
+pub mod bound_histogram;
+#[cfg(feature = "std")]
+pub mod cached_field;
+#[cfg(feature = "std")]
+pub mod categorical_stats_store;
+#[cfg(feature = "constant_time")]
+pub mod constant_time;
+#[cfg(feature = "std")]
+pub mod continual_counter_store;
+#[cfg(feature = "std")]
+pub mod distinct_count_store;
+#[cfg(feature = "std")]
+pub mod dp_dataset;
+#[cfg(feature = "std")]
+pub mod dp_packet;
+#[cfg(feature = "std")]
+pub mod dp_querier;
+#[cfg(feature = "std")]
+pub mod dp_query;
+#[cfg(feature = "std")]
+pub mod epoch_stats_store;
+pub mod gamma;
+pub mod heavy_tail;
+#[cfg(feature = "std")]
+pub mod hierarchical_histogram_store;
+#[cfg(feature = "std")]
+pub mod labeled_stats;
 pub mod laplace;
+pub mod noise_source;
+#[cfg(feature = "std")]
+pub mod observation_log_store;
+#[cfg(feature = "offchain")]
+pub mod offchain;
+#[cfg(feature = "std")]
+pub mod paired_stats_store;
+#[cfg(feature = "std")]
+pub mod piecewise;
+pub mod postprocess;
+#[cfg(feature = "std")]
+pub mod privacy_accountant;
 pub mod random;
+#[cfg(feature = "std")]
+pub mod rappor;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod release;
+#[cfg(feature = "std")]
+pub mod replay_log;
+#[cfg(feature = "std")]
 pub mod running_stats_store;
+#[cfg(feature = "std")]
+pub mod smooth_sensitivity;
+pub mod staircase;
+pub mod static_store;
+#[cfg(feature = "std")]
+pub mod stratified_stats_store;
+#[cfg(feature = "std")]
+pub mod suffix_index;
+pub mod synthetic;
 
+#[cfg(feature = "std")]
+pub use ab_test::*;
+#[cfg(feature = "std")]
+pub use boolean_stats_store::*;
+#[cfg(feature = "std")]
+pub use bound_heap::*;
+#[cfg(feature = "std")]
+pub use bound_histogram::*;
+#[cfg(feature = "std")]
+pub use cached_field::*;
+#[cfg(feature = "std")]
+pub use categorical_stats_store::*;
+#[cfg(feature = "constant_time")]
+pub use constant_time::*;
+#[cfg(feature = "std")]
+pub use continual_counter_store::*;
+#[cfg(feature = "std")]
+pub use distinct_count_store::*;
+#[cfg(feature = "std")]
+pub use dp_dataset::*;
+#[cfg(feature = "std")]
+pub use dp_packet::*;
+#[cfg(feature = "std")]
+pub use dp_querier::*;
+#[cfg(feature = "std")]
+pub use dp_query::*;
+#[cfg(feature = "std")]
+pub use epoch_stats_store::*;
+pub use gamma::*;
+pub use heavy_tail::*;
+#[cfg(feature = "std")]
+pub use hierarchical_histogram_store::*;
+#[cfg(feature = "std")]
+pub use labeled_stats::*;
 pub use laplace::*;
+pub use noise_source::*;
+#[cfg(feature = "std")]
+pub use observation_log_store::*;
+#[cfg(feature = "offchain")]
+pub use offchain::*;
+#[cfg(feature = "std")]
+pub use paired_stats_store::*;
+#[cfg(feature = "std")]
+pub use piecewise::*;
+pub use postprocess::*;
+#[cfg(feature = "std")]
+pub use privacy_accountant::*;
 pub use random::*;
+#[cfg(feature = "std")]
+pub use rappor::*;
+#[cfg(feature = "std")]
+pub use registry::*;
+#[cfg(feature = "std")]
+pub use release::*;
+#[cfg(feature = "std")]
+pub use replay_log::*;
+#[cfg(feature = "std")]
 pub use running_stats_store::*;
+#[cfg(feature = "std")]
+pub use smooth_sensitivity::*;
+pub use staircase::*;
+pub use static_store::*;
+#[cfg(feature = "std")]
+pub use stratified_stats_store::*;
+#[cfg(feature = "std")]
+pub use suffix_index::*;
+pub use synthetic::*;
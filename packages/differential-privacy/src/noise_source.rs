@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::I32F32;
+
+use crate::{laplace, laplace_scale, staircase, NoiseResult};
+
+/// Mechanism used to add noise to a released statistic
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NoiseSource {
+    #[default]
+    Laplace,
+    /// more accurate than Laplace at the same epsilon, see `staircase`
+    Staircase,
+}
+
+impl NoiseSource {
+    /// Draws one noise sample for a query with the given sensitivity and epsilon
+    pub fn sample(
+        &self,
+        rng: &mut ChaChaRng,
+        sensitivity: I32F32,
+        epsilon: I32F32,
+    ) -> NoiseResult<I32F32> {
+        match self {
+            NoiseSource::Laplace => laplace(rng, laplace_scale(sensitivity, epsilon)?),
+            NoiseSource::Staircase => staircase(rng, epsilon, sensitivity),
+        }
+    }
+
+    /// Draws `n` independent noise samples, e.g. one per histogram bin
+    pub fn sample_vec(
+        &self,
+        rng: &mut ChaChaRng,
+        sensitivity: I32F32,
+        epsilon: I32F32,
+        n: usize,
+    ) -> NoiseResult<Vec<I32F32>> {
+        (0..n).map(|_| self.sample(rng, sensitivity, epsilon)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bad_epsilons_are_errors_for_both_sources() {
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        for source in [NoiseSource::Laplace, NoiseSource::Staircase] {
+            assert!(source.sample(&mut rng, I32F32::from_num(1), I32F32::from_num(0)).is_err());
+            assert!(source.sample(&mut rng, I32F32::from_num(1_000_000), I32F32::from_bits(1)).is_err());
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn noise_mean_is_close_to_zero(
+            seed in any::<[u8; 32]>(),
+            staircase in any::<bool>(),
+            sensitivity_quarters in 2..=32i32,
+            epsilon_quarters in 1..=8i32,
+        ) {
+            let source = if staircase { NoiseSource::Staircase } else { NoiseSource::Laplace };
+            let sensitivity = I32F32::from_num(sensitivity_quarters) / 4;
+            let epsilon = I32F32::from_num(epsilon_quarters) / 4;
+            let n = 2000;
+            let mut rng = ChaChaRng::from_seed(seed);
+            let mean = source.sample_vec(&mut rng, sensitivity, epsilon, n)
+                .unwrap()
+                .iter()
+                .map(|x| x.to_num::<f64>())
+                .sum::<f64>() / n as f64;
+            // the standard error of the mean is about 0.03 scales for both sources
+            let scale = (sensitivity / epsilon).to_num::<f64>();
+            prop_assert!(mean.abs() < 0.25 * scale, "mean {} scale {}", mean, scale);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::I32F32;
+
+use crate::CachedField;
+
+const LEN_KEY: &[u8] = b"len";
+const ENTRY_KEY: &[u8] = b"entry";
+
+/// Opt-in, append-only log of raw observations. Only use this in contracts that are allowed to
+/// keep raw data inside the enclave, e.g. to re-derive alternative statistics once data
+/// collection has ended.
+///
+/// Every entry is stored under its own key in the `FixedBytes` encoding: 9 bytes, a version
+/// byte and the 8 byte big-endian value, so new entries have the same encrypted length
+/// regardless of the observation. Entries appended before the version byte was introduced keep
+/// their 8 byte encoding and are still read, so a log that spans the upgrade mixes both lengths,
+/// and the length of an entry reveals when it was appended but not its value.
+pub struct ObservationLogStore<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// maximum number of entries, if any
+    capacity: Option<u32>,
+    length: CachedField<u32>,
+}
+
+impl<'a> ObservationLogStore<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            capacity: None,
+            length: CachedField::new(LEN_KEY),
+        }
+    }
+
+    /// Limit the log to at most `capacity` entries. Pushing to a full log returns an error.
+    pub const fn with_capacity(
+        self,
+        capacity: u32,
+    ) -> Self {
+        Self {
+            namespace: self.namespace,
+            prefix: self.prefix,
+            capacity: Some(capacity),
+            length: self.length,
+        }
+    }
+
+    /// This is used to produce a new ObservationLogStore. It can be used when you want to associate
+    /// an ObservationLogStore to multiple suffixes and you still want to define the ObservationLogStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            capacity: self.capacity,
+            length: CachedField::new(LEN_KEY),
+        }
+    }
+}
+
+impl<'a> ObservationLogStore<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn entry_key(&self, pos: u32) -> Vec<u8> {
+        [self.as_slice(), ENTRY_KEY, &pos.to_be_bytes()].concat()
+    }
+
+    pub fn get_capacity(&self) -> Option<u32> {
+        self.capacity
+    }
+
+    pub fn get_len(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.length.load_or(storage, self.as_slice(), 0)
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_len(storage)? == 0)
+    }
+
+    fn set_len(
+        &self,
+        storage: &mut dyn Storage,
+        length: u32,
+    ) {
+        self.length.save(storage, self.as_slice(), length);
+    }
+
+    /// Appends an observation to the end of the log
+    pub fn push(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        let length = self.get_len(storage)?;
+        if let Some(capacity) = self.capacity {
+            if length >= capacity {
+                return Err(StdError::generic_err("Observation log is full"));
+            }
+        }
+        let new_length = length.checked_add(1).ok_or(
+            StdError::generic_err("Observation log overflow")
+        )?;
+        storage.set(&self.entry_key(length), &x.to_stored_bytes());
+        self.set_len(storage, new_length);
+        Ok(())
+    }
+
+    /// Returns the observation at position `pos`
+    pub fn get_at(&self, storage: &dyn Storage, pos: u32) -> StdResult<I32F32> {
+        if pos >= self.get_len(storage)? {
+            return Err(StdError::generic_err("Observation log index out of bounds"));
+        }
+        if let Some(entry_vec) = storage.get(&self.entry_key(pos)) {
+            I32F32::from_stored_bytes(&entry_vec)
+        } else {
+            Err(StdError::generic_err("Observation log entry missing from storage"))
+        }
+    }
+
+    /// Returns one page of observations, with pages numbered from 0
+    pub fn paging(&self, storage: &dyn Storage, start_page: u32, size: u32) -> StdResult<Vec<I32F32>> {
+        let length = self.get_len(storage)?;
+        let start = start_page.saturating_mul(size).min(length);
+        let end = start.saturating_add(size).min(length);
+        (start..end).map(|pos| self.get_at(storage, pos)).collect()
+    }
+
+    /// Removes every entry in the log
+    pub fn clear(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let length = self.get_len(storage)?;
+        for pos in 0..length {
+            storage.remove(&self.entry_key(pos));
+        }
+        self.set_len(storage, 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn entries_are_appended_paged_and_cleared() {
+        let mut storage = MockStorage::new();
+        let log = ObservationLogStore::new(b"log").with_capacity(5);
+        let other = log.add_suffix(b"other");
+        for x in 0..5 {
+            log.push(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        assert!(log.push(&mut storage, I32F32::from_num(5)).is_err());
+        assert_eq!(log.get_len(&storage).unwrap(), 5);
+        assert_eq!(log.get_at(&storage, 3).unwrap(), I32F32::from_num(3));
+        assert!(log.get_at(&storage, 5).is_err());
+        assert!(other.is_empty(&storage).unwrap());
+
+        let page = |start_page, size| log.paging(&storage, start_page, size).unwrap();
+        assert_eq!(page(1, 2), vec![I32F32::from_num(2), I32F32::from_num(3)]);
+        assert_eq!(page(2, 2), vec![I32F32::from_num(4)]);
+        assert!(page(3, 2).is_empty());
+
+        log.clear(&mut storage).unwrap();
+        assert!(log.is_empty(&storage).unwrap());
+        assert!(log.get_at(&storage, 0).is_err());
+        log.push(&mut storage, I32F32::from_num(7)).unwrap();
+        assert_eq!(log.get_at(&storage, 0).unwrap(), I32F32::from_num(7));
+    }
+
+    #[test]
+    fn entries_have_the_same_length_and_old_entries_are_read() {
+        let mut storage = MockStorage::new();
+        let log = ObservationLogStore::new(b"log");
+        // an entry written before the version byte was introduced
+        storage.set(&log.entry_key(0), &I32F32::from_num(-2).to_be_bytes());
+        log.set_len(&mut storage, 1);
+        for x in [0, 1_000_000] {
+            log.push(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        assert_eq!(storage.get(&log.entry_key(1)).unwrap().len(), 9);
+        assert_eq!(storage.get(&log.entry_key(2)).unwrap().len(), 9);
+        assert_eq!(
+            log.paging(&storage, 0, 10).unwrap(),
+            vec![I32F32::from_num(-2), I32F32::from_num(0), I32F32::from_num(1_000_000)]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use rand::RngCore;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::I32F32;
+
+use crate::{laplace, NoiseResult};
+
+// f64 reference versions of the noise samplers, for validating on-chain results off-chain.
+// They take their random numbers from the ChaChaRng in the same way as the fixed-point
+// versions, so with the same seed the two draw the same samples up to rounding, and the
+// difference measures the error of the fixed-point arithmetic.
+
+/// f64 version of `random_unit_interval`, in (0, 1]
+pub fn random_unit_interval_f64(rng: &mut ChaChaRng) -> f64 {
+    (f64::from(rng.next_u32()) + 1.0) / 4294967296.0
+}
+
+/// f64 version of `laplace`: the difference of two exponential samples with mean `scale`
+pub fn laplace_f64(rng: &mut ChaChaRng, scale: f64) -> f64 {
+    let e1 = -scale * random_unit_interval_f64(rng).ln();
+    let e2 = -scale * random_unit_interval_f64(rng).ln();
+    e1 - e2
+}
+
+/// Gaussian noise with mean 0 and standard deviation `sigma`, using the Box-Muller transform.
+/// There is no on-chain Gaussian mechanism; use it with `gaussian_sigma` to compare the
+/// accuracy of (epsilon, delta)-DP releases against the Laplace noise used on-chain.
+pub fn gaussian_f64(rng: &mut ChaChaRng, sigma: f64) -> f64 {
+    let u1 = random_unit_interval_f64(rng);
+    let u2 = random_unit_interval_f64(rng);
+    sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * core::f64::consts::PI * u2).cos()
+}
+
+/// Standard deviation of the classic Gaussian mechanism for (epsilon, delta)-DP with the given
+/// L2 sensitivity, sensitivity * sqrt(2 ln(1.25 / delta)) / epsilon, valid for epsilon < 1
+pub fn gaussian_sigma(epsilon: f64, delta: f64, sensitivity: f64) -> f64 {
+    sensitivity * (2.0 * (1.25 / delta).ln()).sqrt() / epsilon
+}
+
+/// Difference between `laplace` and `laplace_f64` over `samples` draws from the same seed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseComparison {
+    /// mean of fixed-point sample - f64 sample, the bias of the fixed-point sampler
+    pub mean_error: f64,
+    /// largest absolute difference between paired samples
+    pub max_abs_error: f64,
+    /// sample means of the fixed-point and f64 noise
+    pub fixed_mean: f64,
+    pub reference_mean: f64,
+}
+
+/// Draws `samples` Laplace samples with scale `scale` from both samplers, each from its own
+/// `ChaChaRng` seeded with `seed`, and compares them pair by pair
+pub fn compare_laplace(seed: [u8; 32], scale: I32F32, samples: u32) -> NoiseResult<NoiseComparison> {
+    let mut fixed_rng = ChaChaRng::from_seed(seed);
+    let mut reference_rng = ChaChaRng::from_seed(seed);
+    let mut total_error = 0.0;
+    let mut max_abs_error: f64 = 0.0;
+    let (mut fixed_total, mut reference_total) = (0.0, 0.0);
+    for _ in 0..samples {
+        let fixed = laplace(&mut fixed_rng, scale)?.to_num::<f64>();
+        let reference = laplace_f64(&mut reference_rng, scale.to_num::<f64>());
+        total_error += fixed - reference;
+        max_abs_error = max_abs_error.max((fixed - reference).abs());
+        fixed_total += fixed;
+        reference_total += reference;
+    }
+    let n = f64::from(samples.max(1));
+    Ok(NoiseComparison {
+        mean_error: total_error / n,
+        max_abs_error,
+        fixed_mean: fixed_total / n,
+        reference_mean: reference_total / n,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_point_laplace_matches_reference() {
+        let comparison = compare_laplace([11; 32], I32F32::from_num(2), 5000).unwrap();
+        assert!(comparison.mean_error.abs() < 0.0001);
+        assert!(comparison.max_abs_error < 0.001);
+        assert!(comparison.reference_mean.abs() < 0.15);
+    }
+
+    #[test]
+    fn gaussian_has_requested_spread() {
+        let mut rng = ChaChaRng::from_seed([12; 32]);
+        let n = 20000;
+        let samples: Vec<f64> = (0..n).map(|_| gaussian_f64(&mut rng, 3.0)).collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n as f64;
+        assert!(mean.abs() < 0.1);
+        assert!((variance.sqrt() - 3.0).abs() < 0.1);
+        assert!((gaussian_sigma(0.5, 1e-5, 1.0) - 9.6896).abs() < 0.001);
+    }
+}
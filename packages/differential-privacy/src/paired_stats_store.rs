@@ -0,0 +1,148 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::I32F32;
+
+use crate::{Release, ResetPolicy, RunningStatsStatus, RunningStatsStore};
+
+/// Paired observations, e.g. a measurement before and after an intervention for each
+/// participant. Only the difference `after - before` of each pair is kept, in a
+/// `RunningStatsStore`, so `fuzzy_mean_difference` estimates the average effect of the
+/// intervention.
+///
+/// Differences are clamped into [min_difference, max_difference], which fixes the sensitivity
+/// of the sum of differences to `max_difference - min_difference` whatever the range of the
+/// measurements themselves. A release costs 2 * epsilon, for the noisy sum and the noisy count.
+pub struct PairedStatsStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// store of the differences
+    differences: RunningStatsStore<'a, Ser>,
+}
+
+impl<'a, Ser: Serde> PairedStatsStore<'a, Ser> {
+    /// constructor, for differences clamped into [min_difference, max_difference]
+    pub const fn new(
+        namespace: &'a [u8],
+        min_difference: I32F32,
+        max_difference: I32F32,
+    ) -> Self {
+        assert!(
+            min_difference.to_bits() < max_difference.to_bits(),
+            "Minimum difference must be less than maximum difference"
+        );
+        Self {
+            differences: RunningStatsStore::new(namespace).with_clipping(min_difference, max_difference),
+        }
+    }
+
+    /// This is used to produce a new PairedStatsStore. It can be used when you want to associate
+    /// a PairedStatsStore to multiple suffixes and you still want to define the PairedStatsStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        Self {
+            differences: self.differences.add_suffix(suffix),
+        }
+    }
+}
+
+impl<'a, Ser: Serde> PairedStatsStore<'a, Ser> {
+    /// Resets the store and sets the epsilon of each noisy value and the privacy budget
+    pub fn init(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+        privacy_budget: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if privacy_budget < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy budget must not be negative"));
+        }
+        self.differences.reset(storage, ResetPolicy::new(Some(epsilon), None, Some(privacy_budget)))
+    }
+
+    /// Returns the store of the differences, e.g. to set a minimum count for release
+    pub fn differences(&self) -> &RunningStatsStore<'a, Ser> {
+        &self.differences
+    }
+
+    pub fn get_count(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.differences.get_count(storage)
+    }
+
+    pub fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        self.differences.get_privacy_budget(storage)
+    }
+
+    pub fn get_status(&self, storage: &dyn Storage) -> StdResult<RunningStatsStatus> {
+        self.differences.get_status(storage)
+    }
+
+    pub fn set_status(&self, storage: &mut dyn Storage, status: RunningStatsStatus) -> StdResult<()> {
+        self.differences.set_status(storage, status)
+    }
+
+    /// Limit the number of releases of the mean difference, as for
+    /// `RunningStatsStore::set_max_total_queries`
+    pub fn set_max_total_queries(&self, storage: &mut dyn Storage, max_queries: u32) -> StdResult<()> {
+        self.differences.set_max_total_queries(storage, max_queries)
+    }
+
+    pub fn get_total_queries(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.differences.get_total_queries(storage)
+    }
+
+    fn difference(before: I32F32, after: I32F32) -> StdResult<I32F32> {
+        after.checked_sub(before).ok_or(
+            StdError::generic_err("Difference overflow")
+        )
+    }
+
+    /// Adds the measurements of one participant, before and after
+    pub fn add_pair(&self, storage: &mut dyn Storage, before: I32F32, after: I32F32) -> StdResult<()> {
+        self.differences.add_observation(storage, Self::difference(before, after)?)
+    }
+
+    /// Removes a pair that was added before, e.g. when a participant withdraws
+    pub fn remove_pair(&self, storage: &mut dyn Storage, before: I32F32, after: I32F32) -> StdResult<()> {
+        self.differences.remove_observation(storage, Self::difference(before, after)?)
+    }
+
+    /// Privately releases the mean difference `after - before` over the pairs. Costs 2 * epsilon,
+    /// and counts towards the query limit.
+    pub fn fuzzy_mean_difference(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.differences.fuzzy_average(storage, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn releases_mean_difference() {
+        let mut storage = MockStorage::new();
+        let store: PairedStatsStore = PairedStatsStore::new(b"paired", I32F32::from_num(-10), I32F32::from_num(10));
+        store.init(&mut storage, I32F32::from_num(5), I32F32::from_num(10)).unwrap();
+
+        for before in 0..200 {
+            let before = I32F32::from_num(100 + before);
+            store.add_pair(&mut storage, before, before + I32F32::from_num(3)).unwrap();
+        }
+        // clamped to a difference of 10
+        store.add_pair(&mut storage, I32F32::from_num(0), I32F32::from_num(1000)).unwrap();
+        store.remove_pair(&mut storage, I32F32::from_num(0), I32F32::from_num(1000)).unwrap();
+        assert_eq!(store.get_count(&storage).unwrap(), 200);
+        assert!(store.add_pair(&mut storage, I32F32::MIN, I32F32::MAX).is_err());
+
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        let mean_difference = store.fuzzy_mean_difference(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert!((mean_difference - I32F32::from_num(3)).abs() < I32F32::from_num(0.5));
+    }
+}
@@ -0,0 +1,256 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::exp;
+
+use crate::{random_unit_interval, CachedField};
+
+const COUNT_KEY: &[u8] = b"count";
+const SUM_KEY: &[u8] = b"sum";
+const EPSILON_KEY: &[u8] = b"epsilon";
+
+// Piecewise mechanism (Wang et al., 2019) for local DP. Each user perturbs their own value
+// before submitting it, so the contract never sees a raw value. Every report is an unbiased
+// estimate of the user's value, and the mean of the reports estimates the mean of the values
+// with lower variance than Laplace or Duchi's mechanism.
+
+/// Smallest epsilon of the piecewise mechanism, 1/128. Closer to zero, e^(epsilon / 2) - 1
+/// loses its precision in I32F32 and the bound C grows without limit.
+pub const PIECEWISE_MIN_EPSILON: I32F32 = I32F32::from_bits(1 << 25);
+
+/// Largest epsilon of the piecewise mechanism. e^(epsilon / 2) no longer fits in I32F32 above
+/// about 43.
+pub const PIECEWISE_MAX_EPSILON: I32F32 = I32F32::from_bits(40 << 32);
+
+fn check_piecewise_epsilon(epsilon: I32F32) -> StdResult<()> {
+    if epsilon < PIECEWISE_MIN_EPSILON || epsilon > PIECEWISE_MAX_EPSILON {
+        return Err(StdError::generic_err("Epsilon is outside the range of the piecewise mechanism"));
+    }
+    Ok(())
+}
+
+/// Bound C of the perturbed values, (e^(epsilon / 2) + 1) / (e^(epsilon / 2) - 1), on the
+/// mechanism's internal [-1, 1] scale. Epsilon must be between `PIECEWISE_MIN_EPSILON` and
+/// `PIECEWISE_MAX_EPSILON`.
+pub fn piecewise_bound(epsilon: I32F32) -> StdResult<I32F32> {
+    check_piecewise_epsilon(epsilon)?;
+    let one = I32F32::from_num(1);
+    let half_exp = exp::<I32F32, I32F32>(epsilon / 2)
+        .map_err(|_| StdError::generic_err("Epsilon too large"))?;
+    (half_exp + one).checked_div(half_exp - one).ok_or(
+        StdError::generic_err("Epsilon too small")
+    )
+}
+
+/// Range of the reports `piecewise_perturb` can produce for a value in [0, 1]
+pub fn piecewise_report_range(epsilon: I32F32) -> StdResult<(I32F32, I32F32)> {
+    let c = piecewise_bound(epsilon)?;
+    let one = I32F32::from_num(1);
+    Ok(((one - c) / 2, (one + c) / 2))
+}
+
+/// Perturbs a value in [0, 1] with epsilon-local DP. The report lies in
+/// `piecewise_report_range(epsilon)` and its expected value is the original value. Epsilon must
+/// be between `PIECEWISE_MIN_EPSILON` and `PIECEWISE_MAX_EPSILON`.
+pub fn piecewise_perturb(
+    rng: &mut ChaChaRng,
+    value_in_unit_interval: I32F32,
+    epsilon: I32F32,
+) -> StdResult<I32F32> {
+    let zero = I32F32::from_num(0);
+    let one = I32F32::from_num(1);
+    if value_in_unit_interval < zero || value_in_unit_interval > one {
+        return Err(StdError::generic_err("Value must be in the unit interval"));
+    }
+
+    // map to [-1, 1]
+    let t = 2 * value_in_unit_interval - one;
+    let c = piecewise_bound(epsilon)?;
+    // e^(epsilon / 2) / (e^(epsilon / 2) + 1) = (C + 1) / 2C
+    let high_probability = (c + one) / (2 * c);
+    let left = (c + one) / 2 * t - (c - one) / 2;
+    let right = left + c - one;
+
+    let perturbed = if random_unit_interval(rng) < high_probability {
+        // high probability piece around t
+        left + random_unit_interval(rng) * (right - left)
+    } else {
+        // low probability pieces [-C, left) and (right, C]
+        let left_length = left + c;
+        let position = random_unit_interval(rng) * (left_length + c - right);
+        if position < left_length {
+            -c + position
+        } else {
+            right + (position - left_length)
+        }
+    };
+
+    // map back to the unit interval scale
+    Ok((perturbed + one) / 2)
+}
+
+/// Aggregates reports from `piecewise_perturb` to estimate the mean of the users' values.
+/// Reports are already private, so the estimate is released without further noise.
+pub struct PiecewiseMeanStore<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    count: CachedField<u32>,
+    sum: CachedField<I64F64>,
+    epsilon: CachedField<I32F32>,
+}
+
+impl<'a> PiecewiseMeanStore<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            count: CachedField::new(COUNT_KEY),
+            sum: CachedField::new(SUM_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+
+    /// This is used to produce a new PiecewiseMeanStore. It can be used when you want to associate
+    /// a PiecewiseMeanStore to multiple suffixes and you still want to define the PiecewiseMeanStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            count: CachedField::new(COUNT_KEY),
+            sum: CachedField::new(SUM_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+}
+
+impl<'a> PiecewiseMeanStore<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_count(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.count.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Returns the epsilon that clients perturb their values with
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        self.epsilon.may_load(storage, self.as_slice())?.ok_or(
+            StdError::generic_err("Epsilon not set")
+        )
+    }
+
+    /// Set the epsilon that clients perturb their values with, between `PIECEWISE_MIN_EPSILON`
+    /// and `PIECEWISE_MAX_EPSILON`. It can only be set before the first report, since reports
+    /// with different epsilons cannot be checked against one range.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if self.get_count(storage)? > 0 {
+            return Err(StdError::generic_err("Cannot change epsilon after reports have been added"));
+        }
+        check_piecewise_epsilon(epsilon)?;
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    /// Adds a perturbed report, rejecting it if it is outside the range the mechanism can produce
+    pub fn add_report(&self, storage: &mut dyn Storage, report: I32F32) -> StdResult<()> {
+        let (lower, upper) = piecewise_report_range(self.get_epsilon(storage)?)?;
+        if report < lower || report > upper {
+            return Err(StdError::generic_err("Report is outside the piecewise mechanism range"));
+        }
+        let new_count = self.get_count(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        let sum = self.sum.load_or(storage, self.as_slice(), I64F64::from_num(0))?;
+        self.count.save(storage, self.as_slice(), new_count);
+        self.sum.save(storage, self.as_slice(), sum + I64F64::from_num(report));
+        Ok(())
+    }
+
+    /// Estimated mean of the users' values, clamped to [0, 1]
+    pub fn estimate_mean(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        let count = self.get_count(storage)?;
+        if count == 0 {
+            return Err(StdError::generic_err("No data to count"));
+        }
+        let sum = self.sum.load_or(storage, self.as_slice(), I64F64::from_num(0))?;
+        let mean = I32F32::from_num(sum / I64F64::from_num(count));
+        Ok(mean.clamp(I32F32::from_num(0), I32F32::from_num(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bound_matches_the_closed_form() {
+        // (e + 1) / (e - 1) for epsilon = 2
+        let c = piecewise_bound(I32F32::from_num(2)).unwrap();
+        assert!((c - I32F32::from_num(2.163953)).abs() < I32F32::from_num(0.0001), "{c}");
+        let (lower, upper) = piecewise_report_range(I32F32::from_num(2)).unwrap();
+        assert_eq!(lower + upper, I32F32::from_num(1));
+    }
+
+    #[test]
+    fn extreme_epsilons_are_errors_instead_of_panics() {
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        for epsilon in [I32F32::from_num(0), I32F32::from_bits(1), I32F32::from_num(44), I32F32::MAX] {
+            assert!(piecewise_bound(epsilon).is_err());
+            assert!(piecewise_perturb(&mut rng, I32F32::from_num(0.5), epsilon).is_err());
+        }
+        assert!(piecewise_bound(PIECEWISE_MIN_EPSILON).is_ok());
+        assert!(piecewise_bound(PIECEWISE_MAX_EPSILON).is_ok());
+
+        let mut storage = MockStorage::new();
+        let store = PiecewiseMeanStore::new(b"piecewise");
+        assert!(store.set_epsilon(&mut storage, I32F32::from_bits(1)).is_err());
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(50)).is_err());
+        assert!(store.get_epsilon(&storage).is_err());
+    }
+
+    #[test]
+    fn reports_estimate_the_mean() {
+        let mut storage = MockStorage::new();
+        let store = PiecewiseMeanStore::new(b"piecewise");
+        let epsilon = I32F32::from_num(4);
+        store.set_epsilon(&mut storage, epsilon).unwrap();
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        for i in 0..2000 {
+            let value = if i % 4 == 0 { I32F32::from_num(1) } else { I32F32::from_num(0) };
+            let report = piecewise_perturb(&mut rng, value, epsilon).unwrap();
+            store.add_report(&mut storage, report).unwrap();
+        }
+        let mean = store.estimate_mean(&storage).unwrap();
+        assert!((mean - I32F32::from_num(0.25)).abs() < I32F32::from_num(0.05), "{mean}");
+
+        // outside the range of the mechanism, and epsilon is fixed once reports are in
+        assert!(store.add_report(&mut storage, I32F32::from_num(2)).is_err());
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(2)).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+use substrate_fixed::types::{I32F32, I64F64};
+
+// Post-processing: any function of a DP release that does not look at the data again is just
+// as private as the release, so these can be applied to noisy answers at no privacy cost, to
+// make them look like answers that the true data could have produced.
+
+/// Rounds `value` to the nearest multiple of `step`, with ties away from zero, e.g. a noisy
+/// count to a whole number or an average to two decimals. Returns `value` unchanged if `step`
+/// is not positive, and saturates at the limits of I32F32.
+pub fn round_to(value: I32F32, step: I32F32) -> I32F32 {
+    if step <= 0 {
+        return value;
+    }
+    let step = I64F64::from_num(step);
+    let steps = (I64F64::from_num(value) / step).round();
+    I32F32::saturating_from_num(steps * step)
+}
+
+/// Clamps `value` into [lo, hi], e.g. a noisy count to [0, population] or a noisy proportion
+/// to [0, 1]. If lo > hi, every value is clamped to hi.
+pub fn clamp(value: I32F32, lo: I32F32, hi: I32F32) -> I32F32 {
+    value.max(lo).min(hi)
+}
+
+/// Replaces `values` with the closest non-decreasing sequence, in least squares, e.g. to
+/// release noisy cumulative counts that never go down. Uses the pool adjacent violators
+/// algorithm: runs of values that decrease are replaced by their average, in O(n) time.
+pub fn enforce_monotone(values: &mut [I32F32]) {
+    // blocks of pooled values, as (sum, length), whose averages are non-decreasing
+    let mut blocks: Vec<(I64F64, usize)> = Vec::with_capacity(values.len());
+    for value in values.iter() {
+        let mut block = (I64F64::from_num(*value), 1_usize);
+        while let Some(&(sum, len)) = blocks.last() {
+            // sum / len > block.0 / block.1, compared without dividing
+            if sum * I64F64::from_num(block.1) <= block.0 * I64F64::from_num(len) {
+                break;
+            }
+            block = (sum + block.0, len + block.1);
+            blocks.pop();
+        }
+        blocks.push(block);
+    }
+
+    let mut i = 0;
+    for (sum, len) in blocks {
+        let average = I32F32::from_num(sum / I64F64::from_num(len));
+        for value in &mut values[i..i + len] {
+            *value = average;
+        }
+        i += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(values: &[f64]) -> Vec<I32F32> {
+        values.iter().map(|value| I32F32::from_num(*value)).collect()
+    }
+
+    #[test]
+    fn rounds_and_clamps() {
+        assert_eq!(round_to(I32F32::from_num(12.4), I32F32::from_num(1)), I32F32::from_num(12));
+        assert_eq!(round_to(I32F32::from_num(-2.5), I32F32::from_num(1)), I32F32::from_num(-3));
+        assert_eq!(round_to(I32F32::from_num(37), I32F32::from_num(5)), I32F32::from_num(35));
+        assert_eq!(round_to(I32F32::from_num(1.5), I32F32::from_num(0)), I32F32::from_num(1.5));
+        assert_eq!(clamp(I32F32::from_num(-0.2), I32F32::from_num(0), I32F32::from_num(1)), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn monotone_sequence_pools_violators() {
+        let mut values = fixed(&[1.0, 3.0, 2.0, 2.0, 5.0, 4.0]);
+        enforce_monotone(&mut values);
+        assert_eq!(values, fixed(&[1.0, 7.0 / 3.0, 7.0 / 3.0, 7.0 / 3.0, 4.5, 4.5]));
+
+        let mut sorted = fixed(&[-1.0, 0.0, 0.0, 8.0]);
+        enforce_monotone(&mut sorted);
+        assert_eq!(sorted, fixed(&[-1.0, 0.0, 0.0, 8.0]));
+    }
+}
@@ -0,0 +1,1023 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use secret_toolkit::serialization::Serde;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::{exp, ln, sqrt};
+
+use crate::{BooleanStatsStore, CachedField, CategoricalStatsStore, FieldValue, DistinctCountStore, HierarchicalHistogramStore, RunningStatsStore};
+
+const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const NEXT_RESERVATION_KEY: &[u8] = b"next-reservation";
+const RESERVATION_KEY: &[u8] = b"reservation";
+const WINDOW_BUDGET_KEY: &[u8] = b"window-budget";
+const SPENT_KEY: &[u8] = b"spent";
+const EPOCH_SPENT_KEY: &[u8] = b"epoch-spent";
+const EXPIRED_KEY: &[u8] = b"expired";
+const LAST_REFILL_KEY: &[u8] = b"last-refill";
+
+/// Budget locked by `reserve_budget` until it is committed or released
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BudgetReservation {
+    pub amount: I32F32,
+    /// last block height at which the reservation can be committed
+    pub expires_at_height: u64,
+}
+
+/// A common epsilon pool that several stores in one contract can draw from. Charges are
+/// atomic: either the whole cost of a release is affordable and deducted, or nothing is charged.
+pub struct PrivacyAccountant<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    privacy_budget: CachedField<I32F32>,
+    next_reservation: CachedField<u64>,
+    last_refill: CachedField<u64>,
+}
+
+impl<'a> PrivacyAccountant<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            next_reservation: CachedField::new(NEXT_RESERVATION_KEY),
+            last_refill: CachedField::new(LAST_REFILL_KEY),
+        }
+    }
+
+    /// This is used to produce a new PrivacyAccountant. It can be used when you want to associate
+    /// a PrivacyAccountant to multiple suffixes and you still want to define the PrivacyAccountant
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            next_reservation: CachedField::new(NEXT_RESERVATION_KEY),
+            last_refill: CachedField::new(LAST_REFILL_KEY),
+        }
+    }
+}
+
+impl<'a> PrivacyAccountant<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_privacy_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the privacy budget
+    pub fn set_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) {
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the last period the budget was refilled for with `refill`, if any
+    pub fn get_last_refill(&self, storage: &dyn Storage) -> StdResult<Option<u64>> {
+        self.last_refill.may_load(storage, self.as_slice())
+    }
+
+    /// Resets the budget to `amount` if `period` is later than the last period it was refilled
+    /// for, and returns whether it did. The refilled period is kept with the accountant, so the
+    /// budget is refilled once per period however many datasets sharing it call this.
+    pub fn refill(&self, storage: &mut dyn Storage, period: u64, amount: I32F32) -> StdResult<bool> {
+        if matches!(self.get_last_refill(storage)?, Some(last_refill) if period <= last_refill) {
+            return Ok(false);
+        }
+        self.set_privacy_budget(storage, amount);
+        self.last_refill.save(storage, self.as_slice(), period);
+        Ok(true)
+    }
+
+    /// Returns true if the remaining budget covers `cost`
+    pub fn can_afford(&self, storage: &dyn Storage, cost: I32F32) -> StdResult<bool> {
+        Ok(self.get_privacy_budget(storage)? >= cost)
+    }
+
+    /// Deducts `cost` from the budget, or returns an error without charging anything if the
+    /// remaining budget is insufficient or the cost is negative
+    pub fn charge(&self, storage: &mut dyn Storage, cost: I32F32) -> StdResult<()> {
+        if cost < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy cost must not be negative"));
+        }
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        self.set_privacy_budget(storage, privacy_budget - cost);
+        Ok(())
+    }
+
+    /// Charges the combined cost of several statistics released together. Either the total is
+    /// affordable and deducted, or nothing is charged. Every cost must not be negative.
+    pub fn charge_all(&self, storage: &mut dyn Storage, costs: &[I32F32]) -> StdResult<()> {
+        let mut total_cost = I32F32::from_num(0);
+        for cost in costs {
+            if *cost < I32F32::from_num(0) {
+                return Err(StdError::generic_err("Privacy cost must not be negative"));
+            }
+            total_cost = total_cost.checked_add(*cost).ok_or(
+                StdError::generic_err("Privacy cost overflow")
+            )?;
+        }
+        self.charge(storage, total_cost)
+    }
+
+    fn reservation_key(&self, id: u64) -> Vec<u8> {
+        [self.as_slice(), RESERVATION_KEY, &id.to_be_bytes()].concat()
+    }
+
+    /// Returns a reservation that has not been committed or released yet
+    pub fn get_reservation(&self, storage: &dyn Storage, id: u64) -> Option<BudgetReservation> {
+        let bytes = storage.get(&self.reservation_key(id))?;
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (expires_at_height, amount) = bytes.split_at(8);
+        Some(BudgetReservation {
+            amount: I32F32::from_stored(amount).ok()?,
+            expires_at_height: u64::from_stored(expires_at_height).ok()?,
+        })
+    }
+
+    /// Locks `amount` of the budget for an analysis that spans several transactions, e.g. an
+    /// IBC round trip, so that other queries cannot exhaust the budget before it completes. The
+    /// amount is deducted now, as if it were charged, and returns to the budget when the
+    /// reservation is released, or as far as it is not spent when it is committed. Returns the
+    /// id of the reservation, or an error without reserving anything if the remaining budget is
+    /// insufficient.
+    pub fn reserve_budget(
+        &self,
+        storage: &mut dyn Storage,
+        amount: I32F32,
+        expires_at_height: u64,
+    ) -> StdResult<u64> {
+        if amount <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Reservation amount must be greater than zero"));
+        }
+        self.charge(storage, amount)?;
+        let id = self.next_reservation.load_or(storage, self.as_slice(), 0)?;
+        let next_id = id.checked_add(1).ok_or(StdError::generic_err("Reservation id overflow"))?;
+        self.next_reservation.save(storage, self.as_slice(), next_id);
+        let record = [expires_at_height.to_stored(), amount.to_stored()].concat();
+        storage.set(&self.reservation_key(id), &record);
+        Ok(id)
+    }
+
+    /// Spends `cost` of a reservation, at most its amount, and returns the rest to the budget.
+    /// Fails if the reservation does not exist or `block_height` is past its expiry; release an
+    /// expired reservation instead.
+    ///
+    /// Stores with a shared budget charge the accountant themselves. To run one of their
+    /// releases on a reservation, release the reservation and run the query in the same
+    /// message: if the query fails, the whole transaction reverts and the reservation stays.
+    pub fn commit_reservation(
+        &self,
+        storage: &mut dyn Storage,
+        id: u64,
+        cost: I32F32,
+        block_height: u64,
+    ) -> StdResult<()> {
+        let reservation = self.get_reservation(storage, id).ok_or(
+            StdError::generic_err("Reservation not found")
+        )?;
+        if block_height > reservation.expires_at_height {
+            return Err(StdError::generic_err("Reservation expired"));
+        }
+        if cost < I32F32::from_num(0) || cost > reservation.amount {
+            return Err(StdError::generic_err("Cost must be between zero and the reserved amount"));
+        }
+        self.refund(storage, reservation.amount - cost)?;
+        storage.remove(&self.reservation_key(id));
+        Ok(())
+    }
+
+    /// Cancels a reservation, returning all of its amount to the budget. Expired reservations
+    /// keep their amount locked until they are released.
+    pub fn release_reservation(&self, storage: &mut dyn Storage, id: u64) -> StdResult<()> {
+        let reservation = self.get_reservation(storage, id).ok_or(
+            StdError::generic_err("Reservation not found")
+        )?;
+        self.refund(storage, reservation.amount)?;
+        storage.remove(&self.reservation_key(id));
+        Ok(())
+    }
+
+    fn refund(&self, storage: &mut dyn Storage, amount: I32F32) -> StdResult<()> {
+        let privacy_budget = self.get_privacy_budget(storage)?.checked_add(amount).ok_or(
+            StdError::generic_err("Privacy budget overflow")
+        )?;
+        self.set_privacy_budget(storage, privacy_budget);
+        Ok(())
+    }
+}
+
+/// A privacy budget for data that is only kept for a while: the epochs of an `EpochStatsStore`
+/// declared `with_retained_epochs` and `with_expiring_budget`. Every release is charged to the
+/// epoch it was computed from, and the epochs that have not expired may spend at most the window
+/// budget together. Spending on an epoch is forgotten when the store rotates the epoch out and
+/// deletes its data, never earlier, and budget left unspent does not accumulate.
+///
+/// Expiry follows the data, not the block height: if spending were forgotten while the data it
+/// was spent on could still be queried, the total epsilon spent on that data would grow without
+/// bound. Only an `EpochStatsStore` can expire an epoch.
+pub struct ExpiringPrivacyAccountant<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    window_budget: CachedField<I32F32>,
+    /// spending on every epoch that has not expired
+    spent: CachedField<I32F32>,
+}
+
+impl<'a> ExpiringPrivacyAccountant<'a> {
+    /// constructor
+    pub const fn new(namespace: &'a [u8]) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            window_budget: CachedField::new(WINDOW_BUDGET_KEY),
+            spent: CachedField::new(SPENT_KEY),
+        }
+    }
+
+    /// This is used to produce a new ExpiringPrivacyAccountant. It can be used when you want to
+    /// associate an ExpiringPrivacyAccountant to multiple suffixes and you still want to define
+    /// the ExpiringPrivacyAccountant as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            window_budget: CachedField::new(WINDOW_BUDGET_KEY),
+            spent: CachedField::new(SPENT_KEY),
+        }
+    }
+}
+
+impl<'a> ExpiringPrivacyAccountant<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    /// Returns the budget that the epochs which have not expired share
+    pub fn get_window_budget(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        // default privacy budget = 1
+        self.window_budget.load_or(storage, self.as_slice(), I32F32::from(1))
+    }
+
+    /// Set the budget that the epochs which have not expired share. Only call this from
+    /// admin-only handlers: raising it lets those epochs spend more.
+    pub fn set_window_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
+    ) -> StdResult<()> {
+        if budget < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy budget must not be negative"));
+        }
+        self.window_budget.save(storage, self.as_slice(), budget);
+        Ok(())
+    }
+
+    /// Returns the budget spent on the epochs that have not expired
+    pub fn get_spent(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        self.spent.load_or(storage, self.as_slice(), I32F32::from_num(0))
+    }
+
+    /// Returns the budget left for the epochs that have not expired
+    pub fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        let remaining = self.get_window_budget(storage)? - self.get_spent(storage)?;
+        Ok(remaining.max(I32F32::from_num(0)))
+    }
+
+    /// Key of an entry for `epoch` of the data stored under `data`, so that several epoch
+    /// stores can share one accountant
+    fn epoch_key(&self, key: &[u8], data: &[u8], epoch: u64) -> Vec<u8> {
+        [self.as_slice(), key, to_length_prefixed(data).as_slice(), &epoch.to_be_bytes()].concat()
+    }
+
+    /// Returns the budget spent on `epoch` of the data stored under `data`
+    pub(crate) fn get_spent_for_epoch(&self, storage: &dyn Storage, data: &[u8], epoch: u64) -> StdResult<I32F32> {
+        match storage.get(&self.epoch_key(EPOCH_SPENT_KEY, data, epoch)) {
+            Some(bytes) => I32F32::from_stored(&bytes),
+            None => Ok(I32F32::from_num(0)),
+        }
+    }
+
+    /// Returns an error if `cost` cannot be charged to `epoch` of the data stored under `data`,
+    /// because the epoch has expired or the budget left is insufficient
+    pub(crate) fn check_charge(&self, storage: &dyn Storage, data: &[u8], epoch: u64, cost: I32F32) -> StdResult<()> {
+        if cost < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy cost must not be negative"));
+        }
+        if storage.get(&self.epoch_key(EXPIRED_KEY, data, epoch)).is_some() {
+            return Err(StdError::generic_err("Epoch has expired"));
+        }
+        if self.get_privacy_budget(storage)? < cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        Ok(())
+    }
+
+    /// Charges `cost` to `epoch` of the data stored under `data`, or returns an error without
+    /// charging anything, see `check_charge`
+    pub(crate) fn charge(&self, storage: &mut dyn Storage, data: &[u8], epoch: u64, cost: I32F32) -> StdResult<()> {
+        self.check_charge(storage, data, epoch, cost)?;
+        let overflow = || StdError::generic_err("Privacy cost overflow");
+        let spent_for_epoch = self.get_spent_for_epoch(storage, data, epoch)?.checked_add(cost).ok_or_else(overflow)?;
+        let spent = self.get_spent(storage)?.checked_add(cost).ok_or_else(overflow)?;
+        storage.set(&self.epoch_key(EPOCH_SPENT_KEY, data, epoch), &spent_for_epoch.to_stored());
+        self.spent.save(storage, self.as_slice(), spent);
+        Ok(())
+    }
+
+    /// Forgets the spending on `epoch` of the data stored under `data`, and refuses any later
+    /// charge to it. Only call this once the data of the epoch has been deleted.
+    pub(crate) fn expire(&self, storage: &mut dyn Storage, data: &[u8], epoch: u64) -> StdResult<()> {
+        let spent_for_epoch = self.get_spent_for_epoch(storage, data, epoch)?;
+        let spent = (self.get_spent(storage)? - spent_for_epoch).max(I32F32::from_num(0));
+        storage.remove(&self.epoch_key(EPOCH_SPENT_KEY, data, epoch));
+        storage.set(&self.epoch_key(EXPIRED_KEY, data, epoch), &[1]);
+        self.spent.save(storage, self.as_slice(), spent);
+        Ok(())
+    }
+}
+
+/// Anything that holds a privacy budget: a `PrivacyAccountant` or a stats store
+pub trait BudgetHolder {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32>;
+    /// Returns an error if the holder cannot take a new budget, e.g. a closed store
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()>;
+}
+
+impl<'a> BudgetHolder for PrivacyAccountant<'a> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        PrivacyAccountant::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        PrivacyAccountant::set_privacy_budget(self, storage, budget);
+        Ok(())
+    }
+}
+
+impl<'a, Ser: Serde> BudgetHolder for RunningStatsStore<'a, Ser> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        RunningStatsStore::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        RunningStatsStore::set_privacy_budget(self, storage, budget)
+    }
+}
+
+impl<'a, Ser: Serde> BudgetHolder for CategoricalStatsStore<'a, Ser> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        CategoricalStatsStore::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        CategoricalStatsStore::set_privacy_budget(self, storage, budget)
+    }
+}
+
+impl<'a, Ser: Serde> BudgetHolder for BooleanStatsStore<'a, Ser> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        BooleanStatsStore::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        BooleanStatsStore::set_privacy_budget(self, storage, budget)
+    }
+}
+
+impl<'a, Ser: Serde> BudgetHolder for DistinctCountStore<'a, Ser> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        DistinctCountStore::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        DistinctCountStore::set_privacy_budget(self, storage, budget)
+    }
+}
+
+impl<'a, Ser: Serde> BudgetHolder for HierarchicalHistogramStore<'a, Ser> {
+    fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        HierarchicalHistogramStore::get_privacy_budget(self, storage)
+    }
+
+    fn set_privacy_budget(&self, storage: &mut dyn Storage, budget: I32F32) -> StdResult<()> {
+        HierarchicalHistogramStore::set_privacy_budget(self, storage, budget)
+    }
+}
+
+/// Moves `amount` of unused privacy budget from one store or accountant to another, e.g. from
+/// an inactive dataset to an active one. Either the whole amount is moved or nothing changes.
+/// Only call this from admin-only handlers: it raises the budget of `to_store`.
+pub fn transfer_budget(
+    storage: &mut dyn Storage,
+    from_store: &dyn BudgetHolder,
+    to_store: &dyn BudgetHolder,
+    amount: I32F32,
+) -> StdResult<()> {
+    if amount <= I32F32::from_num(0) {
+        return Err(StdError::generic_err("Transfer amount must be greater than zero"));
+    }
+    // validate both sides before anything is written
+    let from_budget = from_store.get_privacy_budget(storage)?;
+    if from_budget < amount {
+        return Err(StdError::generic_err("Privacy budget exhausted"));
+    }
+    let to_budget = to_store.get_privacy_budget(storage)?;
+    if to_budget.checked_add(amount).is_none() {
+        return Err(StdError::generic_err("Privacy budget overflow"));
+    }
+    // writing the same budget back fails before anything changes if the receiving holder cannot
+    // take a new budget, e.g. a closed store
+    to_store.set_privacy_budget(storage, to_budget)?;
+
+    from_store.set_privacy_budget(storage, from_budget - amount)?;
+    // read again after deducting, so a transfer to the same budget leaves it unchanged; the
+    // sum is at most the one checked above
+    let to_budget = to_store.get_privacy_budget(storage)?;
+    to_store.set_privacy_budget(storage, to_budget.saturating_add(amount))?;
+    Ok(())
+}
+
+/// Charges `cost_a` to one store or accountant and `cost_b` to another, e.g. for a statistic
+/// that compares two datasets. Either both are charged or neither is, also when both draw from
+/// the same shared budget.
+pub fn charge_jointly(
+    storage: &mut dyn Storage,
+    store_a: &dyn BudgetHolder,
+    cost_a: I32F32,
+    store_b: &dyn BudgetHolder,
+    cost_b: I32F32,
+) -> StdResult<()> {
+    let budget_a = store_a.get_privacy_budget(storage)?;
+    if budget_a < cost_a {
+        return Err(StdError::generic_err("Privacy budget exhausted"));
+    }
+    store_a.set_privacy_budget(storage, budget_a - cost_a)?;
+    // read after charging a, so that a shared budget has to cover both costs
+    let budget_b = store_b.get_privacy_budget(storage)?;
+    if budget_b < cost_b {
+        store_a.set_privacy_budget(storage, budget_a)?;
+        return Err(StdError::generic_err("Privacy budget exhausted"));
+    }
+    store_b.set_privacy_budget(storage, budget_b - cost_b)?;
+    Ok(())
+}
+
+/// Central epsilon of `n` reports that are each `local_epsilon`-local DP, when the reports are
+/// shuffled before release so that their order hides which user sent which report. This is
+/// the amplification by shuffling bound of Feldman, McMillan and Talwar (2021):
+///
+/// epsilon = ln(1 + (e^eps0 - 1) / (e^eps0 + 1) * (8 sqrt(e^eps0 ln(4 / delta)) / sqrt(n) + 8 e^eps0 / n))
+///
+/// for `local_epsilon` eps0 <= ln(n / (16 ln(2 / delta))), giving (epsilon, delta)-DP. Outside
+/// that range no amplification is claimed and `local_epsilon` is returned. `n` above
+/// `i32::MAX`, or a delta so small that 4 / delta does not fit in I32F32, is an error.
+pub fn amplified_epsilon(local_epsilon: I32F32, n: u32, delta: I32F32) -> StdResult<I32F32> {
+    let zero = I32F32::from_num(0);
+    let one = I32F32::from_num(1);
+    if local_epsilon <= zero {
+        return Err(StdError::generic_err("Epsilon must be greater than zero"));
+    }
+    if delta <= zero || delta >= one {
+        return Err(StdError::generic_err("Delta must be between zero and one"));
+    }
+    let math_err = |_| StdError::generic_err("Amplification bound out of range");
+
+    let n_fixed = I32F32::checked_from_num(n).ok_or(
+        StdError::generic_err("Too many reports for the amplification bound")
+    )?;
+    let four_over_delta = I32F32::from_num(4).checked_div(delta).ok_or(
+        StdError::generic_err("Delta too small for the amplification bound")
+    )?;
+    let ln_two_over_delta = ln::<I32F32, I32F32>(four_over_delta / 2).map_err(math_err)?;
+    let limit_ratio = n_fixed / (16 * ln_two_over_delta);
+    if limit_ratio <= one || local_epsilon > ln::<I32F32, I32F32>(limit_ratio).map_err(math_err)? {
+        return Ok(local_epsilon);
+    }
+
+    let exp_eps = exp::<I32F32, I32F32>(local_epsilon).map_err(math_err)?;
+    let ln_four_over_delta = ln::<I32F32, I32F32>(four_over_delta).map_err(math_err)?;
+    let sqrt_term = sqrt::<I32F32, I32F32>(exp_eps * ln_four_over_delta).map_err(math_err)?;
+    let sqrt_n = sqrt::<I32F32, I32F32>(n_fixed).map_err(math_err)?;
+    let inner = (exp_eps - one) / (exp_eps + one)
+        * (8 * sqrt_term / sqrt_n + 8 * exp_eps / n_fixed);
+    let epsilon = ln::<I32F32, I32F32>(one + inner).map_err(math_err)?;
+    Ok(epsilon.min(local_epsilon))
+}
+
+// Privacy loss distributions are cut off where the remaining mass is below the resolution of
+// I64F64, and are limited to 1024 buckets, so that composing two of them, which takes time
+// quadratic in their length, stays within about a million multiplications
+const PLD_GAUSSIAN_TAILS: i32 = 9;
+const PLD_MAX_BUCKETS: usize = 1 << 10;
+// e^-43 is below 2^-62, so smaller exponentials are treated as zero, or as e^-43 where an
+// upper bound is needed
+const PLD_EXP_CUTOFF: i32 = -43;
+// relative error of the erfc approximation, 1.2e-7, with room for the rounding of its polynomial
+const PLD_ERFC_MARGIN: i64 = 2;
+
+/// Mechanism composed by a `PldAccountant`, with sensitivity 1. Scale the noise parameter by
+/// the sensitivity of the statistic first, e.g. a Laplace release with sensitivity 2 and
+/// epsilon 0.5 is `Laplace { epsilon: 0.5 }`, since epsilon already accounts for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PldMechanism {
+    /// Laplace noise with scale 1 / epsilon
+    Laplace { epsilon: I32F32 },
+    /// Gaussian noise with standard deviation sigma
+    Gaussian { sigma: I32F32 },
+    /// two-sided geometric noise on integers, P(k) proportional to e^(-epsilon |k|)
+    Geometric { epsilon: I32F32 },
+}
+
+/// Composes many releases, possibly from different mechanisms, by convolving their discretized
+/// privacy loss distributions (Koskela, Jälkö and Honkela, 2020; Meiser and Mohammadi, 2018).
+/// Losses are rounded up to a multiple of `interval`, and every rounding of the probabilities
+/// moves mass towards higher losses, so the (epsilon, delta) it reports holds, and is usually
+/// much tighter than advanced composition, especially for Gaussian releases.
+/// Smaller intervals are tighter but slower: composition takes time quadratic in the number of
+/// buckets, which grows with the total epsilon divided by the interval. This is meant for
+/// planning budgets and reporting guarantees, not for charging queries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PldAccountant {
+    interval: I64F64,
+    /// loss index of the first bucket, whose loss is first * interval
+    first: i64,
+    /// probability of each loss bucket under the dataset with the individual
+    probabilities: Vec<I64F64>,
+    /// probability that the privacy loss is unbounded
+    infinity_mass: I64F64,
+}
+
+/// Lower bound on e^x
+fn pld_exp(x: I64F64) -> StdResult<I64F64> {
+    if x < I64F64::from_num(PLD_EXP_CUTOFF) {
+        return Ok(I64F64::from_num(0));
+    }
+    exp::<I64F64, I64F64>(x).map_err(|_| StdError::generic_err("Privacy loss out of range"))
+}
+
+/// Upper bound on e^x
+fn pld_exp_up(x: I64F64) -> StdResult<I64F64> {
+    let x = x.max(I64F64::from_num(PLD_EXP_CUTOFF));
+    Ok(pld_exp(x)?.saturating_add(I64F64::from_bits(1)))
+}
+
+fn pld_decimal(digits: i64, scale: i64) -> I64F64 {
+    I64F64::from_num(digits) / I64F64::from_num(scale)
+}
+
+/// Lower bound on the standard normal CDF, from the erfc approximation in Numerical Recipes with
+/// a relative error below 1.2e-7. The error and the rounding are taken off the CDF, so that the
+/// buckets built from it never move mass towards lower losses.
+fn pld_normal_cdf(z: I64F64) -> StdResult<I64F64> {
+    let one = I64F64::from_num(1);
+    let x = z.abs() / sqrt::<I64F64, I64F64>(I64F64::from_num(2))
+        .map_err(|_| StdError::generic_err("Square root is undefined"))?;
+    let t = one / (one + x / 2);
+    let coefficients = [
+        pld_decimal(17087277, 100000000),
+        pld_decimal(-82215223, 100000000),
+        pld_decimal(148851587, 100000000),
+        pld_decimal(-113520398, 100000000),
+        pld_decimal(27886807, 100000000),
+        pld_decimal(-18628806, 100000000),
+        pld_decimal(9678418, 100000000),
+        pld_decimal(37409196, 100000000),
+        pld_decimal(100002368, 100000000),
+        pld_decimal(-126551223, 100000000),
+    ];
+    let polynomial = coefficients[1..]
+        .iter()
+        .fold(coefficients[0], |total, coefficient| *coefficient + t * total);
+    let margin = pld_decimal(PLD_ERFC_MARGIN, 10000000);
+    let ulp = I64F64::from_bits(1);
+    if z < 0 {
+        let erfc = t * pld_exp(polynomial - x * x)?;
+        Ok((erfc - erfc * margin) / 2)
+    } else {
+        let erfc = (t * pld_exp_up(polynomial - x * x)?).saturating_add(ulp);
+        let erfc = erfc.saturating_add(erfc * margin).saturating_add(ulp);
+        Ok(one.saturating_sub((erfc / 2).saturating_add(ulp)))
+    }
+}
+
+impl PldAccountant {
+    /// An accountant that has composed nothing yet. `interval` is the width of the loss
+    /// buckets, e.g. 0.001.
+    pub fn new(interval: I32F32) -> StdResult<Self> {
+        if interval <= 0 {
+            return Err(StdError::generic_err("Interval must be greater than zero"));
+        }
+        Ok(PldAccountant {
+            interval: I64F64::from_num(interval),
+            first: 0,
+            probabilities: vec![I64F64::from_num(1)],
+            infinity_mass: I64F64::from_num(0),
+        })
+    }
+
+    /// Privacy loss distribution of a single release, built from the CDF of its privacy loss
+    /// between `lowest` and `highest`. Each bucket takes the mass of the losses that round up
+    /// to it, the first bucket also takes everything below it and anything above the last
+    /// bucket counts as unbounded loss.
+    fn single<F>(&self, lowest: I64F64, highest: I64F64, cdf: F) -> StdResult<Self>
+    where
+        F: Fn(I64F64) -> StdResult<I64F64>,
+    {
+        let first = (lowest / self.interval).ceil().to_num::<i64>();
+        let last = (highest / self.interval).ceil().to_num::<i64>();
+        if last - first >= PLD_MAX_BUCKETS as i64 {
+            return Err(StdError::generic_err("Privacy loss distribution is too large, use a larger interval"));
+        }
+        let one = I64F64::from_num(1);
+        let mut probabilities = Vec::with_capacity((last - first + 1) as usize);
+        let mut below = I64F64::from_num(0);
+        for index in first..=last {
+            let cumulative = cdf(I64F64::from_num(index) * self.interval)?.clamp(below, one);
+            probabilities.push(cumulative - below);
+            below = cumulative;
+        }
+        Ok(PldAccountant {
+            interval: self.interval,
+            first,
+            probabilities,
+            infinity_mass: one - below,
+        })
+    }
+
+    fn of_mechanism(&self, mechanism: PldMechanism) -> StdResult<Self> {
+        let one = I64F64::from_num(1);
+        match mechanism {
+            PldMechanism::Laplace { epsilon } => {
+                if epsilon <= 0 {
+                    return Err(StdError::generic_err("Epsilon must be greater than zero"));
+                }
+                let epsilon = I64F64::from_num(epsilon);
+                // the loss is epsilon below the released point and -epsilon above the
+                // neighboring one, and linear in between
+                self.single(-epsilon, epsilon, |loss| {
+                    if loss < -epsilon {
+                        Ok(I64F64::from_num(0))
+                    } else if loss >= epsilon {
+                        Ok(one)
+                    } else {
+                        Ok(pld_exp((loss - epsilon) / 2)? / 2)
+                    }
+                })
+            }
+            PldMechanism::Geometric { epsilon } => {
+                if epsilon <= 0 {
+                    return Err(StdError::generic_err("Epsilon must be greater than zero"));
+                }
+                let epsilon = I64F64::from_num(epsilon);
+                let alpha = pld_exp(-epsilon)?;
+                // the loss is epsilon with probability 1 / (1 + alpha), -epsilon otherwise
+                self.single(-epsilon, epsilon, |loss| {
+                    if loss < -epsilon {
+                        Ok(I64F64::from_num(0))
+                    } else if loss >= epsilon {
+                        Ok(one)
+                    } else {
+                        Ok(alpha / (one + alpha))
+                    }
+                })
+            }
+            PldMechanism::Gaussian { sigma } => {
+                if sigma <= 0 {
+                    return Err(StdError::generic_err("Sigma must be greater than zero"));
+                }
+                // the loss is normal with mean 1 / (2 sigma^2) and standard deviation 1 / sigma
+                let spread = one / I64F64::from_num(sigma);
+                let mean = spread * spread / 2;
+                let tails = spread * I64F64::from_num(PLD_GAUSSIAN_TAILS);
+                self.single(mean - tails, mean + tails, |loss| pld_normal_cdf((loss - mean) / spread))
+            }
+        }
+    }
+
+    /// Distribution of the sum of the privacy losses of two independent releases. Every product
+    /// is rounded up, so no bucket ends up with less mass than it should.
+    fn convolve(&self, other: &Self) -> StdResult<Self> {
+        let len = self.probabilities.len() + other.probabilities.len() - 1;
+        if len > PLD_MAX_BUCKETS {
+            return Err(StdError::generic_err("Privacy loss distribution is too large, use a larger interval"));
+        }
+        let ulp = I64F64::from_bits(1);
+        let mut probabilities = vec![I64F64::from_num(0); len];
+        for (i, p) in self.probabilities.iter().enumerate() {
+            if *p == 0 {
+                continue;
+            }
+            for (j, q) in other.probabilities.iter().enumerate() {
+                if *q == 0 {
+                    continue;
+                }
+                probabilities[i + j] = probabilities[i + j].saturating_add(*p * *q).saturating_add(ulp);
+            }
+        }
+        Ok(PldAccountant {
+            interval: self.interval,
+            first: self.first + other.first,
+            probabilities,
+            infinity_mass: self.infinity_mass + other.infinity_mass - self.infinity_mass * other.infinity_mass,
+        })
+    }
+
+    /// Composes `count` releases of `mechanism` with everything composed so far
+    pub fn compose(&mut self, mechanism: PldMechanism, count: u32) -> StdResult<()> {
+        let mut power = self.of_mechanism(mechanism)?;
+        let mut count = count;
+        // composes by repeated squaring, so many identical releases only take a few convolutions
+        while count > 0 {
+            if count & 1 == 1 {
+                *self = self.convolve(&power)?;
+            }
+            count >>= 1;
+            if count > 0 {
+                power = power.convolve(&power)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Smallest delta for which everything composed so far is (epsilon, delta)-DP:
+    /// the sum over losses l above epsilon of P(l) (1 - e^(epsilon - l)), plus the probability of
+    /// unbounded loss
+    pub fn delta_for_epsilon(&self, epsilon: I32F32) -> StdResult<I32F32> {
+        if epsilon < 0 {
+            return Err(StdError::generic_err("Epsilon must not be negative"));
+        }
+        let epsilon = I64F64::from_num(epsilon);
+        let exp_epsilon = exp::<I64F64, I64F64>(epsilon).map_err(|_| StdError::generic_err("Epsilon out of range"))?;
+        let step = pld_exp(-self.interval)?;
+        let mut delta = self.infinity_mass;
+        // e^(-l) for the current loss l, which is above epsilon and so at most 1
+        let mut exp_neg_loss: Option<I64F64> = None;
+        for (offset, probability) in self.probabilities.iter().enumerate() {
+            let loss = I64F64::from_num(self.first + offset as i64) * self.interval;
+            if loss <= epsilon {
+                continue;
+            }
+            let current = match exp_neg_loss {
+                Some(previous) => previous * step,
+                None => pld_exp(-loss)?,
+            };
+            exp_neg_loss = Some(current);
+            let excess = I64F64::from_num(1).saturating_sub(exp_epsilon.saturating_mul(current));
+            // rounded up, like the masses themselves
+            delta = delta.saturating_add(*probability * excess).saturating_add(I64F64::from_bits(1));
+        }
+        // round up, so that the reported delta is never smaller than the computed one
+        let rounded = I32F32::saturating_from_num(delta);
+        if I64F64::from_num(rounded) < delta {
+            return Ok(rounded.saturating_add(I32F32::from_bits(1)));
+        }
+        Ok(rounded)
+    }
+
+    /// Smallest epsilon, a multiple of the interval, for which everything composed so far is
+    /// (epsilon, delta)-DP. Returns an error if delta is below the probability of unbounded loss.
+    pub fn epsilon_for_delta(&self, delta: I32F32) -> StdResult<I32F32> {
+        let zero = I64F64::from_num(0);
+        if delta <= 0 || delta >= 1 {
+            return Err(StdError::generic_err("Delta must be between zero and one"));
+        }
+        let target = I64F64::from_num(delta);
+        if self.infinity_mass > target {
+            return Err(StdError::generic_err("Delta is below the probability of unbounded privacy loss"));
+        }
+        let step = pld_exp(-self.interval)?;
+        let mut index = self.first + self.probabilities.len() as i64 - 1;
+        if index < 0 {
+            // every loss is negative, so delta at epsilon 0 is the probability of unbounded loss
+            return Ok(I32F32::from_num(0));
+        }
+        // walking down the multiples l of the interval from the highest loss: above holds the
+        // mass of the losses above l, and weighted the same mass weighted by e^(l - loss), so
+        // that delta at l is infinity_mass + above - weighted
+        let mut above = zero;
+        let mut weighted = zero;
+        loop {
+            if self.infinity_mass + above - weighted > target {
+                index += 1;
+                break;
+            }
+            if index == 0 {
+                break;
+            }
+            let probability = usize::try_from(index - self.first)
+                .ok()
+                .and_then(|offset| self.probabilities.get(offset))
+                .copied()
+                .unwrap_or(zero);
+            above += probability;
+            weighted = step * (weighted + probability);
+            index -= 1;
+        }
+        let epsilon = I64F64::from_num(index) * self.interval;
+        I32F32::checked_from_num(epsilon).ok_or(StdError::generic_err("Epsilon overflow"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn failed_transfers_leave_both_budgets_unchanged() {
+        let mut storage = MockStorage::new();
+        let from = PrivacyAccountant::new(b"from");
+        let to = PrivacyAccountant::new(b"to");
+        from.set_privacy_budget(&mut storage, I32F32::from_num(5));
+        to.set_privacy_budget(&mut storage, I32F32::MAX);
+
+        assert!(transfer_budget(&mut storage, &from, &to, I32F32::from_num(2)).is_err());
+        assert_eq!(from.get_privacy_budget(&storage).unwrap(), I32F32::from_num(5));
+        assert_eq!(to.get_privacy_budget(&storage).unwrap(), I32F32::MAX);
+
+        to.set_privacy_budget(&mut storage, I32F32::from_num(1));
+        assert!(transfer_budget(&mut storage, &from, &to, I32F32::from_num(6)).is_err());
+        transfer_budget(&mut storage, &from, &to, I32F32::from_num(2)).unwrap();
+        assert_eq!(from.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+        assert_eq!(to.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+
+        // a transfer to the same budget changes nothing
+        transfer_budget(&mut storage, &from, &from, I32F32::from_num(2)).unwrap();
+        assert_eq!(from.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+    }
+
+    #[test]
+    fn expiring_spending_is_forgotten_only_on_expiry() {
+        let mut storage = MockStorage::new();
+        let accountant = ExpiringPrivacyAccountant::new(b"window");
+        accountant.set_window_budget(&mut storage, I32F32::from_num(3)).unwrap();
+        accountant.charge(&mut storage, b"north", 0, I32F32::from_num(1)).unwrap();
+        accountant.charge(&mut storage, b"south", 0, I32F32::from_num(1)).unwrap();
+        accountant.charge(&mut storage, b"north", 1, I32F32::from_num(1)).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+        assert!(accountant.charge(&mut storage, b"north", 1, I32F32::from_num(0.5)).is_err());
+        assert!(accountant.charge(&mut storage, b"north", 1, I32F32::from_num(-1)).is_err());
+
+        // only the spending on the expired epoch of the expired data is forgotten
+        accountant.expire(&mut storage, b"north", 0).unwrap();
+        assert_eq!(accountant.get_spent(&storage).unwrap(), I32F32::from_num(2));
+        assert_eq!(accountant.get_spent_for_epoch(&storage, b"south", 0).unwrap(), I32F32::from_num(1));
+        assert_eq!(accountant.get_spent_for_epoch(&storage, b"north", 0).unwrap(), I32F32::from_num(0));
+        assert!(accountant.charge(&mut storage, b"north", 0, I32F32::from_num(1)).is_err());
+        accountant.charge(&mut storage, b"north", 2, I32F32::from_num(1)).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn negative_costs_are_refused() {
+        let mut storage = MockStorage::new();
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(2));
+        assert!(accountant.charge(&mut storage, I32F32::from_num(-1)).is_err());
+        assert!(accountant.charge_all(&mut storage, &[I32F32::from_num(1), I32F32::from_num(-1)]).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(2));
+        accountant.charge_all(&mut storage, &[I32F32::from_num(1), I32F32::from_num(0)]).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+    }
+
+    #[test]
+    fn reservations_lock_the_budget_until_committed_or_released() {
+        let mut storage = MockStorage::new();
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(10));
+
+        // an unaffordable reservation reserves nothing
+        assert!(accountant.reserve_budget(&mut storage, I32F32::from_num(11), 100).is_err());
+        assert!(accountant.reserve_budget(&mut storage, I32F32::from_num(0), 100).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(10));
+
+        let first = accountant.reserve_budget(&mut storage, I32F32::from_num(4), 100).unwrap();
+        let second = accountant.reserve_budget(&mut storage, I32F32::from_num(3), 100).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+        assert_eq!(
+            accountant.get_reservation(&storage, first),
+            Some(BudgetReservation { amount: I32F32::from_num(4), expires_at_height: 100 })
+        );
+        // the locked budget is not available to other charges
+        assert!(accountant.charge(&mut storage, I32F32::from_num(4)).is_err());
+
+        // committing spends part of the reservation and refunds the rest
+        assert!(accountant.commit_reservation(&mut storage, first, I32F32::from_num(5), 50).is_err());
+        accountant.commit_reservation(&mut storage, first, I32F32::from_num(1), 50).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(6));
+        assert_eq!(accountant.get_reservation(&storage, first), None);
+        assert!(accountant.commit_reservation(&mut storage, first, I32F32::from_num(1), 50).is_err());
+        assert!(accountant.release_reservation(&mut storage, first).is_err());
+
+        // an expired reservation cannot be committed, only released in full
+        assert!(accountant.commit_reservation(&mut storage, second, I32F32::from_num(1), 101).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(6));
+        accountant.release_reservation(&mut storage, second).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(9));
+        assert_eq!(accountant.get_reservation(&storage, second), None);
+    }
+
+    fn assert_between(value: I32F32, lowest: f64, highest: f64) {
+        assert!(value >= I32F32::from_num(lowest) && value <= I32F32::from_num(highest), "{} not in [{}, {}]", value, lowest, highest);
+    }
+
+    #[test]
+    fn laplace_losses_bound_the_exact_delta() {
+        let one = I32F32::from_num(1);
+        let mut accountant = PldAccountant::new(one / 128).unwrap();
+        accountant.compose(PldMechanism::Laplace { epsilon: one }, 1).unwrap();
+        // a single Laplace release has delta 1 - e^((epsilon' - epsilon) / 2) at epsilon' <= epsilon
+        assert_between(accountant.delta_for_epsilon(I32F32::from_num(0)).unwrap(), 0.39347, 0.395);
+        assert_between(accountant.delta_for_epsilon(one / 2).unwrap(), 0.22120, 0.223);
+        assert_eq!(accountant.delta_for_epsilon(one).unwrap(), 0);
+        // 1 + 2 ln(0.75)
+        assert_between(accountant.epsilon_for_delta(one / 4).unwrap(), 0.42464, 0.44);
+
+        // two releases: basic composition gives delta 0 at epsilon 2, and a fine grid gives
+        // 0.24187 at epsilon 1
+        accountant.compose(PldMechanism::Laplace { epsilon: one }, 1).unwrap();
+        assert_between(accountant.delta_for_epsilon(one).unwrap(), 0.24187, 0.244);
+        assert_eq!(accountant.delta_for_epsilon(I32F32::from_num(2)).unwrap(), 0);
+    }
+
+    #[test]
+    fn gaussian_losses_bound_the_exact_delta() {
+        let one = I32F32::from_num(1);
+        let mut accountant = PldAccountant::new(one / 32).unwrap();
+        // two releases with sigma 2 are one with sigma sqrt(2), whose delta is
+        // Phi(1 / (2 sigma) - epsilon sigma) - e^epsilon Phi(-1 / (2 sigma) - epsilon sigma)
+        accountant.compose(PldMechanism::Gaussian { sigma: I32F32::from_num(2) }, 2).unwrap();
+        assert_between(accountant.delta_for_epsilon(one).unwrap(), 0.03963, 0.045);
+        assert_between(accountant.delta_for_epsilon(one / 2).unwrap(), 0.12372, 0.135);
+    }
+
+    #[test]
+    fn distributions_are_limited_in_size() {
+        let one = I32F32::from_num(1);
+        let mut accountant = PldAccountant::new(one / 1024).unwrap();
+        assert!(accountant.compose(PldMechanism::Laplace { epsilon: one }, 1).is_err());
+
+        // 513 buckets fit, but their composition does not
+        let mut accountant = PldAccountant::new(one / 256).unwrap();
+        accountant.compose(PldMechanism::Laplace { epsilon: one }, 1).unwrap();
+        assert!(accountant.compose(PldMechanism::Laplace { epsilon: one }, 1).is_err());
+    }
+
+    #[test]
+    fn amplification_matches_the_published_bound() {
+        // ln(1 + (e - 1) / (e + 1) * (8 sqrt(e ln(4 / delta)) / sqrt(n) + 8 e / n))
+        let delta = I32F32::from_num(0.000001);
+        assert_between(amplified_epsilon(I32F32::from_num(1), 10_000, delta).unwrap(), 0.2135, 0.2145);
+        assert_between(amplified_epsilon(I32F32::from_num(1), 100_000, delta).unwrap(), 0.0722, 0.0729);
+        // too few reports to amplify an epsilon of 3
+        assert_eq!(amplified_epsilon(I32F32::from_num(3), 100, delta).unwrap(), I32F32::from_num(3));
+    }
+
+    #[test]
+    fn amplification_out_of_range_is_an_error() {
+        let one = I32F32::from_num(1);
+        assert!(amplified_epsilon(one, u32::MAX, I32F32::from_num(0.000001)).is_err());
+        assert!(amplified_epsilon(one, 10_000, I32F32::from_bits(4)).is_err());
+        assert!(amplified_epsilon(one, 10_000, I32F32::from_num(0)).is_err());
+        assert!(amplified_epsilon(I32F32::from_num(0), 10_000, I32F32::from_num(0.000001)).is_err());
+    }
+}
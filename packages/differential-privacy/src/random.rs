@@ -1,10 +1,219 @@
+use alloc::vec::Vec;
 use rand::RngCore;
+#[cfg(feature = "std")]
+use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
+#[cfg(feature = "std")]
+use secret_toolkit::crypto::sha_256;
+use substrate_fixed::transcendental::ln;
 use substrate_fixed::types::{I32F32,I64F64};
 
-// returns a random fixed point number between 0..1 as I32F32
+use crate::{NoiseError, NoiseResult};
+
+/// Domain tags for the noise of the standard statistics, for use with `derive_subrng`
+pub const COUNT_DOMAIN: &[u8] = b"count";
+pub const SUM_DOMAIN: &[u8] = b"sum";
+pub const AVERAGE_DOMAIN: &[u8] = b"average";
+pub const RANGE_DOMAIN: &[u8] = b"range";
+pub const WEIGHTED_AVERAGE_DOMAIN: &[u8] = b"weighted-average";
+
+#[cfg(feature = "std")]
+const SUBRNG_PREFIX: &[u8] = b"secret-data-tools/subrng";
+
+// returns a random fixed point number in (0, 1] as I32F32. Zero is excluded so that the
+// logarithm of the result is always defined. The 2^32 possible values are evenly spaced
+// multiples of 2^-32, which I32F32 represents exactly.
 pub fn random_unit_interval(rng: &mut ChaChaRng) -> I32F32 {
-    let numerator = rng.next_u32();
-    let ratio = I64F64::from_num(numerator) / I64F64::from_num(u32::MAX);
+    unit_interval_from_u32(rng.next_u32())
+}
+
+fn unit_interval_from_u32(numerator: u32) -> I32F32 {
+    let ratio = I64F64::from_num(u64::from(numerator) + 1) / I64F64::from_num(1u64 << 32);
     I32F32::from_num(ratio)
-}
\ No newline at end of file
+}
+
+/// Returns a uniformly random integer in 0..bound, without the modulo bias of taking the
+/// remainder of a raw draw. `bound` must not be zero.
+pub fn random_below(rng: &mut ChaChaRng, bound: u64) -> u64 {
+    assert!(bound > 0, "bound must not be zero");
+    // draws below the threshold would make the smallest remainders more likely
+    let threshold = bound.wrapping_neg() % bound;
+    loop {
+        let draw = rng.next_u64();
+        if draw >= threshold {
+            return draw % bound;
+        }
+    }
+}
+
+/// Shuffles `items` in place with the Fisher-Yates algorithm, so every ordering is equally likely
+pub fn shuffle<T>(rng: &mut ChaChaRng, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = random_below(rng, i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Returns the numbers 0..n in a uniformly random order
+pub fn random_permutation(rng: &mut ChaChaRng, n: usize) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..n).collect();
+    shuffle(rng, &mut permutation);
+    permutation
+}
+
+/// Draws an index with the given probabilities, which should sum to 1. If rounding leaves the
+/// sum just below 1, the shortfall goes to the most likely index, so an index with probability
+/// 0 is never drawn.
+pub fn sample_distribution(rng: &mut ChaChaRng, probabilities: &[I64F64]) -> usize {
+    let u = I64F64::from_num(random_unit_interval(rng));
+    let mut cumulative = I64F64::from_num(0);
+    for (index, probability) in probabilities.iter().enumerate() {
+        cumulative += *probability;
+        if u <= cumulative {
+            return index;
+        }
+    }
+    probabilities
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, probability)| **probability)
+        .map_or(0, |(index, _)| index)
+}
+
+/// Picks `k` distinct indices of `weights` at random, where each pick takes one of the remaining
+/// indices with probability proportional to its weight, as in drawing raffle tickets without
+/// putting them back. The indices are returned in the order they were picked. Items with
+/// weight 0 are never picked.
+///
+/// Uses exponential ranks (Efraimidis and Spirakis, 2006): each item gets the key -ln(u) / w
+/// for a uniform u, and the `k` smallest keys win, so one draw per item is enough.
+///
+/// Returns an error if a weight is negative or fewer than `k` weights are positive.
+pub fn weighted_sample(rng: &mut ChaChaRng, weights: &[I32F32], k: usize) -> NoiseResult<Vec<usize>> {
+    if weights.iter().any(|weight| *weight < 0) {
+        return Err(NoiseError("Weights must not be negative"));
+    }
+    if weights.iter().filter(|weight| **weight > 0).count() < k {
+        return Err(NoiseError("Fewer than k items have positive weight"));
+    }
+    let mut keys: Vec<(I64F64, usize)> = Vec::with_capacity(weights.len());
+    for (index, weight) in weights.iter().enumerate() {
+        // draw for every item, so the number of draws does not depend on the weights
+        let log_u = ln::<I64F64, I64F64>(I64F64::from_num(random_unit_interval(rng)))
+            .map_err(|_| NoiseError("Logarithm of uniform draw is undefined"))?;
+        if *weight > 0 {
+            keys.push((-log_u / I64F64::from_num(*weight), index));
+        }
+    }
+    keys.sort_unstable();
+    Ok(keys.into_iter().take(k).map(|(_, index)| index).collect())
+}
+
+/// Derives an independent `ChaChaRng` from a 32-byte seed, e.g. the block random seed, and a
+/// domain tag. Streams with different tags are unrelated, so draws for different statistics or
+/// stores do not line up even when every one of them starts from the same seed, as they would
+/// if each query seeded its own `ChaChaRng` with the seed directly. The same seed and tag
+/// always give the same stream.
+#[cfg(feature = "std")]
+pub fn derive_subrng(rng_seed: &[u8; 32], domain_tag: &[u8]) -> ChaChaRng {
+    ChaChaRng::from_seed(sha_256(&[SUBRNG_PREFIX, rng_seed, domain_tag].concat()))
+}
+
+/// Draws a fresh seed for `derive_subrng` from `rng`, so that every query on a shared `rng`
+/// derives its own streams
+#[cfg(feature = "std")]
+pub(crate) fn draw_seed(rng: &mut ChaChaRng) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    seed
+}
+
+/// Derives one `ChaChaRng` per domain tag, in the order given, see `derive_subrng`
+#[cfg(feature = "std")]
+pub fn derive_subrngs(rng_seed: &[u8; 32], domain_tags: &[&[u8]]) -> Vec<ChaChaRng> {
+    domain_tags.iter().map(|tag| derive_subrng(rng_seed, tag)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn unit_interval_excludes_zero_and_includes_one() {
+        assert_eq!(unit_interval_from_u32(0), I32F32::from_bits(1));
+        assert_eq!(unit_interval_from_u32(u32::MAX), I32F32::from_num(1));
+        assert_eq!(unit_interval_from_u32(1 << 31).to_bits(), (1 << 31) + 1);
+    }
+
+    #[test]
+    fn permutations_are_uniform() {
+        let mut rng = ChaChaRng::from_seed([4; 32]);
+        let permutation = random_permutation(&mut rng, 50);
+        let mut sorted = permutation.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..50).collect::<Vec<usize>>());
+        assert!(random_permutation(&mut rng, 0).is_empty());
+
+        // each of the 6 orderings of 3 items comes up about 1000 times in 6000 shuffles
+        let mut counts = [0u32; 6];
+        for _ in 0..6000 {
+            let mut items = [0u8, 1, 2];
+            shuffle(&mut rng, &mut items);
+            let index = usize::from(items[0]) * 2 + usize::from(items[1] > items[2]);
+            counts[index] += 1;
+        }
+        assert!(counts.iter().all(|count| (850..1150).contains(count)), "{:?}", counts);
+    }
+
+    #[test]
+    fn weighted_sample_follows_weights() {
+        let mut rng = ChaChaRng::from_seed([6; 32]);
+        let weights = [I32F32::from_num(1), I32F32::from_num(0), I32F32::from_num(3), I32F32::from_num(0.5)];
+        let mut counts = [0u32; 4];
+        for _ in 0..9000 {
+            counts[weighted_sample(&mut rng, &weights, 1).unwrap()[0]] += 1;
+        }
+        // expected 2000, 0, 6000 and 1000
+        assert_eq!(counts[1], 0);
+        assert!((1850..2150).contains(&counts[0]), "{:?}", counts);
+        assert!((5800..6200).contains(&counts[2]), "{:?}", counts);
+        assert!((900..1100).contains(&counts[3]), "{:?}", counts);
+
+        let mut picked = weighted_sample(&mut rng, &weights, 3).unwrap();
+        picked.sort_unstable();
+        assert_eq!(picked, vec![0, 2, 3]);
+        assert!(weighted_sample(&mut rng, &weights, 4).is_err());
+        assert!(weighted_sample(&mut rng, &[I32F32::from_num(-1)], 0).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn subrngs_are_domain_separated() {
+        let seed = [9; 32];
+        let mut count_rng = derive_subrng(&seed, COUNT_DOMAIN);
+        let mut sum_rng = derive_subrng(&seed, SUM_DOMAIN);
+        let count_draws: Vec<u32> = (0..8).map(|_| count_rng.next_u32()).collect();
+        let sum_draws: Vec<u32> = (0..8).map(|_| sum_rng.next_u32()).collect();
+        assert_ne!(count_draws, sum_draws);
+
+        // the same seed and tag give the same stream, and it is not the stream of the raw seed
+        let mut rngs = derive_subrngs(&seed, &[COUNT_DOMAIN, AVERAGE_DOMAIN]);
+        assert_eq!((0..8).map(|_| rngs[0].next_u32()).collect::<Vec<u32>>(), count_draws);
+        let mut raw = ChaChaRng::from_seed(seed);
+        assert_ne!((0..8).map(|_| raw.next_u32()).collect::<Vec<u32>>(), count_draws);
+    }
+
+    #[test]
+    fn shortfall_goes_to_the_most_likely_index() {
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        // sums to 3/4, so a quarter of the draws fall past the end
+        let probabilities = [I64F64::from_num(0.25), I64F64::from_num(0.5), I64F64::from_num(0)];
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            counts[sample_distribution(&mut rng, &probabilities)] += 1;
+        }
+        assert_eq!(counts[2], 0);
+        assert!(counts[1] > 2 * counts[0], "counts {:?}", counts);
+    }
+}
@@ -0,0 +1,270 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::exp;
+
+use crate::{random_unit_interval, CachedField, FieldValue};
+
+const TOTAL_KEY: &[u8] = b"total";
+const BIT_KEY: &[u8] = b"bit";
+const EPSILON_KEY: &[u8] = b"epsilon";
+
+// Basic one-time RAPPOR (Erlingsson et al., 2014) for local DP over k categories. A category is
+// one-hot encoded into k bits and every bit is kept with probability p = e^(epsilon / 2) /
+// (1 + e^(epsilon / 2)) or flipped otherwise. Changing the category changes two bits, so the
+// report is epsilon-local DP.
+
+/// Smallest epsilon of a RAPPOR report, 1/128. Closer to zero, p - q rounds towards zero in
+/// I32F32 and the estimates cannot be decoded.
+pub const RAPPOR_MIN_EPSILON: I32F32 = I32F32::from_bits(1 << 25);
+
+/// Largest epsilon of a RAPPOR report. e^(epsilon / 2) no longer fits in I32F32 above about 43.
+pub const RAPPOR_MAX_EPSILON: I32F32 = I32F32::from_bits(40 << 32);
+
+fn check_rappor_epsilon(epsilon: I32F32) -> StdResult<()> {
+    if epsilon < RAPPOR_MIN_EPSILON || epsilon > RAPPOR_MAX_EPSILON {
+        return Err(StdError::generic_err("Epsilon is outside the range of RAPPOR"));
+    }
+    Ok(())
+}
+
+/// Probability that a bit of a RAPPOR report is kept
+fn keep_probability(epsilon: I32F32) -> StdResult<I32F32> {
+    check_rappor_epsilon(epsilon)?;
+    let half_exp = exp::<I32F32, I32F32>(epsilon / 2)
+        .map_err(|_| StdError::generic_err("Epsilon too large"))?;
+    Ok(half_exp / (I32F32::from_num(1) + half_exp))
+}
+
+/// Encodes `category` in `0..k` as a RAPPOR report of `k` randomized bits. Epsilon must be
+/// between `RAPPOR_MIN_EPSILON` and `RAPPOR_MAX_EPSILON`.
+pub fn rappor_encode(
+    rng: &mut ChaChaRng,
+    category: u16,
+    k: u16,
+    epsilon: I32F32,
+) -> StdResult<Vec<bool>> {
+    if category >= k {
+        return Err(StdError::generic_err("Category out of range"));
+    }
+    let keep = keep_probability(epsilon)?;
+    Ok((0..k)
+        .map(|bit| {
+            let value = bit == category;
+            if random_unit_interval(rng) < keep {
+                value
+            } else {
+                !value
+            }
+        })
+        .collect())
+}
+
+/// Accumulates RAPPOR reports and estimates how many users are in each category. Only the
+/// number of reports with each bit set is stored.
+pub struct RapporStore<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// number of categories
+    categories: u16,
+    total: CachedField<u32>,
+    epsilon: CachedField<I32F32>,
+}
+
+impl<'a> RapporStore<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        categories: u16,
+    ) -> Self {
+        assert!(categories > 0, "Number of categories must be greater than zero");
+        Self {
+            namespace,
+            prefix: None,
+            categories,
+            total: CachedField::new(TOTAL_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+
+    /// This is used to produce a new RapporStore. It can be used when you want to associate
+    /// a RapporStore to multiple suffixes and you still want to define the RapporStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            categories: self.categories,
+            total: CachedField::new(TOTAL_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+        }
+    }
+}
+
+impl<'a> RapporStore<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn bit_key(&self, bit: u16) -> Vec<u8> {
+        [self.as_slice(), BIT_KEY, &bit.to_be_bytes()].concat()
+    }
+
+    pub fn get_categories(&self) -> u16 {
+        self.categories
+    }
+
+    /// Returns the number of reports received
+    pub fn get_total(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn get_bit_count(
+        &self,
+        storage: &dyn Storage,
+        bit: u16,
+    ) -> StdResult<u32> {
+        match storage.get(&self.bit_key(bit)) {
+            Some(count_vec) => u32::from_stored(&count_vec),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the epsilon that clients encode their reports with
+    pub fn get_epsilon(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I32F32> {
+        self.epsilon.may_load(storage, self.as_slice())?.ok_or(
+            StdError::generic_err("Epsilon not set")
+        )
+    }
+
+    /// Set the epsilon that clients encode their reports with, between `RAPPOR_MIN_EPSILON` and
+    /// `RAPPOR_MAX_EPSILON`. It can only be set before the first report, since the decoder
+    /// assumes every report used the same epsilon.
+    pub fn set_epsilon(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+    ) -> StdResult<()> {
+        if self.get_total(storage)? > 0 {
+            return Err(StdError::generic_err("Cannot change epsilon after reports have been added"));
+        }
+        check_rappor_epsilon(epsilon)?;
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
+    }
+
+    /// Adds a report from `rappor_encode`
+    pub fn add_report(&self, storage: &mut dyn Storage, report: &[bool]) -> StdResult<()> {
+        self.get_epsilon(storage)?;
+        if report.len() != usize::from(self.categories) {
+            return Err(StdError::generic_err("Report length does not match number of categories"));
+        }
+        let new_total = self.get_total(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        for (bit, is_set) in (0..self.categories).zip(report.iter()) {
+            if *is_set {
+                let count = self.get_bit_count(storage, bit)?;
+                storage.set(&self.bit_key(bit), &(count + 1).to_be_bytes());
+            }
+        }
+        self.total.save(storage, self.as_slice(), new_total);
+        Ok(())
+    }
+
+    /// Unbiased estimates of the number of users in each category,
+    /// (bit count - total * q) / (p - q) where q = 1 - p is the flip probability. Estimates can
+    /// be negative for rare categories. An estimate that does not fit in I32F32 is an error.
+    pub fn estimate_counts(&self, storage: &dyn Storage) -> StdResult<Vec<I32F32>> {
+        let total = self.get_total(storage)?;
+        if total == 0 {
+            return Err(StdError::generic_err("No data to count"));
+        }
+        let p = I64F64::from_num(keep_probability(self.get_epsilon(storage)?)?);
+        let q = I64F64::from_num(1) - p;
+        let expected_flips = I64F64::from_num(total) * q;
+        (0..self.categories)
+            .map(|bit| {
+                let count = I64F64::from_num(self.get_bit_count(storage, bit)?);
+                (count - expected_flips)
+                    .checked_div(p - q)
+                    .and_then(I32F32::checked_from_num)
+                    .ok_or(StdError::generic_err("Estimated count overflowed"))
+            })
+            .collect()
+    }
+
+    /// Estimated fraction of users in each category, with negative estimates set to zero
+    pub fn estimate_frequencies(&self, storage: &dyn Storage) -> StdResult<Vec<I32F32>> {
+        let total = I32F32::from_num(self.get_total(storage)?);
+        Ok(self.estimate_counts(storage)?
+            .into_iter()
+            .map(|count| (count / total).max(I32F32::from_num(0)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn extreme_epsilons_are_errors_instead_of_panics() {
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        let mut storage = MockStorage::new();
+        let store = RapporStore::new(b"rappor", 4);
+        for epsilon in [I32F32::from_num(0), I32F32::from_bits(1), I32F32::from_num(44), I32F32::MAX] {
+            assert!(rappor_encode(&mut rng, 1, 4, epsilon).is_err());
+            assert!(store.set_epsilon(&mut storage, epsilon).is_err());
+        }
+        assert!(rappor_encode(&mut rng, 1, 4, RAPPOR_MIN_EPSILON).is_ok());
+        assert!(rappor_encode(&mut rng, 1, 4, RAPPOR_MAX_EPSILON).is_ok());
+    }
+
+    #[test]
+    fn estimates_that_do_not_fit_are_errors() {
+        let mut storage = MockStorage::new();
+        let store = RapporStore::new(b"rappor", 2);
+        store.set_epsilon(&mut storage, RAPPOR_MIN_EPSILON).unwrap();
+        // p - q is about epsilon / 4, so the estimate scales the counts by about 512
+        store.total.save(&mut storage, store.as_slice(), 2_000_000_000);
+        storage.set(&store.bit_key(0), &2_000_000_000u32.to_be_bytes());
+        assert!(store.estimate_counts(&storage).is_err());
+    }
+
+    #[test]
+    fn reports_estimate_the_counts() {
+        let mut storage = MockStorage::new();
+        let store = RapporStore::new(b"rappor", 3);
+        let epsilon = I32F32::from_num(4);
+        store.set_epsilon(&mut storage, epsilon).unwrap();
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        for i in 0..2000u32 {
+            let category = if i % 2 == 0 { 0 } else { (i % 4 / 2 + 1) as u16 };
+            let report = rappor_encode(&mut rng, category, 3, epsilon).unwrap();
+            store.add_report(&mut storage, &report).unwrap();
+        }
+        let frequencies = store.estimate_frequencies(&storage).unwrap();
+        assert!((frequencies[0] - I32F32::from_num(0.5)).abs() < I32F32::from_num(0.05), "{frequencies:?}");
+        assert!((frequencies[1] + frequencies[2] - I32F32::from_num(0.5)).abs() < I32F32::from_num(0.05));
+        assert!(store.set_epsilon(&mut storage, I32F32::from_num(2)).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use secret_toolkit::crypto::sha_256;
+
+const EXACT_KEY: &[u8] = b"exact";
+const COVERED_KEY: &[u8] = b"covered";
+/// recorded for a prefix that is extended by namespaces of more than one store type
+const MIXED: &[u8] = b"";
+
+/// Record of the namespaces used by the stores of a contract. Stores append their keys directly
+/// to their namespace, so two stores whose namespaces are equal, or where one is a prefix of the
+/// other, can silently overwrite each other's data. Registering each namespace when its store is
+/// initialized turns such a collision between two different store types into an error.
+///
+/// Namespaces of the same store type may overlap, since that is how `add_suffix` derives the
+/// namespace of each suffixed store. Registering the same namespace again with the same type is
+/// allowed, so a store can be re-initialized.
+pub struct NamespaceRegistry<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+}
+
+impl<'a> NamespaceRegistry<'a> {
+    /// constructor
+    pub const fn new(namespace: &'a [u8]) -> Self {
+        Self { namespace }
+    }
+
+    fn exact_key(&self, prefix: &[u8]) -> Vec<u8> {
+        [self.namespace, EXACT_KEY, &sha_256(prefix)].concat()
+    }
+
+    fn covered_key(&self, prefix: &[u8]) -> Vec<u8> {
+        [self.namespace, COVERED_KEY, &sha_256(prefix)].concat()
+    }
+
+    /// Returns the store type registered for exactly `prefix`, if any
+    pub fn get_store_type(&self, storage: &dyn Storage, prefix: &[u8]) -> StdResult<Option<String>> {
+        storage
+            .get(&self.exact_key(prefix))
+            .map(|store_type| {
+                String::from_utf8(store_type).map_err(|_| StdError::generic_err("Invalid store type in registry"))
+            })
+            .transpose()
+    }
+
+    /// Records that a store of type `store_type` uses `prefix` as its namespace. Returns an error
+    /// if the prefix is empty, or if it equals, extends or is extended by a namespace registered
+    /// for a different store type.
+    pub fn register(&self, storage: &mut dyn Storage, store_type: &str, prefix: &[u8]) -> StdResult<()> {
+        if prefix.is_empty() {
+            return Err(StdError::generic_err("Store namespace must not be empty"));
+        }
+        if store_type.is_empty() {
+            return Err(StdError::generic_err("Store type must not be empty"));
+        }
+        let store_type = store_type.as_bytes();
+        let collision = |other: &[u8]| {
+            StdError::generic_err(format!(
+                "Namespace {:?} overlaps a namespace of store type {}",
+                prefix,
+                if other == MIXED { "(several)".to_string() } else { String::from_utf8_lossy(other).into_owned() },
+            ))
+        };
+
+        // a registered namespace that is a prefix of, or equal to, the new one
+        for len in 1..=prefix.len() {
+            if let Some(other) = storage.get(&self.exact_key(&prefix[..len])) {
+                if other != store_type {
+                    return Err(collision(&other));
+                }
+            }
+        }
+        // a registered namespace that extends the new one
+        if let Some(other) = storage.get(&self.covered_key(prefix)) {
+            if other != store_type {
+                return Err(collision(&other));
+            }
+        }
+
+        storage.set(&self.exact_key(prefix), store_type);
+        for len in 1..prefix.len() {
+            let key = self.covered_key(&prefix[..len]);
+            match storage.get(&key) {
+                Some(other) if other == store_type || other == MIXED => {}
+                Some(_) => storage.set(&key, MIXED),
+                None => storage.set(&key, store_type),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn overlapping_namespaces_of_different_types_are_rejected() {
+        let mut storage = MockStorage::new();
+        let registry = NamespaceRegistry::new(b"registry");
+        registry.register(&mut storage, "RunningStatsStore", b"stats").unwrap();
+        // re-initializing and suffixed stores of the same type
+        registry.register(&mut storage, "RunningStatsStore", b"stats").unwrap();
+        registry.register(&mut storage, "RunningStatsStore", b"stats\x00\x02eu").unwrap();
+        registry.register(&mut storage, "ReplayLog", b"replay").unwrap();
+
+        assert!(registry.register(&mut storage, "ReplayLog", b"stats").is_err());
+        assert!(registry.register(&mut storage, "ReplayLog", b"stats-log").is_err());
+        assert!(registry.register(&mut storage, "ReplayLog", b"stat").is_err());
+        assert!(registry.register(&mut storage, "RunningStatsStore", b"rep").is_err());
+        assert!(registry.register(&mut storage, "ReplayLog", b"").is_err());
+        assert_eq!(
+            registry.get_store_type(&storage, b"stats").unwrap(),
+            Some("RunningStatsStore".to_string()),
+        );
+        assert_eq!(registry.get_store_type(&storage, b"stat").unwrap(), None);
+    }
+}
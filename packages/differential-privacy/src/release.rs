@@ -0,0 +1,48 @@
+use cosmwasm_std::StdResult;
+
+// A release can be refused after it has been charged, e.g. when the noisy count falls short of
+// the minimum count for release: the check itself spends budget. On-chain, an error returned
+// from execute reverts every storage write of the transaction, the charge included, so the
+// refusal could be probed again for free. Charged refusals are therefore returned as
+// `Ok(Release::Refused)`, and contracts must let the transaction commit when they see one.
+
+/// Outcome of a fuzzy query that was charged, or that was refused in a way that may have been
+/// charged
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Release<T> {
+    /// the statistics were released and their cost was charged
+    Released(T),
+    /// nothing was released. Whatever was charged for the refusal, e.g. the minimum-count
+    /// check, is written to storage and is only kept if the transaction succeeds.
+    Refused,
+}
+
+impl<T> Release<T> {
+    /// Returns the released value, or None if the release was refused
+    pub fn released(self) -> Option<T> {
+        match self {
+            Release::Released(value) => Some(value),
+            Release::Refused => None,
+        }
+    }
+
+    pub fn is_refused(&self) -> bool {
+        matches!(self, Release::Refused)
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Release<U> {
+        match self {
+            Release::Released(value) => Release::Released(f(value)),
+            Release::Refused => Release::Refused,
+        }
+    }
+
+    /// Maps the released value with a function that can fail, e.g. to pick one statistic out
+    /// of a report. A refusal is passed through.
+    pub fn try_map<U>(self, f: impl FnOnce(T) -> StdResult<U>) -> StdResult<Release<U>> {
+        match self {
+            Release::Released(value) => Ok(Release::Released(f(value)?)),
+            Release::Refused => Ok(Release::Refused),
+        }
+    }
+}
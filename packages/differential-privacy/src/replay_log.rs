@@ -0,0 +1,322 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::crypto::sha_256;
+use substrate_fixed::types::I32F32;
+
+use crate::{CachedField, DpResult, FieldValue, NoiseSource, QueryKind};
+
+const LEN_KEY: &[u8] = b"len";
+const ENTRY_KEY: &[u8] = b"entry";
+const FINGERPRINT_PREFIX: &[u8] = b"secret-data-tools/replay";
+
+// fingerprint, stream, word position, data version, epsilon, noise source, then the average
+// sensitivity and the clipping range, each behind a flag byte
+const HEADER_LEN: usize = 32 + 8 + 16 + 8 + 8 + 1 + 1 + 8 + 1 + 8 + 8;
+// query kind, then one or two fixed point values
+const RESULT_LEN: usize = 1 + 8 + 8;
+
+/// Returns the fingerprint of a `ChaChaRng` at its current position: a hash of its seed, stream
+/// and word position. The fingerprint does not reveal the seed, but only the same seed at the same
+/// position gives the same fingerprint.
+pub fn rng_fingerprint(rng: &ChaChaRng) -> [u8; 32] {
+    sha_256(&[
+        FINGERPRINT_PREFIX,
+        &rng.get_seed(),
+        &rng.get_stream().to_be_bytes(),
+        &rng.get_word_pos().to_be_bytes(),
+    ].concat())
+}
+
+/// Record of one release: where the random number generator was when the noise was drawn, the
+/// parameters of the mechanism, including those the sensitivity is computed from, and the
+/// released statistics
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayEntry {
+    /// `rng_fingerprint` of the generator before the first noise value was drawn
+    pub seed_fingerprint: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+    /// data version of the store at the release
+    pub data_version: u64,
+    pub epsilon: I32F32,
+    pub noise_source: NoiseSource,
+    /// average sensitivity of the store at the release; the observed bounds follow from the data
+    /// version
+    pub avg_sensitivity: Option<I32F32>,
+    pub clipping: Option<(I32F32, I32F32)>,
+    pub queries: Vec<QueryKind>,
+    pub results: Vec<DpResult>,
+}
+
+impl ReplayEntry {
+    /// Rebuilds the generator used for this release from the seed it was created with, e.g. the
+    /// seed passed to `ChaChaRng::from_seed`. Returns an error if the seed does not match the
+    /// recorded fingerprint.
+    pub fn rng(&self, rng_seed: &[u8; 32]) -> StdResult<ChaChaRng> {
+        let mut rng = ChaChaRng::from_seed(*rng_seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        if rng_fingerprint(&rng) != self.seed_fingerprint {
+            return Err(StdError::generic_err("Seed does not match the release"));
+        }
+        Ok(rng)
+    }
+
+    fn to_stored(&self) -> Vec<u8> {
+        let noise_source: u8 = match self.noise_source {
+            NoiseSource::Laplace => 0,
+            NoiseSource::Staircase => 1,
+        };
+        let zero = I32F32::from_num(0);
+        let avg_sensitivity = self.avg_sensitivity.unwrap_or(zero);
+        let (lower, upper) = self.clipping.unwrap_or((zero, zero));
+        let mut bytes = [
+            self.seed_fingerprint.as_slice(),
+            &self.stream.to_be_bytes(),
+            &self.word_pos.to_be_bytes(),
+            &self.data_version.to_be_bytes(),
+            &self.epsilon.to_stored(),
+            &[noise_source],
+            &[u8::from(self.avg_sensitivity.is_some())],
+            &avg_sensitivity.to_stored(),
+            &[u8::from(self.clipping.is_some())],
+            &lower.to_stored(),
+            &upper.to_stored(),
+        ].concat();
+        for (query, result) in self.queries.iter().zip(&self.results) {
+            let kind: u8 = match query {
+                QueryKind::Count => 0,
+                QueryKind::Average => 1,
+                QueryKind::Range => 2,
+                QueryKind::WeightedAverage => 3,
+            };
+            let (first, second) = match result {
+                DpResult::Count(value) | DpResult::Average(value) | DpResult::WeightedAverage(value) => {
+                    (*value, I32F32::from_num(0))
+                }
+                DpResult::Range(min, max) => (*min, *max),
+            };
+            bytes.push(kind);
+            bytes.extend(first.to_stored());
+            bytes.extend(second.to_stored());
+        }
+        bytes
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        if bytes.len() < HEADER_LEN || (bytes.len() - HEADER_LEN) % RESULT_LEN != 0 {
+            return Err(StdError::generic_err("Invalid replay entry"));
+        }
+        let (header, results) = bytes.split_at(HEADER_LEN);
+        let mut seed_fingerprint = [0; 32];
+        seed_fingerprint.copy_from_slice(&header[..32]);
+        let noise_source = match header[72] {
+            0 => NoiseSource::Laplace,
+            1 => NoiseSource::Staircase,
+            _ => return Err(StdError::generic_err("Invalid replay entry")),
+        };
+        let avg_sensitivity = match header[73] {
+            0 => None,
+            1 => Some(I32F32::from_stored(&header[74..82])?),
+            _ => return Err(StdError::generic_err("Invalid replay entry")),
+        };
+        let clipping = match header[82] {
+            0 => None,
+            1 => Some((I32F32::from_stored(&header[83..91])?, I32F32::from_stored(&header[91..99])?)),
+            _ => return Err(StdError::generic_err("Invalid replay entry")),
+        };
+        let mut entry = ReplayEntry {
+            seed_fingerprint,
+            stream: u64::from_stored(&header[32..40])?,
+            word_pos: u128::from_be_bytes(header[40..56].try_into().map_err(|_| {
+                StdError::generic_err("Invalid replay entry")
+            })?),
+            data_version: u64::from_stored(&header[56..64])?,
+            epsilon: I32F32::from_stored(&header[64..72])?,
+            noise_source,
+            avg_sensitivity,
+            clipping,
+            queries: vec![],
+            results: vec![],
+        };
+        for record in results.chunks(RESULT_LEN) {
+            let first = I32F32::from_stored(&record[1..9])?;
+            let second = I32F32::from_stored(&record[9..])?;
+            let (query, result) = match record[0] {
+                0 => (QueryKind::Count, DpResult::Count(first)),
+                1 => (QueryKind::Average, DpResult::Average(first)),
+                2 => (QueryKind::Range, DpResult::Range(first, second)),
+                3 => (QueryKind::WeightedAverage, DpResult::WeightedAverage(first)),
+                _ => return Err(StdError::generic_err("Invalid replay entry")),
+            };
+            entry.queries.push(query);
+            entry.results.push(result);
+        }
+        Ok(entry)
+    }
+}
+
+/// Append-only log of releases, kept so that a dispute about a released statistic can be settled
+/// inside the enclave: given the seed of the release, `RunningStatsStore::replay_release`
+/// recomputes the statistics and compares them with the ones that were released. Only a
+/// fingerprint of the seed is stored, so the log does not help anyone remove the noise.
+pub struct ReplayLog<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    length: CachedField<u32>,
+}
+
+impl<'a> ReplayLog<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+    ) -> Self {
+        Self {
+            namespace,
+            prefix: None,
+            length: CachedField::new(LEN_KEY),
+        }
+    }
+
+    /// This is used to produce a new ReplayLog. It can be used when you want to associate
+    /// a ReplayLog to multiple suffixes and you still want to define the ReplayLog
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            length: CachedField::new(LEN_KEY),
+        }
+    }
+}
+
+impl<'a> ReplayLog<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn entry_key(&self, pos: u32) -> Vec<u8> {
+        [self.as_slice(), ENTRY_KEY, &pos.to_be_bytes()].concat()
+    }
+
+    pub fn get_len(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.length.load_or(storage, self.as_slice(), 0)
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_len(storage)? == 0)
+    }
+
+    /// Appends a release to the end of the log and returns its position
+    pub fn push(&self, storage: &mut dyn Storage, entry: &ReplayEntry) -> StdResult<u32> {
+        let length = self.get_len(storage)?;
+        let new_length = length.checked_add(1).ok_or(
+            StdError::generic_err("Replay log overflow")
+        )?;
+        storage.set(&self.entry_key(length), &entry.to_stored());
+        self.length.save(storage, self.as_slice(), new_length);
+        Ok(length)
+    }
+
+    /// Returns the release at position `pos`
+    pub fn get_at(&self, storage: &dyn Storage, pos: u32) -> StdResult<ReplayEntry> {
+        if pos >= self.get_len(storage)? {
+            return Err(StdError::generic_err("Replay log index out of bounds"));
+        }
+        match storage.get(&self.entry_key(pos)) {
+            Some(entry) => ReplayEntry::from_stored(&entry),
+            None => Err(StdError::generic_err("Replay log entry missing from storage")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::RngCore;
+    use crate::{ResetPolicy, RunningStatsStatus, RunningStatsStore};
+
+    #[test]
+    fn releases_can_be_replayed() {
+        let mut storage = MockStorage::new();
+        let log = ReplayLog::new(b"replay");
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_replay_log(&log);
+        store.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), Some(I32F32::from_num(10)), Some(I32F32::from_num(10)))).unwrap();
+        for x in [3, 5, 8] {
+            store.add_observation(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let seed = [21; 32];
+        let mut rng = ChaChaRng::from_seed(seed);
+        // a generator that has already been used for something else
+        rng.next_u64();
+        let queries = [QueryKind::Count, QueryKind::Average];
+        let released = store.fuzzy_report(&mut storage, &mut rng, &queries).unwrap().released().unwrap();
+        store.fuzzy_report(&mut storage, &mut rng, &[QueryKind::Count]).unwrap().released().unwrap();
+
+        assert_eq!(log.get_len(&storage).unwrap(), 2);
+        let entry = log.get_at(&storage, 0).unwrap();
+        assert_eq!(entry.queries, queries);
+        assert_eq!(entry.results, released);
+        assert!(store.replay_release(&storage, &seed, 0).unwrap());
+        assert!(store.replay_release(&storage, &seed, 1).unwrap());
+        assert!(store.replay_release(&storage, &[22; 32], 0).is_err());
+    }
+
+    #[test]
+    fn entries_keep_the_sensitivity() {
+        let entry = ReplayEntry {
+            seed_fingerprint: [3; 32],
+            stream: 1,
+            word_pos: 2,
+            data_version: 3,
+            epsilon: I32F32::from_num(1),
+            noise_source: NoiseSource::Staircase,
+            avg_sensitivity: Some(I32F32::from_num(4)),
+            clipping: Some((I32F32::from_num(-2), I32F32::from_num(5))),
+            queries: vec![QueryKind::Range],
+            results: vec![DpResult::Range(I32F32::from_num(-1), I32F32::from_num(3))],
+        };
+        assert_eq!(ReplayEntry::from_stored(&entry.to_stored()).unwrap(), entry);
+        let entry = ReplayEntry { avg_sensitivity: None, clipping: None, ..entry };
+        assert_eq!(ReplayEntry::from_stored(&entry.to_stored()).unwrap(), entry);
+    }
+
+    #[test]
+    fn changed_sensitivity_is_reported_instead_of_a_mismatch() {
+        let mut storage = MockStorage::new();
+        let log = ReplayLog::new(b"replay");
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_replay_log(&log);
+        store.reset(&mut storage, ResetPolicy::new(Some(I32F32::from_num(1)), Some(I32F32::from_num(10)), Some(I32F32::from_num(10)))).unwrap();
+        for x in [3, 5, 8] {
+            store.add_observation(&mut storage, I32F32::from_num(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let seed = [21; 32];
+        let mut rng = ChaChaRng::from_seed(seed);
+        store.fuzzy_report(&mut storage, &mut rng, &[QueryKind::Average]).unwrap().released().unwrap();
+        assert_eq!(log.get_at(&storage, 0).unwrap().avg_sensitivity, Some(I32F32::from_num(10)));
+
+        store.set_average_sensitivity(&mut storage, Some(I32F32::from_num(20))).unwrap();
+        assert!(store.replay_release(&storage, &seed, 0).is_err());
+        store.set_average_sensitivity(&mut storage, Some(I32F32::from_num(10))).unwrap();
+        assert!(store.replay_release(&storage, &seed, 0).unwrap());
+    }
+}
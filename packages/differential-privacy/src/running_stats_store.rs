@@ -1,11 +1,16 @@
-use std::{sync::Mutex, marker::PhantomData};
+use std::marker::PhantomData;
 use cosmwasm_std::{Storage, StdResult, StdError};
 use cosmwasm_storage::to_length_prefixed;
 use rand_chacha::ChaChaRng;
-use secret_toolkit::serialization::{Serde, Bincode2};
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use secret_toolkit::{serialization::{Serde, Bincode2}, crypto::sha_256};
 use substrate_fixed::types::{I32F32, I64F64};
+use substrate_fixed::transcendental::{exp, ln};
 
-use crate::laplace;
+#[cfg(feature = "constant_time")]
+use crate::{charged_cost, NoiseResult};
+use crate::{add_count_noise, derive_subrng, AVERAGE_DOMAIN, COUNT_DOMAIN, RANGE_DOMAIN, SUM_DOMAIN, WEIGHTED_AVERAGE_DOMAIN, draw_seed, min_count_gate_cost, noisy_count_reaches, CachedField, NoiseSource, Release, StoredDpResult, FieldValue, PrivacyAccountant, ReplayEntry, ReplayLog, rng_fingerprint, BoundHeap, HeapOrder, BoundHistogram, HistogramBins, SuffixIndex};
 
 const COUNT_KEY: &[u8] = b"count";
 const SUM_KEY: &[u8] = b"sum";
@@ -14,17 +19,572 @@ const LOWER_BOUND_KEY: &[u8] = b"lb";
 const EPSILON_KEY: &[u8] = b"epsilon";
 const SENSITIVITY_FOR_AVG_KEY: &[u8] = b"a-sen";
 const PRIVACY_BUDGET_KEY: &[u8] = b"budget";
+const MIN_COUNT_KEY: &[u8] = b"min-count";
+const COOLDOWN_KEY: &[u8] = b"cooldown";
+const LAST_QUERY_KEY: &[u8] = b"last-query";
+const RESULT_CACHE_KEY: &[u8] = b"cached";
+const DATA_VERSION_KEY: &[u8] = b"version";
 const STATUS_KEY: &[u8] = b"status";
+const CONTRIBUTOR_KEY: &[u8] = b"contrib";
+const MIN_HEAP_KEY: &[u8] = b"min-heap";
+const MAX_HEAP_KEY: &[u8] = b"max-heap";
+const HISTOGRAM_KEY: &[u8] = b"hist";
+const SUFFIXES_KEY: &[u8] = b"suffixes";
+const INITIALIZED_KEY: &[u8] = b"init";
+const WEIGHT_SUM_KEY: &[u8] = b"w-sum";
+const WEIGHTED_SUM_KEY: &[u8] = b"wx-sum";
+const MAX_QUERIES_KEY: &[u8] = b"max-queries";
+const QUERY_COUNT_KEY: &[u8] = b"queries";
+const DEADLINE_KEY: &[u8] = b"deadline";
+const RATE_LIMIT_KEY: &[u8] = b"rate-limit";
+const RATE_EPOCH_KEY: &[u8] = b"rate-epoch";
+const RATE_COUNT_KEY: &[u8] = b"rate-count";
+const RATE_SALT_KEY: &[u8] = b"rate-salt";
+const MIN_EPSILON_KEY: &[u8] = b"min-eps";
+const MAX_EPSILON_KEY: &[u8] = b"max-eps";
+const MAX_DELTA_KEY: &[u8] = b"max-delta";
+const MAX_BUDGET_KEY: &[u8] = b"max-budget";
+const DELTA_SPENT_KEY: &[u8] = b"delta-spent";
+const REJECTED_KEY: &[u8] = b"rejected";
+const CLAMPED_KEY: &[u8] = b"clamped";
+const COMMITMENT_KEY: &[u8] = b"commitment";
+const COMMITMENT_SALT_KEY: &[u8] = b"commit-salt";
+const FROZEN_KEY: &[u8] = b"frozen";
 
+/// Lifecycle of a store. Observations are only accepted while `CollectingData` and statistics
+/// are only released while `CalculatingStats`.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RunningStatsStatus {
     CollectingData,
-    CalculatingStats
+    CalculatingStats,
+    /// observations are rejected for now; set `CollectingData` to resume
+    Paused,
+    /// final: no further observations or queries, and the privacy budget is frozen
+    Closed,
 }
 
+impl RunningStatsStatus {
+    /// Checks that a store may move from this status to `next`. Collection can be paused and
+    /// resumed, and a store can move on to calculating stats from either, but never back.
+    /// Any status can be closed, and a closed store stays closed.
+    pub fn check_transition(self, next: RunningStatsStatus) -> StdResult<()> {
+        use RunningStatsStatus::*;
+        let allowed = match (self, next) {
+            (current, next) if current == next => true,
+            (Closed, _) => false,
+            (_, Closed) => true,
+            (CollectingData, _) | (Paused, _) => true,
+            (CalculatingStats, _) => false,
+        };
+        if allowed {
+            Ok(())
+        } else if (self, next) == (CalculatingStats, CollectingData) {
+            Err(StdError::generic_err("Cannot set status to collecting data after changing to calculating stats"))
+        } else {
+            Err(StdError::generic_err(format!("Cannot change status from {:?} to {:?}", self, next)))
+        }
+    }
+}
+
+/// Statistic requested from `fuzzy_report`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryKind {
+    Count,
+    Average,
+    Range,
+    WeightedAverage,
+}
+
+/// Statistic released by `fuzzy_report`, in the same order as the requested `QueryKind`s
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpResult {
+    Count(I32F32),
+    Average(I32F32),
+    Range(I32F32, I32F32),
+    WeightedAverage(I32F32),
+}
+
+/// Released statistics and the privacy parameters they were released under, frozen under a
+/// label by `freeze_snapshot`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FrozenSnapshot {
+    pub data_version: u64,
+    /// statistics that had been released at `data_version`, in the same order as `results`
+    pub queries: Vec<QueryKind>,
+    pub results: Vec<StoredDpResult>,
+    pub params: DpParamsResponse,
+}
+
+/// Noise streams of one release, one per statistic, derived from a seed drawn from the caller's
+/// rng. The noise of one statistic never shares draws with another, however many statistics or
+/// stores the caller's rng is used for.
+struct StatisticRngs {
+    count: ChaChaRng,
+    sum: ChaChaRng,
+    /// the count that an average is divided by
+    average: ChaChaRng,
+    range: ChaChaRng,
+    weighted_average: ChaChaRng,
+}
+
+impl StatisticRngs {
+    fn draw(rng: &mut ChaChaRng) -> Self {
+        let seed = draw_seed(rng);
+        Self {
+            count: derive_subrng(&seed, COUNT_DOMAIN),
+            sum: derive_subrng(&seed, SUM_DOMAIN),
+            average: derive_subrng(&seed, AVERAGE_DOMAIN),
+            range: derive_subrng(&seed, RANGE_DOMAIN),
+            weighted_average: derive_subrng(&seed, WEIGHTED_AVERAGE_DOMAIN),
+        }
+    }
+}
+
+/// Noisy answers and budget state drawn by a constant-work release before anything is written
+#[cfg(feature = "constant_time")]
+struct DrawnReport {
+    results: Vec<DpResult>,
+    allowed: bool,
+    /// cost of the release, including the minimum-count check
+    privacy_cost: I32F32,
+    /// cost of the minimum-count check alone
+    gate_cost: I32F32,
+    /// whether the release was refused by the minimum-count check alone, which is then charged
+    gate_charged: bool,
+    privacy_budget: I32F32,
+    total_queries: u32,
+}
+
+/// Transformation applied to observations when they are added, see `with_transform`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transform {
+    #[default]
+    None,
+    /// a * x + b, with a different from zero. The sensitivity of the sum scales by |a|.
+    Affine { a: I32F32, b: I32F32 },
+    /// ln(1 + x), for x greater than -1. Clipping to [ln(1 + lower), ln(1 + upper)] gives the
+    /// sum a sensitivity of ln(1 + upper) - ln(1 + lower), and the released average becomes a
+    /// geometric mean of 1 + x, minus 1, which is far less sensitive to a few huge values.
+    Log1p,
+}
+
+impl Transform {
+    /// Maps an observation into the transformed domain
+    pub fn apply(&self, x: I32F32) -> StdResult<I32F32> {
+        match self {
+            Transform::None => Ok(x),
+            Transform::Affine { a, b } => a
+                .checked_mul(x)
+                .and_then(|ax| ax.checked_add(*b))
+                .ok_or(StdError::generic_err("Transformed observation overflowed")),
+            Transform::Log1p => {
+                let one_plus_x = I32F32::from_num(1).checked_add(x).ok_or(
+                    StdError::generic_err("Transformed observation overflowed")
+                )?;
+                if one_plus_x <= 0 {
+                    return Err(StdError::generic_err("Log1p transform requires observations greater than -1"));
+                }
+                ln::<I32F32, I32F32>(one_plus_x).map_err(|_| StdError::generic_err("Logarithm is undefined"))
+            }
+        }
+    }
+
+    /// Maps a released value back from the transformed domain. Released values carry noise and
+    /// can land far outside the range of any observation, so the result saturates at the ends
+    /// of I32F32 instead of failing: an error at this point would revert the charge for a
+    /// release whose noise has already been drawn, and so give a free retry.
+    pub fn invert(&self, y: I32F32) -> I32F32 {
+        match self {
+            Transform::None => y,
+            Transform::Affine { a, b } => {
+                let shifted = I64F64::from_num(y) - I64F64::from_num(*b);
+                match shifted.checked_div(I64F64::from_num(*a)) {
+                    Some(x) => I32F32::saturating_from_num(x),
+                    None if (shifted < 0) != (*a < 0) => I32F32::MIN,
+                    None => I32F32::MAX,
+                }
+            }
+            Transform::Log1p => {
+                // e^y is below the resolution of I32F32 well before -32
+                if y < I32F32::from_num(-32) {
+                    return I32F32::from_num(-1);
+                }
+                match exp::<I32F32, I32F32>(y) {
+                    Ok(exp_y) => exp_y - I32F32::from_num(1),
+                    Err(_) if y < 0 => I32F32::from_num(-1),
+                    Err(_) => I32F32::MAX,
+                }
+            }
+        }
+    }
+
+    /// Maps a released statistic back from the transformed domain. Counts are unchanged, and
+    /// the ends of a range are swapped if the transform is decreasing.
+    fn invert_result(&self, result: DpResult) -> DpResult {
+        match result {
+            DpResult::Count(count) => DpResult::Count(count),
+            DpResult::Average(average) => DpResult::Average(self.invert(average)),
+            DpResult::WeightedAverage(average) => DpResult::WeightedAverage(self.invert(average)),
+            DpResult::Range(min, max) => {
+                let (min, max) = (self.invert(min), self.invert(max));
+                DpResult::Range(min.min(max), min.max(max))
+            }
+        }
+    }
+}
+
+/// What happens to an observation outside the clipping range, see `with_outlier_policy`.
+/// Without a clipping range every observation is accepted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutlierPolicy {
+    /// drop the observation and count it as rejected
+    Reject,
+    /// clamp the observation into the clipping range and count it as clamped
+    #[default]
+    Clamp,
+    /// add the observation as it is. The clipping range then no longer bounds the sensitivity,
+    /// so this cannot be combined with `SensitivityPolicy::Clipped`.
+    Accept,
+}
+
+/// An incoming observation after the transform and the outlier policy
+enum Ingested {
+    Kept(I32F32),
+    Clamped(I32F32),
+    Rejected,
+}
+
+/// Where the sensitivity of the sum comes from in average queries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensitivityPolicy {
+    /// use the average sensitivity given at initialization, which is then required
+    Fixed,
+    /// use the range between the observed lower and upper bounds, which must be non-empty
+    ObservedRange,
+    /// use the clipping range, which must be configured with `with_clipping`
+    Clipped,
+}
+
+// the values of the original two statuses are unchanged, so stores and snapshots written
+// before Paused and Closed existed read back as they were
 pub const STATUS_COLLECTING_DATA: u8 = 0;
 pub const STATUS_CALCULATING_STATS: u8 = 1;
+pub const STATUS_PAUSED: u8 = 2;
+pub const STATUS_CLOSED: u8 = 3;
+
+impl FieldValue for RunningStatsStatus {
+    fn to_stored(&self) -> Vec<u8> {
+        match self {
+            RunningStatsStatus::CollectingData => vec![STATUS_COLLECTING_DATA],
+            RunningStatsStatus::CalculatingStats => vec![STATUS_CALCULATING_STATS],
+            RunningStatsStatus::Paused => vec![STATUS_PAUSED],
+            RunningStatsStatus::Closed => vec![STATUS_CLOSED],
+        }
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        match u8::from_stored(bytes)? {
+            STATUS_COLLECTING_DATA => Ok(RunningStatsStatus::CollectingData),
+            STATUS_CALCULATING_STATS => Ok(RunningStatsStatus::CalculatingStats),
+            STATUS_PAUSED => Ok(RunningStatsStatus::Paused),
+            STATUS_CLOSED => Ok(RunningStatsStatus::Closed),
+            _ => Err(StdError::generic_err("Invalid u8 value for storage status")),
+        }
+    }
+}
+
+/// Exported state of a `RunningStatsStore`, with fixed point values as big-endian bytes. Bound
+/// heaps, bound histograms and contributor markers are not part of the snapshot.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RunningStatsSnapshot {
+    pub count: u32,
+    pub sum: Vec<u8>,
+    pub upper_bound: Vec<u8>,
+    pub lower_bound: Vec<u8>,
+    pub epsilon: Vec<u8>,
+    pub avg_sensitivity: Option<Vec<u8>>,
+    pub privacy_budget: Vec<u8>,
+    pub status: u8,
+    /// sum of weights and weighted sum, absent in snapshots taken before weights were supported
+    #[serde(default)]
+    pub weights: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Name of `RunningStatsSnapshot` used by `export_state` and `import_state`
+pub type StoredRunningStats = RunningStatsSnapshot;
+
+/// How the privacy cost of releases from a store adds up
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositionMode {
+    /// releases are charged to the store's own budget
+    PerStore,
+    /// releases are charged to a `PrivacyAccountant` shared with other stores
+    Shared,
+}
+
+/// Privacy settings and state of a `RunningStatsStore`, returned by `get_params`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DpParams {
+    pub epsilon: I32F32,
+    /// remaining privacy budget, from the shared accountant if there is one
+    pub privacy_budget: I32F32,
+    pub composition: CompositionMode,
+    pub clipping: Option<(I32F32, I32F32)>,
+    pub noise_source: NoiseSource,
+    pub sensitivity_policy: Option<SensitivityPolicy>,
+    pub status: RunningStatsStatus,
+    pub min_count_for_release: u32,
+    pub max_total_queries: Option<u32>,
+    pub total_queries: u32,
+}
+
+/// Limits on the privacy parameters of a `RunningStatsStore`, see `set_privacy_policy`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrivacyPolicy {
+    /// smallest epsilon a query may be answered with
+    pub min_epsilon_per_query: I32F32,
+    /// largest epsilon a query may be answered with
+    pub max_epsilon_per_query: I32F32,
+    /// largest total delta that can be charged with `charge_delta`
+    pub max_delta_total: I32F32,
+    /// largest privacy budget the store can be given
+    pub max_privacy_budget: I32F32,
+}
+
+impl PrivacyPolicy {
+    /// Checks that the epsilon floor is positive and at most the ceiling, that the delta limit
+    /// is in [0, 1) and that the budget limit is not negative
+    pub fn validate(&self) -> StdResult<()> {
+        if self.min_epsilon_per_query <= 0 || self.min_epsilon_per_query > self.max_epsilon_per_query {
+            return Err(StdError::generic_err("Epsilon floor must be greater than zero and at most the ceiling"));
+        }
+        if self.max_delta_total < 0 || self.max_delta_total >= 1 {
+            return Err(StdError::generic_err("Delta limit must be at least zero and less than one"));
+        }
+        if self.max_privacy_budget < 0 {
+            return Err(StdError::generic_err("Privacy budget limit must not be negative"));
+        }
+        Ok(())
+    }
+
+    /// Checks that `policy` is at least as strict as this one: it must not lower the epsilon
+    /// floor or raise any of the limits
+    pub(crate) fn check_tightened_by(&self, policy: &PrivacyPolicy) -> StdResult<()> {
+        if policy.min_epsilon_per_query < self.min_epsilon_per_query
+            || policy.max_epsilon_per_query > self.max_epsilon_per_query
+            || policy.max_delta_total > self.max_delta_total
+            || policy.max_privacy_budget > self.max_privacy_budget
+        {
+            return Err(StdError::generic_err("Privacy policy can only be tightened"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_epsilon(&self, epsilon: I32F32) -> StdResult<()> {
+        if epsilon < self.min_epsilon_per_query || epsilon > self.max_epsilon_per_query {
+            return Err(StdError::generic_err("Epsilon is outside the privacy policy"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_privacy_budget(&self, budget: I32F32) -> StdResult<()> {
+        if budget > self.max_privacy_budget {
+            return Err(StdError::generic_err("Privacy budget is above the privacy policy"));
+        }
+        Ok(())
+    }
+}
+
+// stored as the four limits in order, so stores other than `RunningStatsStore` keep it in one field
+impl FieldValue for PrivacyPolicy {
+    fn to_stored(&self) -> Vec<u8> {
+        [
+            self.min_epsilon_per_query.to_stored(),
+            self.max_epsilon_per_query.to_stored(),
+            self.max_delta_total.to_stored(),
+            self.max_privacy_budget.to_stored(),
+        ].concat()
+    }
+
+    fn from_stored(bytes: &[u8]) -> StdResult<Self> {
+        if bytes.len() != 32 {
+            return Err(StdError::generic_err("Invalid privacy policy"));
+        }
+        Ok(PrivacyPolicy {
+            min_epsilon_per_query: I32F32::from_stored(&bytes[..8])?,
+            max_epsilon_per_query: I32F32::from_stored(&bytes[8..16])?,
+            max_delta_total: I32F32::from_stored(&bytes[16..24])?,
+            max_privacy_budget: I32F32::from_stored(&bytes[24..])?,
+        })
+    }
+}
+
+/// What `reset` does with one setting of a `RunningStatsStore`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetValue {
+    /// keep the current value
+    Keep,
+    /// go back to the default
+    Default,
+    /// set a new value
+    Set(I32F32),
+}
+
+impl ResetValue {
+    fn resolve(self, current: Option<I32F32>) -> Option<I32F32> {
+        match self {
+            ResetValue::Keep => current,
+            ResetValue::Default => None,
+            ResetValue::Set(value) => Some(value),
+        }
+    }
+}
+
+impl From<Option<I32F32>> for ResetValue {
+    fn from(value: Option<I32F32>) -> Self {
+        match value {
+            Some(value) => ResetValue::Set(value),
+            None => ResetValue::Default,
+        }
+    }
+}
+
+/// Settings that `RunningStatsStore::reset` keeps, restores or replaces. Everything else about
+/// the data is always reset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResetPolicy {
+    pub epsilon: ResetValue,
+    pub avg_sensitivity: ResetValue,
+    /// `Keep` keeps the remaining budget, `Set` restores it to a new amount
+    pub privacy_budget: ResetValue,
+}
+
+impl ResetPolicy {
+    /// resets every setting to its default
+    pub const DEFAULT: Self = Self {
+        epsilon: ResetValue::Default,
+        avg_sensitivity: ResetValue::Default,
+        privacy_budget: ResetValue::Default,
+    };
+
+    /// keeps epsilon, average sensitivity and the remaining budget
+    pub const KEEP_ALL: Self = Self {
+        epsilon: ResetValue::Keep,
+        avg_sensitivity: ResetValue::Keep,
+        privacy_budget: ResetValue::Keep,
+    };
+
+    /// Sets each given value and resets the others to their defaults, as `clear` does
+    pub fn new(epsilon: Option<I32F32>, avg_sensitivity: Option<I32F32>, privacy_budget: Option<I32F32>) -> Self {
+        Self {
+            epsilon: epsilon.into(),
+            avg_sensitivity: avg_sensitivity.into(),
+            privacy_budget: privacy_budget.into(),
+        }
+    }
+}
 
+impl Default for ResetPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Query response form of `DpParams`, with fixed point values as big-endian bytes and the
+/// status as its stored u8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct DpParamsResponse {
+    pub epsilon: Vec<u8>,
+    pub privacy_budget: Vec<u8>,
+    pub composition: CompositionMode,
+    pub clipping: Option<(Vec<u8>, Vec<u8>)>,
+    /// "laplace" or "staircase"
+    pub noise_source: String,
+    /// "fixed", "observed_range" or "clipped", if a policy was configured
+    pub sensitivity_policy: Option<String>,
+    pub status: u8,
+    pub min_count_for_release: u32,
+    pub max_total_queries: Option<u32>,
+    pub total_queries: u32,
+}
+
+impl From<DpParams> for DpParamsResponse {
+    fn from(params: DpParams) -> Self {
+        DpParamsResponse {
+            epsilon: params.epsilon.to_stored(),
+            privacy_budget: params.privacy_budget.to_stored(),
+            composition: params.composition,
+            clipping: params.clipping.map(|(lower, upper)| (lower.to_stored(), upper.to_stored())),
+            noise_source: match params.noise_source {
+                NoiseSource::Laplace => "laplace",
+                NoiseSource::Staircase => "staircase",
+            }.to_string(),
+            sensitivity_policy: params.sensitivity_policy.map(|policy| match policy {
+                SensitivityPolicy::Fixed => "fixed",
+                SensitivityPolicy::ObservedRange => "observed_range",
+                SensitivityPolicy::Clipped => "clipped",
+            }.to_string()),
+            status: params.status.to_stored()[0],
+            min_count_for_release: params.min_count_for_release,
+            max_total_queries: params.max_total_queries,
+            total_queries: params.total_queries,
+        }
+    }
+}
+
+/// Settings of a `RunningStatsStore` that are fixed when it is declared, with the const
+/// `with_*` builders, rather than kept in storage
+#[derive(Clone, Copy)]
+struct StoreConfig<'a> {
+    /// optional (lower, upper) range that incoming observations are clamped into
+    clipping: Option<(I32F32, I32F32)>,
+    /// largest weight accepted by `add_weighted_observation`, which scales its sensitivity
+    max_weight: Option<I32F32>,
+    /// keep min and max heaps of the observations so bounds can be recomputed after removals
+    bound_heaps: bool,
+    /// keep a coarse histogram of the observations for ranges, percentiles and, without clipping
+    /// or bound heaps, bounds approximated after removals
+    bound_histogram: Option<HistogramBins>,
+    /// common privacy budget shared with other stores, used instead of this store's own budget
+    shared_budget: Option<&'a PrivacyAccountant<'a>>,
+    /// log of releases that can be replayed to settle disputes
+    replay_log: Option<&'a ReplayLog<'a>>,
+    /// how the sensitivity of the sum is chosen for average queries
+    sensitivity_policy: Option<SensitivityPolicy>,
+    /// mechanism used to add noise to released statistics
+    noise_source: NoiseSource,
+    /// keep the first noisy answer to each statistic and return it for repeated queries
+    cache_results: bool,
+    /// chain a blinded hash of every added or removed observation into a commitment
+    commit_observations: bool,
+    /// transformation applied to observations at ingestion and undone on released averages
+    transform: Transform,
+    /// what happens to observations outside the clipping range
+    outlier_policy: OutlierPolicy,
+}
+
+impl<'a> StoreConfig<'a> {
+    const DEFAULT: Self = Self {
+        clipping: None,
+        max_weight: None,
+        bound_heaps: false,
+        bound_histogram: None,
+        shared_budget: None,
+        replay_log: None,
+        sensitivity_policy: None,
+        noise_source: NoiseSource::Laplace,
+        cache_results: false,
+        commit_observations: false,
+        transform: Transform::None,
+        outlier_policy: OutlierPolicy::Clamp,
+    };
+}
+
+/// Every numeric field is stored as fixed-width big-endian bytes through `FieldValue`. The `Ser`
+/// parameter is kept for compatibility with other secret-toolkit storage types, and to read an
+/// average sensitivity written by earlier versions, which serialized it with `Ser`.
 pub struct RunningStatsStore<'a, Ser = Bincode2>
 where
     Ser: Serde,
@@ -33,14 +593,34 @@ where
     namespace: &'a [u8],
     /// needed if any suffixes were added to the original namespace.
     prefix: Option<Vec<u8>>,
-    count: Mutex<Option<u32>>,
-    sum: Mutex<Option<I64F64>>,
-    upper_bound: Mutex<Option<I32F32>>,
-    lower_bound: Mutex<Option<I32F32>>,
-    epsilon: Mutex<Option<I32F32>>,
-    avg_sensitivity: Mutex<Option<I32F32>>,
-    privacy_budget: Mutex<Option<I32F32>>,
-    status: Mutex<Option<RunningStatsStatus>>,
+    count: CachedField<u32>,
+    sum: CachedField<I64F64>,
+    upper_bound: CachedField<I32F32>,
+    lower_bound: CachedField<I32F32>,
+    epsilon: CachedField<I32F32>,
+    avg_sensitivity: CachedField<I32F32>,
+    privacy_budget: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    min_blocks_between_queries: CachedField<u64>,
+    last_query_height: CachedField<u64>,
+    data_version: CachedField<u64>,
+    status: CachedField<RunningStatsStatus>,
+    weight_sum: CachedField<I64F64>,
+    weighted_sum: CachedField<I64F64>,
+    max_total_queries: CachedField<u32>,
+    total_queries: CachedField<u32>,
+    collection_deadline: CachedField<u64>,
+    max_observations_per_epoch: CachedField<u32>,
+    rate_epoch_blocks: CachedField<u64>,
+    min_epsilon_per_query: CachedField<I32F32>,
+    max_epsilon_per_query: CachedField<I32F32>,
+    max_delta_total: CachedField<I32F32>,
+    max_privacy_budget: CachedField<I32F32>,
+    delta_spent: CachedField<I32F32>,
+    rejected_count: CachedField<u32>,
+    clamped_count: CachedField<u32>,
+    /// settings chosen with the const `with_*` builders
+    config: StoreConfig<'a>,
     serialization_type: PhantomData<Ser>,
 }
 
@@ -52,18 +632,177 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         Self {
             namespace,
             prefix: None,
-            count: Mutex::new(None),
-            sum: Mutex::new(None),
-            upper_bound: Mutex::new(None),
-            lower_bound: Mutex::new(None),
-            epsilon: Mutex::new(None),
-            avg_sensitivity: Mutex::new(None),
-            privacy_budget: Mutex::new(None),
-            status: Mutex::new(None),
+            count: CachedField::new(COUNT_KEY),
+            sum: CachedField::new(SUM_KEY),
+            upper_bound: CachedField::new(UPPER_BOUND_KEY),
+            lower_bound: CachedField::new(LOWER_BOUND_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            avg_sensitivity: CachedField::new(SENSITIVITY_FOR_AVG_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            min_blocks_between_queries: CachedField::new(COOLDOWN_KEY),
+            last_query_height: CachedField::new(LAST_QUERY_KEY),
+            data_version: CachedField::new(DATA_VERSION_KEY),
+            status: CachedField::new(STATUS_KEY),
+            weight_sum: CachedField::new(WEIGHT_SUM_KEY),
+            weighted_sum: CachedField::new(WEIGHTED_SUM_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            collection_deadline: CachedField::new(DEADLINE_KEY),
+            max_observations_per_epoch: CachedField::new(RATE_LIMIT_KEY),
+            rate_epoch_blocks: CachedField::new(RATE_EPOCH_KEY),
+            min_epsilon_per_query: CachedField::new(MIN_EPSILON_KEY),
+            max_epsilon_per_query: CachedField::new(MAX_EPSILON_KEY),
+            max_delta_total: CachedField::new(MAX_DELTA_KEY),
+            max_privacy_budget: CachedField::new(MAX_BUDGET_KEY),
+            delta_spent: CachedField::new(DELTA_SPENT_KEY),
+            rejected_count: CachedField::new(REJECTED_KEY),
+            clamped_count: CachedField::new(CLAMPED_KEY),
+            config: StoreConfig::DEFAULT,
             serialization_type: PhantomData,
         }
     }
 
+    /// Clamp every observation into the range [lower, upper] when it is added. The sensitivity
+    /// of the sum is then fixed at `upper - lower`, independent of the collected data, and the
+    /// observed upper and lower bounds are never updated. `lower` must be less than `upper`.
+    pub const fn with_clipping(
+        mut self,
+        lower: I32F32,
+        upper: I32F32,
+    ) -> Self {
+        assert!(lower.to_bits() < upper.to_bits(), "Clipping lower bound must be less than upper bound");
+        self.config.clipping = Some((lower, upper));
+        self
+    }
+
+    /// Keep a min heap and a max heap of all observations in storage, so that the exact upper 
+    /// and lower bounds can be recomputed when an observation is removed. This doubles the storage 
+    /// cost of every observation, and has no effect if the store uses clipping.
+    pub const fn with_bound_heaps(mut self) -> Self {
+        self.config.bound_heaps = true;
+        self
+    }
+
+    /// Keep a coarse histogram of observation counts with `bins` equal-width bins over 
+    /// [lower, upper). When the extreme observation is removed, the upper and lower bounds are 
+    /// recomputed approximately from the histogram without storing raw observations, unless the
+    /// store uses clipping or bound heaps, which already bound the data. The histogram is kept
+    /// either way, and `fuzzy_range` and `fuzzy_percentile_of` are released from it.
+    pub const fn with_bound_histogram(
+        mut self,
+        lower: I32F32,
+        upper: I32F32,
+        bins: u16,
+    ) -> Self {
+        assert!(lower.to_bits() < upper.to_bits(), "Histogram lower bound must be less than upper bound");
+        assert!(bins > 0, "Histogram must have at least one bin");
+        self.config.bound_histogram = Some(HistogramBins { lower, upper, bins });
+        self
+    }
+
+    /// Choose where the sensitivity of the sum comes from, instead of falling back from the 
+    /// average sensitivity to the clipping range to the observed bounds. Average queries fail if 
+    /// the chosen source is not available.
+    pub const fn with_sensitivity_policy(
+        mut self,
+        policy: SensitivityPolicy,
+    ) -> Self {
+        self.config.sensitivity_policy = Some(policy);
+        self
+    }
+
+    /// Add noise with a different mechanism than the default Laplace mechanism, e.g. 
+    /// `NoiseSource::Staircase`
+    pub const fn with_noise_source(
+        mut self,
+        noise_source: NoiseSource,
+    ) -> Self {
+        self.config.noise_source = noise_source;
+        self
+    }
+
+    /// Aggregate observations in a transformed domain, e.g. `Transform::Log1p` for skewed data
+    /// such as token amounts. Every observation is transformed when it is added or removed,
+    /// before clipping, so the clipping range, the tracked bounds and the average sensitivity
+    /// are all in the transformed domain. Released averages and ranges are transformed back.
+    pub const fn with_transform(
+        mut self,
+        transform: Transform,
+    ) -> Self {
+        if let Transform::Affine { a, .. } = transform {
+            assert!(a.to_bits() != 0, "Affine transform must not have a zero slope");
+        }
+        self.config.transform = transform;
+        self
+    }
+
+    /// Choose what happens to observations outside the clipping range: clamp them into it (the
+    /// default), reject them, or accept them as they are. Rejected and clamped observations are
+    /// counted, and the counts can be released with `fuzzy_outlier_counts`.
+    pub const fn with_outlier_policy(
+        mut self,
+        outlier_policy: OutlierPolicy,
+    ) -> Self {
+        self.config.outlier_policy = outlier_policy;
+        self
+    }
+
+    /// Draw from a privacy budget shared with other stores in the contract, instead of this 
+    /// store's own budget. The full cost of each query is checked against the shared budget 
+    /// before anything is charged.
+    pub const fn with_shared_budget(
+        mut self,
+        accountant: &'a PrivacyAccountant<'a>,
+    ) -> Self {
+        self.config.shared_budget = Some(accountant);
+        self
+    }
+
+    /// Record every release in a `ReplayLog`: a fingerprint of the random number generator, the
+    /// mechanism parameters and the released statistics. Given the seed, `replay_release` can
+    /// then recompute a release inside the enclave and check that it matches what was released.
+    pub const fn with_replay_log(
+        mut self,
+        log: &'a ReplayLog<'a>,
+    ) -> Self {
+        self.config.replay_log = Some(log);
+        self
+    }
+
+    /// Keep the first noisy answer to each kind of statistic in storage and return it again for
+    /// repeated queries, at no extra privacy cost. Without this, every repeat of a query gets
+    /// fresh noise, which can be averaged away. The cached answers are dropped when the store is
+    /// cleared or a snapshot is imported.
+    pub const fn with_result_cache(mut self) -> Self {
+        self.config.cache_results = true;
+        self
+    }
+
+    /// Keep a running commitment to the ingested observations: every added or removed
+    /// observation is hashed with a secret salt, set with `set_commitment_salt`, and chained into
+    /// a linear SHA-256 hash chain. This is not a Merkle tree, so it commits to the whole
+    /// sequence of changes and cannot prove that a single observation is included. Auditors can
+    /// check that the commitment at the end of collection matches the one at a release, i.e. that
+    /// the dataset was not swapped in between, without learning any observation.
+    pub const fn with_observation_commitment(mut self) -> Self {
+        self.config.commit_observations = true;
+        self
+    }
+
+    /// Accept weighted observations with weights in [0, max_weight], e.g. stake-weighted votes
+    /// with the stake capped at `max_weight`. One observation then moves the weighted sum by up
+    /// to `max_weight` times the sensitivity of the sum, and the sum of weights by up to
+    /// `max_weight`, so `fuzzy_weighted_average` scales its noise by `max_weight`.
+    pub const fn with_max_weight(
+        mut self,
+        max_weight: I32F32,
+    ) -> Self {
+        assert!(max_weight.to_bits() > 0, "Max weight must be greater than zero");
+        self.config.max_weight = Some(max_weight);
+        self
+    }
+
     /// This is used to produce a new RunningStatsStore. It can be used when you want to associate 
     /// a RunningStatsStore to multiple suffixes and you still want to define the RunningStatsStore 
     /// as a static constant
@@ -74,14 +813,33 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         Self {
             namespace: self.namespace,
             prefix: Some(prefix),
-            count: Mutex::new(None),
-            sum: Mutex::new(None),
-            upper_bound: Mutex::new(None),
-            lower_bound: Mutex::new(None),
-            epsilon: Mutex::new(None),
-            avg_sensitivity: Mutex::new(None),
-            privacy_budget: Mutex::new(None),
-            status: Mutex::new(None),
+            count: CachedField::new(COUNT_KEY),
+            sum: CachedField::new(SUM_KEY),
+            upper_bound: CachedField::new(UPPER_BOUND_KEY),
+            lower_bound: CachedField::new(LOWER_BOUND_KEY),
+            epsilon: CachedField::new(EPSILON_KEY),
+            avg_sensitivity: CachedField::new(SENSITIVITY_FOR_AVG_KEY),
+            privacy_budget: CachedField::new(PRIVACY_BUDGET_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            min_blocks_between_queries: CachedField::new(COOLDOWN_KEY),
+            last_query_height: CachedField::new(LAST_QUERY_KEY),
+            data_version: CachedField::new(DATA_VERSION_KEY),
+            status: CachedField::new(STATUS_KEY),
+            weight_sum: CachedField::new(WEIGHT_SUM_KEY),
+            weighted_sum: CachedField::new(WEIGHTED_SUM_KEY),
+            max_total_queries: CachedField::new(MAX_QUERIES_KEY),
+            total_queries: CachedField::new(QUERY_COUNT_KEY),
+            collection_deadline: CachedField::new(DEADLINE_KEY),
+            max_observations_per_epoch: CachedField::new(RATE_LIMIT_KEY),
+            rate_epoch_blocks: CachedField::new(RATE_EPOCH_KEY),
+            min_epsilon_per_query: CachedField::new(MIN_EPSILON_KEY),
+            max_epsilon_per_query: CachedField::new(MAX_EPSILON_KEY),
+            max_delta_total: CachedField::new(MAX_DELTA_KEY),
+            max_privacy_budget: CachedField::new(MAX_BUDGET_KEY),
+            delta_spent: CachedField::new(DELTA_SPENT_KEY),
+            rejected_count: CachedField::new(REJECTED_KEY),
+            clamped_count: CachedField::new(CLAMPED_KEY),
+            config: self.config,
             serialization_type: self.serialization_type,
         }
     }
@@ -96,31 +854,108 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         }
     }
 
-    fn get_count(
+    fn suffix_index(&self) -> SuffixIndex {
+        SuffixIndex::new([self.as_slice(), SUFFIXES_KEY].concat())
+    }
+
+    /// Same as `add_suffix`, but also records the suffix in storage so that every registered 
+    /// suffixed store can be enumerated later with `suffixed_stores`
+    pub fn add_registered_suffix(&self, storage: &mut dyn Storage, suffix: &[u8]) -> StdResult<Self> {
+        self.suffix_index().register(storage, suffix)?;
+        Ok(self.add_suffix(suffix))
+    }
+
+    /// Returns the number of registered suffixes
+    pub fn get_suffixes_len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.suffix_index().len(storage)
+    }
+
+    /// Returns one page of registered suffixes, with pages numbered from 0
+    pub fn get_suffixes(&self, storage: &dyn Storage, start_page: u32, size: u32) -> StdResult<Vec<Vec<u8>>> {
+        self.suffix_index().paging(storage, start_page, size)
+    }
+
+    /// Iterates over every registered suffix, in the order they were registered
+    pub fn iter_suffixes<'s>(&self, storage: &'s dyn Storage) -> StdResult<impl Iterator<Item = StdResult<Vec<u8>>> + 's> {
+        self.suffix_index().iter(storage)
+    }
+
+    /// Privately releases the count of every registered suffixed store, charged to each store's
+    /// own budget (or to the shared budget), together with its suffix. Every suffixed store must
+    /// be ready for release, and every suffix is read, so contracts with many suffixes should
+    /// page through `suffixed_stores` instead. A store whose minimum-count check refuses the
+    /// release is charged for the check and returned as `Release::Refused`.
+    pub fn fuzzy_counts_for_all(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+    ) -> StdResult<Vec<(Vec<u8>, Release<I32F32>)>> {
+        let suffixes = self.iter_suffixes(storage)?.collect::<StdResult<Vec<_>>>()?;
+        suffixes
+            .into_iter()
+            .map(|suffix| {
+                let fuzzy_count = self.add_suffix(&suffix).fuzzy_count(storage, rng)?;
+                Ok((suffix, fuzzy_count))
+            })
+            .collect()
+    }
+
+    /// Returns one page of the stores for registered suffixes, with pages numbered from 0
+    pub fn suffixed_stores(&self, storage: &dyn Storage, start_page: u32, size: u32) -> StdResult<Vec<Self>> {
+        Ok(self.get_suffixes(storage, start_page, size)?
+            .iter()
+            .map(|suffix| self.add_suffix(suffix))
+            .collect())
+    }
+
+    /// Returns the clipping range, if one was configured
+    pub fn get_clipping(&self) -> Option<(I32F32, I32F32)> {
+        self.config.clipping
+    }
+
+    /// The clipping range, unless outliers are accepted as they are, in which case it bounds
+    /// neither the observations nor the sensitivity
+    fn enforced_clipping(&self) -> Option<(I32F32, I32F32)> {
+        match self.config.outlier_policy {
+            OutlierPolicy::Accept => None,
+            OutlierPolicy::Reject | OutlierPolicy::Clamp => self.config.clipping,
+        }
+    }
+
+    pub fn get_max_weight(&self) -> Option<I32F32> {
+        self.config.max_weight
+    }
+
+    fn min_heap(&self) -> BoundHeap {
+        BoundHeap::new([self.as_slice(), MIN_HEAP_KEY].concat(), HeapOrder::Min)
+    }
+
+    fn max_heap(&self) -> BoundHeap {
+        BoundHeap::new([self.as_slice(), MAX_HEAP_KEY].concat(), HeapOrder::Max)
+    }
+
+    /// true if bounds are tracked using the min and max heaps
+    fn uses_bound_heaps(&self) -> bool {
+        self.config.bound_heaps && self.enforced_clipping().is_none()
+    }
+
+    /// true if bounds are approximated from the bound histogram when an observation is removed
+    fn uses_bound_histogram(&self) -> bool {
+        self.config.bound_histogram.is_some() && self.enforced_clipping().is_none() && !self.config.bound_heaps
+    }
+
+    /// Returns the histogram of observations, if the store keeps one
+    fn bound_histogram(&self) -> Option<BoundHistogram> {
+        self.config.bound_histogram.map(|layout| {
+            BoundHistogram::new([self.as_slice(), HISTOGRAM_KEY].concat(), layout)
+        })
+    }
+
+    pub(crate) fn get_count(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<u32> {
-        let mut may_count = self.count.lock().unwrap();
-        match *may_count {
-            Some(count) => {
-                Ok(count)
-            }
-            None => {
-                let count_key = [self.as_slice(), COUNT_KEY].concat();
-                if let Some(count_vec) = storage.get(&count_key) {
-                    let count_bytes = count_vec
-                        .as_slice()
-                        .try_into()
-                        .map_err(|err| StdError::parse_err("u32", err))?;
-                    let count = u32::from_be_bytes(count_bytes);
-                    *may_count = Some(count);
-                    Ok(count)
-                } else {
-                    *may_count = Some(0);
-                    Ok(0)
-                }
-            }
-        }
+        self.count.load_or(storage, self.as_slice(), 0)
     }
 
     pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
@@ -132,43 +967,14 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         storage: &mut dyn Storage,
         count: u32,
     ) {
-        let count_key = [self.as_slice(), COUNT_KEY].concat();
-        storage.set(&count_key, &count.to_be_bytes());
-
-        let mut may_count = self.count.lock().unwrap();
-        *may_count = Some(count);
+        self.count.save(storage, self.as_slice(), count);
     }
 
     pub fn get_sum(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<I64F64> {
-        let mut may_sum = self.sum.lock().unwrap();
-        match *may_sum {
-            Some(sum) => {
-                Ok(sum)
-            }
-            None => {
-                let sum_key = [self.as_slice(), SUM_KEY].concat();
-                if let Some(sum_vec) = storage.get(&sum_key) {
-                    let sum = I64F64::from_be_bytes(
-                        match sum_vec.try_into() {
-                            Ok(sum_bytes) => sum_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_sum = Some(sum);
-                    Ok(sum)
-                } else {
-                    // default upper bound
-                    let sum = I64F64::from(0);
-                    *may_sum = Some(sum);
-                    Ok(sum)
-                }
-            }
-        }
+        self.sum.load_or(storage, self.as_slice(), I64F64::from(0))
     }
 
     fn set_sum(
@@ -176,43 +982,31 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         storage: &mut dyn Storage,
         sum: I64F64,
     ) {
-        let sum_key = [self.as_slice(), SUM_KEY].concat();
-        storage.set(&sum_key, &sum.to_be_bytes());
+        self.sum.save(storage, self.as_slice(), sum);
+    }
+
+    /// Returns the sum of the weights of the observations added with `add_weighted_observation`
+    pub fn get_weight_sum(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I64F64> {
+        self.weight_sum.load_or(storage, self.as_slice(), I64F64::from(0))
+    }
 
-        let mut may_sum = self.sum.lock().unwrap();
-        *may_sum = Some(sum);
+    /// Returns the sum of weight times value of the observations added with `add_weighted_observation`
+    pub fn get_weighted_sum(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<I64F64> {
+        self.weighted_sum.load_or(storage, self.as_slice(), I64F64::from(0))
     }
 
     pub fn get_upper_bound(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<I32F32> {
-        let mut may_upper_bound = self.upper_bound.lock().unwrap();
-        match *may_upper_bound {
-            Some(upper_bound) => {
-                Ok(upper_bound)
-            }
-            None => {
-                let upper_bound_key = [self.as_slice(), UPPER_BOUND_KEY].concat();
-                if let Some(upper_bound_vec) = storage.get(&upper_bound_key) {
-                    let upper_bound = I32F32::from_be_bytes(
-                        match upper_bound_vec.try_into() {
-                            Ok(upper_bound_bytes) => upper_bound_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_upper_bound = Some(upper_bound);
-                    Ok(upper_bound)
-                } else {
-                    // default upper bound
-                    let upper_bound = I32F32::min_value();
-                    *may_upper_bound = Some(upper_bound);
-                    Ok(upper_bound)
-                }
-            }
-        }
+        // default upper bound
+        self.upper_bound.load_or(storage, self.as_slice(), I32F32::min_value())
     }
 
     fn set_upper_bound(
@@ -220,43 +1014,15 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         storage: &mut dyn Storage,
         upper_bound: I32F32,
     ) {
-        let upper_bound_key = [self.as_slice(), UPPER_BOUND_KEY].concat();
-        storage.set(&upper_bound_key, &upper_bound.to_be_bytes());
-
-        let mut may_upper_bound = self.upper_bound.lock().unwrap();
-        *may_upper_bound = Some(upper_bound);
+        self.upper_bound.save(storage, self.as_slice(), upper_bound);
     }
 
     pub fn get_lower_bound(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<I32F32> {
-        let mut may_lower_bound = self.lower_bound.lock().unwrap();
-        match *may_lower_bound {
-            Some(lower_bound) => {
-                Ok(lower_bound)
-            }
-            None => {
-                let lower_bound_key = [self.as_slice(), LOWER_BOUND_KEY].concat();
-                if let Some(lower_bound_vec) = storage.get(&lower_bound_key) {
-                    let lower_bound = I32F32::from_be_bytes(
-                        match lower_bound_vec.try_into() {
-                            Ok(lower_bound_bytes) => lower_bound_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_lower_bound = Some(lower_bound);
-                    Ok(lower_bound)
-                } else {
-                    // default upper bound
-                    let lower_bound = I32F32::max_value();
-                    *may_lower_bound = Some(lower_bound);
-                    Ok(lower_bound)
-                }
-            }
-        }
+        // default lower bound
+        self.lower_bound.load_or(storage, self.as_slice(), I32F32::max_value())
     }
 
     fn set_lower_bound(
@@ -264,181 +1030,500 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         storage: &mut dyn Storage,
         lower_bound: I32F32,
     ) {
-        let lower_bound_key = [self.as_slice(), LOWER_BOUND_KEY].concat();
-        storage.set(&lower_bound_key, &lower_bound.to_be_bytes());
+        self.lower_bound.save(storage, self.as_slice(), lower_bound);
+    }
+
+    /// Returns true once the store has been initialized with `reset`, `import_snapshot` or
+    /// `RunningStatsStoreBuilder::init`
+    pub fn is_initialized(&self, storage: &dyn Storage) -> bool {
+        storage.get(&[self.as_slice(), INITIALIZED_KEY].concat()).is_some()
+    }
+
+    /// Returns an error if the store was never initialized, so that the default epsilon and
+    /// privacy budget cannot silently stand in for parameters that were never written
+    pub fn ensure_initialized(&self, storage: &dyn Storage) -> StdResult<()> {
+        if !self.is_initialized(storage) {
+            return Err(StdError::generic_err("Running stats store not initialized"));
+        }
+        Ok(())
+    }
 
-        let mut may_lower_bound = self.lower_bound.lock().unwrap();
-        *may_lower_bound = Some(lower_bound);
+    fn set_initialized(&self, storage: &mut dyn Storage) {
+        storage.set(&[self.as_slice(), INITIALIZED_KEY].concat(), &[1]);
     }
 
+    /// Returns epsilon, or an error if the store was never initialized
     pub fn get_epsilon(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<I32F32> {
-        let mut may_epsilon = self.epsilon.lock().unwrap();
-        match *may_epsilon {
-            Some(epsilon) => {
-                Ok(epsilon)
-            }
-            None => {
-                let epsilon_key = [self.as_slice(), EPSILON_KEY].concat();
-                if let Some(epsilon_vec) = storage.get(&epsilon_key) {
-                    let epsilon = I32F32::from_be_bytes(
-                        match epsilon_vec.try_into() {
-                            Ok(epsilon_bytes) => epsilon_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_epsilon = Some(epsilon);
-                    Ok(epsilon)
-                } else {
-                    // default epsilon = 1
-                    let epsilon = I32F32::from(1);
-                    *may_epsilon = Some(epsilon);
-                    Ok(epsilon)
-                }
-            }
-        }
+        self.ensure_initialized(storage)?;
+        // default epsilon = 1, if none was given at initialization
+        self.epsilon.load_or(storage, self.as_slice(), I32F32::from(1))
     }
 
-    /// Set the epsilon
+    /// Set the epsilon. Returns an error if it is not positive or is outside the privacy policy,
+    /// if there is one, or if the store is closed.
     pub fn set_epsilon(
         &self, 
         storage: &mut dyn Storage, 
         epsilon: I32F32,
-    ) {
-        let epsilon_key = [self.as_slice(), EPSILON_KEY].concat();
-        storage.set(&epsilon_key, &epsilon.to_be_bytes());
-
-        let mut may_epsilon = self.epsilon.lock().unwrap();
-        *may_epsilon = Some(epsilon);
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        self.check_privacy_policy(storage, epsilon)?;
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        Ok(())
     }
 
-    pub fn get_avg_sensitivity(
-        &self,
-        storage: &dyn Storage,
-    ) -> StdResult<Option<I32F32>> {
-        let mut may_sensitivity = self.avg_sensitivity.lock().unwrap();
-        match *may_sensitivity {
-            Some(sensitivity) => {
-                Ok(Some(sensitivity))
-            }
-            None => {
-                let sensitivity_key = [self.as_slice(), SENSITIVITY_FOR_AVG_KEY].concat();
-                if let Some(sensitivity_vec) = storage.get(&sensitivity_key) {
-                    let sensitivity = I32F32::from_be_bytes(
-                        match sensitivity_vec.try_into() {
-                            Ok(sensitivity_bytes) => sensitivity_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_sensitivity = Some(sensitivity);
-                    Ok(Some(sensitivity))
-                } else {
-                    Ok(None)
-                }
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        let prefix = self.as_slice();
+        match (
+            self.min_epsilon_per_query.may_load(storage, prefix)?,
+            self.max_epsilon_per_query.may_load(storage, prefix)?,
+            self.max_delta_total.may_load(storage, prefix)?,
+        ) {
+            (Some(min_epsilon_per_query), Some(max_epsilon_per_query), Some(max_delta_total)) => {
+                // policies set before the budget limit existed do not limit the budget
+                let max_privacy_budget = self.max_privacy_budget.load_or(storage, prefix, I32F32::MAX)?;
+                Ok(Some(PrivacyPolicy { min_epsilon_per_query, max_epsilon_per_query, max_delta_total, max_privacy_budget }))
             }
+            _ => Ok(None),
         }
     }
 
-    /// Set the average sensitivity
-    pub fn set_average_sensitivity(
-        &self, 
-        storage: &mut dyn Storage, 
-        avg_sensitivity: Option<I32F32>,
-    ) -> StdResult<()> {
-        let average_sensitivity_key = [self.as_slice(), SENSITIVITY_FOR_AVG_KEY].concat();
-        let stored_average_sensitivity: Option<[u8;8]>;
-        if let Some(may_sensitivity) = avg_sensitivity {
-            stored_average_sensitivity = Some(may_sensitivity.to_be_bytes());
-        } else {
-            stored_average_sensitivity = None;
+    /// Sets limits on epsilon, delta and the privacy budget that every later parameter update
+    /// and release is checked against, so that an admin key cannot quietly set a meaningless
+    /// epsilon such as 1000 or grant an unlimited budget. Once a policy is set it can only be
+    /// tightened: a new policy must not lower the epsilon floor or raise any of the limits. The
+    /// current epsilon and budget, if they were set, must be within the new policy.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        policy.validate()?;
+        if let Some(current) = self.get_privacy_policy(storage)? {
+            current.check_tightened_by(&policy)?;
+        }
+        if let Some(epsilon) = self.epsilon.may_load(storage, self.as_slice())? {
+            policy.check_epsilon(epsilon)?;
+        }
+        let privacy_budget = match self.config.shared_budget {
+            Some(accountant) => Some(accountant.get_privacy_budget(storage)?),
+            None => self.privacy_budget.may_load(storage, self.as_slice())?,
+        };
+        if let Some(privacy_budget) = privacy_budget {
+            policy.check_privacy_budget(privacy_budget)?;
+        }
+        self.min_epsilon_per_query.save(storage, self.as_slice(), policy.min_epsilon_per_query);
+        self.max_epsilon_per_query.save(storage, self.as_slice(), policy.max_epsilon_per_query);
+        self.max_delta_total.save(storage, self.as_slice(), policy.max_delta_total);
+        self.max_privacy_budget.save(storage, self.as_slice(), policy.max_privacy_budget);
+        Ok(())
+    }
+
+    pub(crate) fn check_privacy_policy(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(epsilon)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the total delta charged with `charge_delta` since the store was last cleared
+    pub fn get_delta_spent(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        self.delta_spent.load_or(storage, self.as_slice(), I32F32::from_num(0))
+    }
+
+    /// Records the delta of an (epsilon, delta)-DP release made from this store's data outside
+    /// of `fuzzy_report`, e.g. with smooth sensitivity. The store's own releases are pure
+    /// epsilon-DP and spend no delta. Returns an error, and records nothing, if the total would
+    /// exceed the delta limit of the privacy policy.
+    pub fn charge_delta(&self, storage: &mut dyn Storage, delta: I32F32) -> StdResult<()> {
+        if delta < 0 {
+            return Err(StdError::generic_err("Delta must not be negative"));
         }
-        let data = Ser::serialize(&stored_average_sensitivity)?;
-        storage.set(&average_sensitivity_key, &data);
+        let delta_spent = self.get_delta_spent(storage)?.checked_add(delta).ok_or(
+            StdError::generic_err("Delta overflow")
+        )?;
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            if delta_spent > policy.max_delta_total {
+                return Err(StdError::generic_err("Delta limit of the privacy policy exceeded"));
+            }
+        }
+        self.delta_spent.save(storage, self.as_slice(), delta_spent);
+        Ok(())
+    }
+
+    pub fn get_avg_sensitivity(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<I32F32>> {
+        match self.avg_sensitivity.may_load(storage, self.as_slice()) {
+            Ok(avg_sensitivity) => Ok(avg_sensitivity),
+            Err(err) => self.get_legacy_avg_sensitivity(storage).ok_or(err),
+        }
+    }
 
-        let mut may_avg_sensibility = self.avg_sensitivity.lock().unwrap();
-        *may_avg_sensibility = avg_sensitivity;
+    /// Reads an average sensitivity stored by earlier versions as `Ser::serialize(&Option<[u8; 8]>)`.
+    /// With `Bincode2` a stored value has the same bytes as the current encoding, so only a
+    /// stored `None` ends up here, but other serializations of either are read too.
+    fn get_legacy_avg_sensitivity(&self, storage: &dyn Storage) -> Option<Option<I32F32>> {
+        let sensitivity_key = [self.as_slice(), SENSITIVITY_FOR_AVG_KEY].concat();
+        let sensitivity_vec = storage.get(&sensitivity_key)?;
+        let sensitivity: Option<[u8; 8]> = Ser::deserialize(&sensitivity_vec).ok()?;
+        Some(sensitivity.map(I32F32::from_be_bytes))
+    }
 
+    /// Set the average sensitivity, or remove it so that the sensitivity is derived from the bounds
+    pub fn set_average_sensitivity(
+        &self, 
+        storage: &mut dyn Storage, 
+        avg_sensitivity: Option<I32F32>,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        match avg_sensitivity {
+            Some(sensitivity) if sensitivity <= I32F32::from_num(0) => {
+                return Err(StdError::generic_err("Average sensitivity must be greater than zero"));
+            }
+            Some(sensitivity) => self.avg_sensitivity.save(storage, self.as_slice(), sensitivity),
+            None => self.avg_sensitivity.remove(storage, self.as_slice()),
+        }
         Ok(())
     }
 
+    /// Returns the remaining privacy budget, which is the shared budget if the store uses one
     pub fn get_privacy_budget(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<I32F32> {
-        let mut may_privacy_budget = self.privacy_budget.lock().unwrap();
-        match *may_privacy_budget {
-            Some(privacy_budget) => {
-                Ok(privacy_budget)
-            }
-            None => {
-                let budget_key = [self.as_slice(), PRIVACY_BUDGET_KEY].concat();
-                if let Some(budget_vec) = storage.get(&budget_key) {
-                    let privacy_budget = I32F32::from_be_bytes(
-                        match budget_vec.try_into() {
-                            Ok(privacy_budget_bytes) => privacy_budget_bytes,
-                            Err(err) => { 
-                                return Err(StdError::generic_err(format!("{:?}", err))) 
-                            },
-                        }
-                    );
-                    *may_privacy_budget = Some(privacy_budget);
-                    Ok(privacy_budget)
-                } else {
-                    // default privacy budget = 1
-                    let privacy_budget = I32F32::from(1);
-                    *may_privacy_budget = Some(privacy_budget);
-                    Ok(privacy_budget)
-                }
-            }
+        if let Some(accountant) = self.config.shared_budget {
+            return accountant.get_privacy_budget(storage);
         }
+        self.ensure_initialized(storage)?;
+        // default privacy budget = 1, if none was given at initialization
+        self.privacy_budget.load_or(storage, self.as_slice(), I32F32::from(1))
     }
 
-    /// Set the privacy budget
+    /// Set the privacy budget, which is the shared budget if the store uses one. Returns an error
+    /// if the store is closed, since closing freezes the budget, or if the budget is above the
+    /// privacy policy, if there is one.
     pub fn set_privacy_budget(
         &self, 
         storage: &mut dyn Storage, 
         budget: I32F32,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(budget)?;
+        }
+        self.save_privacy_budget(storage, budget);
+        Ok(())
+    }
+
+    /// Writes the privacy budget after a release is charged. Constant-work releases write it back
+    /// whatever the status, so this does not check it.
+    fn save_privacy_budget(
+        &self,
+        storage: &mut dyn Storage,
+        budget: I32F32,
     ) {
-        let budget_key = [self.as_slice(), PRIVACY_BUDGET_KEY].concat();
-        storage.set(&budget_key, &budget.to_be_bytes());
+        if let Some(accountant) = self.config.shared_budget {
+            accountant.set_privacy_budget(storage, budget);
+            return;
+        }
+        self.privacy_budget.save(storage, self.as_slice(), budget);
+    }
+
+    /// Returns the minimum number of observations needed before any statistic is released
+    pub fn get_min_count_for_release(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of observations needed before any statistic is released, even
+    /// with noise. Each release first compares a noisy count with the minimum, at an extra cost
+    /// of epsilon; if it falls short, only that cost is charged and the release is refused.
+    /// This is kept when the store is cleared.
+    pub fn set_min_count_for_release(
+        &self,
+        storage: &mut dyn Storage,
+        min_count: u32,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+        Ok(())
+    }
+
+    /// Returns the number of blocks that must pass between two releases, 0 if there is no cooldown
+    pub fn get_min_blocks_between_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u64> {
+        // default: no cooldown
+        self.min_blocks_between_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the number of blocks that must pass between two releases, so that the budget cannot
+    /// be drained, or the noise averaged away, by many queries in a short window. With a
+    /// cooldown, statistics are released with `fuzzy_report_at`, which is given the current
+    /// block height. This is kept when the store is cleared.
+    pub fn set_min_blocks_between_queries(
+        &self,
+        storage: &mut dyn Storage,
+        blocks: u64,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        self.min_blocks_between_queries.save(storage, self.as_slice(), blocks);
+        Ok(())
+    }
+
+    /// Returns the block height of the last release made with `fuzzy_report_at`, if any
+    pub fn get_last_query_height(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u64>> {
+        self.last_query_height.may_load(storage, self.as_slice())
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u32>> {
+        self.max_total_queries.may_load(storage, self.as_slice())
+    }
+
+    /// Limit the number of releases from the store, whatever epsilon they use, so that many
+    /// queries with a tiny epsilon each cannot be combined to reconstruct the data. Every report
+    /// that charges the budget counts as one release; answers served from the result cache do
+    /// not. This is kept when the store is cleared.
+    pub fn set_max_total_queries(
+        &self,
+        storage: &mut dyn Storage,
+        max_queries: u32,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        self.max_total_queries.save(storage, self.as_slice(), max_queries);
+        Ok(())
+    }
+
+    /// Returns the number of releases made since the store was last cleared
+    pub fn get_total_queries(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u32> {
+        self.total_queries.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn check_query_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if let Some(max_queries) = self.get_max_total_queries(storage)? {
+            if self.get_total_queries(storage)? >= max_queries {
+                return Err(StdError::generic_err("Query limit reached"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a release towards the query limit
+    pub(crate) fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let total_queries = self.get_total_queries(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Query count overflow")
+        )?;
+        self.total_queries.save(storage, self.as_slice(), total_queries);
+        Ok(())
+    }
+
+    /// Returns the version of the data in the store. It starts at 0 and goes up by one every time
+    /// an observation is added or removed and every time the store is cleared or a snapshot is
+    /// imported, so a statistic released at one version reflects the latest data as long as the
+    /// version has not changed. It is private because it reveals the exact number of changes.
+    fn get_data_version(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<u64> {
+        self.data_version.load_or(storage, self.as_slice(), 0)
+    }
+
+    fn bump_data_version(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let new_version = self.get_data_version(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Data version overflow")
+        )?;
+        self.data_version.save(storage, self.as_slice(), new_version);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn check_query_cooldown(&self, storage: &dyn Storage, block_height: u64) -> StdResult<()> {
+        let min_blocks = self.get_min_blocks_between_queries(storage)?;
+        if let Some(last_height) = self.get_last_query_height(storage)? {
+            if block_height < last_height.saturating_add(min_blocks) {
+                return Err(StdError::generic_err("Query cooldown has not elapsed"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Privacy cost of the noisy minimum-count check made before each release, see
+    /// `set_min_count_for_release`
+    pub(crate) fn release_gate_cost(&self, storage: &dyn Storage, epsilon: I32F32) -> StdResult<I32F32> {
+        Ok(min_count_gate_cost(self.get_min_count_for_release(storage)?, epsilon))
+    }
+
+    /// Draws the noisy minimum-count check of a release and returns whether it passed. If the
+    /// noisy count falls short, the check alone is charged and counted towards the query limit,
+    /// and the caller must refuse the release with `Release::Refused`, not an error, so that
+    /// the charge is kept.
+    pub(crate) fn check_release_gate(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<bool> {
+        let min_count = self.get_min_count_for_release(storage)?;
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < gate_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if noisy_count_reaches(rng, self.get_count(storage)?, min_count, epsilon)? {
+            return Ok(true);
+        }
+        self.charge_release_gate(storage, epsilon)?;
+        Ok(false)
+    }
+
+    /// Charges the minimum-count check of a release alone and counts it towards the query
+    /// limit, e.g. for a joint release refused by the check of another store after this one
+    /// passed, since the check was drawn all the same
+    pub(crate) fn charge_release_gate(&self, storage: &mut dyn Storage, epsilon: I32F32) -> StdResult<()> {
+        let gate_cost = self.release_gate_cost(storage, epsilon)?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        self.save_privacy_budget(storage, privacy_budget - gate_cost);
+        self.record_query(storage)
+    }
+
+    /// Checks the conditions of `fuzzy_report` for a release made outside of it, e.g. by
+    /// `fuzzy_difference_of_means`. The caller must also budget for and draw
+    /// `check_release_gate`.
+    pub(crate) fn check_ready_for_release(&self, storage: &dyn Storage) -> StdResult<()> {
+        self.check_no_query_cooldown(storage)?;
+        self.check_query_limit(storage)?;
+        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
+            return Err(StdError::generic_err("Status not set to calculating stats") );
+        }
+        if self.get_count(storage)? == 0 {
+            return Err(StdError::generic_err("No data to count"));
+        }
+        Ok(())
+    }
+
+    /// Checks the conditions of a release made for a store that combines several running stats
+    /// stores under its own status and budget, e.g. `StratifiedStatsStore`. Unlike
+    /// `check_ready_for_release`, the status and count are left to the caller, since some of
+    /// the parts may be empty.
+    pub(crate) fn check_ready_for_joint_release(&self, storage: &dyn Storage) -> StdResult<()> {
+        self.check_no_query_cooldown(storage)?;
+        self.check_query_limit(storage)
+    }
+
+    /// Noisy sum over noisy count, with the store's sensitivity and noise source, at a cost of
+    /// 2 * epsilon that the caller must charge. The average is transformed back like the one
+    /// released by `fuzzy_average`, see `with_transform`.
+    pub(crate) fn uncharged_noisy_average(&self, storage: &dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<I32F32> {
+        let (dp_count, dp_sum) = self.uncharged_noisy_count_and_sum(storage, rng, epsilon)?;
+        Ok(self.config.transform.invert(saturating_quotient(dp_sum, dp_count, I64F64::from_num(1))))
+    }
+
+    /// Noisy count and noisy sum, with the store's sensitivity and noise source, at a cost of
+    /// 2 * epsilon that the caller must charge
+    pub(crate) fn uncharged_noisy_count_and_sum(&self, storage: &dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<(I64F64, I64F64)> {
+        let sensitivity = self.sum_sensitivity(storage)?;
+        let seed = draw_seed(rng);
+        let dp_sum = self.get_sum(storage)?.saturating_add(I64F64::from_num(
+            self.config.noise_source.sample(&mut derive_subrng(&seed, SUM_DOMAIN), sensitivity, epsilon)?
+        ));
+        let count_noise = self.config.noise_source.sample(&mut derive_subrng(&seed, AVERAGE_DOMAIN), I32F32::from_num(1_u32), epsilon)?;
+        let dp_count = I64F64::from_num(self.get_count(storage)?) + I64F64::from_num(count_noise);
+        Ok((dp_count, dp_sum))
     }
 
     pub fn get_status(
         &self,
         storage: &dyn Storage,
     ) -> StdResult<RunningStatsStatus> {
-        let mut may_status = self.status.lock().unwrap();
-        match *may_status {
-            Some(status) => {
-                Ok(status)
-            }
-            None => {
-                let status_key = [self.as_slice(), STATUS_KEY].concat();
-                if let Some(status_vec) = storage.get(&status_key) {
-                    let status_byte = status_vec
-                        .as_slice()
-                        .try_into()
-                        .map_err(|err| StdError::parse_err("u8", err))?;
-                    let status = u8::from_be_bytes(status_byte);
-                    let status = match status {
-                        STATUS_COLLECTING_DATA => RunningStatsStatus::CollectingData,
-                        STATUS_CALCULATING_STATS => RunningStatsStatus::CalculatingStats,
-                        _ => { return Err(StdError::generic_err("Invalid u8 value for storage status")) }
-                    };
-                    *may_status = Some(status);
-                    Ok(status)
+        self.status.load_or(storage, self.as_slice(), RunningStatsStatus::CollectingData)
+    }
+
+    /// Returns the block height from which the store behaves as `CalculatingStats`, if one was set
+    pub fn get_collection_deadline(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<u64>> {
+        self.collection_deadline.may_load(storage, self.as_slice())
+    }
+
+    /// Set a block height at which data collection ends on its own: from that height the store
+    /// behaves as `CalculatingStats`, see `get_status_at`, without a separate transaction to
+    /// close collection. The store only learns the height from the `_at` methods,
+    /// `add_observation_at`, `add_observation_for` and `fuzzy_report_at`, or from
+    /// `apply_collection_deadline`, so once a deadline is set observations are only accepted
+    /// through the first two, and statistics are released once it is applied. The deadline is
+    /// removed when the store is cleared. Returns an error if the store is closed.
+    pub fn set_collection_deadline(
+        &self,
+        storage: &mut dyn Storage,
+        block_height: u64,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        self.collection_deadline.save(storage, self.as_slice(), block_height);
+        Ok(())
+    }
+
+    /// Returns the status the store has at the given block height: once the collection
+    /// deadline has passed, a store that was still collecting or paused is `CalculatingStats`,
+    /// or `Paused` if it has no data, as `set_status` would not let it calculate stats. Set a
+    /// later deadline and resume collection to collect again.
+    pub fn get_status_at(
+        &self,
+        storage: &dyn Storage,
+        block_height: u64,
+    ) -> StdResult<RunningStatsStatus> {
+        let status = self.get_status(storage)?;
+        let deadline = self.get_collection_deadline(storage)?;
+        let deadline_passed = matches!(deadline, Some(deadline) if block_height >= deadline);
+        Ok(match status {
+            RunningStatsStatus::CollectingData | RunningStatsStatus::Paused if deadline_passed => {
+                if self.get_count(storage)? == 0 {
+                    RunningStatsStatus::Paused
                 } else {
-                    *may_status = Some(RunningStatsStatus::CollectingData);
-                    Ok(RunningStatsStatus::CollectingData)
+                    RunningStatsStatus::CalculatingStats
                 }
             }
+            status => status,
+        })
+    }
+
+    /// Saves the status the store has at the given block height, so that collection ends once
+    /// the deadline has passed. Call it at the start of every execute message that touches the
+    /// store when observations are added through methods that do not take the height.
+    pub fn apply_collection_deadline(
+        &self,
+        storage: &mut dyn Storage,
+        block_height: u64,
+    ) -> StdResult<()> {
+        let status = self.get_status_at(storage, block_height)?;
+        if status != self.get_status(storage)? {
+            self.status.save(storage, self.as_slice(), status);
         }
+        Ok(())
+    }
+
+    /// A closed store keeps its data, budget and settings as they are, so it cannot be cleared,
+    /// overwritten or reconfigured
+    fn check_not_closed(&self, storage: &dyn Storage) -> StdResult<()> {
+        if self.get_status(storage)? == RunningStatsStatus::Closed {
+            return Err(StdError::generic_err("Store is closed"));
+        }
+        Ok(())
     }
 
     pub fn set_status(
@@ -446,24 +1531,59 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         storage: &mut dyn Storage,
         status: RunningStatsStatus,
     ) -> StdResult<()> {
-        let status_key = [self.as_slice(), STATUS_KEY].concat();
-        match status { 
-            RunningStatsStatus::CollectingData => {
-                if self.get_status(storage)? == RunningStatsStatus::CalculatingStats {
-                    return Err(StdError::generic_err("Cannot set status to collecting data after changing to calculating stats"));
-                }
-                storage.set(&status_key, &[STATUS_COLLECTING_DATA]);
-            }
-            RunningStatsStatus::CalculatingStats => {
-                if self.get_count(storage)? == 0 {
-                    return Err(StdError::generic_err("No data in running stats store"));
-                }
-                storage.set(&status_key, &[STATUS_CALCULATING_STATS]);
-            }
+        self.get_status(storage)?.check_transition(status)?;
+        if status == RunningStatsStatus::CalculatingStats && self.get_count(storage)? == 0 {
+            return Err(StdError::generic_err("No data in running stats store"));
+        }
+        self.status.save(storage, self.as_slice(), status);
+        Ok(())
+    }
+
+    /// Reads every field of the store from storage in a fixed order, whatever query is being
+    /// served, so the host sees the same storage reads for every statistic. Values are cached,
+    /// replacing anything cached before, so the query that follows reads these fields from
+    /// memory. The shared budget is read too, if the store uses one. Histogram bins and bound
+    /// heaps are not read.
+    pub fn preload(&self, storage: &dyn Storage) -> StdResult<()> {
+        let prefix = self.as_slice();
+        self.is_initialized(storage);
+        self.status.reload(storage, prefix)?;
+        self.count.reload(storage, prefix)?;
+        self.sum.reload(storage, prefix)?;
+        self.upper_bound.reload(storage, prefix)?;
+        self.lower_bound.reload(storage, prefix)?;
+        self.epsilon.reload(storage, prefix)?;
+        if let Err(err) = self.avg_sensitivity.reload(storage, prefix) {
+            self.get_legacy_avg_sensitivity(storage).ok_or(err)?;
+        }
+        self.privacy_budget.reload(storage, prefix)?;
+        self.min_count_for_release.reload(storage, prefix)?;
+        self.min_blocks_between_queries.reload(storage, prefix)?;
+        self.last_query_height.reload(storage, prefix)?;
+        self.data_version.reload(storage, prefix)?;
+        self.weight_sum.reload(storage, prefix)?;
+        self.weighted_sum.reload(storage, prefix)?;
+        self.max_total_queries.reload(storage, prefix)?;
+        self.total_queries.reload(storage, prefix)?;
+        self.collection_deadline.reload(storage, prefix)?;
+        self.max_observations_per_epoch.reload(storage, prefix)?;
+        self.rate_epoch_blocks.reload(storage, prefix)?;
+        self.min_epsilon_per_query.reload(storage, prefix)?;
+        self.max_epsilon_per_query.reload(storage, prefix)?;
+        self.max_delta_total.reload(storage, prefix)?;
+        self.max_privacy_budget.reload(storage, prefix)?;
+        self.delta_spent.reload(storage, prefix)?;
+        self.rejected_count.reload(storage, prefix)?;
+        self.clamped_count.reload(storage, prefix)?;
+        if let Some(accountant) = self.config.shared_budget {
+            accountant.get_privacy_budget(storage)?;
         }
         Ok(())
     }
 
+    /// Resets epsilon, average sensitivity and privacy budget to the given values or back to
+    /// their defaults, together with the data, as `reset` does.
+    #[deprecated(note = "use `reset`, which can also keep the current epsilon, sensitivity or budget")]
     pub fn clear(
         &self,
         storage: &mut dyn Storage,
@@ -471,125 +1591,2618 @@ impl<'a, Ser: Serde> RunningStatsStore<'a, Ser> {
         avg_sensitivity: Option<I32F32>,
         privacy_budget: Option<I32F32>,
     ) -> StdResult<()> {
-        self.set_status(storage, RunningStatsStatus::CollectingData)?;
+        self.reset(storage, ResetPolicy::new(epsilon, avg_sensitivity, privacy_budget))
+    }
+
+    /// Resets the store to its initial state so that a new round of data can be collected: the
+    /// status goes back to collecting data (even after stats have been calculated), count, sum,
+    /// bounds, query count, collection deadline and commitment are reset. Epsilon, average
+    /// sensitivity and privacy budget are kept, set or reset to their defaults as `policy` says.
+    /// A shared privacy budget is left untouched. This also marks the store as initialized.
+    pub fn reset(
+        &self,
+        storage: &mut dyn Storage,
+        policy: ResetPolicy,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        let epsilon = policy.epsilon.resolve(self.epsilon.may_load(storage, self.as_slice())?);
+        if matches!(epsilon, Some(epsilon) if epsilon <= I32F32::from_num(0)) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        let avg_sensitivity = policy.avg_sensitivity.resolve(self.get_avg_sensitivity(storage)?);
+        let privacy_budget = policy.privacy_budget.resolve(self.privacy_budget.may_load(storage, self.as_slice())?);
+        // the defaults of 1 must be within the privacy policy too
+        if let Some(privacy_policy) = self.get_privacy_policy(storage)? {
+            privacy_policy.check_epsilon(epsilon.unwrap_or(I32F32::from(1)))?;
+            if self.config.shared_budget.is_none() {
+                privacy_policy.check_privacy_budget(privacy_budget.unwrap_or(I32F32::from(1)))?;
+            }
+        }
+        self.status.remove(storage, self.as_slice());
         self.set_count(storage, 0);
         self.set_sum(storage, I64F64::from(0));
-        self.set_upper_bound(storage, I32F32::min_value());
-        self.set_lower_bound(storage, I32F32::max_value());
+        self.weight_sum.remove(storage, self.as_slice());
+        self.weighted_sum.remove(storage, self.as_slice());
+        self.total_queries.remove(storage, self.as_slice());
+        self.collection_deadline.remove(storage, self.as_slice());
+        self.delta_spent.remove(storage, self.as_slice());
+        self.rejected_count.remove(storage, self.as_slice());
+        self.clamped_count.remove(storage, self.as_slice());
+        // the salt is kept, so a new round can be committed to with the same salt
+        storage.remove(&[self.as_slice(), COMMITMENT_KEY].concat());
+        self.upper_bound.remove(storage, self.as_slice());
+        self.lower_bound.remove(storage, self.as_slice());
+        if self.uses_bound_heaps() {
+            self.min_heap().clear(storage)?;
+            self.max_heap().clear(storage)?;
+        }
+        if let Some(histogram) = self.bound_histogram() {
+            histogram.clear(storage);
+        }
         if let Some(epsilon) = epsilon {
-            self.set_epsilon(storage, epsilon);
+            self.set_epsilon(storage, epsilon)?;
         } else {
-            let epsilon_key = [self.as_slice(), EPSILON_KEY].concat();
-            storage.remove(&epsilon_key);
+            self.epsilon.remove(storage, self.as_slice());
         }
         self.set_average_sensitivity(storage, avg_sensitivity)?;
-        if let Some(privacy_budget) = privacy_budget {
-            self.set_privacy_budget(storage, privacy_budget);
-        } else {
-            let budget_key = [self.as_slice(), PRIVACY_BUDGET_KEY].concat();
-            storage.remove(&budget_key);
+        // a shared budget belongs to the accountant, so it is not reset here
+        if self.config.shared_budget.is_none() {
+            if let Some(privacy_budget) = privacy_budget {
+                self.save_privacy_budget(storage, privacy_budget);
+            } else {
+                self.privacy_budget.remove(storage, self.as_slice());
+            }
         }
+        self.bump_data_version(storage)?;
+        self.set_initialized(storage);
         Ok(())
     }
 
-    pub fn add_observation(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
-        if self.get_status(storage)? != RunningStatsStatus::CollectingData {
-            return Err(StdError::generic_err("Status is not set to collecting data") );
+    /// Exports the current state of the store, e.g. to migrate it to another contract or namespace
+    pub fn export_snapshot(&self, storage: &dyn Storage) -> StdResult<RunningStatsSnapshot> {
+        Ok(RunningStatsSnapshot {
+            count: self.get_count(storage)?,
+            sum: self.get_sum(storage)?.to_stored(),
+            upper_bound: self.get_upper_bound(storage)?.to_stored(),
+            lower_bound: self.get_lower_bound(storage)?.to_stored(),
+            epsilon: self.get_epsilon(storage)?.to_stored(),
+            avg_sensitivity: self.get_avg_sensitivity(storage)?.map(|sensitivity| sensitivity.to_stored()),
+            privacy_budget: self.get_privacy_budget(storage)?.to_stored(),
+            status: self.get_status(storage)?.to_stored()[0],
+            weights: Some((
+                self.get_weight_sum(storage)?.to_stored(),
+                self.get_weighted_sum(storage)?.to_stored(),
+            )),
+        })
+    }
+
+    /// Overwrites the state of the store with a snapshot. The imported privacy budget replaces the
+    /// remaining budget, so only import snapshots from a trusted source. A shared privacy budget is
+    /// left untouched. The imported epsilon and budget must be within the privacy policy.
+    pub fn import_snapshot(&self, storage: &mut dyn Storage, snapshot: &RunningStatsSnapshot) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        let status = RunningStatsStatus::from_stored(&[snapshot.status])?;
+        if status == RunningStatsStatus::CalculatingStats && snapshot.count == 0 {
+            return Err(StdError::generic_err("No data in running stats snapshot"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_epsilon(I32F32::from_stored(&snapshot.epsilon)?)?;
+            if self.config.shared_budget.is_none() {
+                policy.check_privacy_budget(I32F32::from_stored(&snapshot.privacy_budget)?)?;
+            }
+        }
+        self.set_count(storage, snapshot.count);
+        // a snapshot does not carry the observations, so the commitment starts over
+        storage.remove(&[self.as_slice(), COMMITMENT_KEY].concat());
+        self.set_sum(storage, I64F64::from_stored(&snapshot.sum)?);
+        match &snapshot.weights {
+            Some((weight_sum, weighted_sum)) => {
+                self.weight_sum.save(storage, self.as_slice(), I64F64::from_stored(weight_sum)?);
+                self.weighted_sum.save(storage, self.as_slice(), I64F64::from_stored(weighted_sum)?);
+            }
+            None => {
+                self.weight_sum.remove(storage, self.as_slice());
+                self.weighted_sum.remove(storage, self.as_slice());
+            }
+        }
+        self.set_upper_bound(storage, I32F32::from_stored(&snapshot.upper_bound)?);
+        self.set_lower_bound(storage, I32F32::from_stored(&snapshot.lower_bound)?);
+        self.set_epsilon(storage, I32F32::from_stored(&snapshot.epsilon)?)?;
+        let avg_sensitivity = match &snapshot.avg_sensitivity {
+            Some(sensitivity) => Some(I32F32::from_stored(sensitivity)?),
+            None => None,
+        };
+        self.set_average_sensitivity(storage, avg_sensitivity)?;
+        if self.config.shared_budget.is_none() {
+            self.save_privacy_budget(storage, I32F32::from_stored(&snapshot.privacy_budget)?);
         }
+        self.status.save(storage, self.as_slice(), status);
+        self.bump_data_version(storage)?;
+        self.set_initialized(storage);
+        Ok(())
+    }
 
-        let new_count = self.get_count(storage)?.checked_add(1).ok_or(
-            StdError::generic_err("Count overflow")
-        )?;
-        self.set_count(storage, new_count);
+    /// Same as `export_snapshot`, e.g. to back up a live aggregation in the contract's own state
+    /// during a migration
+    pub fn export_state(&self, storage: &dyn Storage) -> StdResult<StoredRunningStats> {
+        self.export_snapshot(storage)
+    }
 
-        let new_sum = self.get_sum(storage)?.checked_add(I64F64::from_num(x)).ok_or(
-            StdError::generic_err("Sum overflow")
-        )?;
-        self.set_sum(storage, new_sum);
+    /// Same as `import_snapshot`, for state returned by `export_state`
+    pub fn import_state(&self, storage: &mut dyn Storage, state: StoredRunningStats) -> StdResult<()> {
+        self.import_snapshot(storage, &state)
+    }
 
-        if self.get_upper_bound(storage)? < x {
-            self.set_upper_bound(storage, x);
-        }
+    /// Returns the privacy settings and state of the store in one read, e.g. for a front-end to
+    /// display. Convert it with `DpParamsResponse::from` to return it from a query.
+    pub fn get_params(&self, storage: &dyn Storage) -> StdResult<DpParams> {
+        Ok(DpParams {
+            epsilon: self.get_epsilon(storage)?,
+            privacy_budget: self.get_privacy_budget(storage)?,
+            composition: if self.config.shared_budget.is_some() { CompositionMode::Shared } else { CompositionMode::PerStore },
+            clipping: self.get_clipping(),
+            noise_source: self.get_noise_source(),
+            sensitivity_policy: self.get_sensitivity_policy(),
+            status: self.get_status(storage)?,
+            min_count_for_release: self.get_min_count_for_release(storage)?,
+            max_total_queries: self.get_max_total_queries(storage)?,
+            total_queries: self.get_total_queries(storage)?,
+        })
+    }
 
-        if self.get_lower_bound(storage)? > x {
-            self.set_lower_bound(storage, x);
+    /// Sets the secret salt that blinds observations in the commitment kept by stores declared
+    /// `with_observation_commitment`. The salt must be set before the first observation, and
+    /// should come from a source the host cannot predict, e.g. the contract's entropy. Without
+    /// it, a small range of observations could be recovered by hashing every candidate value.
+    pub fn set_commitment_salt(&self, storage: &mut dyn Storage, salt: &[u8; 32]) -> StdResult<()> {
+        if !self.config.commit_observations {
+            return Err(StdError::generic_err("Store does not keep an observation commitment"));
         }
-
+        if self.get_count(storage)? > 0 {
+            return Err(StdError::generic_err("Commitment salt must be set before any observation"));
+        }
+        storage.set(&[self.as_slice(), COMMITMENT_SALT_KEY].concat(), salt);
         Ok(())
     }
 
-    pub fn fuzzy_count(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<I32F32> {
-        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
-            return Err(StdError::generic_err("Status not set to calculating stats") );
+    /// Returns the commitment to every observation added or removed since the store was last
+    /// cleared, or None if the store does not keep one or no observation has been added yet. It
+    /// changes with every add and remove, so it reveals the number of changes just like the data
+    /// version, and should only be served to admins and auditors.
+    pub fn get_commitment(&self, storage: &dyn Storage) -> StdResult<Option<[u8; 32]>> {
+        if !self.config.commit_observations {
+            return Ok(None);
+        }
+        match storage.get(&[self.as_slice(), COMMITMENT_KEY].concat()) {
+            Some(commitment) => commitment.try_into().map(Some).map_err(|_| {
+                StdError::generic_err("Invalid observation commitment")
+            }),
+            None => Ok(None),
         }
+    }
 
-        if self.is_empty(storage)? {
-            return Err(StdError::generic_err("No data to count"));
+    /// Chains an added (operation 0) or removed (operation 1) observation into the commitment.
+    /// Each leaf is the hash of the salt, the operation and the stored bytes of the observation,
+    /// and the new commitment is the hash of the previous commitment followed by the leaf.
+    fn update_commitment(&self, storage: &mut dyn Storage, operation: u8, x: I32F32) -> StdResult<()> {
+        if !self.config.commit_observations {
+            return Ok(());
         }
+        let salt = storage.get(&[self.as_slice(), COMMITMENT_SALT_KEY].concat()).ok_or(
+            StdError::generic_err("Commitment salt is not set")
+        )?;
+        let leaf = sha_256(&[salt.as_slice(), &[operation], &x.to_stored()].concat());
+        let previous = self.get_commitment(storage)?.unwrap_or([0; 32]);
+        let commitment = sha_256(&[previous.as_slice(), &leaf].concat());
+        storage.set(&[self.as_slice(), COMMITMENT_KEY].concat(), &commitment);
+        Ok(())
+    }
 
-        let epsilon = self.get_epsilon(storage)?;
-        let privacy_budget = self.get_privacy_budget(storage)?;
-        if privacy_budget < epsilon { // privacy cost of COUNT = 1 * epsilon
-            return Err(StdError::generic_err("Privacy budget exhausted"));
+    /// Transforms an incoming observation and applies the outlier policy to it
+    fn ingest(&self, x: I32F32) -> StdResult<Ingested> {
+        let x = self.config.transform.apply(x)?;
+        let (lower, upper) = match self.config.clipping {
+            Some(range) => range,
+            None => return Ok(Ingested::Kept(x)),
+        };
+        if x >= lower && x <= upper {
+            return Ok(Ingested::Kept(x));
         }
+        Ok(match self.config.outlier_policy {
+            OutlierPolicy::Reject => Ingested::Rejected,
+            OutlierPolicy::Clamp => Ingested::Clamped(x.clamp(lower, upper)),
+            OutlierPolicy::Accept => Ingested::Kept(x),
+        })
+    }
 
-        // sensitivity is always 1 for COUNT queries
-        let sensitivity = I32F32::from_num(1_u32);
-        
-        // calculate a fuzzy count
-        let scale = sensitivity / epsilon;
-        let noise = laplace(rng, scale);
-        let fuzzy_count = I32F32::from_num(self.get_count(storage)?) + noise;
+    fn count_outlier(&self, storage: &mut dyn Storage, counter: &CachedField<u32>) -> StdResult<()> {
+        let count = counter.load_or(storage, self.as_slice(), 0)?.checked_add(1).ok_or(
+            StdError::generic_err("Outlier count overflow")
+        )?;
+        counter.save(storage, self.as_slice(), count);
+        Ok(())
+    }
 
-        // update the remaining privacy budget
-        self.set_privacy_budget(storage, privacy_budget - epsilon);
+    /// Takes back the count of an outlier whose observation is removed
+    fn uncount_outlier(&self, storage: &mut dyn Storage, counter: &CachedField<u32>) -> StdResult<()> {
+        let count = counter.load_or(storage, self.as_slice(), 0)?.saturating_sub(1);
+        counter.save(storage, self.as_slice(), count);
+        Ok(())
+    }
 
-        Ok(fuzzy_count)
+    /// Adds an observation, after the transform and the outlier policy. An observation
+    /// rejected by the policy is only counted, and is not added. A store with a collection
+    /// deadline refuses it, since the deadline cannot be checked without the block height; use
+    /// `add_observation_at` instead. A store with an observation rate limit refuses it too; use
+    /// `add_observation_for`.
+    pub fn add_observation(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        self.check_no_rate_limit(storage)?;
+        self.add_observation_at_height(storage, None, x)
     }
 
-    pub fn fuzzy_average(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<I32F32> {
-        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
-            return Err(StdError::generic_err("Status not set to calculating stats") );
+    fn add_observation_at_height(&self, storage: &mut dyn Storage, block_height: Option<u64>, x: I32F32) -> StdResult<()> {
+        match self.ingest(x)? {
+            Ingested::Kept(x) => self.add_ingested(storage, block_height, x),
+            Ingested::Clamped(x) => {
+                self.add_ingested(storage, block_height, x)?;
+                self.count_outlier(storage, &self.clamped_count)
+            }
+            Ingested::Rejected => {
+                self.check_collecting(storage, block_height)?;
+                self.count_outlier(storage, &self.rejected_count)
+            }
         }
+    }
 
-        if self.is_empty(storage)? {
-            return Err(StdError::generic_err("No data to count"));
+    /// Observations are only accepted while collecting data. With a collection deadline, the
+    /// block height is needed to tell whether it has passed, so observations must then come
+    /// through the `_at` methods, which apply the deadline before this check.
+    fn check_collecting(&self, storage: &dyn Storage, block_height: Option<u64>) -> StdResult<()> {
+        if self.get_status(storage)? != RunningStatsStatus::CollectingData {
+            return Err(StdError::generic_err("Status is not set to collecting data") );
         }
-
-        // sequential queries for sum + count
-        let epsilon = self.get_epsilon(storage)?;
-        let privacy_cost: I32F32 = 2 * epsilon;
-        let privacy_budget = self.get_privacy_budget(storage)?;
-        if privacy_budget < privacy_cost { 
-            return Err(StdError::generic_err("Privacy budget exhausted"));
+        if block_height.is_none() && self.get_collection_deadline(storage)?.is_some() {
+            return Err(StdError::generic_err("Store has a collection deadline, use the methods that take the block height"));
         }
+        Ok(())
+    }
 
-        let sensitivity: I32F32;
-        if let Some(sensitivity_for_average) = self.get_avg_sensitivity(storage)? {
-            sensitivity = sensitivity_for_average;
-        } else {
-            // using a bounded sensitivity for sum
-            sensitivity = self.get_upper_bound(storage)? - self.get_lower_bound(storage)?;
+    /// Returns true if `x` lies outside the clipping range after the transform, so that the
+    /// outlier policy applies to it, e.g. to refuse the message instead of dropping the
+    /// observation
+    pub fn is_outlier(&self, x: I32F32) -> StdResult<bool> {
+        let x = self.config.transform.apply(x)?;
+        Ok(matches!(self.config.clipping, Some((lower, upper)) if x < lower || x > upper))
+    }
+
+    /// Adds an observation that has already been transformed and clipped
+    fn add_ingested(&self, storage: &mut dyn Storage, block_height: Option<u64>, x: I32F32) -> StdResult<()> {
+        self.check_collecting(storage, block_height)?;
+        // first, so that a missing salt refuses the observation before anything is written
+        self.update_commitment(storage, 0, x)?;
+
+        let new_count = self.get_count(storage)?.checked_add(1).ok_or(
+            StdError::generic_err("Count overflow")
+        )?;
+        self.set_count(storage, new_count);
+
+        let new_sum = self.get_sum(storage)?.checked_add(I64F64::from_num(x)).ok_or(
+            StdError::generic_err("Sum overflow")
+        )?;
+        self.set_sum(storage, new_sum);
+
+        // bounds are data-independent when clipping, so do not track them
+        if self.enforced_clipping().is_none() {
+            if self.get_upper_bound(storage)? < x {
+                self.set_upper_bound(storage, x);
+            }
+
+            if self.get_lower_bound(storage)? > x {
+                self.set_lower_bound(storage, x);
+            }
         }
 
-        let scale = sensitivity / epsilon;
-    
-        let sum_noise = laplace(rng, scale);
-        let dp_sum = self.get_sum(storage)? + I64F64::from_num(sum_noise);
-    
-        // calculate fuzzy count
+        if self.uses_bound_heaps() {
+            self.min_heap().push(storage, x)?;
+            self.max_heap().push(storage, x)?;
+        }
+
+        if let Some(histogram) = self.bound_histogram() {
+            histogram.add(storage, x)?;
+        }
+
+        self.bump_data_version(storage)
+    }
+
+    /// Adds an observation at the given block height, which is refused once the collection
+    /// deadline has passed. A store with an observation rate limit refuses it; use
+    /// `add_observation_for`.
+    pub fn add_observation_at(&self, storage: &mut dyn Storage, block_height: u64, x: I32F32) -> StdResult<()> {
+        self.check_no_rate_limit(storage)?;
+        self.apply_collection_deadline(storage, block_height)?;
+        self.add_observation_at_height(storage, Some(block_height), x)
+    }
+
+    /// Removes a previously added observation, e.g. when a user withdraws their data. 
+    /// 
+    /// Count and sum are always kept consistent. If the store was declared `with_bound_heaps`, 
+    /// the observation must be present and the upper and lower bounds are recomputed exactly. 
+    /// If it was declared `with_bound_histogram`, the bounds are recomputed approximately from the 
+    /// histogram. Otherwise the bounds are left unchanged (they may be wider than the remaining 
+    /// data) until the store becomes empty, and the removal is only checked against the bounds
+    /// and the remaining sum, see `check_removal`.
+    ///
+    /// A store declared `with_max_weight` refuses it, since the weight of the observation is
+    /// needed to keep the weighted sums consistent; use `remove_weighted_observation`. A store
+    /// with a collection deadline refuses it too; use `remove_observation_at`.
+    pub fn remove_observation(&self, storage: &mut dyn Storage, x: I32F32) -> StdResult<()> {
+        self.remove_observation_at_height(storage, None, x)
+    }
+
+    /// Removes a previously added observation at the given block height, which is refused
+    /// once the collection deadline has passed, like adds
+    pub fn remove_observation_at(&self, storage: &mut dyn Storage, block_height: u64, x: I32F32) -> StdResult<()> {
+        self.apply_collection_deadline(storage, block_height)?;
+        self.remove_observation_at_height(storage, Some(block_height), x)
+    }
+
+    fn remove_observation_at_height(&self, storage: &mut dyn Storage, block_height: Option<u64>, x: I32F32) -> StdResult<()> {
+        if self.config.max_weight.is_some() {
+            return Err(StdError::generic_err("Store holds weighted observations, use remove_weighted_observation"));
+        }
+        self.remove_from_stats(storage, block_height, x)
+    }
+
+    /// Removes an observation from the count, sum, bounds, histogram and outlier counts,
+    /// leaving the weighted sums to the caller
+    fn remove_from_stats(&self, storage: &mut dyn Storage, block_height: Option<u64>, x: I32F32) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        self.check_collecting(storage, block_height)?;
+
+        let (x, clamped) = match self.ingest(x)? {
+            Ingested::Kept(x) => (x, false),
+            Ingested::Clamped(x) => (x, true),
+            Ingested::Rejected => return Err(StdError::generic_err("Observation was rejected as an outlier")),
+        };
+
+        let new_count = self.get_count(storage)?.checked_sub(1).ok_or(
+            StdError::generic_err("No observations to remove")
+        )?;
+
+        if self.uses_bound_heaps() {
+            if !self.min_heap().remove(storage, x)? || !self.max_heap().remove(storage, x)? {
+                return Err(StdError::generic_err("Observation not found"));
+            }
+        }
+
+        let new_sum = self.get_sum(storage)?.checked_sub(I64F64::from_num(x)).ok_or(
+            StdError::generic_err("Sum overflow")
+        )?;
+        self.check_removal(storage, x, new_count, new_sum)?;
+        self.set_count(storage, new_count);
+        self.set_sum(storage, new_sum);
+
+        if self.enforced_clipping().is_none() {
+            if new_count == 0 {
+                self.set_upper_bound(storage, I32F32::min_value());
+                self.set_lower_bound(storage, I32F32::max_value());
+            } else if self.uses_bound_heaps() {
+                if let Some(upper_bound) = self.max_heap().peek(storage)? {
+                    self.set_upper_bound(storage, upper_bound);
+                }
+                if let Some(lower_bound) = self.min_heap().peek(storage)? {
+                    self.set_lower_bound(storage, lower_bound);
+                }
+            }
+        }
+
+        if let Some(histogram) = self.bound_histogram() {
+            histogram.remove(storage, x)?;
+            if new_count > 0 && self.uses_bound_histogram() {
+                let upper_bound = self.get_upper_bound(storage)?;
+                if x >= upper_bound {
+                    let upper_bound = histogram.recompute_upper_bound(storage, upper_bound)?;
+                    self.set_upper_bound(storage, upper_bound);
+                }
+                let lower_bound = self.get_lower_bound(storage)?;
+                if x <= lower_bound {
+                    let lower_bound = histogram.recompute_lower_bound(storage, lower_bound)?;
+                    self.set_lower_bound(storage, lower_bound);
+                }
+            }
+        }
+
+        if clamped {
+            self.uncount_outlier(storage, &self.clamped_count)?;
+        }
+        self.update_commitment(storage, 1, x)?;
+        self.bump_data_version(storage)
+    }
+
+    /// Without bound heaps the removed observation cannot be looked up, so refuse removals that
+    /// no set of added observations could explain: a value outside the clipping range or the
+    /// tracked bounds, or a remaining sum that does not fit between the remaining count times
+    /// the lower and upper bounds. A removal that passes can still be of a value that was never
+    /// added, if it is within the bounds.
+    fn check_removal(&self, storage: &dyn Storage, x: I32F32, new_count: u32, new_sum: I64F64) -> StdResult<()> {
+        let (lower, upper) = match self.enforced_clipping() {
+            Some(range) => range,
+            None => (self.get_lower_bound(storage)?, self.get_upper_bound(storage)?),
+        };
+        if x < lower || x > upper {
+            return Err(StdError::generic_err("Observation not found"));
+        }
+        let remaining = I64F64::from_num(new_count);
+        let min_sum = remaining.saturating_mul(I64F64::from_num(lower));
+        let max_sum = remaining.saturating_mul(I64F64::from_num(upper));
+        if new_sum < min_sum || new_sum > max_sum {
+            return Err(StdError::generic_err("Removal is inconsistent with the remaining observations"));
+        }
+        Ok(())
+    }
+
+    /// Replaces a previously added observation with a new value. A store declared
+    /// `with_max_weight` refuses it, like `remove_observation`. A store with a collection
+    /// deadline or an observation rate limit refuses it too; use `update_observation_at` or
+    /// `update_observation_for`.
+    pub fn update_observation(
+        &self,
+        storage: &mut dyn Storage,
+        old: I32F32,
+        new: I32F32,
+    ) -> StdResult<()> {
+        self.check_no_rate_limit(storage)?;
+        self.remove_observation_at_height(storage, None, old)?;
+        self.add_observation_at_height(storage, None, new)
+    }
+
+    /// Replaces a previously added observation at the given block height, which is refused once
+    /// the collection deadline has passed. A store with an observation rate limit refuses it;
+    /// use `update_observation_for`.
+    pub fn update_observation_at(
+        &self,
+        storage: &mut dyn Storage,
+        block_height: u64,
+        old: I32F32,
+        new: I32F32,
+    ) -> StdResult<()> {
+        self.check_no_rate_limit(storage)?;
+        self.apply_collection_deadline(storage, block_height)?;
+        self.remove_observation_at_height(storage, Some(block_height), old)?;
+        self.add_observation_at_height(storage, Some(block_height), new)
+    }
+
+    /// Replaces an observation of a contributor at the given block height. The new value counts
+    /// towards the contributor's observation rate limit like `add_observation_for`, and the
+    /// collection deadline is applied first.
+    pub fn update_observation_for(
+        &self,
+        storage: &mut dyn Storage,
+        contributor: &[u8],
+        block_height: u64,
+        old: I32F32,
+        new: I32F32,
+    ) -> StdResult<()> {
+        let rate_record = self.check_rate_limit(storage, contributor, block_height)?;
+        self.apply_collection_deadline(storage, block_height)?;
+        self.remove_observation_at_height(storage, Some(block_height), old)?;
+        self.add_observation_at_height(storage, Some(block_height), new)?;
+        if let Some((rate_key, record)) = rate_record {
+            storage.set(&rate_key, &record);
+        }
+        Ok(())
+    }
+
+    /// Adds an observation with a weight, e.g. a sentiment score weighted by the voter's stake.
+    /// The observation counts towards every unweighted statistic as usual, and its weight and
+    /// weighted value are added to the sums used by `fuzzy_weighted_average`. The weight must
+    /// be in [0, max_weight], so the store must be declared `with_max_weight`.
+    pub fn add_weighted_observation(
+        &self,
+        storage: &mut dyn Storage,
+        x: I32F32,
+        weight: I32F32,
+    ) -> StdResult<()> {
+        self.check_no_rate_limit(storage)?;
+        let max_weight = self.config.max_weight.ok_or(
+            StdError::generic_err("Weighted observations require a max weight")
+        )?;
+        if weight < I32F32::from_num(0) || weight > max_weight {
+            return Err(StdError::generic_err("Weight must be between zero and the max weight"));
+        }
+
+        let (x, clamped) = match self.ingest(x)? {
+            Ingested::Kept(x) => (x, false),
+            Ingested::Clamped(x) => (x, true),
+            Ingested::Rejected => return self.add_observation(storage, x),
+        };
+        let weight = I64F64::from_num(weight);
+        let new_weight_sum = self.get_weight_sum(storage)?.checked_add(weight).ok_or(
+            StdError::generic_err("Weight sum overflow")
+        )?;
+        let weighted_x = weight.checked_mul(I64F64::from_num(x)).ok_or(
+            StdError::generic_err("Weighted sum overflow")
+        )?;
+        let new_weighted_sum = self.get_weighted_sum(storage)?.checked_add(weighted_x).ok_or(
+            StdError::generic_err("Weighted sum overflow")
+        )?;
+
+        self.add_ingested(storage, None, x)?;
+        self.weight_sum.save(storage, self.as_slice(), new_weight_sum);
+        self.weighted_sum.save(storage, self.as_slice(), new_weighted_sum);
+        if clamped {
+            self.count_outlier(storage, &self.clamped_count)?;
+        }
+        Ok(())
+    }
+
+    /// Removes an observation added with `add_weighted_observation`, given with the weight it
+    /// was added with, and takes its weight and weighted value out of the sums used by
+    /// `fuzzy_weighted_average`. Observations added to the store without a weight are removed
+    /// with a weight of zero. Otherwise it is checked like `remove_observation`, and the
+    /// remaining sum of weights cannot become negative.
+    pub fn remove_weighted_observation(
+        &self,
+        storage: &mut dyn Storage,
+        x: I32F32,
+        weight: I32F32,
+    ) -> StdResult<()> {
+        let max_weight = self.config.max_weight.ok_or(
+            StdError::generic_err("Weighted observations require a max weight")
+        )?;
+        if weight < I32F32::from_num(0) || weight > max_weight {
+            return Err(StdError::generic_err("Weight must be between zero and the max weight"));
+        }
+
+        let ingested_x = match self.ingest(x)? {
+            Ingested::Kept(x) | Ingested::Clamped(x) => x,
+            Ingested::Rejected => return Err(StdError::generic_err("Observation was rejected as an outlier")),
+        };
+        let weight = I64F64::from_num(weight);
+        let new_weight_sum = self.get_weight_sum(storage)?
+            .checked_sub(weight)
+            .filter(|weight_sum| *weight_sum >= I64F64::from(0))
+            .ok_or(StdError::generic_err("Weight is larger than the remaining sum of weights"))?;
+        let weighted_x = weight.checked_mul(I64F64::from_num(ingested_x)).ok_or(
+            StdError::generic_err("Weighted sum overflow")
+        )?;
+        let new_weighted_sum = self.get_weighted_sum(storage)?.checked_sub(weighted_x).ok_or(
+            StdError::generic_err("Weighted sum overflow")
+        )?;
+
+        self.remove_from_stats(storage, None, x)?;
+        self.weight_sum.save(storage, self.as_slice(), new_weight_sum);
+        self.weighted_sum.save(storage, self.as_slice(), new_weighted_sum);
+        Ok(())
+    }
+
+    /// Returns true if the contributor has already submitted an observation with `add_observation_once`
+    pub fn has_contributed(&self, storage: &dyn Storage, contributor: &[u8]) -> bool {
+        // without a salt nobody can have contributed
+        self.contributor_key(storage, contributor).map_or(false, |key| storage.get(&key).is_some())
+    }
+
+    /// Adds an observation at most once per contributor. Only a salted hash of the contributor
+    /// id (e.g. the sender address) is recorded in storage, with the salt of
+    /// `set_rate_limit_salt`, which must be set first. Repeat submissions are rejected so that
+    /// each contributor affects the statistics by at most one record.
+    pub fn add_observation_once(
+        &self,
+        storage: &mut dyn Storage,
+        contributor: &[u8],
+        x: I32F32,
+    ) -> StdResult<()> {
+        let contributor_key = self.contributor_key(storage, contributor)?;
+        if storage.get(&contributor_key).is_some() {
+            return Err(StdError::generic_err("Contributor has already submitted an observation"));
+        }
+        self.add_observation(storage, x)?;
+        storage.set(&contributor_key, &[1]);
+        Ok(())
+    }
+
+    fn contributor_key(&self, storage: &dyn Storage, contributor: &[u8]) -> StdResult<Vec<u8>> {
+        Ok([self.as_slice(), CONTRIBUTOR_KEY, &self.salted_contributor_hash(storage, contributor)?].concat())
+    }
+
+    /// Hash of a contributor id with the salt of `set_rate_limit_salt`, so that a stored hash
+    /// cannot be matched against a list of known addresses
+    fn salted_contributor_hash(&self, storage: &dyn Storage, contributor: &[u8]) -> StdResult<[u8; 32]> {
+        let salt = storage.get(&[self.as_slice(), RATE_SALT_KEY].concat()).ok_or(
+            StdError::generic_err("Rate limit salt is not set")
+        )?;
+        Ok(sha_256(&[salt.as_slice(), contributor].concat()))
+    }
+
+    /// Returns the most observations one contributor may add per epoch with
+    /// `add_observation_for`, and the length of an epoch in blocks, if there is a limit
+    pub fn get_observation_rate_limit(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<(u32, u64)>> {
+        let max_observations = self.max_observations_per_epoch.may_load(storage, self.as_slice())?;
+        let epoch_blocks = self.rate_epoch_blocks.may_load(storage, self.as_slice())?;
+        Ok(max_observations.zip(epoch_blocks))
+    }
+
+    /// Limit each contributor to `max_observations_per_address_per_epoch` observations added
+    /// with `add_observation_for` in every epoch of `epoch_blocks` blocks, so that spam
+    /// contributors can neither dominate the statistics nor run up gas and storage costs. Each
+    /// contributor can still add several records, so the privacy guarantee of a record does not
+    /// cover a contributor as a whole. Once a limit is set, every other way of adding
+    /// observations is refused, and `set_rate_limit_salt` must be called before the first
+    /// `add_observation_for`. This is kept when the store is cleared.
+    pub fn set_observation_rate_limit(
+        &self,
+        storage: &mut dyn Storage,
+        max_observations_per_address_per_epoch: u32,
+        epoch_blocks: u64,
+    ) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        if epoch_blocks == 0 {
+            return Err(StdError::generic_err("Epoch must be at least one block"));
+        }
+        self.max_observations_per_epoch.save(storage, self.as_slice(), max_observations_per_address_per_epoch);
+        self.rate_epoch_blocks.save(storage, self.as_slice(), epoch_blocks);
+        Ok(())
+    }
+
+    /// Sets the secret salt that the contributor ids of `add_observation_for` and
+    /// `add_observation_once` are hashed with, so that the stored hashes cannot be matched
+    /// against a list of known addresses. It should come from a source the host cannot predict,
+    /// e.g. the contract's entropy. The salt can only be set once, since a new salt would forget
+    /// the observations each contributor already added in the current epoch, and who already
+    /// contributed with `add_observation_once`.
+    pub fn set_rate_limit_salt(&self, storage: &mut dyn Storage, salt: &[u8; 32]) -> StdResult<()> {
+        self.check_not_closed(storage)?;
+        let salt_key = [self.as_slice(), RATE_SALT_KEY].concat();
+        if storage.get(&salt_key).is_some() {
+            return Err(StdError::generic_err("Rate limit salt is already set"));
+        }
+        storage.set(&salt_key, salt);
+        Ok(())
+    }
+
+    /// Without a contributor the rate limit cannot be applied, so stores with one only accept
+    /// observations through `add_observation_for`
+    fn check_no_rate_limit(&self, storage: &dyn Storage) -> StdResult<()> {
+        if self.get_observation_rate_limit(storage)?.is_some() {
+            return Err(StdError::generic_err("Store has an observation rate limit, use add_observation_for"));
+        }
+        Ok(())
+    }
+
+    /// Adds an observation from a contributor at the given block height, refused if the
+    /// contributor has already added the most observations allowed in the current epoch. Only a
+    /// salted hash of the contributor id is stored, with the epoch and the number of
+    /// observations added in it. The collection deadline is applied first.
+    pub fn add_observation_for(
+        &self,
+        storage: &mut dyn Storage,
+        contributor: &[u8],
+        block_height: u64,
+        x: I32F32,
+    ) -> StdResult<()> {
+        let rate_record = self.check_rate_limit(storage, contributor, block_height)?;
+        self.apply_collection_deadline(storage, block_height)?;
+        self.add_observation_at_height(storage, Some(block_height), x)?;
+        if let Some((rate_key, record)) = rate_record {
+            storage.set(&rate_key, &record);
+        }
+        Ok(())
+    }
+
+    /// Refuses an observation from a contributor who has already added the most observations
+    /// allowed in the epoch of `block_height`. Returns the storage key and the record to save
+    /// once the observation is added, or None if the store has no rate limit.
+    fn check_rate_limit(
+        &self,
+        storage: &dyn Storage,
+        contributor: &[u8],
+        block_height: u64,
+    ) -> StdResult<Option<(Vec<u8>, Vec<u8>)>> {
+        let (max_observations, epoch_blocks) = match self.get_observation_rate_limit(storage)? {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+        let epoch = block_height / epoch_blocks;
+        let rate_key = [self.as_slice(), RATE_COUNT_KEY, &self.salted_contributor_hash(storage, contributor)?].concat();
+        let added = match storage.get(&rate_key) {
+            Some(record) if record.len() == 12 && record[..8] == epoch.to_be_bytes() => {
+                u32::from_be_bytes([record[8], record[9], record[10], record[11]])
+            }
+            // nothing added yet, or only in an earlier epoch
+            _ => 0,
+        };
+        if added >= max_observations {
+            return Err(StdError::generic_err("Contributor has reached the observation limit for this epoch"));
+        }
+        let record = [epoch.to_be_bytes().as_slice(), &(added + 1).to_be_bytes()].concat();
+        Ok(Some((rate_key, record)))
+    }
+
+    pub fn get_sensitivity_policy(&self) -> Option<SensitivityPolicy> {
+        self.config.sensitivity_policy
+    }
+
+    pub fn get_noise_source(&self) -> NoiseSource {
+        self.config.noise_source
+    }
+
+    pub fn get_outlier_policy(&self) -> OutlierPolicy {
+        self.config.outlier_policy
+    }
+
+    /// Range between the observed bounds, or an error if it is empty or overflows, which would 
+    /// release an average with no noise or with useless noise
+    fn observed_range(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        let range = self.get_upper_bound(storage)?
+            .checked_sub(self.get_lower_bound(storage)?)
+            .ok_or(StdError::generic_err("Observed range overflows"))?;
+        if range <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Observed range is empty"));
+        }
+        Ok(range)
+    }
+
+    /// Sensitivity of the sum, chosen by the sensitivity policy. An empty range is an error
+    /// whichever source it comes from.
+    fn sum_sensitivity(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        let sensitivity = match self.config.sensitivity_policy {
+            Some(SensitivityPolicy::Fixed) => self.get_avg_sensitivity(storage)?.ok_or(
+                StdError::generic_err("Average sensitivity not set")
+            ),
+            Some(SensitivityPolicy::ObservedRange) => self.observed_range(storage),
+            Some(SensitivityPolicy::Clipped) => match self.enforced_clipping() {
+                Some(range) => clipping_width(range),
+                None => Err(StdError::generic_err("Clipping range not set")),
+            },
+            None => {
+                if let Some(sensitivity_for_average) = self.get_avg_sensitivity(storage)? {
+                    Ok(sensitivity_for_average)
+                } else if let Some(range) = self.enforced_clipping() {
+                    // clipped observations have a data-independent sensitivity for sum
+                    clipping_width(range)
+                } else {
+                    // using a bounded sensitivity for sum
+                    self.observed_range(storage)
+                }
+            }
+        }?;
+        if sensitivity <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Sensitivity range is empty"));
+        }
+        Ok(sensitivity)
+    }
+
+    /// Sensitivities of the weighted sum and of the sum of weights: one observation moves the
+    /// weighted sum by at most the max weight times the sensitivity of the sum, and the sum of
+    /// weights by at most the max weight
+    fn weighted_sensitivities(&self, storage: &dyn Storage) -> StdResult<(I32F32, I32F32)> {
+        let max_weight = self.config.max_weight.ok_or(
+            StdError::generic_err("Weighted average requires a max weight")
+        )?;
+        let weighted_sum_sensitivity = self.sum_sensitivity(storage)?.checked_mul(max_weight).ok_or(
+            StdError::generic_err("Weighted sum sensitivity overflows")
+        )?;
+        Ok((weighted_sum_sensitivity, max_weight))
+    }
+
+    /// Checks that stats can be released at all, before any cost is computed
+    #[cfg(not(feature = "constant_time"))]
+    fn check_release(&self, storage: &dyn Storage) -> StdResult<()> {
+        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
+            return Err(StdError::generic_err("Status not set to calculating stats") );
+        }
+
+        if self.is_empty(storage)? {
+            return Err(StdError::generic_err("No data to count"));
+        }
+
+        Ok(())
+    }
+
+    /// Privacy cost of a query, in multiples of epsilon
+    pub(crate) fn query_cost(&self, epsilon: I32F32, query: QueryKind) -> I32F32 {
+        match query {
+            // privacy cost of COUNT = 1 * epsilon
+            QueryKind::Count => epsilon,
+            // sequential queries for sum + count
+            QueryKind::Average => 2 * epsilon,
+            // each observation is in one bin, so the histogram costs 1 * epsilon
+            QueryKind::Range => epsilon,
+            // sequential queries for weighted sum + sum of weights
+            QueryKind::WeightedAverage => 2 * epsilon,
+        }
+    }
+
+    /// Returns the total privacy cost of a `fuzzy_report` of `queries` at the current epsilon,
+    /// including the minimum-count check. With a result cache, statistics already released at
+    /// the current data version and epsilon are free, a statistic requested twice is charged
+    /// once, and the minimum-count check is only charged if something is drawn afresh.
+    pub fn report_cost(&self, storage: &dyn Storage, queries: &[QueryKind]) -> StdResult<I32F32> {
+        let epsilon = self.get_epsilon(storage)?;
+        let mut privacy_cost = I32F32::from_num(0);
+        let mut any_fresh = false;
+        for (idx, query) in queries.iter().enumerate() {
+            if self.config.cache_results
+                && (queries[..idx].contains(query) || self.get_cached_result(storage, *query)?.is_some())
+            {
+                continue;
+            }
+            any_fresh = true;
+            privacy_cost = privacy_cost.checked_add(self.query_cost(epsilon, *query)).ok_or(
+                StdError::generic_err("Privacy cost overflow")
+            )?;
+        }
+        if any_fresh {
+            privacy_cost = privacy_cost.checked_add(self.release_gate_cost(storage, epsilon)?).ok_or(
+                StdError::generic_err("Privacy cost overflow")
+            )?;
+        }
+        Ok(privacy_cost)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_count(&self, storage: &dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<I32F32> {
+        // sensitivity is always 1 for COUNT queries
         let sensitivity = I32F32::from_num(1_u32);
-        let scale = sensitivity / epsilon;
+        
+        // calculate a fuzzy count
+        let noise = self.config.noise_source.sample(rng, sensitivity, epsilon)?;
+        Ok(add_count_noise(self.get_count(storage)?, noise)?)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_average(&self, storage: &dyn Storage, sum_rng: &mut ChaChaRng, count_rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<I32F32> {
+        let sensitivity = self.sum_sensitivity(storage)?;
+
+        let sum_noise = self.config.noise_source.sample(sum_rng, sensitivity, epsilon)?;
+        let dp_sum = self.get_sum(storage)?.saturating_add(I64F64::from_num(sum_noise));
     
-        let noise = laplace(rng, scale);
-        let real_count = I32F32::from_num(self.get_count(storage)?);
-        let dp_count = I64F64::from_num(real_count + noise);
+        // calculate fuzzy count
+        let dp_count = I64F64::from_num(self.noisy_count(storage, count_rng, epsilon)?);
     
-        let dp_average = I32F32::from_num(dp_sum / dp_count);
+        Ok(saturating_quotient(dp_sum, dp_count, I64F64::from_num(1)))
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_weighted_average(&self, storage: &dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<I32F32> {
+        let (weighted_sum_sensitivity, weight_sensitivity) = self.weighted_sensitivities(storage)?;
+        let weight_sum = self.get_weight_sum(storage)?;
+        if weight_sum <= I64F64::from(0) {
+            return Err(StdError::generic_err("No weighted observations"));
+        }
+
+        let weighted_sum_noise = self.config.noise_source.sample(rng, weighted_sum_sensitivity, epsilon)?;
+        let dp_weighted_sum = self.get_weighted_sum(storage)?.saturating_add(I64F64::from_num(weighted_sum_noise));
+
+        let weight_noise = self.config.noise_source.sample(rng, weight_sensitivity, epsilon)?;
+        let dp_weight_sum = weight_sum.saturating_add(I64F64::from_num(weight_noise));
+
+        Ok(saturating_quotient(dp_weighted_sum, dp_weight_sum, I64F64::from_bits(1)))
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_range(&self, storage: &dyn Storage, rng: &mut ChaChaRng, epsilon: I32F32) -> StdResult<(I32F32, I32F32)> {
+        let (layout, histogram) = self.config.bound_histogram.zip(self.bound_histogram()).ok_or(
+            StdError::generic_err("Fuzzy range requires a bound histogram")
+        )?;
+
+        let sensitivity = I32F32::from_num(1_u32);
+        let noise_vec = self.config.noise_source.sample_vec(rng, sensitivity, epsilon, usize::from(layout.bins))?;
+        let mut noisy_counts = Vec::with_capacity(usize::from(layout.bins));
+        for (idx, noise) in (0..layout.bins).zip(noise_vec) {
+            noisy_counts.push(add_count_noise(histogram.get_bin_count(storage, idx)?, noise)?);
+        }
+        Ok(range_from_noisy_counts(layout, &noisy_counts, epsilon))
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_result(&self, storage: &dyn Storage, rngs: &mut StatisticRngs, epsilon: I32F32, query: QueryKind) -> StdResult<DpResult> {
+        Ok(match query {
+            QueryKind::Count => DpResult::Count(self.noisy_count(storage, &mut rngs.count, epsilon)?),
+            QueryKind::Average => {
+                DpResult::Average(self.noisy_average(storage, &mut rngs.sum, &mut rngs.average, epsilon)?)
+            }
+            QueryKind::Range => {
+                let (min, max) = self.noisy_range(storage, &mut rngs.range, epsilon)?;
+                DpResult::Range(min, max)
+            }
+            QueryKind::WeightedAverage => {
+                DpResult::WeightedAverage(self.noisy_weighted_average(storage, &mut rngs.weighted_average, epsilon)?)
+            }
+        })
+    }
+
+    /// Releases several statistics together. The combined cost is checked against the budget 
+    /// and charged once, so either every statistic is released or none is. A release refused
+    /// by the minimum-count check is charged for the check alone and returns
+    /// `Release::Refused`.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_report(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        self.check_no_query_cooldown(storage)?;
+        self.release_cached_report(storage, rng, queries)
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    fn release_report(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        self.check_release(storage)?;
+        self.check_query_limit(storage)?;
+
+        let epsilon = self.get_epsilon(storage)?;
+        self.check_privacy_policy(storage, epsilon)?;
+        let mut privacy_cost = self.release_gate_cost(storage, epsilon)?;
+        for query in queries {
+            privacy_cost = privacy_cost.checked_add(self.query_cost(epsilon, *query)).ok_or(
+                StdError::generic_err("Privacy cost overflow")
+            )?;
+        }
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        let replay_start = self.config.replay_log.map(|_| rng.clone());
+        let results = self.noisy_results(storage, rng, queries)?;
 
         // update the remaining privacy budget
-        self.set_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+        self.log_release(storage, replay_start, queries, &results)?;
+
+        Ok(Release::Released(results))
+    }
+
+    /// Draws the noisy answers to `queries` without checking or charging anything. Each
+    /// statistic draws its noise from its own stream, see `derive_subrng`.
+    #[cfg(not(feature = "constant_time"))]
+    fn noisy_results(&self, storage: &dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Vec<DpResult>> {
+        let epsilon = self.get_epsilon(storage)?;
+        let mut rngs = StatisticRngs::draw(rng);
+        queries
+            .iter()
+            .map(|query| self.noisy_result(storage, &mut rngs, epsilon, *query))
+            .collect()
+    }
+
+    /// Releases several statistics together. The combined cost is checked against the budget 
+    /// and charged once, so either every statistic is released or none is. This is the
+    /// constant-work version: every field and histogram bin is read and every noise value is
+    /// drawn before anything is checked, and every refusal returns `Release::Refused`.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_report(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        let cooldown_elapsed = self.query_cooldown_elapsed(storage, None)?;
+        self.release_cached_report(storage, rng, queries, cooldown_elapsed)
+    }
+
+    /// Only the queries marked in `fresh` are charged for; the others are answered from the
+    /// result cache by the caller, but their noise is drawn all the same.
+    #[cfg(feature = "constant_time")]
+    fn release_report(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        queries: &[QueryKind],
+        fresh: &[bool],
+        cooldown_elapsed: bool,
+    ) -> StdResult<Release<Vec<DpResult>>> {
+        let replay_start = self.config.replay_log.map(|_| rng.clone());
+        let report = self.draw_report(storage, rng, queries, fresh)?;
+        let allowed = report.allowed & cooldown_elapsed;
+        let gate_charged = report.gate_charged & cooldown_elapsed;
+        let any_fresh = fresh.iter().fold(false, |any, fresh| any | fresh);
 
-        Ok(dp_average)
+        // the budget and query count are written back either way
+        let charged = charged_cost(report.privacy_cost, allowed) + charged_cost(report.gate_cost, gate_charged);
+        self.save_privacy_budget(storage, report.privacy_budget - charged);
+        let counted = (allowed & any_fresh) | gate_charged;
+        self.total_queries.save(storage, self.as_slice(), report.total_queries.saturating_add(u32::from(counted)));
+
+        if !allowed {
+            return Ok(Release::Refused);
+        }
+        self.log_release(storage, replay_start, queries, &report.results)?;
+        Ok(Release::Released(report.results))
+    }
+
+    /// Draws the noisy answers to `queries` without charging anything
+    #[cfg(feature = "constant_time")]
+    fn noisy_results(&self, storage: &dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Vec<DpResult>> {
+        Ok(self.draw_report(storage, rng, queries, &vec![true; queries.len()])?.results)
     }
-}
\ No newline at end of file
+
+    /// Reads every field and draws every noise value of a constant-work release, and works out
+    /// whether the release is allowed, without writing anything. Only the queries marked in
+    /// `fresh` are charged and checked; a report with none of them is always allowed.
+    #[cfg(feature = "constant_time")]
+    fn draw_report(&self, storage: &dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind], fresh: &[bool]) -> StdResult<DrawnReport> {
+        let one = I32F32::from_num(1);
+
+        // read every field in a fixed order, whatever the state of the store
+        self.preload(storage)?;
+        let initialized = self.is_initialized(storage);
+        let status = self.get_status(storage)?;
+        let count = self.get_count(storage)?;
+        let sum = self.get_sum(storage)?;
+        let weight_sum = self.get_weight_sum(storage)?;
+        let weighted_sum = self.get_weighted_sum(storage)?;
+        let min_count = self.get_min_count_for_release(storage)?;
+        let max_queries = self.get_max_total_queries(storage)?;
+        let total_queries = self.get_total_queries(storage)?;
+        let epsilon = self.epsilon.load_or(storage, self.as_slice(), one)?;
+        let privacy_budget = match self.config.shared_budget {
+            Some(accountant) => accountant.get_privacy_budget(storage)?,
+            None => self.privacy_budget.load_or(storage, self.as_slice(), one)?,
+        };
+        let policy = self.get_privacy_policy(storage)?;
+        let histogram = self.config.bound_histogram.zip(self.bound_histogram());
+        let mut bin_counts = Vec::new();
+        if let Some((layout, histogram)) = &histogram {
+            for idx in 0..layout.bins {
+                bin_counts.push(histogram.get_bin_count(storage, idx)?);
+            }
+        }
+        // only reads fields that are already cached by preload
+        let sensitivity = self.sum_sensitivity(storage);
+        let weighted_sensitivities = self.weighted_sensitivities(storage);
+
+        // draw every noise value, whichever statistics were requested, each statistic from its
+        // own stream
+        let mut rngs = StatisticRngs::draw(rng);
+        let count_noise = self.config.noise_source.sample(&mut rngs.count, one, epsilon)?;
+        let sum_noise = self.config.noise_source.sample(&mut rngs.sum, *sensitivity.as_ref().unwrap_or(&one), epsilon)?;
+        let average_count_noise = self.config.noise_source.sample(&mut rngs.average, one, epsilon)?;
+        let bin_noise = self.config.noise_source.sample_vec(&mut rngs.range, one, epsilon, bin_counts.len())?;
+        let (weighted_sum_sensitivity, weight_sensitivity) = *weighted_sensitivities.as_ref().unwrap_or(&(one, one));
+        let weighted_sum_noise = self.config.noise_source.sample(&mut rngs.weighted_average, weighted_sum_sensitivity, epsilon)?;
+        let weight_noise = self.config.noise_source.sample(&mut rngs.weighted_average, weight_sensitivity, epsilon)?;
+        let gate_passed = noisy_count_reaches(rng, count, min_count, epsilon)?;
+        let any_fresh = fresh.iter().fold(false, |any, fresh| any | fresh);
+        let gate_cost = charged_cost(min_count_gate_cost(min_count, epsilon), any_fresh);
+
+        let fuzzy_count = add_count_noise(count, count_noise)?;
+        let dp_sum = sum.saturating_add(I64F64::from_num(sum_noise));
+        let dp_count = I64F64::from_num(count) + I64F64::from_num(average_count_noise);
+        let dp_average = saturating_quotient(dp_sum, dp_count, I64F64::from_num(1));
+        let noisy_bin_counts: Vec<I32F32> = bin_counts
+            .iter()
+            .zip(bin_noise)
+            .map(|(bin_count, noise)| add_count_noise(*bin_count, noise))
+            .collect::<NoiseResult<_>>()?;
+        let dp_weighted_average = saturating_quotient(
+            weighted_sum.saturating_add(I64F64::from_num(weighted_sum_noise)),
+            weight_sum.saturating_add(I64F64::from_num(weight_noise)),
+            I64F64::from_bits(1),
+        );
+        let fuzzy_range = histogram.map(|(layout, _)| range_from_noisy_counts(layout, &noisy_bin_counts, epsilon));
+
+        // evaluate every condition without short-circuiting
+        let mut allowed = initialized
+            & (status == RunningStatsStatus::CalculatingStats)
+            & (count > 0)
+            & max_queries.map_or(true, |max_queries| total_queries < max_queries)
+            & policy.map_or(true, |policy| {
+                (epsilon >= policy.min_epsilon_per_query) & (epsilon <= policy.max_epsilon_per_query)
+            });
+        let mut privacy_cost = gate_cost;
+        let mut results = Vec::with_capacity(queries.len());
+        for (query, fresh) in queries.iter().zip(fresh) {
+            let cost = privacy_cost.checked_add(charged_cost(self.query_cost(epsilon, *query), *fresh));
+            allowed &= cost.is_some();
+            privacy_cost = cost.unwrap_or(privacy_cost);
+            results.push(match query {
+                QueryKind::Count => DpResult::Count(fuzzy_count),
+                QueryKind::Average => {
+                    allowed &= !fresh | sensitivity.is_ok();
+                    DpResult::Average(dp_average)
+                }
+                QueryKind::Range => {
+                    allowed &= !fresh | fuzzy_range.is_some();
+                    let (min, max) = fuzzy_range.unwrap_or((I32F32::from_num(0), I32F32::from_num(0)));
+                    DpResult::Range(min, max)
+                }
+                QueryKind::WeightedAverage => {
+                    allowed &= !fresh | (weighted_sensitivities.is_ok() & (weight_sum > I64F64::from(0)));
+                    DpResult::WeightedAverage(dp_weighted_average)
+                }
+            });
+        }
+        allowed &= privacy_budget >= privacy_cost;
+        let gate_charged = any_fresh & allowed & !gate_passed;
+        allowed = (allowed & gate_passed) | !any_fresh;
+
+        Ok(DrawnReport {
+            results,
+            allowed,
+            privacy_cost,
+            gate_cost,
+            gate_charged,
+            privacy_budget,
+            total_queries,
+        })
+    }
+
+    /// Records a release in the replay log, if the store keeps one. `replay_start` is the random
+    /// number generator as it was before the first noise value was drawn.
+    fn log_release(
+        &self,
+        storage: &mut dyn Storage,
+        replay_start: Option<ChaChaRng>,
+        queries: &[QueryKind],
+        results: &[DpResult],
+    ) -> StdResult<()> {
+        let (log, rng) = match self.config.replay_log.zip(replay_start) {
+            Some(log_and_rng) => log_and_rng,
+            None => return Ok(()),
+        };
+        let entry = ReplayEntry {
+            seed_fingerprint: rng_fingerprint(&rng),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+            data_version: self.get_data_version(storage)?,
+            epsilon: self.get_epsilon(storage)?,
+            noise_source: self.config.noise_source,
+            avg_sensitivity: self.get_avg_sensitivity(storage)?,
+            clipping: self.config.clipping,
+            queries: queries.to_vec(),
+            results: results.to_vec(),
+        };
+        log.push(storage, &entry)?;
+        Ok(())
+    }
+
+    /// Settles a dispute about the release at position `pos` of the replay log: rebuilds the
+    /// random number generator from `rng_seed`, the seed it was created with, recomputes the
+    /// released statistics and returns whether they match. Nothing is charged or written. Only
+    /// call this inside the enclave, e.g. from an admin-only entry point, and never expose the
+    /// seed. Returns an error if the seed does not match the release, or if the data, epsilon,
+    /// noise mechanism, average sensitivity or clipping range have changed since, in which case
+    /// the release can no longer be replayed.
+    pub fn replay_release(&self, storage: &dyn Storage, rng_seed: &[u8; 32], pos: u32) -> StdResult<bool> {
+        let log = self.config.replay_log.ok_or(
+            StdError::generic_err("Store does not keep a replay log")
+        )?;
+        let entry = log.get_at(storage, pos)?;
+        let mut rng = entry.rng(rng_seed)?;
+        if entry.data_version != self.get_data_version(storage)?
+            || entry.epsilon != self.get_epsilon(storage)?
+            || entry.noise_source != self.config.noise_source
+            || entry.avg_sensitivity != self.get_avg_sensitivity(storage)?
+            || entry.clipping != self.config.clipping
+        {
+            return Err(StdError::generic_err("Store has changed since the release"));
+        }
+        let results = self.noisy_results(storage, &mut rng, &entry.queries)?;
+        Ok(results == entry.results)
+    }
+
+    /// Answers are cached per epsilon, so a query at a different epsilon is drawn afresh
+    fn result_cache_key(&self, storage: &dyn Storage, query: QueryKind) -> StdResult<Vec<u8>> {
+        let kind: u8 = match query {
+            QueryKind::Count => 0,
+            QueryKind::Average => 1,
+            QueryKind::Range => 2,
+            QueryKind::WeightedAverage => 3,
+        };
+        let epsilon = self.epsilon.load_or(storage, self.as_slice(), I32F32::from_num(1))?;
+        Ok([self.as_slice(), RESULT_CACHE_KEY, &[kind], &epsilon.to_stored()].concat())
+    }
+
+    /// Returns the cached answer to `query`, if it was computed at the current data version
+    /// and epsilon
+    fn get_cached_result(&self, storage: &dyn Storage, query: QueryKind) -> StdResult<Option<DpResult>> {
+        let cached = match storage.get(&self.result_cache_key(storage, query)?) {
+            Some(cached) => cached,
+            None => return Ok(None),
+        };
+        if cached.len() < 8 {
+            return Err(StdError::generic_err("Invalid cached result"));
+        }
+        let (version, bytes) = cached.split_at(8);
+        if u64::from_stored(version)? != self.get_data_version(storage)? {
+            return Ok(None);
+        }
+        let (first, second) = bytes.split_at(bytes.len() / 2);
+        Ok(Some(match query {
+            QueryKind::Count => DpResult::Count(I32F32::from_stored(bytes)?),
+            QueryKind::Average => DpResult::Average(I32F32::from_stored(bytes)?),
+            QueryKind::Range => DpResult::Range(I32F32::from_stored(first)?, I32F32::from_stored(second)?),
+            QueryKind::WeightedAverage => DpResult::WeightedAverage(I32F32::from_stored(bytes)?),
+        }))
+    }
+
+    /// Caches the answer to `query` at the current epsilon, together with the current data
+    /// version
+    fn set_cached_result(&self, storage: &mut dyn Storage, query: QueryKind, result: &DpResult) -> StdResult<()> {
+        let bytes = match result {
+            DpResult::Count(value) | DpResult::Average(value) | DpResult::WeightedAverage(value) => value.to_stored(),
+            DpResult::Range(min, max) => [min.to_stored(), max.to_stored()].concat(),
+        };
+        let version = self.get_data_version(storage)?.to_stored();
+        let key = self.result_cache_key(storage, query)?;
+        storage.set(&key, &[version, bytes].concat());
+        Ok(())
+    }
+
+    /// Returns the answers to `queries` if every one of them has already been released at the
+    /// current data version, or None otherwise. Nothing is charged and no noise is drawn, so
+    /// this can be called from a query handler. Requires `with_result_cache`.
+    pub fn get_released_report(&self, storage: &dyn Storage, queries: &[QueryKind]) -> StdResult<Option<Vec<DpResult>>> {
+        if !self.config.cache_results {
+            return Err(StdError::generic_err("Store does not cache results"));
+        }
+        let cached = queries
+            .iter()
+            .map(|query| self.get_cached_result(storage, *query))
+            .collect::<StdResult<Vec<_>>>()?;
+        match cached.into_iter().collect::<Option<Vec<DpResult>>>() {
+            Some(results) => Ok(Some(self.invert_results(results))),
+            None => Ok(None),
+        }
+    }
+
+    fn frozen_key(&self, label: &str) -> Vec<u8> {
+        [self.as_slice(), FROZEN_KEY, &to_length_prefixed(label.as_bytes())].concat()
+    }
+
+    /// Freezes the exact statistics released at the current data version, together with the
+    /// current privacy parameters, under `label`. A label can only be frozen once and frozen
+    /// snapshots are kept when the store is cleared, so a published statistic can be checked
+    /// against them later, e.g. by an auditor, and cannot be quietly released again with
+    /// different noise. Nothing is charged. Requires `with_result_cache`. Release published
+    /// statistics with `fuzzy_report_labeled`, which returns the frozen answers once a label is
+    /// frozen.
+    pub fn freeze_snapshot(&self, storage: &mut dyn Storage, label: &str) -> StdResult<FrozenSnapshot> {
+        if !self.config.cache_results {
+            return Err(StdError::generic_err("Store does not cache results"));
+        }
+        if label.is_empty() {
+            return Err(StdError::generic_err("Snapshot label must not be empty"));
+        }
+        let key = self.frozen_key(label);
+        if storage.get(&key).is_some() {
+            return Err(StdError::generic_err("Snapshot label is already frozen"));
+        }
+
+        let mut queries = vec![];
+        let mut results = vec![];
+        for query in [QueryKind::Count, QueryKind::Average, QueryKind::Range, QueryKind::WeightedAverage] {
+            if let Some(result) = self.get_cached_result(storage, query)? {
+                queries.push(query);
+                results.push(StoredDpResult::from(self.config.transform.invert_result(result)));
+            }
+        }
+        if queries.is_empty() {
+            return Err(StdError::generic_err("No released statistics to freeze"));
+        }
+
+        let snapshot = FrozenSnapshot {
+            data_version: self.get_data_version(storage)?,
+            queries,
+            results,
+            params: self.get_params(storage)?.into(),
+        };
+        storage.set(&key, &Ser::serialize(&snapshot)?);
+        Ok(snapshot)
+    }
+
+    /// Returns the snapshot frozen under `label`, if any. The library does not check who is
+    /// asking: serve this from a query handler that only answers the contract's auditors.
+    pub fn get_frozen_snapshot(&self, storage: &dyn Storage, label: &str) -> StdResult<Option<FrozenSnapshot>> {
+        storage
+            .get(&self.frozen_key(label))
+            .map(|bytes| Ser::deserialize(&bytes))
+            .transpose()
+    }
+
+    /// Releases `queries` as the statistics published under `label`. The first release under a
+    /// label is drawn and charged as by `fuzzy_report`, and then frozen with `freeze_snapshot`.
+    /// Every later release under the label returns the frozen answers, without drawing noise or
+    /// charging anything, even after the store is cleared or the data changes, so a published
+    /// statistic cannot be released again with different noise. A query that the frozen
+    /// snapshot does not hold is refused. A first release refused by the minimum-count check
+    /// freezes nothing. Requires `with_result_cache`.
+    pub fn fuzzy_report_labeled(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, label: &str, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        if !self.config.cache_results {
+            return Err(StdError::generic_err("Store does not cache results"));
+        }
+        if label.is_empty() {
+            return Err(StdError::generic_err("Snapshot label must not be empty"));
+        }
+        if let Some(snapshot) = self.get_frozen_snapshot(storage, label)? {
+            let results = queries
+                .iter()
+                .map(|query| {
+                    let idx = snapshot.queries.iter().position(|kind| kind == query).ok_or(
+                        StdError::generic_err("Statistic was not released under this label")
+                    )?;
+                    snapshot.results[idx].into_humanized()
+                })
+                .collect::<StdResult<Vec<DpResult>>>()?;
+            return Ok(Release::Released(results));
+        }
+        let results = self.fuzzy_report(storage, rng, queries)?;
+        if !results.is_refused() {
+            self.freeze_snapshot(storage, label)?;
+        }
+        Ok(results)
+    }
+
+    /// Releases a report, answering queries from the result cache where possible. Only the
+    /// statistics that are not cached yet are charged, each one once even if it is requested
+    /// several times, and their answers are cached.
+    #[cfg(not(feature = "constant_time"))]
+    fn release_cached_report(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        if !self.config.cache_results {
+            let results = self.release_report(storage, rng, queries)?;
+            return Ok(results.map(|results| self.invert_results(results)));
+        }
+
+        let mut answers: Vec<(QueryKind, DpResult)> = vec![];
+        let mut missing: Vec<QueryKind> = vec![];
+        for query in queries {
+            match self.get_cached_result(storage, *query)? {
+                Some(result) => answers.push((*query, result)),
+                None if !missing.contains(query) => missing.push(*query),
+                None => {}
+            }
+        }
+        if !missing.is_empty() {
+            let fresh = match self.release_report(storage, rng, &missing)? {
+                Release::Released(fresh) => fresh,
+                Release::Refused => return Ok(Release::Refused),
+            };
+            for (query, result) in missing.into_iter().zip(fresh) {
+                self.set_cached_result(storage, query, &result)?;
+                answers.push((query, result));
+            }
+        }
+
+        let results = queries
+            .iter()
+            .map(|query| {
+                answers
+                    .iter()
+                    .find(|(kind, _)| kind == query)
+                    .map(|(_, result)| *result)
+                    .ok_or_else(|| StdError::generic_err("Unexpected query result"))
+            })
+            .collect::<StdResult<Vec<DpResult>>>()?;
+        Ok(Release::Released(self.invert_results(results)))
+    }
+
+    /// Constant-work version of the cached release: every cached answer is read and every
+    /// statistic is drawn again, whether or not it is cached, and only the queries without a
+    /// cached answer are charged. Every cache entry is written back.
+    #[cfg(feature = "constant_time")]
+    fn release_cached_report(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        queries: &[QueryKind],
+        cooldown_elapsed: bool,
+    ) -> StdResult<Release<Vec<DpResult>>> {
+        let mut cached = Vec::with_capacity(queries.len());
+        let mut fresh = Vec::with_capacity(queries.len());
+        for (idx, query) in queries.iter().enumerate() {
+            let result = self.get_cached_result(storage, *query)?;
+            // with a cache, a query requested twice is charged once
+            let repeated = queries[..idx].contains(query);
+            fresh.push(!self.config.cache_results | (result.is_none() & !repeated));
+            cached.push(result.filter(|_| self.config.cache_results));
+        }
+        let drawn = match self.release_report(storage, rng, queries, &fresh, cooldown_elapsed)? {
+            Release::Released(drawn) => drawn,
+            Release::Refused => return Ok(Release::Refused),
+        };
+        let results: Vec<DpResult> = drawn
+            .into_iter()
+            .zip(cached)
+            .map(|(drawn, cached)| cached.unwrap_or(drawn))
+            .collect();
+        if self.config.cache_results {
+            for (query, result) in queries.iter().zip(&results) {
+                self.set_cached_result(storage, *query, result)?;
+            }
+        }
+        Ok(Release::Released(self.invert_results(results)))
+    }
+
+    /// Maps released statistics back from the transformed domain, see `with_transform`. The
+    /// result cache and the replay log keep them in the transformed domain.
+    fn invert_results(&self, results: Vec<DpResult>) -> Vec<DpResult> {
+        results.into_iter().map(|result| self.config.transform.invert_result(result)).collect()
+    }
+
+    /// `fuzzy_report` for a store with a query cooldown: the report is refused unless at least
+    /// `min_blocks_between_queries` blocks have passed since the last release, and
+    /// `block_height` is recorded as the height of this one. Stores with a collection deadline
+    /// release through it too, since it applies the deadline first.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_report_at(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, block_height: u64, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        self.apply_collection_deadline(storage, block_height)?;
+        self.check_query_cooldown(storage, block_height)?;
+        let results = self.release_cached_report(storage, rng, queries)?;
+        self.last_query_height.save(storage, self.as_slice(), block_height);
+        Ok(results)
+    }
+
+    /// Constant-work version: the cooldown is checked together with the other conditions of
+    /// the release, after every noise value is drawn.
+    #[cfg(feature = "constant_time")]
+    pub fn fuzzy_report_at(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, block_height: u64, queries: &[QueryKind]) -> StdResult<Release<Vec<DpResult>>> {
+        self.apply_collection_deadline(storage, block_height)?;
+        let cooldown_elapsed = self.query_cooldown_elapsed(storage, Some(block_height))?;
+        let results = self.release_cached_report(storage, rng, queries, cooldown_elapsed)?;
+        self.last_query_height.save(storage, self.as_slice(), block_height);
+        Ok(results)
+    }
+
+    /// Whether a release at `block_height` respects the query cooldown. Without a block height
+    /// only stores without a cooldown can release. Reads the same fields either way.
+    #[cfg(feature = "constant_time")]
+    fn query_cooldown_elapsed(&self, storage: &dyn Storage, block_height: Option<u64>) -> StdResult<bool> {
+        let min_blocks = self.get_min_blocks_between_queries(storage)?;
+        let last_height = self.get_last_query_height(storage)?;
+        Ok(match block_height {
+            Some(block_height) => last_height.map_or(true, |last_height| block_height >= last_height.saturating_add(min_blocks)),
+            None => min_blocks == 0,
+        })
+    }
+
+    /// Without the block height the cooldown cannot be enforced, so stores with one only
+    /// release statistics through `fuzzy_report_at`
+    fn check_no_query_cooldown(&self, storage: &dyn Storage) -> StdResult<()> {
+        if self.get_min_blocks_between_queries(storage)? > 0 {
+            return Err(StdError::generic_err("Store has a query cooldown, use fuzzy_report_at"));
+        }
+        Ok(())
+    }
+
+    pub fn fuzzy_count(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.fuzzy_report(storage, rng, &[QueryKind::Count])?.try_map(|results| match results[..] {
+            [DpResult::Count(fuzzy_count)] => Ok(fuzzy_count),
+            _ => Err(StdError::generic_err("Unexpected query result")),
+        })
+    }
+
+    pub fn fuzzy_average(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.fuzzy_report(storage, rng, &[QueryKind::Average])?.try_map(|results| match results[..] {
+            [DpResult::Average(dp_average)] => Ok(dp_average),
+            _ => Err(StdError::generic_err("Unexpected query result")),
+        })
+    }
+
+    /// Privately releases the average of the observations added with `add_weighted_observation`,
+    /// weighted by their weights (cost 2 * epsilon). The weighted sum gets noise scaled to the
+    /// max weight times the sensitivity of the sum, and the sum of weights noise scaled to the
+    /// max weight. Requires `with_max_weight`.
+    pub fn fuzzy_weighted_average(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<I32F32>> {
+        self.fuzzy_report(storage, rng, &[QueryKind::WeightedAverage])?.try_map(|results| match results[..] {
+            [DpResult::WeightedAverage(dp_weighted_average)] => Ok(dp_weighted_average),
+            _ => Err(StdError::generic_err("Unexpected query result")),
+        })
+    }
+
+    /// Privately releases an approximate (min, max) of the observations, from a histogram with
+    /// Laplace noise on each bin count (cost 1 * epsilon). The range runs from the lower edge of
+    /// the first bin to the upper edge of the last bin whose noisy count exceeds 3 / epsilon, so
+    /// a single extreme observation is unlikely to move it. The open end bins report the edges of
+    /// the histogram layout. If no bin passes the threshold, the whole layout range is returned.
+    /// Requires `with_bound_histogram`, which may be combined with clipping or bound heaps.
+    pub fn fuzzy_range(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<(I32F32, I32F32)>> {
+        self.fuzzy_report(storage, rng, &[QueryKind::Range])?.try_map(|results| match results[..] {
+            [DpResult::Range(fuzzy_min, fuzzy_max)] => Ok((fuzzy_min, fuzzy_max)),
+            _ => Err(StdError::generic_err("Unexpected query result")),
+        })
+    }
+
+    /// Releases noisy counts of the observations rejected and clamped by the outlier policy, as
+    /// (rejected, clamped), e.g. to tell whether the clipping range fits the data. Each
+    /// observation is counted at most once in one of the two, so both counts together cost
+    /// 1 * epsilon, plus the minimum-count check if the store has one. Not available with the
+    /// `constant_time` feature, since it returns early when refused.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_outlier_counts(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng) -> StdResult<Release<(I32F32, I32F32)>> {
+        self.check_ready_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        self.check_privacy_policy(storage, epsilon)?;
+        let privacy_cost = epsilon.checked_add(self.release_gate_cost(storage, epsilon)?).ok_or(
+            StdError::generic_err("Privacy cost overflow")
+        )?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        let sensitivity = I32F32::from_num(1_u32);
+        let rejected = self.rejected_count.load_or(storage, self.as_slice(), 0)?;
+        let clamped = self.clamped_count.load_or(storage, self.as_slice(), 0)?;
+        let fuzzy_rejected = add_count_noise(rejected, self.config.noise_source.sample(rng, sensitivity, epsilon)?)?;
+        let fuzzy_clamped = add_count_noise(clamped, self.config.noise_source.sample(rng, sensitivity, epsilon)?)?;
+
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+        Ok(Release::Released((fuzzy_rejected, fuzzy_clamped)))
+    }
+
+    /// Releases the noisy fraction of observations below `x`, between 0 and 1, e.g. to tell a
+    /// user roughly where their own value ranks. The fraction is estimated from noisy counts of
+    /// the bound histogram bins, interpolating linearly inside the bin that holds `x`, so it is
+    /// only as fine as the histogram layout. An observation changes one bin count by one, so the
+    /// release costs 1 * epsilon, plus the minimum-count check if the store has one. Requires a
+    /// store declared `with_bound_histogram`, which may also use clipping or bound heaps. With
+    /// clipping, `x` and the observations are compared after the transform and clamping. Not
+    /// available with the `constant_time` feature, since it returns early when refused.
+    #[cfg(not(feature = "constant_time"))]
+    pub fn fuzzy_percentile_of(&self, storage: &mut dyn Storage, rng: &mut ChaChaRng, x: I32F32) -> StdResult<Release<I32F32>> {
+        let (layout, histogram) = self.config.bound_histogram.zip(self.bound_histogram()).ok_or(
+            StdError::generic_err("Fuzzy percentile requires a bound histogram")
+        )?;
+        self.check_ready_for_release(storage)?;
+        let epsilon = self.get_epsilon(storage)?;
+        self.check_privacy_policy(storage, epsilon)?;
+        let privacy_cost = epsilon.checked_add(self.release_gate_cost(storage, epsilon)?).ok_or(
+            StdError::generic_err("Privacy cost overflow")
+        )?;
+        let privacy_budget = self.get_privacy_budget(storage)?;
+        if privacy_budget < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !self.check_release_gate(storage, rng, epsilon)? {
+            return Ok(Release::Refused);
+        }
+
+        let x = self.config.transform.apply(x)?;
+        let sensitivity = I32F32::from_num(1_u32);
+        let noise_vec = self.config.noise_source.sample_vec(rng, sensitivity, epsilon, usize::from(layout.bins))?;
+        let mut noisy_counts = Vec::with_capacity(usize::from(layout.bins));
+        for (idx, noise) in (0..layout.bins).zip(noise_vec) {
+            let noisy_count = I64F64::from_num(histogram.get_bin_count(storage, idx)?) + I64F64::from_num(noise);
+            noisy_counts.push(noisy_count.max(I64F64::from_num(0)));
+        }
+
+        self.save_privacy_budget(storage, privacy_budget - privacy_cost);
+        self.record_query(storage)?;
+        Ok(Release::Released(fraction_below(layout, &noisy_counts, x)))
+    }
+}
+
+/// Fraction of the noisy histogram mass below `x`: the bins wholly below `x`, plus the share of
+/// the bin holding `x` that lies below it. Values outside the layout count as below or above
+/// every bin. Returns 0 if every noisy count is zero.
+#[cfg(not(feature = "constant_time"))]
+fn fraction_below(layout: HistogramBins, noisy_counts: &[I64F64], x: I32F32) -> I32F32 {
+    let zero = I64F64::from_num(0);
+    let one = I64F64::from_num(1);
+    let total = noisy_counts.iter().fold(zero, |total, count| total.saturating_add(*count));
+    if total <= zero {
+        return I32F32::from_num(0);
+    }
+
+    let range = I64F64::from_num(layout.upper) - I64F64::from_num(layout.lower);
+    let position = (I64F64::from_num(x.clamp(layout.lower, layout.upper)) - I64F64::from_num(layout.lower))
+        * I64F64::from_num(layout.bins) / range;
+    let idx = layout.bin_index(x);
+    let share = (position - I64F64::from_num(idx)).clamp(zero, one);
+    let below = noisy_counts[..usize::from(idx)]
+        .iter()
+        .fold(zero, |below, count| below.saturating_add(*count))
+        .saturating_add(noisy_counts[usize::from(idx)] * share);
+    I32F32::from_num((below / total).clamp(zero, one))
+}
+
+/// Noisy numerator over noisy denominator, saturating at the ends of I32F32. Noise can push the
+/// denominator close to zero or below it, and so the quotient out of range, but failing at that
+/// point would revert the charge for a release whose noise has already been drawn and so give
+/// a free retry. A denominator below `floor` is raised to it instead.
+fn saturating_quotient(numerator: I64F64, denominator: I64F64, floor: I64F64) -> I32F32 {
+    match numerator.checked_div(denominator.max(floor)) {
+        Some(quotient) => I32F32::saturating_from_num(quotient),
+        None if numerator < 0 => I32F32::MIN,
+        None => I32F32::MAX,
+    }
+}
+
+/// Width `upper - lower` of a clipping range, or an error if it does not fit in I32F32
+pub(crate) fn clipping_width((lower, upper): (I32F32, I32F32)) -> StdResult<I32F32> {
+    let width = upper.checked_sub(lower).ok_or(
+        StdError::generic_err("Clipping range overflows")
+    )?;
+    if width <= I32F32::from_num(0) {
+        return Err(StdError::generic_err("Clipping range is empty"));
+    }
+    Ok(width)
+}
+
+/// Approximate (min, max) from noisy histogram bin counts: from the lower edge of the first bin
+/// to the upper edge of the last bin whose noisy count exceeds 3 / epsilon, or the whole layout
+/// range if no bin does. Branching here is fine, since the counts already have noise.
+fn range_from_noisy_counts(layout: HistogramBins, noisy_counts: &[I32F32], epsilon: I32F32) -> (I32F32, I32F32) {
+    let threshold = 3 * I32F32::from_num(1_u32) / epsilon;
+    let mut first: Option<u16> = None;
+    let mut last: Option<u16> = None;
+    for (idx, noisy_count) in (0..layout.bins).zip(noisy_counts) {
+        if *noisy_count > threshold {
+            first.get_or_insert(idx);
+            last = Some(idx);
+        }
+    }
+
+    let fuzzy_min = first
+        .and_then(|idx| layout.bin_lower_edge(idx))
+        .unwrap_or(layout.lower);
+    let fuzzy_max = last
+        .and_then(|idx| layout.bin_upper_edge(idx))
+        .unwrap_or(layout.upper);
+    (fuzzy_min, fuzzy_max)
+}
+
+/// Named, validated parameters for initializing a `RunningStatsStore`. Nothing is written to
+/// storage unless every parameter is valid.
+///
+/// ```ignore
+/// RunningStatsStoreBuilder::new(&STORE)
+///     .epsilon(I32F32::from_num(0.1))
+///     .privacy_budget(I32F32::from_num(1))
+///     .init(deps.storage)?;
+/// ```
+pub struct RunningStatsStoreBuilder<'s, 'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    store: &'s RunningStatsStore<'a, Ser>,
+    epsilon: Option<I32F32>,
+    avg_sensitivity: Option<I32F32>,
+    privacy_budget: Option<I32F32>,
+    min_count_for_release: Option<u32>,
+    min_blocks_between_queries: Option<u64>,
+    max_total_queries: Option<u32>,
+    privacy_policy: Option<PrivacyPolicy>,
+}
+
+impl<'s, 'a, Ser: Serde> RunningStatsStoreBuilder<'s, 'a, Ser> {
+    /// constructor
+    pub fn new(store: &'s RunningStatsStore<'a, Ser>) -> Self {
+        Self {
+            store,
+            epsilon: None,
+            avg_sensitivity: None,
+            privacy_budget: None,
+            min_count_for_release: None,
+            min_blocks_between_queries: None,
+            max_total_queries: None,
+            privacy_policy: None,
+        }
+    }
+
+    /// Epsilon charged per query, must be greater than zero
+    pub fn epsilon(mut self, epsilon: I32F32) -> Self {
+        self.epsilon = Some(epsilon);
+        self
+    }
+
+    /// Fixed sensitivity used for the sum in average queries, must be greater than zero
+    pub fn avg_sensitivity(mut self, avg_sensitivity: I32F32) -> Self {
+        self.avg_sensitivity = Some(avg_sensitivity);
+        self
+    }
+
+    /// Total privacy budget of the store, must not be negative. A store declared
+    /// `with_shared_budget` draws on the accountant's budget instead, and refuses this setting.
+    pub fn privacy_budget(mut self, privacy_budget: I32F32) -> Self {
+        self.privacy_budget = Some(privacy_budget);
+        self
+    }
+
+    /// Minimum number of observations needed before any statistic is released, checked with
+    /// noise as in `set_min_count_for_release`
+    pub fn min_count_for_release(mut self, min_count: u32) -> Self {
+        self.min_count_for_release = Some(min_count);
+        self
+    }
+
+    /// Number of blocks that must pass between two releases
+    pub fn min_blocks_between_queries(mut self, blocks: u64) -> Self {
+        self.min_blocks_between_queries = Some(blocks);
+        self
+    }
+
+    /// Largest number of releases allowed from the store
+    pub fn max_total_queries(mut self, max_queries: u32) -> Self {
+        self.max_total_queries = Some(max_queries);
+        self
+    }
+
+    /// Limits on epsilon, delta and the privacy budget, set before epsilon and the budget so that
+    /// they are checked against them
+    pub fn privacy_policy(mut self, policy: PrivacyPolicy) -> Self {
+        self.privacy_policy = Some(policy);
+        self
+    }
+
+    /// Checks every parameter, as well as the clipping range of the store
+    pub fn validate(&self) -> StdResult<()> {
+        if let Some(epsilon) = self.epsilon {
+            if epsilon <= I32F32::from_num(0) {
+                return Err(StdError::generic_err("Epsilon must be greater than zero"));
+            }
+        }
+        if let Some(avg_sensitivity) = self.avg_sensitivity {
+            if avg_sensitivity <= I32F32::from_num(0) {
+                return Err(StdError::generic_err("Average sensitivity must be greater than zero"));
+            }
+        }
+        if let Some(privacy_budget) = self.privacy_budget {
+            if privacy_budget < I32F32::from_num(0) {
+                return Err(StdError::generic_err("Privacy budget must not be negative"));
+            }
+            if self.store.config.shared_budget.is_some() {
+                return Err(StdError::generic_err("Store has a shared budget, set it on the accountant"));
+            }
+        }
+        match self.store.get_sensitivity_policy() {
+            Some(SensitivityPolicy::Fixed) if self.avg_sensitivity.is_none() => {
+                return Err(StdError::generic_err("Fixed sensitivity policy requires an average sensitivity"));
+            }
+            Some(SensitivityPolicy::Clipped) if self.store.get_clipping().is_none() => {
+                return Err(StdError::generic_err("Clipped sensitivity policy requires a clipping range"));
+            }
+            Some(SensitivityPolicy::Clipped) if self.store.get_outlier_policy() == OutlierPolicy::Accept => {
+                return Err(StdError::generic_err("Clipped sensitivity policy cannot accept outliers"));
+            }
+            _ => {}
+        }
+        if let Some((lower, upper)) = self.store.get_clipping() {
+            if lower >= upper {
+                return Err(StdError::generic_err("Clipping lower bound must be less than upper bound"));
+            }
+        }
+        if let Some(policy) = self.privacy_policy {
+            policy.validate()?;
+            // unset values take the defaults of 1
+            policy.check_epsilon(self.epsilon.unwrap_or(I32F32::from(1)))?;
+            if self.store.config.shared_budget.is_none() {
+                policy.check_privacy_budget(self.privacy_budget.unwrap_or(I32F32::from(1)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the parameters and then resets the store with them, see `RunningStatsStore::clear`
+    pub fn init(self, storage: &mut dyn Storage) -> StdResult<()> {
+        self.validate()?;
+        if let Some(policy) = self.privacy_policy {
+            self.store.set_privacy_policy(storage, policy)?;
+        }
+        self.store.reset(storage, ResetPolicy::new(self.epsilon, self.avg_sensitivity, self.privacy_budget))?;
+        if let Some(min_count) = self.min_count_for_release {
+            self.store.set_min_count_for_release(storage, min_count)?;
+        }
+        if let Some(blocks) = self.min_blocks_between_queries {
+            self.store.set_min_blocks_between_queries(storage, blocks)?;
+        }
+        if let Some(max_queries) = self.max_total_queries {
+            self.store.set_max_total_queries(storage, max_queries)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+    use secret_toolkit::serialization::Json;
+    use crate::transfer_budget;
+
+    fn fixed(x: i32) -> I32F32 {
+        I32F32::from_num(x)
+    }
+
+    #[test]
+    fn removals_must_fit_the_remaining_data() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        // outside the observed bounds
+        assert!(store.remove_observation(&mut storage, fixed(7)).is_err());
+        assert!(store.remove_observation(&mut storage, fixed(1)).is_err());
+        store.remove_observation(&mut storage, fixed(6)).unwrap();
+        // 6 - 5 = 1 left for one observation, below the lower bound of 2
+        assert!(store.remove_observation(&mut storage, fixed(5)).is_err());
+        assert_eq!(store.get_count(&storage).unwrap(), 2);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(6));
+    }
+
+    #[test]
+    fn weighted_removals_keep_the_weighted_sums() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_max_weight(fixed(10));
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.add_weighted_observation(&mut storage, fixed(2), fixed(3)).unwrap();
+        store.add_weighted_observation(&mut storage, fixed(4), fixed(5)).unwrap();
+        store.add_observation(&mut storage, fixed(6)).unwrap();
+
+        // the weight of the observation is needed
+        assert!(store.remove_observation(&mut storage, fixed(4)).is_err());
+        assert!(store.update_observation(&mut storage, fixed(4), fixed(5)).is_err());
+        // more than the remaining weight
+        assert!(store.remove_weighted_observation(&mut storage, fixed(4), fixed(9)).is_err());
+        assert_eq!(store.get_count(&storage).unwrap(), 3);
+
+        store.remove_weighted_observation(&mut storage, fixed(4), fixed(5)).unwrap();
+        assert_eq!(store.get_weight_sum(&storage).unwrap(), I64F64::from_num(3));
+        assert_eq!(store.get_weighted_sum(&storage).unwrap(), I64F64::from_num(6));
+        // unweighted observations are removed with a weight of zero
+        store.remove_weighted_observation(&mut storage, fixed(6), fixed(0)).unwrap();
+        assert_eq!(store.get_weight_sum(&storage).unwrap(), I64F64::from_num(3));
+        assert_eq!(store.get_count(&storage).unwrap(), 1);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(2));
+    }
+
+    #[test]
+    fn exported_state_is_imported_into_another_store() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(2)), None, Some(fixed(10)))).unwrap();
+        for x in [3, 5, 10] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        let state = store.export_state(&storage).unwrap();
+
+        let migrated: RunningStatsStore = RunningStatsStore::new(b"migrated");
+        migrated.import_state(&mut storage, state.clone()).unwrap();
+        assert_eq!(migrated.get_count(&storage).unwrap(), 3);
+        assert_eq!(migrated.get_upper_bound(&storage).unwrap(), fixed(10));
+        assert_eq!(migrated.get_lower_bound(&storage).unwrap(), fixed(3));
+        assert_eq!(migrated.get_epsilon(&storage).unwrap(), fixed(2));
+        assert_eq!(migrated.export_state(&storage).unwrap(), state);
+    }
+
+    #[test]
+    fn reset_keeps_or_restores_settings() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(2)), None, Some(fixed(10)))).unwrap();
+        store.add_observation(&mut storage, fixed(4)).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        store.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(8));
+
+        store.reset(&mut storage, ResetPolicy::KEEP_ALL).unwrap();
+        assert_eq!(store.get_status(&storage).unwrap(), RunningStatsStatus::CollectingData);
+        assert_eq!(store.get_count(&storage).unwrap(), 0);
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(2));
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(8));
+
+        let policy = ResetPolicy { privacy_budget: ResetValue::Set(fixed(10)), ..ResetPolicy::KEEP_ALL };
+        store.reset(&mut storage, policy).unwrap();
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(2));
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(10));
+
+        store.reset(&mut storage, ResetPolicy::DEFAULT).unwrap();
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(1));
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(1));
+
+        // epsilon must be positive however it is set
+        assert!(store.set_epsilon(&mut storage, fixed(0)).is_err());
+        assert!(store.reset(&mut storage, ResetPolicy::new(Some(fixed(-1)), None, None)).is_err());
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(1));
+    }
+
+    #[test]
+    fn releases_counts_for_every_registered_suffix() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        for (suffix, count) in [(b"north", 30), (b"south", 60)] {
+            let suffixed = store.add_registered_suffix(&mut storage, suffix).unwrap();
+            suffixed.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+            for _ in 0..count {
+                suffixed.add_observation(&mut storage, fixed(1)).unwrap();
+            }
+            suffixed.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        }
+        // registering again does not add the suffix twice
+        store.add_registered_suffix(&mut storage, b"north").unwrap();
+        let suffixes = store.iter_suffixes(&storage).unwrap().collect::<StdResult<Vec<_>>>().unwrap();
+        assert_eq!(suffixes, vec![b"north".to_vec(), b"south".to_vec()]);
+
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        let counts = store.fuzzy_counts_for_all(&mut storage, &mut rng).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[0].0, b"north".to_vec());
+        assert!((counts[0].1.released().unwrap() - fixed(30)).abs() < fixed(8));
+        assert!((counts[1].1.released().unwrap() - fixed(60)).abs() < fixed(8));
+        assert_eq!(store.add_suffix(b"south").get_privacy_budget(&storage).unwrap(), fixed(9));
+    }
+
+    #[test]
+    fn reads_legacy_average_sensitivity() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        let key = [b"stats".as_slice(), SENSITIVITY_FOR_AVG_KEY].concat();
+        storage.set(&key, &Bincode2::serialize(&Some(fixed(3).to_be_bytes())).unwrap());
+        assert_eq!(store.get_avg_sensitivity(&storage).unwrap(), Some(fixed(3)));
+        storage.set(&key, &Bincode2::serialize(&None::<[u8; 8]>).unwrap());
+        store.avg_sensitivity.invalidate();
+        assert_eq!(store.get_avg_sensitivity(&storage).unwrap(), None);
+        store.preload(&storage).unwrap();
+
+        let json_store: RunningStatsStore<Json> = RunningStatsStore::new(b"json");
+        let key = [b"json".as_slice(), SENSITIVITY_FOR_AVG_KEY].concat();
+        storage.set(&key, &Json::serialize(&Some(fixed(5).to_be_bytes())).unwrap());
+        assert_eq!(json_store.get_avg_sensitivity(&storage).unwrap(), Some(fixed(5)));
+
+        // written back in the current encoding
+        json_store.set_average_sensitivity(&mut storage, Some(fixed(6))).unwrap();
+        assert_eq!(json_store.get_avg_sensitivity(&storage).unwrap(), Some(fixed(6)));
+    }
+
+    #[test]
+    fn transforms_round_trip_and_saturate() {
+        let affine = Transform::Affine { a: fixed(-2), b: fixed(3) };
+        for x in [-7, 0, 5, 1000] {
+            assert_eq!(affine.invert(affine.apply(fixed(x)).unwrap()), fixed(x));
+        }
+        for x in [-0.5, 0.0, 9.0, 1_000_000.0] {
+            let x = I32F32::from_num(x);
+            let round_trip = Transform::Log1p.invert(Transform::Log1p.apply(x).unwrap());
+            assert!((round_trip - x).abs() <= x.abs() / 10_000 + I32F32::from_num(0.0001));
+        }
+        assert!(Transform::Log1p.apply(fixed(-1)).is_err());
+        // a decreasing transform swaps the ends of a range
+        assert_eq!(affine.invert_result(DpResult::Range(fixed(-7), fixed(1))), DpResult::Range(fixed(1), fixed(5)));
+
+        // noisy values far outside the data saturate instead of failing
+        let shallow = Transform::Affine { a: I32F32::from_num(0.001), b: fixed(0) };
+        assert_eq!(shallow.invert(fixed(1_000_000)), I32F32::MAX);
+        assert_eq!(shallow.invert(fixed(-1_000_000)), I32F32::MIN);
+        let decreasing = Transform::Affine { a: I32F32::from_num(-0.001), b: fixed(0) };
+        assert_eq!(decreasing.invert(fixed(1_000_000)), I32F32::MIN);
+        assert_eq!(Transform::Log1p.invert(fixed(100)), I32F32::MAX);
+        assert_eq!(Transform::Log1p.invert(I32F32::MAX), I32F32::MAX);
+        assert_eq!(Transform::Log1p.invert(fixed(-100)), fixed(-1));
+        assert_eq!(Transform::Log1p.invert(I32F32::MIN), fixed(-1));
+    }
+
+    #[test]
+    fn transformed_averages_are_released_in_the_original_domain() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats")
+            .with_transform(Transform::Log1p)
+            .with_clipping(fixed(0), fixed(7));
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(10)), None, Some(fixed(100)))).unwrap();
+        // the arithmetic mean is about 10 000, but the geometric mean of 1 + x is far lower
+        for _ in 0..990 {
+            store.add_observation(&mut storage, fixed(99)).unwrap();
+        }
+        for _ in 0..10 {
+            store.add_observation(&mut storage, fixed(999_999)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([4; 32]);
+        let average = store.fuzzy_average(&mut storage, &mut rng).unwrap().released().unwrap();
+        // the clipped logarithms average to 0.99 ln 100 + 0.01 * 7
+        let expected = I32F32::from_num(101.42);
+        assert!((average - expected).abs() < fixed(1), "{average}");
+
+        let affine: RunningStatsStore = RunningStatsStore::new(b"affine")
+            .with_transform(Transform::Affine { a: fixed(-2), b: fixed(100) })
+            .with_clipping(fixed(0), fixed(100));
+        affine.reset(&mut storage, ResetPolicy::new(Some(fixed(10)), None, Some(fixed(100)))).unwrap();
+        for x in [10, 20, 30] {
+            for _ in 0..100 {
+                affine.add_observation(&mut storage, fixed(x)).unwrap();
+            }
+        }
+        affine.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let average = affine.fuzzy_average(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert!((average - fixed(20)).abs() < fixed(1), "{average}");
+    }
+
+    #[test]
+    fn noisy_quotients_saturate() {
+        let one = I64F64::from_num(1);
+        // a noisy count close to zero or below it is raised to one
+        assert_eq!(saturating_quotient(I64F64::from_num(5), I64F64::from_num(0.25), one), fixed(5));
+        assert_eq!(saturating_quotient(I64F64::from_num(5), I64F64::from_num(-3), one), fixed(5));
+        assert_eq!(saturating_quotient(I64F64::from_num(12), I64F64::from_num(4), one), fixed(3));
+        // quotients outside I32F32 saturate
+        assert_eq!(saturating_quotient(I64F64::from_num(1_000_000_000_000_i64), one, one), I32F32::MAX);
+        assert_eq!(saturating_quotient(I64F64::from_num(-1_000_000_000_000_i64), one, one), I32F32::MIN);
+        // as do quotients outside I64F64
+        let ulp = I64F64::from_bits(1);
+        assert_eq!(saturating_quotient(one, I64F64::from_num(0), ulp), I32F32::MAX);
+        assert_eq!(saturating_quotient(-one, I64F64::from_num(-0.5), ulp), I32F32::MIN);
+    }
+
+    #[test]
+    fn overflowing_sums_release_a_saturated_average() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), Some(fixed(10)), Some(fixed(10)))).unwrap();
+        store.add_observation(&mut storage, fixed(4)).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut snapshot = store.export_snapshot(&storage).unwrap();
+        snapshot.count = 2;
+        snapshot.sum = I64F64::MAX.to_stored();
+        store.import_snapshot(&mut storage, &snapshot).unwrap();
+
+        // positive noise would take the sum past I64F64, and the average is far past I32F32
+        let mut rng = ChaChaRng::from_seed([6; 32]);
+        assert_eq!(store.fuzzy_average(&mut storage, &mut rng).unwrap().released().unwrap(), I32F32::MAX);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(8));
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    #[test]
+    fn percentiles_are_released_from_the_histogram() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_bound_histogram(fixed(0), fixed(100), 10);
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(10)), None, Some(fixed(100)))).unwrap();
+        for x in 0..100 {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        let quarter = store.fuzzy_percentile_of(&mut storage, &mut rng, fixed(25)).unwrap().released().unwrap();
+        assert!((quarter - I32F32::from_num(0.25)).abs() < I32F32::from_num(0.02), "{quarter}");
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(90));
+        // values outside the layout are below or above every bin
+        let below = store.fuzzy_percentile_of(&mut storage, &mut rng, fixed(-50)).unwrap().released().unwrap();
+        assert!(below < I32F32::from_num(0.02), "{below}");
+        let above = store.fuzzy_percentile_of(&mut storage, &mut rng, fixed(500)).unwrap().released().unwrap();
+        assert!(above > I32F32::from_num(0.98), "{above}");
+
+        let plain: RunningStatsStore = RunningStatsStore::new(b"plain");
+        plain.reset(&mut storage, ResetPolicy::new(Some(fixed(10)), None, Some(fixed(100)))).unwrap();
+        plain.add_observation(&mut storage, fixed(1)).unwrap();
+        plain.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert!(plain.fuzzy_percentile_of(&mut storage, &mut rng, fixed(1)).is_err());
+        assert_eq!(plain.get_privacy_budget(&storage).unwrap(), fixed(100));
+    }
+
+    #[cfg(not(feature = "constant_time"))]
+    #[test]
+    fn histograms_are_kept_with_clipping_and_bound_heaps() {
+        let mut storage = MockStorage::new();
+        let clipped: RunningStatsStore = RunningStatsStore::new(b"clipped")
+            .with_clipping(fixed(0), fixed(100))
+            .with_bound_histogram(fixed(0), fixed(100), 10);
+        let heaps: RunningStatsStore = RunningStatsStore::new(b"heaps")
+            .with_bound_heaps()
+            .with_bound_histogram(fixed(0), fixed(100), 10);
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        for store in [&clipped, &heaps] {
+            store.reset(&mut storage, ResetPolicy::new(Some(fixed(10)), None, Some(fixed(100)))).unwrap();
+            for x in 0..100 {
+                store.add_observation(&mut storage, fixed(x)).unwrap();
+            }
+            // the first ten observations are withdrawn
+            for x in 0..10 {
+                store.remove_observation(&mut storage, fixed(x)).unwrap();
+            }
+            store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+            // 40 of the 90 remaining observations are below 50
+            let half = store.fuzzy_percentile_of(&mut storage, &mut rng, fixed(50)).unwrap().released().unwrap();
+            assert!((half - I32F32::from_num(0.444)).abs() < I32F32::from_num(0.02), "{half}");
+            // the emptied first bin may still pass the noisy threshold
+            let (min, max) = store.fuzzy_range(&mut storage, &mut rng).unwrap().released().unwrap();
+            assert!(min <= fixed(10), "{min}");
+            assert_eq!(max, fixed(100));
+        }
+        // heaps still track the exact bounds, and clipping leaves them alone
+        assert_eq!(heaps.get_lower_bound(&storage).unwrap(), fixed(10));
+        assert_eq!(heaps.get_upper_bound(&storage).unwrap(), fixed(99));
+    }
+
+    #[test]
+    #[should_panic(expected = "Clipping lower bound must be less than upper bound")]
+    fn empty_clipping_range_is_rejected() {
+        let _store: RunningStatsStore = RunningStatsStore::new(b"stats").with_clipping(fixed(5), fixed(5));
+    }
+
+    #[test]
+    fn empty_sensitivity_ranges_are_rejected() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats")
+            .with_sensitivity_policy(SensitivityPolicy::Fixed);
+        assert!(store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), Some(fixed(0)), Some(fixed(10)))).is_err());
+        assert!(store.set_average_sensitivity(&mut storage, Some(fixed(-1))).is_err());
+        assert!(clipping_width((fixed(3), fixed(3))).is_err());
+        assert!(clipping_width((I32F32::MIN, I32F32::MAX)).is_err());
+
+        // the observed range of a single observation is empty
+        let observed: RunningStatsStore = RunningStatsStore::new(b"observed")
+            .with_sensitivity_policy(SensitivityPolicy::ObservedRange);
+        observed.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        observed.add_observation(&mut storage, fixed(4)).unwrap();
+        observed.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        assert!(observed.fuzzy_average(&mut storage, &mut rng).is_err());
+    }
+
+    #[test]
+    fn min_count_is_checked_with_noise_and_charged() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_min_count_for_release(&mut storage, 20).unwrap();
+        for _ in 0..3 {
+            store.add_observation(&mut storage, fixed(4)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert_eq!(store.report_cost(&storage, &[QueryKind::Count]).unwrap(), fixed(2));
+
+        // a refused release is charged for the check alone, and is not an error, which would
+        // revert the charge with the rest of the transaction
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        assert_eq!(store.fuzzy_count(&mut storage, &mut rng).unwrap(), Release::Refused);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(9));
+        assert_eq!(store.get_total_queries(&storage).unwrap(), 1);
+
+        let big: RunningStatsStore = RunningStatsStore::new(b"big");
+        big.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        big.set_min_count_for_release(&mut storage, 20).unwrap();
+        for _ in 0..100 {
+            big.add_observation(&mut storage, fixed(4)).unwrap();
+        }
+        big.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        big.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(big.get_privacy_budget(&storage).unwrap(), fixed(8));
+    }
+
+    #[cfg(feature = "constant_time")]
+    #[test]
+    fn constant_time_refusals_look_alike() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(1)))).unwrap();
+        store.set_min_count_for_release(&mut storage, 20).unwrap();
+        for _ in 0..100 {
+            store.add_observation(&mut storage, fixed(4)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        // the count and the check cost 2, so the release is refused without being charged, in
+        // the same way as a refusal by the check
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        assert_eq!(store.fuzzy_count(&mut storage, &mut rng).unwrap(), Release::Refused);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(1));
+        assert_eq!(store.get_total_queries(&storage).unwrap(), 0);
+    }
+
+    #[test]
+    fn cached_results_are_kept_per_epsilon() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_result_cache();
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([4; 32]);
+        assert_eq!(store.report_cost(&storage, &[QueryKind::Count, QueryKind::Count]).unwrap(), fixed(1));
+        let first = store.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap();
+        assert_eq!(store.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap(), first);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(9));
+        // only the statistics that are not cached yet are charged
+        assert_eq!(store.report_cost(&storage, &[QueryKind::Count]).unwrap(), fixed(0));
+        assert_eq!(store.report_cost(&storage, &[QueryKind::Count, QueryKind::Average]).unwrap(), fixed(2));
+
+        // a different epsilon is drawn and charged afresh
+        store.set_epsilon(&mut storage, fixed(2)).unwrap();
+        assert_ne!(store.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap(), first);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(7));
+
+        // and the answer at the first epsilon is still cached
+        store.set_epsilon(&mut storage, fixed(1)).unwrap();
+        assert_eq!(store.fuzzy_count(&mut storage, &mut rng).unwrap().released().unwrap(), first);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(7));
+    }
+
+    #[test]
+    fn each_statistic_draws_from_its_own_stream() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        // the noise of the count does not depend on the other statistics of the report
+        let count_alone = store
+            .fuzzy_report(&mut storage, &mut ChaChaRng::from_seed([5; 32]), &[QueryKind::Count])
+            .unwrap().released().unwrap();
+        let with_average = store
+            .fuzzy_report(&mut storage, &mut ChaChaRng::from_seed([5; 32]), &[QueryKind::Average, QueryKind::Count])
+            .unwrap().released().unwrap();
+        assert_eq!(with_average[1], count_alone[0]);
+    }
+
+    #[test]
+    fn frozen_labels_pin_released_statistics() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_result_cache();
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([5; 32]);
+        assert!(store.fuzzy_report_labeled(&mut storage, &mut rng, "", &[QueryKind::Count]).is_err());
+        let first = store.fuzzy_report_labeled(&mut storage, &mut rng, "q3", &[QueryKind::Count]).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(9));
+        let snapshot = store.get_frozen_snapshot(&storage, "q3").unwrap().unwrap();
+        assert_eq!(snapshot.queries, vec![QueryKind::Count]);
+
+        // clearing the store and collecting the same data again does not give new noise
+        store.reset(&mut storage, ResetPolicy::KEEP_ALL).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert_eq!(store.fuzzy_report_labeled(&mut storage, &mut rng, "q3", &[QueryKind::Count]).unwrap().released().unwrap(), first);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(9));
+        // a statistic that was not frozen under the label is refused
+        assert!(store.fuzzy_report_labeled(&mut storage, &mut rng, "q3", &[QueryKind::Average]).is_err());
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(9));
+
+        // a new label is released and charged as usual
+        store.fuzzy_report_labeled(&mut storage, &mut rng, "q4", &[QueryKind::Average]).unwrap().released().unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(7));
+
+        let uncached: RunningStatsStore = RunningStatsStore::new(b"uncached");
+        uncached.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        uncached.add_observation(&mut storage, fixed(2)).unwrap();
+        uncached.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert!(uncached.fuzzy_report_labeled(&mut storage, &mut rng, "q3", &[QueryKind::Count]).is_err());
+        assert_eq!(uncached.get_privacy_budget(&storage).unwrap(), fixed(10));
+    }
+
+    #[test]
+    fn status_transitions_follow_the_table() {
+        use RunningStatsStatus::*;
+        let statuses = [CollectingData, CalculatingStats, Paused, Closed];
+        // rows are the current status, columns the next one, in the order above
+        let allowed = [
+            [true, true, true, true],
+            [false, true, false, true],
+            [true, true, true, true],
+            [false, false, false, true],
+        ];
+        for (current, row) in statuses.iter().zip(allowed) {
+            for (next, allowed) in statuses.iter().zip(row) {
+                assert_eq!(current.check_transition(*next).is_ok(), allowed, "{:?} to {:?}", current, next);
+            }
+        }
+    }
+
+    #[test]
+    fn closed_stores_are_frozen() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation(&mut storage, fixed(x)).unwrap();
+        }
+        store.set_status(&mut storage, RunningStatsStatus::Closed).unwrap();
+
+        assert!(store.set_privacy_budget(&mut storage, fixed(100)).is_err());
+        assert!(store.set_epsilon(&mut storage, fixed(2)).is_err());
+        assert!(store.set_collection_deadline(&mut storage, 100).is_err());
+        assert!(store.set_average_sensitivity(&mut storage, Some(fixed(1000))).is_err());
+        assert!(store.set_min_count_for_release(&mut storage, 0).is_err());
+        assert!(store.set_min_blocks_between_queries(&mut storage, 0).is_err());
+        assert!(store.set_max_total_queries(&mut storage, u32::MAX).is_err());
+        assert!(store.set_observation_rate_limit(&mut storage, 1, 1).is_err());
+        assert!(store.set_rate_limit_salt(&mut storage, &[7; 32]).is_err());
+        let policy = PrivacyPolicy {
+            min_epsilon_per_query: fixed(1),
+            max_epsilon_per_query: fixed(1),
+            max_delta_total: I32F32::from_num(0.01),
+            max_privacy_budget: fixed(10),
+        };
+        assert!(store.set_privacy_policy(&mut storage, policy).is_err());
+        assert!(store.remove_observation(&mut storage, fixed(2)).is_err());
+        assert!(store.add_observation(&mut storage, fixed(2)).is_err());
+        assert!(store.reset(&mut storage, ResetPolicy::new(None, None, None)).is_err());
+        assert!(store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).is_err());
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        assert!(store.fuzzy_count(&mut storage, &mut rng).is_err());
+
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(10));
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(1));
+        assert_eq!(store.get_count(&storage).unwrap(), 3);
+        assert_eq!(store.get_collection_deadline(&storage).unwrap(), None);
+        assert_eq!(store.get_avg_sensitivity(&storage).unwrap(), None);
+        assert_eq!(store.get_max_total_queries(&storage).unwrap(), None);
+        assert_eq!(store.get_observation_rate_limit(&storage).unwrap(), None);
+        assert_eq!(store.get_privacy_policy(&storage).unwrap(), None);
+
+        // nor can budget be moved into it
+        let other: RunningStatsStore = RunningStatsStore::new(b"other");
+        other.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(5)))).unwrap();
+        assert!(transfer_budget(&mut storage, &other, &store, fixed(1)).is_err());
+        assert_eq!(other.get_privacy_budget(&storage).unwrap(), fixed(5));
+    }
+
+    #[test]
+    fn collection_deadline_ends_collection() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_collection_deadline(&mut storage, 100).unwrap();
+
+        // without the height the deadline cannot be checked
+        assert!(store.add_observation(&mut storage, fixed(1)).is_err());
+        for x in [2, 4, 6] {
+            store.add_observation_at(&mut storage, 99, fixed(x)).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        assert!(store.fuzzy_report_at(&mut storage, &mut rng, 99, &[QueryKind::Count]).is_err());
+
+        assert!(store.add_observation_at(&mut storage, 100, fixed(8)).is_err());
+        assert_eq!(store.get_status(&storage).unwrap(), RunningStatsStatus::CalculatingStats);
+        assert_eq!(store.get_count(&storage).unwrap(), 3);
+        store.fuzzy_report_at(&mut storage, &mut rng, 101, &[QueryKind::Count]).unwrap().released().unwrap();
+    }
+
+    #[test]
+    fn removals_and_updates_respect_the_deadline() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_collection_deadline(&mut storage, 100).unwrap();
+        for x in [2, 4, 6] {
+            store.add_observation_at(&mut storage, 50, fixed(x)).unwrap();
+        }
+
+        // without the height the deadline cannot be checked
+        assert!(store.remove_observation(&mut storage, fixed(4)).is_err());
+        assert!(store.update_observation(&mut storage, fixed(4), fixed(5)).is_err());
+        store.update_observation_at(&mut storage, 60, fixed(4), fixed(5)).unwrap();
+        store.remove_observation_at(&mut storage, 70, fixed(2)).unwrap();
+        assert_eq!(store.get_count(&storage).unwrap(), 2);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(11));
+
+        // the data is fixed once collection has ended
+        assert!(store.remove_observation_at(&mut storage, 100, fixed(5)).is_err());
+        assert!(store.update_observation_at(&mut storage, 101, fixed(5), fixed(3)).is_err());
+        assert_eq!(store.get_status(&storage).unwrap(), RunningStatsStatus::CalculatingStats);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(11));
+    }
+
+    #[test]
+    fn updates_count_towards_the_rate_limit() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_observation_rate_limit(&mut storage, 2, 100).unwrap();
+        store.set_rate_limit_salt(&mut storage, &[7; 32]).unwrap();
+        store.add_observation_for(&mut storage, b"alice", 10, fixed(1)).unwrap();
+
+        assert!(store.update_observation(&mut storage, fixed(1), fixed(2)).is_err());
+        assert!(store.update_observation_at(&mut storage, 20, fixed(1), fixed(2)).is_err());
+        store.update_observation_for(&mut storage, b"alice", 20, fixed(1), fixed(2)).unwrap();
+        // the limit is checked before the old value is removed
+        assert!(store.update_observation_for(&mut storage, b"alice", 30, fixed(2), fixed(3)).is_err());
+        assert_eq!(store.get_count(&storage).unwrap(), 1);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(2));
+        // a new epoch starts a new allowance
+        store.update_observation_for(&mut storage, b"alice", 100, fixed(2), fixed(3)).unwrap();
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(3));
+    }
+
+    #[test]
+    fn removing_a_clamped_observation_uncounts_it() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_clipping(fixed(0), fixed(10));
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.add_observation(&mut storage, fixed(4)).unwrap();
+        store.add_observation(&mut storage, fixed(15)).unwrap();
+        assert_eq!(store.clamped_count.load_or(&storage, store.as_slice(), 0).unwrap(), 1);
+
+        store.remove_observation(&mut storage, fixed(15)).unwrap();
+        assert_eq!(store.clamped_count.load_or(&storage, store.as_slice(), 0).unwrap(), 0);
+        assert_eq!(store.get_sum(&storage).unwrap(), I64F64::from_num(4));
+    }
+
+    #[test]
+    fn empty_stores_pause_at_the_deadline() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_collection_deadline(&mut storage, 100).unwrap();
+
+        assert_eq!(store.get_status_at(&storage, 100).unwrap(), RunningStatsStatus::Paused);
+        store.apply_collection_deadline(&mut storage, 100).unwrap();
+        assert_eq!(store.get_status(&storage).unwrap(), RunningStatsStatus::Paused);
+        assert!(store.add_observation_at(&mut storage, 101, fixed(1)).is_err());
+
+        // a later deadline lets collection resume
+        store.set_collection_deadline(&mut storage, 200).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CollectingData).unwrap();
+        store.add_observation_at(&mut storage, 150, fixed(1)).unwrap();
+        assert_eq!(store.get_status_at(&storage, 200).unwrap(), RunningStatsStatus::CalculatingStats);
+    }
+
+    #[test]
+    fn rate_limited_stores_only_take_counted_adds() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_observation_rate_limit(&mut storage, 2, 100).unwrap();
+
+        assert!(store.add_observation(&mut storage, fixed(1)).is_err());
+        assert!(store.add_observation_at(&mut storage, 10, fixed(1)).is_err());
+        assert!(store.add_observation_once(&mut storage, b"alice", fixed(1)).is_err());
+        // the salt must be set before the first counted add
+        assert!(store.add_observation_for(&mut storage, b"alice", 10, fixed(1)).is_err());
+        assert_eq!(store.get_count(&storage).unwrap(), 0);
+
+        store.set_rate_limit_salt(&mut storage, &[7; 32]).unwrap();
+        assert!(store.set_rate_limit_salt(&mut storage, &[8; 32]).is_err());
+        store.add_observation_for(&mut storage, b"alice", 10, fixed(1)).unwrap();
+        store.add_observation_for(&mut storage, b"alice", 20, fixed(2)).unwrap();
+        assert!(store.add_observation_for(&mut storage, b"alice", 99, fixed(3)).is_err());
+        store.add_observation_for(&mut storage, b"bob", 99, fixed(3)).unwrap();
+        // a new epoch starts a new allowance
+        store.add_observation_for(&mut storage, b"alice", 100, fixed(4)).unwrap();
+        assert_eq!(store.get_count(&storage).unwrap(), 4);
+    }
+
+    #[test]
+    fn contributor_hashes_are_salted() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        store.set_observation_rate_limit(&mut storage, 1, 100).unwrap();
+        store.set_rate_limit_salt(&mut storage, &[7; 32]).unwrap();
+        store.add_observation_for(&mut storage, b"alice", 10, fixed(1)).unwrap();
+
+        let unsalted = [store.as_slice(), RATE_COUNT_KEY, &sha_256(b"alice")].concat();
+        assert!(storage.get(&unsalted).is_none());
+        let salted = [store.as_slice(), RATE_COUNT_KEY, &sha_256(&[[7u8; 32].as_slice(), b"alice"].concat())].concat();
+        assert!(storage.get(&salted).is_some());
+    }
+
+    #[test]
+    fn one_time_contributor_markers_are_salted() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        // the salt must be set before the first contribution
+        assert!(store.add_observation_once(&mut storage, b"alice", fixed(1)).is_err());
+        assert!(!store.has_contributed(&storage, b"alice"));
+
+        store.set_rate_limit_salt(&mut storage, &[7; 32]).unwrap();
+        store.add_observation_once(&mut storage, b"alice", fixed(1)).unwrap();
+        assert!(store.has_contributed(&storage, b"alice"));
+        assert!(!store.has_contributed(&storage, b"bob"));
+        assert!(store.add_observation_once(&mut storage, b"alice", fixed(2)).is_err());
+        assert_eq!(store.get_count(&storage).unwrap(), 1);
+
+        let unsalted = [store.as_slice(), CONTRIBUTOR_KEY, &sha_256(b"alice")].concat();
+        assert!(storage.get(&unsalted).is_none());
+        let salted = [store.as_slice(), CONTRIBUTOR_KEY, &sha_256(&[[7u8; 32].as_slice(), b"alice"].concat())].concat();
+        assert!(storage.get(&salted).is_some());
+    }
+
+    #[test]
+    fn commitment_chains_every_change() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_observation_commitment();
+        let other: RunningStatsStore = RunningStatsStore::new(b"other").with_observation_commitment();
+        for store in [&store, &other] {
+            store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        }
+        // the salt is needed to blind the observations
+        assert!(store.add_observation(&mut storage, fixed(2)).is_err());
+        store.set_commitment_salt(&mut storage, &[7; 32]).unwrap();
+        other.set_commitment_salt(&mut storage, &[7; 32]).unwrap();
+        assert_eq!(store.get_commitment(&storage).unwrap(), None);
+
+        store.add_observation(&mut storage, fixed(2)).unwrap();
+        let first = store.get_commitment(&storage).unwrap().unwrap();
+        store.add_observation(&mut storage, fixed(4)).unwrap();
+        let second = store.get_commitment(&storage).unwrap().unwrap();
+        assert_ne!(first, second);
+        assert!(store.set_commitment_salt(&mut storage, &[8; 32]).is_err());
+
+        // the same changes in a different order give a different commitment
+        other.add_observation(&mut storage, fixed(4)).unwrap();
+        other.add_observation(&mut storage, fixed(2)).unwrap();
+        assert_ne!(other.get_commitment(&storage).unwrap().unwrap(), second);
+
+        store.remove_observation(&mut storage, fixed(4)).unwrap();
+        assert_ne!(store.get_commitment(&storage).unwrap().unwrap(), first);
+        store.reset(&mut storage, ResetPolicy::KEEP_ALL).unwrap();
+        assert_eq!(store.get_commitment(&storage).unwrap(), None);
+    }
+
+    #[test]
+    fn stores_without_a_commitment_refuse_the_salt() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(1)), None, Some(fixed(10)))).unwrap();
+        assert!(store.set_commitment_salt(&mut storage, &[7; 32]).is_err());
+        store.add_observation(&mut storage, fixed(2)).unwrap();
+        assert_eq!(store.get_commitment(&storage).unwrap(), None);
+    }
+
+    #[test]
+    fn privacy_policy_bounds_every_setting() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        let policy = PrivacyPolicy {
+            min_epsilon_per_query: fixed(2),
+            max_epsilon_per_query: fixed(5),
+            max_delta_total: I32F32::from_num(0.01),
+            max_privacy_budget: fixed(10),
+        };
+        store.set_privacy_policy(&mut storage, policy).unwrap();
+
+        // the defaults of 1 are below the epsilon floor
+        assert!(store.reset(&mut storage, ResetPolicy::DEFAULT).is_err());
+        assert!(store.reset(&mut storage, ResetPolicy::new(Some(fixed(3)), None, Some(fixed(20)))).is_err());
+        store.reset(&mut storage, ResetPolicy::new(Some(fixed(3)), None, Some(fixed(10)))).unwrap();
+
+        assert!(store.set_epsilon(&mut storage, fixed(6)).is_err());
+        assert!(store.set_privacy_budget(&mut storage, fixed(11)).is_err());
+        store.set_privacy_budget(&mut storage, fixed(8)).unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(8));
+
+        let looser = PrivacyPolicy { max_privacy_budget: fixed(20), ..policy };
+        assert!(store.set_privacy_policy(&mut storage, looser).is_err());
+        // the current budget must fit a tighter limit
+        let tighter = PrivacyPolicy { max_privacy_budget: fixed(5), ..policy };
+        assert!(store.set_privacy_policy(&mut storage, tighter).is_err());
+        store.set_privacy_budget(&mut storage, fixed(5)).unwrap();
+        store.set_privacy_policy(&mut storage, tighter).unwrap();
+        assert_eq!(store.get_privacy_policy(&storage).unwrap(), Some(tighter));
+    }
+
+    #[test]
+    fn builder_checks_defaults_against_the_policy() {
+        let mut storage = MockStorage::new();
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats");
+        let policy = PrivacyPolicy {
+            min_epsilon_per_query: fixed(2),
+            max_epsilon_per_query: fixed(5),
+            max_delta_total: I32F32::from_num(0.01),
+            max_privacy_budget: fixed(10),
+        };
+        assert!(RunningStatsStoreBuilder::new(&store).privacy_policy(policy).privacy_budget(fixed(5)).validate().is_err());
+        assert!(RunningStatsStoreBuilder::new(&store).privacy_policy(policy).epsilon(fixed(2)).privacy_budget(fixed(11)).validate().is_err());
+        RunningStatsStoreBuilder::new(&store).privacy_policy(policy).epsilon(fixed(2)).privacy_budget(fixed(10)).init(&mut storage).unwrap();
+        assert_eq!(store.get_epsilon(&storage).unwrap(), fixed(2));
+    }
+
+    #[test]
+    fn builder_refuses_a_budget_for_a_shared_budget_store() {
+        let mut storage = MockStorage::new();
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, fixed(3));
+        let store: RunningStatsStore = RunningStatsStore::new(b"stats").with_shared_budget(&accountant);
+        assert!(RunningStatsStoreBuilder::new(&store).privacy_budget(fixed(10)).init(&mut storage).is_err());
+        assert!(!store.is_initialized(&storage));
+
+        RunningStatsStoreBuilder::new(&store).epsilon(fixed(1)).init(&mut storage).unwrap();
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), fixed(3));
+    }
+}
@@ -0,0 +1,212 @@
+use cosmwasm_std::{StdResult, StdError};
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+
+use crate::cauchy;
+
+// Smooth sensitivity (Nissim, Raskhodnikova and Smith, 2007) scales noise to how much one
+// observation can move the statistic on this particular dataset, rather than on the worst case
+// dataset. For small samples this gives far more accurate medians and averages than noise scaled
+// to the global sensitivity. Cauchy noise, with density proportional to 1 / (1 + z^2), is
+// (epsilon / 6, epsilon / 6)-admissible, so smoothing with beta = epsilon / 6 and noise of scale
+// 6 * S / epsilon gives pure epsilon-DP. Laplace noise would only give (epsilon, delta)-DP.
+
+/// Smoothing parameter beta for Cauchy noise with epsilon-DP
+pub fn smooth_beta(epsilon: I32F32) -> StdResult<I32F32> {
+    if epsilon <= I32F32::from_num(0) {
+        return Err(StdError::generic_err("Epsilon must be greater than zero"));
+    }
+    Ok(epsilon / 6)
+}
+
+/// Cauchy noise scaled to the smooth sensitivity
+fn smooth_noise(rng: &mut ChaChaRng, sensitivity: I32F32, epsilon: I32F32) -> StdResult<I32F32> {
+    if sensitivity <= I32F32::from_num(0) {
+        return Ok(I32F32::from_num(0));
+    }
+    let scale = sensitivity.saturating_mul(I32F32::from_num(6)) / epsilon;
+    Ok(cauchy(rng, scale)?)
+}
+
+/// Upper estimate of e^-beta. The series is cut after a positive term, so the factor is never
+/// below e^-beta and the smoothed sensitivity can only be overestimated.
+fn decay_factor(beta: I32F32) -> I32F32 {
+    let beta = beta.min(I32F32::from_num(1));
+    let beta2 = beta * beta;
+    let beta3 = beta2 * beta;
+    let beta4 = beta3 * beta;
+    I32F32::from_num(1) - beta + beta2 / 2 - beta3 / 6 + beta4 / 24
+}
+
+/// Sorted copy of the sample with every value clamped into [lower, upper]
+fn clamped_sorted(sample: &[I32F32], lower: I32F32, upper: I32F32) -> Vec<I32F32> {
+    let mut sorted: Vec<I32F32> = sample.iter().map(|x| x.clamp(lower, upper)).collect();
+    sorted.sort();
+    sorted
+}
+
+/// Smooth sensitivity of the median of `sample`, whose values are clamped into [lower, upper].
+/// Runs in O(n^2) time in the worst case, so it is meant for small samples.
+pub fn smooth_sensitivity_median(
+    sample: &[I32F32],
+    lower: I32F32,
+    upper: I32F32,
+    beta: I32F32,
+) -> I32F32 {
+    let sorted = clamped_sorted(sample, lower, upper);
+    let n = sorted.len() as i64;
+    // position of the (lower) median, counting from 1
+    let m = (n + 1) / 2;
+    // positions outside the sample take the values of the bounds
+    let at = |i: i64| -> I32F32 {
+        if i < 1 {
+            lower
+        } else if i > n {
+            upper
+        } else {
+            sorted[(i - 1) as usize]
+        }
+    };
+
+    let range = upper - lower;
+    let factor = decay_factor(beta);
+    let mut decay = I32F32::from_num(1);
+    let mut smooth = I32F32::from_num(0);
+    for k in 0..=n {
+        // no larger distance can beat the current maximum
+        if decay * range <= smooth {
+            break;
+        }
+        let mut local = I32F32::from_num(0);
+        for t in 0..=(k + 1) {
+            local = local.max(at(m + t) - at(m + t - k - 1));
+        }
+        smooth = smooth.max(decay * local);
+        decay *= factor;
+    }
+    smooth
+}
+
+/// Smooth sensitivity of the average of `n` observations in [lower, upper], when neighbouring
+/// datasets differ by adding or removing one observation
+pub fn smooth_sensitivity_average(
+    n: u32,
+    lower: I32F32,
+    upper: I32F32,
+    beta: I32F32,
+) -> I32F32 {
+    let range = upper - lower;
+    let factor = decay_factor(beta);
+    let mut decay = I32F32::from_num(1);
+    let mut smooth = I32F32::from_num(0);
+    for k in 0..=n {
+        if decay * range <= smooth {
+            break;
+        }
+        // an average over n - k observations moves by at most range / (n - k - 1)
+        let remaining = n.saturating_sub(k).saturating_sub(1).max(1);
+        smooth = smooth.max(decay * range / I32F32::from_num(remaining));
+        decay *= factor;
+    }
+    smooth
+}
+
+/// Median of `sample` with Cauchy noise scaled to its smooth sensitivity, clamped into
+/// [lower, upper]. Gives epsilon-DP.
+pub fn smooth_median(
+    rng: &mut ChaChaRng,
+    sample: &[I32F32],
+    lower: I32F32,
+    upper: I32F32,
+    epsilon: I32F32,
+) -> StdResult<I32F32> {
+    if sample.is_empty() {
+        return Err(StdError::generic_err("No data to count"));
+    }
+    let beta = smooth_beta(epsilon)?;
+    let sensitivity = smooth_sensitivity_median(sample, lower, upper, beta);
+    let sorted = clamped_sorted(sample, lower, upper);
+    let median = sorted[(sorted.len() - 1) / 2];
+    let noise = smooth_noise(rng, sensitivity, epsilon)?;
+    Ok(median.saturating_add(noise).clamp(lower, upper))
+}
+
+/// Average of `sample` with Cauchy noise scaled to its smooth sensitivity, clamped into
+/// [lower, upper]. Gives epsilon-DP.
+pub fn smooth_average(
+    rng: &mut ChaChaRng,
+    sample: &[I32F32],
+    lower: I32F32,
+    upper: I32F32,
+    epsilon: I32F32,
+) -> StdResult<I32F32> {
+    if sample.is_empty() {
+        return Err(StdError::generic_err("No data to count"));
+    }
+    let n = u32::try_from(sample.len())
+        .map_err(|_| StdError::generic_err("Sample too large"))?;
+    let beta = smooth_beta(epsilon)?;
+    let sensitivity = smooth_sensitivity_average(n, lower, upper, beta);
+    let mut sum = I64F64::from_num(0);
+    for x in sample.iter() {
+        sum += I64F64::from_num(x.clamp(lower, upper));
+    }
+    let average = I32F32::from_num(sum / I64F64::from_num(n));
+    let noise = smooth_noise(rng, sensitivity, epsilon)?;
+    Ok(average.saturating_add(noise).clamp(lower, upper))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn fixed(x: f64) -> I32F32 {
+        I32F32::from_num(x)
+    }
+
+    #[test]
+    fn median_sensitivity_shrinks_for_clustered_samples() {
+        let beta = smooth_beta(fixed(1.0)).unwrap();
+        assert_eq!(beta, fixed(1.0) / 6);
+        assert!(smooth_beta(fixed(0.0)).is_err());
+
+        // one or two observations can move the median of a tiny sample across most of the range
+        let tiny = [fixed(50.0)];
+        assert!(smooth_sensitivity_median(&tiny, fixed(0.0), fixed(100.0), beta) > fixed(80.0));
+
+        // many equal observations around the median keep its local sensitivity at zero, and
+        // only the decayed distance to the bounds remains
+        let clustered = vec![fixed(50.0); 101];
+        let smooth = smooth_sensitivity_median(&clustered, fixed(0.0), fixed(100.0), beta);
+        assert!(smooth < fixed(1.0), "smooth sensitivity {}", smooth);
+    }
+
+    #[test]
+    fn average_sensitivity_falls_with_sample_size() {
+        let beta = smooth_beta(fixed(1.0)).unwrap();
+        let small = smooth_sensitivity_average(2, fixed(0.0), fixed(10.0), beta);
+        let large = smooth_sensitivity_average(1000, fixed(0.0), fixed(10.0), beta);
+        assert!(large < small);
+        assert!(large >= fixed(10.0) / 1000);
+    }
+
+    #[test]
+    fn releases_stay_near_the_statistic() {
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        let sample: Vec<I32F32> = (0..201).map(|i| fixed(40.0 + f64::from(i % 21))).collect();
+        let mut medians: Vec<f64> = (0..201)
+            .map(|_| smooth_median(&mut rng, &sample, fixed(0.0), fixed(100.0), fixed(1.0)).unwrap().to_num::<f64>())
+            .collect();
+        medians.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(medians.iter().all(|median| (0.0..=100.0).contains(median)));
+        assert!((medians[100] - 50.0).abs() < 3.0, "median of releases {}", medians[100]);
+
+        let mut sorted: Vec<f64> = (0..201)
+            .map(|_| smooth_average(&mut rng, &sample, fixed(0.0), fixed(100.0), fixed(1.0)).unwrap().to_num::<f64>())
+            .collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((sorted[100] - 50.0).abs() < 3.0, "median of releases {}", sorted[100]);
+        assert!(smooth_average(&mut rng, &[], fixed(0.0), fixed(100.0), fixed(1.0)).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+/// Returns true if one namespace is a prefix of the other. Stores append their keys directly to
+/// the namespace, so two stores whose namespaces overlap can write to the same key.
+pub const fn namespaces_overlap(a: &[u8], b: &[u8]) -> bool {
+    let len = if a.len() < b.len() { a.len() } else { b.len() };
+    let mut idx = 0;
+    while idx < len {
+        if a[idx] != b[idx] {
+            return false;
+        }
+        idx += 1;
+    }
+    true
+}
+
+/// Panics if a namespace is empty or if any two namespaces overlap. Called in a constant, e.g. by
+/// `static_store!`, the check runs at compile time and a bad namespace fails the build.
+pub const fn check_namespaces(namespaces: &[&[u8]]) {
+    let mut i = 0;
+    while i < namespaces.len() {
+        assert!(!namespaces[i].is_empty(), "Store namespace must not be empty");
+        let mut j = i + 1;
+        while j < namespaces.len() {
+            assert!(!namespaces_overlap(namespaces[i], namespaces[j]), "Store namespaces overlap");
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Declares a group of stores as `static` items and checks their namespaces at compile time.
+/// Each store is built with its `new` constructor, whose first argument is the namespace,
+/// followed by any number of const builder calls. The build fails if a namespace is empty or if
+/// two namespaces in the same invocation overlap.
+///
+/// ```ignore
+/// static_store! {
+///     static STATS: RunningStatsStore = RunningStatsStore::new(b"stats")
+///         .with_clipping(I32F32::from_bits(0), I32F32::from_bits(100 << 32));
+///     static LOG: ReplayLog = ReplayLog::new(b"replay");
+/// }
+/// ```
+#[macro_export]
+macro_rules! static_store {
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis static $name:ident : $ty:ty = $store:ident :: new ( $namespace:expr $(, $arg:expr)* )
+            $( . $method:ident ( $($method_arg:expr),* ) )* ;
+    )*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $ty = $store::new($namespace $(, $arg)*) $( .$method($($method_arg),*) )*;
+        )*
+        const _: () = $crate::check_namespaces(&[$($namespace as &[u8]),*]);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_namespaces() {
+        assert!(namespaces_overlap(b"stats", b"stats"));
+        assert!(namespaces_overlap(b"stat", b"stats"));
+        assert!(!namespaces_overlap(b"stats", b"stamp"));
+        check_namespaces(&[b"stats", b"replay", b"events"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Store namespaces overlap")]
+    fn prefix_namespaces_are_rejected() {
+        check_namespaces(&[b"stats", b"replay", b"stats-by-region"]);
+    }
+
+    #[test]
+    fn declares_static_stores() {
+        use crate::{ReplayLog, ResetPolicy, RunningStatsStore};
+        use cosmwasm_std::testing::MockStorage;
+        use substrate_fixed::types::I32F32;
+
+        static_store! {
+            static STATS: RunningStatsStore = RunningStatsStore::new(b"stats")
+                .with_clipping(I32F32::from_bits(0), I32F32::from_bits(100 << 32));
+            static LOG: ReplayLog = ReplayLog::new(b"replay");
+        }
+
+        let mut storage = MockStorage::new();
+        STATS.reset(&mut storage, ResetPolicy::DEFAULT).unwrap();
+        assert_eq!(STATS.get_count(&storage).unwrap(), 0);
+        assert!(LOG.is_empty(&storage).unwrap());
+    }
+}
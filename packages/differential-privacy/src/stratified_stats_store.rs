@@ -0,0 +1,409 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_toolkit::serialization::{Serde, Bincode2};
+use substrate_fixed::types::{I32F32, I64F64};
+
+use crate::{min_count_gate_cost, noisy_count_reaches, CachedField, PrivacyAccountant, PrivacyPolicy, Release, ResetPolicy, RunningStatsStatus, RunningStatsStore};
+
+const EPSILON_KEY: &[u8] = b"strat-eps";
+const MIN_COUNT_KEY: &[u8] = b"strat-min-count";
+const STATUS_KEY: &[u8] = b"strat-status";
+
+/// Running statistics split into strata, e.g. survey responses by region or age group, with one
+/// `RunningStatsStore` per stratum. `fuzzy_stratified_average` combines the stratum averages
+/// with population weights given by the caller, so that strata that are over- or
+/// under-represented among the respondents count as much as they do in the population
+/// (post-stratification).
+///
+/// Observations are clamped into [lower, upper], which fixes the sensitivity of each stratum
+/// sum. Every observation is in exactly one stratum, so the noisy counts and sums of all strata
+/// together cost epsilon each, and one release is charged 2 * epsilon from a single budget
+/// shared by the strata. The per-stratum stores have no budget of their own, and are only
+/// released through this store.
+pub struct StratifiedStatsStore<'a, Ser = Bincode2>
+where
+    Ser: Serde,
+{
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    strata: u16,
+    epsilon: CachedField<I32F32>,
+    min_count_for_release: CachedField<u32>,
+    status: CachedField<RunningStatsStatus>,
+    /// joint budget charged by every release
+    budget: PrivacyAccountant<'a>,
+    /// store that per-stratum stores are derived from
+    stats: RunningStatsStore<'a, Ser>,
+}
+
+impl<'a, Ser: Serde> StratifiedStatsStore<'a, Ser> {
+    /// constructor, for `strata` strata numbered from 0 and observations clamped into
+    /// [lower, upper]
+    pub const fn new(
+        namespace: &'a [u8],
+        strata: u16,
+        lower: I32F32,
+        upper: I32F32,
+    ) -> Self {
+        assert!(strata > 0, "Stratified store must have at least one stratum");
+        assert!(lower.to_bits() < upper.to_bits(), "Lower bound must be less than upper bound");
+        Self {
+            namespace,
+            prefix: None,
+            strata,
+            epsilon: CachedField::new(EPSILON_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            budget: PrivacyAccountant::new(namespace),
+            stats: RunningStatsStore::new(namespace).with_clipping(lower, upper),
+        }
+    }
+
+    /// This is used to produce a new StratifiedStatsStore. It can be used when you want to
+    /// associate a StratifiedStatsStore to multiple suffixes and you still want to define the
+    /// StratifiedStatsStore as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let stats = self.stats.add_suffix(suffix);
+        let budget = self.budget.add_suffix(suffix);
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            strata: self.strata,
+            epsilon: CachedField::new(EPSILON_KEY),
+            min_count_for_release: CachedField::new(MIN_COUNT_KEY),
+            status: CachedField::new(STATUS_KEY),
+            budget,
+            stats,
+        }
+    }
+}
+
+impl<'a, Ser: Serde> StratifiedStatsStore<'a, Ser> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    pub fn get_strata(&self) -> u16 {
+        self.strata
+    }
+
+    /// Returns the running stats store for a stratum
+    fn store_for_stratum(&self, stratum: u16) -> StdResult<RunningStatsStore<'a, Ser>> {
+        if stratum >= self.strata {
+            return Err(StdError::generic_err("Stratum out of range"));
+        }
+        Ok(self.stats.add_suffix(&stratum.to_be_bytes()))
+    }
+
+    /// Resets every stratum and sets the epsilon of each release and the joint privacy budget.
+    /// The strata themselves get a zero budget, so only the joint budget is ever charged.
+    pub fn init(
+        &self,
+        storage: &mut dyn Storage,
+        epsilon: I32F32,
+        privacy_budget: I32F32,
+    ) -> StdResult<()> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        if privacy_budget < I32F32::from_num(0) {
+            return Err(StdError::generic_err("Privacy budget must not be negative"));
+        }
+        if let Some(policy) = self.get_privacy_policy(storage)? {
+            policy.check_privacy_budget(privacy_budget)?;
+        }
+        let stratum_policy = ResetPolicy::new(Some(epsilon), None, Some(I32F32::from_num(0)));
+        for stratum in 0..self.strata {
+            self.store_for_stratum(stratum)?.reset(storage, stratum_policy)?;
+        }
+        self.status.save(storage, self.as_slice(), RunningStatsStatus::CollectingData);
+        self.epsilon.save(storage, self.as_slice(), epsilon);
+        self.budget.set_privacy_budget(storage, privacy_budget);
+        Ok(())
+    }
+
+    /// Returns epsilon, or an error if the store was never initialized
+    pub fn get_epsilon(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        self.epsilon.may_load(storage, self.as_slice())?.ok_or(
+            StdError::generic_err("Stratified store is not initialized")
+        )
+    }
+
+    /// Returns the minimum number of observations, over all strata, needed before the average
+    /// is released
+    pub fn get_min_count_for_release(&self, storage: &dyn Storage) -> StdResult<u32> {
+        // default: no minimum
+        self.min_count_for_release.load_or(storage, self.as_slice(), 0)
+    }
+
+    /// Set the minimum number of observations, over all strata, needed before the average is
+    /// released, even with noise. Each release first compares a noisy total with the minimum,
+    /// at an extra cost of epsilon; if it falls short, only that cost is charged from the joint
+    /// budget and the release is refused.
+    pub fn set_min_count_for_release(&self, storage: &mut dyn Storage, min_count: u32) {
+        self.min_count_for_release.save(storage, self.as_slice(), min_count);
+    }
+
+    /// Returns the largest number of releases allowed from the store, if there is a limit
+    pub fn get_max_total_queries(&self, storage: &dyn Storage) -> StdResult<Option<u32>> {
+        self.store_for_stratum(0)?.get_max_total_queries(storage)
+    }
+
+    /// Limit the number of releases from the store, as for
+    /// `RunningStatsStore::set_max_total_queries`. The limit is set on every stratum, and each
+    /// release, or release refused by the minimum-count check, counts once on every stratum.
+    pub fn set_max_total_queries(&self, storage: &mut dyn Storage, max_queries: u32) -> StdResult<()> {
+        for stratum in 0..self.strata {
+            self.store_for_stratum(stratum)?.set_max_total_queries(storage, max_queries)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the privacy policy of the store, or None if it has none
+    pub fn get_privacy_policy(&self, storage: &dyn Storage) -> StdResult<Option<PrivacyPolicy>> {
+        self.store_for_stratum(0)?.get_privacy_policy(storage)
+    }
+
+    /// Sets limits on epsilon and the joint privacy budget, as for
+    /// `RunningStatsStore::set_privacy_policy`. The policy is set on every stratum, and `init`
+    /// and every release are checked against it. The joint budget must be within it.
+    pub fn set_privacy_policy(&self, storage: &mut dyn Storage, policy: PrivacyPolicy) -> StdResult<()> {
+        policy.validate()?;
+        policy.check_privacy_budget(self.get_privacy_budget(storage)?)?;
+        for stratum in 0..self.strata {
+            self.store_for_stratum(stratum)?.set_privacy_policy(storage, policy)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_status(&self, storage: &dyn Storage) -> StdResult<RunningStatsStatus> {
+        self.status.load_or(storage, self.as_slice(), RunningStatsStatus::CollectingData)
+    }
+
+    /// Sets the status of the store as a whole, with the same transitions as a
+    /// `RunningStatsStore`. Observations are only added while collecting data, and the average
+    /// is only released while calculating stats.
+    pub fn set_status(&self, storage: &mut dyn Storage, status: RunningStatsStatus) -> StdResult<()> {
+        self.get_status(storage)?.check_transition(status)?;
+        if status == RunningStatsStatus::CalculatingStats && self.total_count(storage)? == 0 {
+            return Err(StdError::generic_err("No data in stratified store"));
+        }
+        self.status.save(storage, self.as_slice(), status);
+        Ok(())
+    }
+
+    /// Number of observations over all strata, saturating at u32::MAX
+    fn total_count(&self, storage: &dyn Storage) -> StdResult<u32> {
+        let mut total_count: u32 = 0;
+        for stratum in 0..self.strata {
+            total_count = total_count.saturating_add(self.store_for_stratum(stratum)?.get_count(storage)?);
+        }
+        Ok(total_count)
+    }
+
+    /// Returns the remaining joint privacy budget
+    pub fn get_privacy_budget(&self, storage: &dyn Storage) -> StdResult<I32F32> {
+        self.budget.get_privacy_budget(storage)
+    }
+
+    /// Adds an observation to a stratum
+    pub fn add_observation(
+        &self,
+        storage: &mut dyn Storage,
+        stratum: u16,
+        x: I32F32,
+    ) -> StdResult<()> {
+        if self.get_status(storage)? != RunningStatsStatus::CollectingData {
+            return Err(StdError::generic_err("Status is not set to collecting data"));
+        }
+        self.store_for_stratum(stratum)?.add_observation(storage, x)
+    }
+
+    /// Privately releases the post-stratified average: the average of each stratum, weighted by
+    /// the share of the population in that stratum. `population_weights` has one non-negative
+    /// weight per stratum and is normalized to sum to 1, so counts or percentages work as well.
+    ///
+    /// Each stratum average is its noisy sum over its noisy count, with the count floored at 1
+    /// and the average clamped into [lower, upper], so that small strata cannot blow up the
+    /// estimate. Costs 2 * epsilon, plus epsilon for the minimum-count check if one is set,
+    /// charged once from the joint budget before anything is released; strata with zero weight
+    /// are still noised, so the cost is the same either way. The noise comes from the noise
+    /// source of the strata, and each stratum's query limit and cooldown are checked and
+    /// counted as for any other release. A release refused by the minimum-count check is
+    /// charged for it alone and returns `Release::Refused`.
+    pub fn fuzzy_stratified_average(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        population_weights: &[I32F32],
+    ) -> StdResult<Release<I32F32>> {
+        if population_weights.len() != usize::from(self.strata) {
+            return Err(StdError::generic_err("Expected one population weight per stratum"));
+        }
+        let mut total_weight = I32F32::from_num(0);
+        for weight in population_weights {
+            if *weight < I32F32::from_num(0) {
+                return Err(StdError::generic_err("Population weights must not be negative"));
+            }
+            total_weight = total_weight.checked_add(*weight).ok_or(
+                StdError::generic_err("Population weight overflow")
+            )?;
+        }
+        if total_weight == I32F32::from_num(0) {
+            return Err(StdError::generic_err("Population weights must not all be zero"));
+        }
+
+        let epsilon = self.get_epsilon(storage)?;
+        if self.get_status(storage)? != RunningStatsStatus::CalculatingStats {
+            return Err(StdError::generic_err("Status not set to calculating stats"));
+        }
+        let (lower, upper) = self.stats.get_clipping().ok_or(
+            StdError::generic_err("Clipping range not set")
+        )?;
+        for stratum in 0..self.strata {
+            let store = self.store_for_stratum(stratum)?;
+            store.check_ready_for_joint_release(storage)?;
+            store.check_privacy_policy(storage, epsilon)?;
+        }
+        // one release of counts and one of sums, each covering every stratum in parallel
+        let min_count = self.get_min_count_for_release(storage)?;
+        let gate_cost = min_count_gate_cost(min_count, epsilon);
+        let privacy_cost = 2 * epsilon + gate_cost;
+        if self.get_privacy_budget(storage)? < privacy_cost {
+            return Err(StdError::generic_err("Privacy budget exhausted"));
+        }
+        if !noisy_count_reaches(rng, self.total_count(storage)?, min_count, epsilon)? {
+            self.budget.charge(storage, gate_cost)?;
+            self.record_query(storage)?;
+            return Ok(Release::Refused);
+        }
+
+        let mut estimate = I64F64::from_num(0);
+        for (stratum, weight) in (0..self.strata).zip(population_weights) {
+            let (dp_count, dp_sum) = self.store_for_stratum(stratum)?.uncharged_noisy_count_and_sum(storage, rng, epsilon)?;
+            let dp_average = I32F32::saturating_from_num(dp_sum / dp_count.max(I64F64::from_num(1)))
+                .clamp(lower, upper);
+            estimate += I64F64::from_num(dp_average) * I64F64::from_num(*weight);
+        }
+
+        self.budget.charge(storage, privacy_cost)?;
+        self.record_query(storage)?;
+        Ok(Release::Released(I32F32::from_num(estimate / I64F64::from_num(total_weight))))
+    }
+
+    /// Counts a release towards the query limit of every stratum
+    fn record_query(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        for stratum in 0..self.strata {
+            self.store_for_stratum(stratum)?.record_query(storage)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand::SeedableRng;
+
+    #[test]
+    fn reweights_strata_by_population() {
+        let mut storage = MockStorage::new();
+        let store: StratifiedStatsStore = StratifiedStatsStore::new(b"strata", 2, I32F32::from_num(0), I32F32::from_num(10));
+        store.init(&mut storage, I32F32::from_num(5), I32F32::from_num(10)).unwrap();
+
+        // stratum 0 is over-represented among respondents, but is only a quarter of the population
+        for _ in 0..300 {
+            store.add_observation(&mut storage, 0, I32F32::from_num(2)).unwrap();
+        }
+        for _ in 0..100 {
+            store.add_observation(&mut storage, 1, I32F32::from_num(8)).unwrap();
+        }
+        assert!(store.add_observation(&mut storage, 2, I32F32::from_num(1)).is_err());
+
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        let weights = [I32F32::from_num(25), I32F32::from_num(75)];
+        assert!(store.fuzzy_stratified_average(&mut storage, &mut rng, &weights).is_err());
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        assert!(store.add_observation(&mut storage, 0, I32F32::from_num(2)).is_err());
+
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        let weights = [I32F32::from_num(25), I32F32::from_num(75)];
+        let estimate = store.fuzzy_stratified_average(&mut storage, &mut rng, &weights).unwrap().released().unwrap();
+        // 0.25 * 2 + 0.75 * 8
+        assert!((estimate - I32F32::from_num(6.5)).abs() < I32F32::from_num(0.5));
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+
+        assert!(store.fuzzy_stratified_average(&mut storage, &mut rng, &weights).is_err());
+        assert!(store.fuzzy_stratified_average(&mut storage, &mut rng, &weights[..1]).is_err());
+    }
+
+    #[test]
+    fn too_few_observations_are_refused_at_the_cost_of_the_check() {
+        let mut storage = MockStorage::new();
+        let store: StratifiedStatsStore = StratifiedStatsStore::new(b"strata", 2, I32F32::from_num(0), I32F32::from_num(10));
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(4)).unwrap();
+        store.set_min_count_for_release(&mut storage, 50);
+        store.add_observation(&mut storage, 0, I32F32::from_num(2)).unwrap();
+        store.add_observation(&mut storage, 1, I32F32::from_num(8)).unwrap();
+        store.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+
+        let mut rng = ChaChaRng::from_seed([8; 32]);
+        let weights = [I32F32::from_num(1), I32F32::from_num(1)];
+        assert_eq!(store.fuzzy_stratified_average(&mut storage, &mut rng, &weights).unwrap(), Release::Refused);
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(3));
+
+        let big: StratifiedStatsStore = StratifiedStatsStore::new(b"big", 2, I32F32::from_num(0), I32F32::from_num(10));
+        big.init(&mut storage, I32F32::from_num(1), I32F32::from_num(3)).unwrap();
+        big.set_min_count_for_release(&mut storage, 50);
+        for _ in 0..100 {
+            big.add_observation(&mut storage, 0, I32F32::from_num(2)).unwrap();
+        }
+        big.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        big.fuzzy_stratified_average(&mut storage, &mut rng, &weights).unwrap().released().unwrap();
+        assert_eq!(big.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn strata_cannot_be_released_on_their_own() {
+        let mut storage = MockStorage::new();
+        let store: StratifiedStatsStore = StratifiedStatsStore::new(b"strata", 2, I32F32::from_num(0), I32F32::from_num(10));
+        store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(10)).unwrap();
+        for _ in 0..10 {
+            store.add_observation(&mut storage, 0, I32F32::from_num(4)).unwrap();
+        }
+        let stratum = store.store_for_stratum(0).unwrap();
+        assert_eq!(stratum.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+        stratum.set_status(&mut storage, RunningStatsStatus::CalculatingStats).unwrap();
+        let mut rng = ChaChaRng::from_seed([9; 32]);
+        assert!(stratum.fuzzy_count(&mut storage, &mut rng).is_err());
+        assert_eq!(store.get_privacy_budget(&storage).unwrap(), I32F32::from_num(10));
+    }
+
+    #[test]
+    fn privacy_policy_covers_the_joint_budget() {
+        let mut storage = MockStorage::new();
+        let store: StratifiedStatsStore = StratifiedStatsStore::new(b"strata", 2, I32F32::from_num(0), I32F32::from_num(10));
+        let policy = PrivacyPolicy {
+            min_epsilon_per_query: I32F32::from_num(1),
+            max_epsilon_per_query: I32F32::from_num(2),
+            max_delta_total: I32F32::from_num(0),
+            max_privacy_budget: I32F32::from_num(10),
+        };
+        store.set_privacy_policy(&mut storage, policy).unwrap();
+        assert_eq!(store.get_privacy_policy(&storage).unwrap(), Some(policy));
+        assert!(store.init(&mut storage, I32F32::from_num(1), I32F32::from_num(20)).is_err());
+        assert!(store.init(&mut storage, I32F32::from_num(3), I32F32::from_num(10)).is_err());
+        store.init(&mut storage, I32F32::from_num(2), I32F32::from_num(10)).unwrap();
+    }
+}
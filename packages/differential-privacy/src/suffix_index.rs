@@ -0,0 +1,107 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use secret_toolkit::crypto::sha_256;
+
+use crate::FieldValue;
+
+const LEN_KEY: &[u8] = b"len";
+const ENTRY_KEY: &[u8] = b"entry";
+const MARKER_KEY: &[u8] = b"marker";
+
+/// Index of the suffixes that have been added to a store's namespace. Secret contract storage
+/// cannot be iterated, so stores record each suffix here when it is registered, and the index
+/// is then used to enumerate every suffixed store.
+pub struct SuffixIndex {
+    prefix: Vec<u8>,
+}
+
+impl SuffixIndex {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self { prefix }
+    }
+
+    fn entry_key(&self, pos: u32) -> Vec<u8> {
+        [self.prefix.as_slice(), ENTRY_KEY, &pos.to_be_bytes()].concat()
+    }
+
+    fn marker_key(&self, suffix: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), MARKER_KEY, &sha_256(suffix)].concat()
+    }
+
+    pub fn len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        let len_key = [self.prefix.as_slice(), LEN_KEY].concat();
+        match storage.get(&len_key) {
+            Some(len_vec) => u32::from_stored(&len_vec),
+            None => Ok(0),
+        }
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.len(storage)? == 0)
+    }
+
+    pub fn contains(&self, storage: &dyn Storage, suffix: &[u8]) -> bool {
+        storage.get(&self.marker_key(suffix)).is_some()
+    }
+
+    /// Adds a suffix to the index, if it is not already there
+    pub fn register(&self, storage: &mut dyn Storage, suffix: &[u8]) -> StdResult<()> {
+        if self.contains(storage, suffix) {
+            return Ok(());
+        }
+        let len = self.len(storage)?;
+        let new_len = len.checked_add(1).ok_or(
+            StdError::generic_err("Suffix index overflow")
+        )?;
+        storage.set(&self.entry_key(len), suffix);
+        storage.set(&self.marker_key(suffix), &[1]);
+        let len_key = [self.prefix.as_slice(), LEN_KEY].concat();
+        storage.set(&len_key, &new_len.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn get_at(&self, storage: &dyn Storage, pos: u32) -> StdResult<Vec<u8>> {
+        storage.get(&self.entry_key(pos)).ok_or(
+            StdError::generic_err("Suffix index out of bounds")
+        )
+    }
+
+    /// Iterates over every suffix in the index, in the order they were registered
+    pub fn iter<'s>(self, storage: &'s dyn Storage) -> StdResult<impl Iterator<Item = StdResult<Vec<u8>>> + 's> {
+        let len = self.len(storage)?;
+        Ok((0..len).map(move |pos| self.get_at(storage, pos)))
+    }
+
+    /// Returns one page of suffixes, with pages numbered from 0
+    pub fn paging(&self, storage: &dyn Storage, start_page: u32, size: u32) -> StdResult<Vec<Vec<u8>>> {
+        let len = self.len(storage)?;
+        let start = start_page.saturating_mul(size).min(len);
+        let end = start.saturating_add(size).min(len);
+        (start..end).map(|pos| self.get_at(storage, pos)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn suffixes_are_registered_once_in_order() {
+        let mut storage = MockStorage::new();
+        let index = SuffixIndex::new(b"index".to_vec());
+        assert!(index.is_empty(&storage).unwrap());
+        for suffix in [&b"b"[..], b"a", b"b", b"c"] {
+            index.register(&mut storage, suffix).unwrap();
+        }
+        assert_eq!(index.len(&storage).unwrap(), 3);
+        assert!(index.contains(&storage, b"a"));
+        assert!(!index.contains(&storage, b"d"));
+        assert!(index.get_at(&storage, 3).is_err());
+
+        assert_eq!(index.paging(&storage, 0, 2).unwrap(), vec![b"b".to_vec(), b"a".to_vec()]);
+        assert_eq!(index.paging(&storage, 1, 2).unwrap(), vec![b"c".to_vec()]);
+        assert!(index.paging(&storage, 2, 2).unwrap().is_empty());
+        let suffixes: Vec<Vec<u8>> = index.iter(&storage).unwrap().collect::<StdResult<_>>().unwrap();
+        assert_eq!(suffixes, vec![b"b".to_vec(), b"a".to_vec(), b"c".to_vec()]);
+    }
+}
@@ -0,0 +1,127 @@
+use alloc::vec::Vec;
+use rand_chacha::ChaChaRng;
+use substrate_fixed::types::{I32F32, I64F64};
+use crate::{random_unit_interval, sample_distribution, NoiseError, NoiseResult};
+
+// Synthetic datasets generated from released noisy marginals. The records only depend on the
+// released counts and fresh randomness, so publishing them is post-processing and costs no
+// additional privacy budget. Attributes are sampled independently, so the synthetic data keeps
+// each released marginal but not the correlations between attributes.
+
+/// Turns the noisy counts of a released histogram into probabilities: negative counts become
+/// zero and the rest are scaled to sum to 1. If no count is positive, e.g. for sparse data, every
+/// category is equally likely, so a release that was already charged still yields records and
+/// an error does not reveal that the counts were small. Returns an error if there are no
+/// categories.
+pub fn marginal_distribution(noisy_counts: &[I32F32]) -> NoiseResult<Vec<I64F64>> {
+    if noisy_counts.is_empty() {
+        return Err(NoiseError("Marginal has no categories"));
+    }
+    let counts: Vec<I64F64> = noisy_counts.iter().map(|count| I64F64::from_num((*count).max(I32F32::from_num(0)))).collect();
+    let total = counts.iter().fold(I64F64::from_num(0), |total, count| total + *count);
+    if total == 0 {
+        let uniform = I64F64::from_num(1) / I64F64::from_num(counts.len());
+        return Ok(counts.iter().map(|_| uniform).collect());
+    }
+    Ok(counts.into_iter().map(|count| count / total).collect())
+}
+
+/// Number of records that matches a released histogram: the sum of its positive noisy counts,
+/// rounded to the nearest whole number
+pub fn synthetic_size(noisy_counts: &[I32F32]) -> u32 {
+    let total = noisy_counts
+        .iter()
+        .fold(I64F64::from_num(0), |total, count| total + I64F64::from_num((*count).max(I32F32::from_num(0))));
+    total.round().saturating_to_num::<u32>()
+}
+
+/// Draws `n` categories, each with probability proportional to its positive noisy count
+pub fn synthetic_categories(rng: &mut ChaChaRng, noisy_counts: &[I32F32], n: usize) -> NoiseResult<Vec<usize>> {
+    let distribution = marginal_distribution(noisy_counts)?;
+    Ok((0..n).map(|_| sample_distribution(rng, &distribution)).collect())
+}
+
+/// Draws `n` records with one category per marginal, e.g. the released histograms of age band,
+/// region and plan of a dataset. Each attribute is drawn independently from its own marginal.
+pub fn synthetic_records(rng: &mut ChaChaRng, marginals: &[&[I32F32]], n: usize) -> NoiseResult<Vec<Vec<usize>>> {
+    let distributions = marginals
+        .iter()
+        .map(|marginal| marginal_distribution(marginal))
+        .collect::<NoiseResult<Vec<Vec<I64F64>>>>()?;
+    Ok((0..n)
+        .map(|_| distributions.iter().map(|distribution| sample_distribution(rng, distribution)).collect())
+        .collect())
+}
+
+/// Draws `n` values from a released histogram of equal-width bins over [lower, upper], such as
+/// the one from `HierarchicalHistogramStore::fuzzy_histogram`: a bin is picked by its positive
+/// noisy count, then a value uniformly within it
+pub fn synthetic_values(
+    rng: &mut ChaChaRng,
+    noisy_histogram: &[I32F32],
+    lower: I32F32,
+    upper: I32F32,
+    n: usize,
+) -> NoiseResult<Vec<I32F32>> {
+    if lower >= upper {
+        return Err(NoiseError("Lower bound must be less than upper bound"));
+    }
+    let distribution = marginal_distribution(noisy_histogram)?;
+    let width = (I64F64::from_num(upper) - I64F64::from_num(lower)) / I64F64::from_num(noisy_histogram.len());
+    Ok((0..n)
+        .map(|_| {
+            let bin = I64F64::from_num(sample_distribution(rng, &distribution));
+            let offset = (bin + I64F64::from_num(random_unit_interval(rng))) * width;
+            I32F32::from_num(I64F64::from_num(lower) + offset).min(upper)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn synthetic_data_matches_marginals() {
+        let mut rng = ChaChaRng::from_seed([15; 32]);
+        // a noisy histogram with a negative bin, which is treated as empty
+        let histogram = [I32F32::from_num(30.4), I32F32::from_num(-2.5), I32F32::from_num(9.6), I32F32::from_num(60)];
+        assert_eq!(synthetic_size(&histogram), 100);
+
+        let values = synthetic_values(&mut rng, &histogram, I32F32::from_num(0), I32F32::from_num(8), 5000).unwrap();
+        let mut counts = [0u32; 4];
+        for value in &values {
+            assert!(*value > 0 && *value <= 8);
+            counts[((value.to_num::<f64>() - 1e-9) / 2.0) as usize] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        // expected 1520, 480 and 3000
+        assert!((1400..1640).contains(&counts[0]), "{:?}", counts);
+        assert!((400..560).contains(&counts[2]), "{:?}", counts);
+        assert!((2860..3140).contains(&counts[3]), "{:?}", counts);
+
+        let other = [I32F32::from_num(1), I32F32::from_num(1)];
+        let records = synthetic_records(&mut rng, &[&histogram, &other], 10).unwrap();
+        assert!(records.iter().all(|record| record.len() == 2 && record[0] != 1 && record[1] < 2));
+        assert!(synthetic_categories(&mut rng, &[], 3).is_err());
+    }
+
+    #[test]
+    fn sparse_marginals_fall_back_to_uniform() {
+        let mut rng = ChaChaRng::from_seed([16; 32]);
+        let empty = [I32F32::from_num(-1), I32F32::from_num(0), I32F32::from_num(-0.5)];
+        assert_eq!(synthetic_size(&empty), 0);
+
+        let categories = synthetic_categories(&mut rng, &empty, 3000).unwrap();
+        let mut counts = [0u32; 3];
+        for category in categories {
+            counts[category] += 1;
+        }
+        assert!(counts.iter().all(|count| (900..1100).contains(count)), "{:?}", counts);
+
+        let values = synthetic_values(&mut rng, &empty, I32F32::from_num(0), I32F32::from_num(3), 10).unwrap();
+        assert!(values.iter().all(|value| *value > 0 && *value <= 3));
+        assert_eq!(synthetic_records(&mut rng, &[&empty], 4).unwrap().len(), 4);
+    }
+}
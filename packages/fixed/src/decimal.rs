@@ -0,0 +1,199 @@
+use cosmwasm_std::{Decimal, StdResult, StdError, Uint128, Uint256};
+use substrate_fixed::types::{I32F32, I64F64};
+
+/// Number of decimal places of a cosmwasm `Decimal`
+const DECIMAL_PLACES: u32 = 18;
+
+/// How a conversion rounds values that cannot be represented exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// round toward zero
+    Down,
+    /// round away from zero
+    Up,
+    /// round to the nearest value, with halves rounded away from zero
+    Nearest,
+}
+
+/// Conversions between fixed-point values and cosmwasm `Decimal` and `Uint128`. Every
+/// conversion either states how it rounds or is exact, and values that do not fit in the
+/// target type return an error instead of wrapping or saturating.
+pub trait DecimalConvert: Sized {
+    fn try_from_decimal(value: Decimal, rounding: Rounding) -> StdResult<Self>;
+    fn try_into_decimal(&self, rounding: Rounding) -> StdResult<Decimal>;
+    fn try_from_uint128(value: Uint128) -> StdResult<Self>;
+    fn try_into_uint128(&self, rounding: Rounding) -> StdResult<Uint128>;
+}
+
+fn decimal_scale() -> Uint256 {
+    Uint256::from(10u128.pow(DECIMAL_PLACES))
+}
+
+/// numerator / denominator, rounded
+fn div_round(numerator: Uint256, denominator: Uint256, rounding: Rounding) -> Uint256 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => !remainder.is_zero(),
+        Rounding::Nearest => remainder + remainder >= denominator,
+    };
+    if round_up {
+        quotient + Uint256::from(1u128)
+    } else {
+        quotient
+    }
+}
+
+fn overflow_err(type_name: &str) -> StdError {
+    StdError::generic_err(format!("Value does not fit in {}", type_name))
+}
+
+fn negative_err(type_name: &str) -> StdError {
+    StdError::generic_err(format!("Cannot convert a negative value to {}", type_name))
+}
+
+/// Raw bits of a non-negative fixed-point value with `frac_bits` fractional bits that is
+/// closest to `atomics` / 10^18
+fn decimal_to_bits(atomics: Uint128, frac_bits: u32, rounding: Rounding) -> Uint256 {
+    let numerator = Uint256::from(atomics.u128()) << frac_bits;
+    div_round(numerator, decimal_scale(), rounding)
+}
+
+/// Decimal atomics closest to a non-negative fixed-point value given by its raw bits
+fn bits_to_decimal(bits: u128, frac_bits: u32, rounding: Rounding) -> StdResult<Decimal> {
+    let numerator = Uint256::from(bits) * decimal_scale();
+    let atomics = div_round(numerator, Uint256::from(1u128) << frac_bits, rounding);
+    let atomics = Uint128::try_from(atomics).map_err(|_| overflow_err("Decimal"))?;
+    Ok(Decimal::new(atomics))
+}
+
+/// Integer closest to a non-negative fixed-point value given by its raw bits
+fn bits_to_uint128(bits: u128, frac_bits: u32, rounding: Rounding) -> Uint128 {
+    let integer = div_round(Uint256::from(bits), Uint256::from(1u128) << frac_bits, rounding);
+    // bits fit in u128, so the integer part does too
+    Uint128::try_from(integer).unwrap()
+}
+
+impl DecimalConvert for I32F32 {
+    fn try_from_decimal(value: Decimal, rounding: Rounding) -> StdResult<Self> {
+        let bits = decimal_to_bits(value.atomics(), 32, rounding);
+        let bits = Uint128::try_from(bits).map_err(|_| overflow_err("I32F32"))?.u128();
+        let bits = i64::try_from(bits).map_err(|_| overflow_err("I32F32"))?;
+        Ok(I32F32::from_bits(bits))
+    }
+
+    fn try_into_decimal(&self, rounding: Rounding) -> StdResult<Decimal> {
+        let bits = u64::try_from(self.to_bits()).map_err(|_| negative_err("Decimal"))?;
+        bits_to_decimal(u128::from(bits), 32, rounding)
+    }
+
+    fn try_from_uint128(value: Uint128) -> StdResult<Self> {
+        let integer = i32::try_from(value.u128()).map_err(|_| overflow_err("I32F32"))?;
+        Ok(I32F32::from_num(integer))
+    }
+
+    fn try_into_uint128(&self, rounding: Rounding) -> StdResult<Uint128> {
+        let bits = u64::try_from(self.to_bits()).map_err(|_| negative_err("Uint128"))?;
+        Ok(bits_to_uint128(u128::from(bits), 32, rounding))
+    }
+}
+
+impl DecimalConvert for I64F64 {
+    fn try_from_decimal(value: Decimal, rounding: Rounding) -> StdResult<Self> {
+        let bits = decimal_to_bits(value.atomics(), 64, rounding);
+        let bits = Uint128::try_from(bits).map_err(|_| overflow_err("I64F64"))?.u128();
+        let bits = i128::try_from(bits).map_err(|_| overflow_err("I64F64"))?;
+        Ok(I64F64::from_bits(bits))
+    }
+
+    fn try_into_decimal(&self, rounding: Rounding) -> StdResult<Decimal> {
+        let bits = u128::try_from(self.to_bits()).map_err(|_| negative_err("Decimal"))?;
+        bits_to_decimal(bits, 64, rounding)
+    }
+
+    fn try_from_uint128(value: Uint128) -> StdResult<Self> {
+        let integer = i64::try_from(value.u128()).map_err(|_| overflow_err("I64F64"))?;
+        Ok(I64F64::from_num(integer))
+    }
+
+    fn try_into_uint128(&self, rounding: Rounding) -> StdResult<Uint128> {
+        let bits = u128::try_from(self.to_bits()).map_err(|_| negative_err("Uint128"))?;
+        Ok(bits_to_uint128(bits, 64, rounding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [Rounding; 3] = [Rounding::Down, Rounding::Up, Rounding::Nearest];
+
+    fn uint(value: I32F32, rounding: Rounding) -> u128 {
+        value.try_into_uint128(rounding).unwrap().u128()
+    }
+
+    #[test]
+    fn integers_round_at_halves() {
+        let half = I32F32::from_num(2.5);
+        assert_eq!(uint(half, Rounding::Down), 2);
+        assert_eq!(uint(half, Rounding::Up), 3);
+        assert_eq!(uint(half, Rounding::Nearest), 3);
+        // just below a half
+        let below = half - I32F32::from_bits(1);
+        assert_eq!(uint(below, Rounding::Nearest), 2);
+        assert_eq!(uint(below, Rounding::Up), 3);
+        for rounding in MODES {
+            assert_eq!(uint(I32F32::from_num(2), rounding), 2);
+            assert_eq!(uint(I32F32::from_num(0), rounding), 0);
+        }
+    }
+
+    #[test]
+    fn decimals_round_at_halves() {
+        // 2^-19 is 1907348632812.5 atomics
+        let half = I32F32::from_bits(1 << 13);
+        assert_eq!(half.try_into_decimal(Rounding::Down).unwrap(), Decimal::raw(1_907_348_632_812));
+        assert_eq!(half.try_into_decimal(Rounding::Up).unwrap(), Decimal::raw(1_907_348_632_813));
+        assert_eq!(half.try_into_decimal(Rounding::Nearest).unwrap(), Decimal::raw(1_907_348_632_813));
+        // 2^-32 is 232830643.65... atomics
+        let smallest = I32F32::from_bits(1);
+        assert_eq!(smallest.try_into_decimal(Rounding::Down).unwrap(), Decimal::raw(232_830_643));
+        assert_eq!(smallest.try_into_decimal(Rounding::Nearest).unwrap(), Decimal::raw(232_830_644));
+
+        // one atomic is less than half of 2^-32
+        let atomic = Decimal::raw(1);
+        assert_eq!(I32F32::try_from_decimal(atomic, Rounding::Down).unwrap(), I32F32::from_num(0));
+        assert_eq!(I32F32::try_from_decimal(atomic, Rounding::Nearest).unwrap(), I32F32::from_num(0));
+        assert_eq!(I32F32::try_from_decimal(atomic, Rounding::Up).unwrap(), I32F32::from_bits(1));
+        for rounding in MODES {
+            assert_eq!(I64F64::try_from_decimal(Decimal::percent(250), rounding).unwrap(), I64F64::from_num(2.5));
+        }
+    }
+
+    #[test]
+    fn conversions_at_the_type_bounds() {
+        let max = I32F32::max_value();
+        assert_eq!(uint(max, Rounding::Down), (1 << 31) - 1);
+        assert_eq!(uint(max, Rounding::Up), 1 << 31);
+        assert_eq!(uint(max, Rounding::Nearest), 1 << 31);
+        let wide_max = I64F64::max_value();
+        assert_eq!(wide_max.try_into_uint128(Rounding::Up).unwrap().u128(), 1 << 63);
+
+        // the largest I32F32 lies between two Decimals, and rounding up past it does not fit
+        let up = max.try_into_decimal(Rounding::Up).unwrap();
+        assert_eq!(I32F32::try_from_decimal(up, Rounding::Down).unwrap(), max);
+        assert!(I32F32::try_from_decimal(up, Rounding::Up).is_err());
+        assert!(wide_max.try_into_decimal(Rounding::Up).is_ok());
+
+        for rounding in MODES {
+            assert!(I32F32::try_from_decimal(Decimal::MAX, rounding).is_err());
+            assert!(I64F64::try_from_decimal(Decimal::MAX, rounding).is_err());
+            assert!(I32F32::from_num(-1).try_into_decimal(rounding).is_err());
+            assert!(I64F64::from_bits(-1).try_into_uint128(rounding).is_err());
+        }
+        assert_eq!(I32F32::try_from_uint128(Uint128::new((1 << 31) - 1)).unwrap(), I32F32::from_num(i32::MAX));
+        assert!(I32F32::try_from_uint128(Uint128::new(1 << 31)).is_err());
+        assert!(I64F64::try_from_uint128(Uint128::new(1 << 63)).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use cosmwasm_std::{StdResult, StdError};
+use substrate_fixed::types::{I32F32, I64F64};
+
+/// Version byte written in front of every encoded value
+pub const FIXED_BYTES_VERSION: u8 = 1;
+
+/// Canonical storage encoding of a fixed-point value: a version byte followed by the value's
+/// big-endian bytes. Values stored before versioning, as bare big-endian bytes, are still read.
+pub trait FixedBytes: Sized {
+    fn to_stored_bytes(&self) -> Vec<u8>;
+    fn from_stored_bytes(bytes: &[u8]) -> StdResult<Self>;
+}
+
+/// Returns the big-endian value bytes of an encoded value of `width` bytes
+fn value_bytes<'b>(bytes: &'b [u8], width: usize, type_name: &str) -> StdResult<&'b [u8]> {
+    if bytes.len() == width {
+        // unversioned encoding
+        return Ok(bytes);
+    }
+    if bytes.len() != width + 1 {
+        return Err(StdError::generic_err(format!(
+            "Invalid {} encoding: expected {} bytes, got {}", type_name, width + 1, bytes.len()
+        )));
+    }
+    if bytes[0] != FIXED_BYTES_VERSION {
+        return Err(StdError::generic_err(format!(
+            "Unsupported {} encoding version {}", type_name, bytes[0]
+        )));
+    }
+    Ok(&bytes[1..])
+}
+
+impl FixedBytes for I32F32 {
+    fn to_stored_bytes(&self) -> Vec<u8> {
+        [&[FIXED_BYTES_VERSION][..], &self.to_be_bytes()].concat()
+    }
+
+    fn from_stored_bytes(bytes: &[u8]) -> StdResult<Self> {
+        let value_bytes = value_bytes(bytes, 8, "I32F32")?;
+        // the length was checked above
+        Ok(I32F32::from_be_bytes(value_bytes.try_into().unwrap()))
+    }
+}
+
+impl FixedBytes for I64F64 {
+    fn to_stored_bytes(&self) -> Vec<u8> {
+        [&[FIXED_BYTES_VERSION][..], &self.to_be_bytes()].concat()
+    }
+
+    fn from_stored_bytes(bytes: &[u8]) -> StdResult<Self> {
+        let value_bytes = value_bytes(bytes, 16, "I64F64")?;
+        // the length was checked above
+        Ok(I64F64::from_be_bytes(value_bytes.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use substrate_fixed::types::{I32F32, I64F64};
+
+/// Largest number of decimal places `format_fixed` renders
+pub const MAX_FORMAT_DECIMALS: u8 = 18;
+
+/// A fixed-point type that can be rendered as a decimal string
+pub trait FormatFixed: Copy {
+    /// sign, magnitude of the raw bits, and number of fractional bits
+    fn sign_and_bits(&self) -> (bool, u128, u32);
+}
+
+impl FormatFixed for I32F32 {
+    fn sign_and_bits(&self) -> (bool, u128, u32) {
+        let bits = self.to_bits();
+        (bits < 0, u128::from(bits.unsigned_abs()), 32)
+    }
+}
+
+impl FormatFixed for I64F64 {
+    fn sign_and_bits(&self) -> (bool, u128, u32) {
+        let bits = self.to_bits();
+        (bits < 0, bits.unsigned_abs(), 64)
+    }
+}
+
+/// Renders `value` as a decimal string with exactly `decimals` decimal places (at most 18),
+/// rounding halves away from zero, e.g. "12.50" or "-0.333". Unlike converting to a float,
+/// the result is exact up to the requested precision.
+pub fn format_fixed<T: FormatFixed>(value: T, decimals: u8) -> String {
+    let decimals = decimals.min(MAX_FORMAT_DECIMALS);
+    let (negative, bits, frac_bits) = value.sign_and_bits();
+    let mut integer = bits >> frac_bits;
+    let fraction = bits & ((1u128 << frac_bits) - 1);
+
+    // round the fraction to the requested number of decimal places
+    let scale = 10u128.pow(u32::from(decimals));
+    let mut scaled = {
+        // fraction < 2^64 and scale <= 10^18, so the product fits in u128
+        let product = fraction * scale;
+        let quotient = product >> frac_bits;
+        let remainder = product & ((1u128 << frac_bits) - 1);
+        if remainder >= 1u128 << (frac_bits - 1) {
+            quotient + 1
+        } else {
+            quotient
+        }
+    };
+    if scaled == scale {
+        integer += 1;
+        scaled = 0;
+    }
+
+    let sign = if negative && (integer > 0 || scaled > 0) { "-" } else { "" };
+    if decimals == 0 {
+        format!("{}{}", sign, integer)
+    } else {
+        format!("{}{}.{:0width$}", sign, integer, scaled, width = usize::from(decimals))
+    }
+}
+
+/// Wrapper that implements `Display` with a fixed number of decimal places, e.g.
+/// `FixedDisplay::new(fuzzy_average, 4).to_string()`
+#[derive(Clone, Copy, Debug)]
+pub struct FixedDisplay<T: FormatFixed> {
+    value: T,
+    decimals: u8,
+}
+
+impl<T: FormatFixed> FixedDisplay<T> {
+    pub fn new(value: T, decimals: u8) -> Self {
+        Self { value, decimals }
+    }
+}
+
+impl<T: FormatFixed> fmt::Display for FixedDisplay<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_fixed(self.value, self.decimals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_round_away_from_zero() {
+        assert_eq!(format_fixed(I32F32::from_num(0.125), 2), "0.13");
+        assert_eq!(format_fixed(I32F32::from_num(-0.125), 2), "-0.13");
+        assert_eq!(format_fixed(I32F32::from_num(2.5), 0), "3");
+        assert_eq!(format_fixed(I32F32::from_num(-2.5), 0), "-3");
+        assert_eq!(format_fixed(I64F64::from_num(0.375), 2), "0.38");
+        // just below a half
+        assert_eq!(format_fixed(I32F32::from_num(0.125) - I32F32::from_bits(1), 2), "0.12");
+    }
+
+    #[test]
+    fn fractions_carry_into_the_integer() {
+        assert_eq!(format_fixed(I32F32::from_num(0.9999), 2), "1.00");
+        assert_eq!(format_fixed(I32F32::from_num(-0.9999), 2), "-1.00");
+        assert_eq!(format_fixed(I64F64::from_num(9.9996), 3), "10.000");
+        assert_eq!(format_fixed(I32F32::from_num(0.9999), 0), "1");
+        // a negative value that rounds to zero has no sign
+        assert_eq!(format_fixed(I32F32::from_num(-0.001), 2), "0.00");
+    }
+
+    #[test]
+    fn formats_the_type_bounds() {
+        assert_eq!(format_fixed(I32F32::max_value(), 2), "2147483648.00");
+        assert_eq!(format_fixed(I32F32::min_value(), 2), "-2147483648.00");
+        assert_eq!(format_fixed(I64F64::max_value(), 18), "9223372036854775808.000000000000000000");
+        assert_eq!(format_fixed(I64F64::min_value(), 18), "-9223372036854775808.000000000000000000");
+        assert_eq!(format_fixed(I32F32::from_bits(1), 10), "0.0000000002");
+        // at most 18 decimal places
+        assert_eq!(format_fixed(I64F64::from_num(0.5), 30), format_fixed(I64F64::from_num(0.5), 18));
+        assert_eq!(FixedDisplay::new(I32F32::from_num(12.5), 2).to_string(), "12.50");
+    }
+}
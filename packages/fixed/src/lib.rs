@@ -0,0 +1,11 @@
+#![doc = include_str!("../Readme.md")]
+
+pub mod decimal;
+pub mod fixed_bytes;
+pub mod format;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
+
+pub use decimal::*;
+pub use fixed_bytes::*;
+pub use format::*;
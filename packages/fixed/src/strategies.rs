@@ -0,0 +1,73 @@
+use proptest::prelude::*;
+use substrate_fixed::types::{I32F32, I64F64};
+
+// Proptest strategies for fixed-point values. The fixed-point types are foreign to this crate,
+// so they cannot implement `Arbitrary` here; use these functions instead.
+
+/// Any I32F32, including the extreme values
+pub fn any_i32f32() -> impl Strategy<Value = I32F32> {
+    any::<i64>().prop_map(I32F32::from_bits)
+}
+
+/// Any I64F64, including the extreme values
+pub fn any_i64f64() -> impl Strategy<Value = I64F64> {
+    any::<i128>().prop_map(I64F64::from_bits)
+}
+
+/// I32F32 values in [min, max], using every fractional bit
+pub fn i32f32_in(min: I32F32, max: I32F32) -> impl Strategy<Value = I32F32> {
+    (min.to_bits()..=max.to_bits()).prop_map(I32F32::from_bits)
+}
+
+/// I64F64 values in [min, max], using every fractional bit
+pub fn i64f64_in(min: I64F64, max: I64F64) -> impl Strategy<Value = I64F64> {
+    (min.to_bits()..=max.to_bits()).prop_map(I64F64::from_bits)
+}
+
+/// Positive I32F32 values up to `max`, e.g. for epsilons and sensitivities
+pub fn positive_i32f32(max: I32F32) -> impl Strategy<Value = I32F32> {
+    i32f32_in(I32F32::from_bits(1), max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format_fixed, DecimalConvert, FixedBytes, Rounding};
+
+    proptest! {
+        #[test]
+        fn i32f32_bytes_round_trip(value in any_i32f32()) {
+            prop_assert_eq!(I32F32::from_stored_bytes(&value.to_stored_bytes()).unwrap(), value);
+        }
+
+        #[test]
+        fn i64f64_bytes_round_trip(value in any_i64f64()) {
+            prop_assert_eq!(I64F64::from_stored_bytes(&value.to_stored_bytes()).unwrap(), value);
+        }
+
+        #[test]
+        fn i32f32_decimal_round_trip(value in i32f32_in(I32F32::from_num(0), I32F32::max_value())) {
+            // a Decimal has more than enough precision to tell I32F32 values apart
+            let decimal = value.try_into_decimal(Rounding::Nearest).unwrap();
+            prop_assert_eq!(I32F32::try_from_decimal(decimal, Rounding::Nearest).unwrap(), value);
+        }
+
+        #[test]
+        fn decimal_rounding_brackets_value(value in i32f32_in(I32F32::from_num(0), I32F32::max_value())) {
+            let down = value.try_into_decimal(Rounding::Down).unwrap();
+            let up = value.try_into_decimal(Rounding::Up).unwrap();
+            let nearest = value.try_into_decimal(Rounding::Nearest).unwrap();
+            prop_assert!(down <= nearest && nearest <= up);
+            prop_assert!(up.atomics().u128() - down.atomics().u128() <= 1);
+        }
+
+        #[test]
+        fn format_fixed_parses_back(value in any_i32f32()) {
+            // the string is off by at most half of the last decimal place, plus f64 rounding
+            let formatted = format_fixed(value, 10);
+            let parsed: f64 = formatted.parse().unwrap();
+            let error = (parsed - value.to_num::<f64>()).abs();
+            prop_assert!(error < 1e-6, "{} formatted as {}", value, formatted);
+        }
+    }
+}
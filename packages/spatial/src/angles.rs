@@ -0,0 +1,150 @@
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedPoint2D, FixedVector2D};
+
+/// pi, rounded to the nearest I32F32
+pub const PI: I32F32 = I32F32::from_bits(0x3_243F_6A89);
+/// pi / 2, a right angle
+pub const FRAC_PI_2: I32F32 = I32F32::from_bits(0x3_243F_6A89 / 2);
+/// 2 pi, a full turn
+pub const TAU: I32F32 = I32F32::from_bits(0x3_243F_6A89 * 2);
+
+/// atan(2^-i) in I32F32 bits, for the CORDIC rotations
+const ATAN_TABLE: [i64; 32] = [
+    3373259426, 1991351318, 1052175346, 534100635, 268086748, 134174063, 67103403, 33553749,
+    16777131, 8388597, 4194303, 2097152, 1048576, 524288, 262144, 131072, 65536, 32768, 16384,
+    8192, 4096, 2048, 1024, 512, 256, 128, 64, 32, 16, 8, 4, 2,
+];
+
+/// atan2 of y and x given in any common scale, computed with CORDIC so that it only needs
+/// shifts and additions and gives the same bits on every machine
+fn atan2_wide(mut y: i128, mut x: i128) -> I32F32 {
+    if y == 0 {
+        return if x < 0 { PI } else { I32F32::from_num(0) };
+    }
+    if x == 0 {
+        return if y > 0 { FRAC_PI_2 } else { -FRAC_PI_2 };
+    }
+
+    // rotate the left half plane by pi, so the vector starts within a right angle of the x axis
+    let mut angle: i64 = 0;
+    if x < 0 {
+        angle = if y > 0 { PI.to_bits() } else { -PI.to_bits() };
+        x = -x;
+        y = -y;
+    }
+    // scale to about 61 bits, for precision with room for the CORDIC gain of about 1.65
+    let shift = (128 - x.abs().max(y.abs()).leading_zeros()) as i32 - 61;
+    if shift > 0 {
+        x >>= shift;
+        y >>= shift;
+    } else {
+        x <<= -shift;
+        y <<= -shift;
+    }
+
+    // turn the vector onto the x axis, adding up the angles turned through
+    for (i, step) in ATAN_TABLE.iter().enumerate() {
+        let (y_step, x_step) = (y >> i, x >> i);
+        if y > 0 {
+            x += y_step;
+            y -= x_step;
+            angle += step;
+        } else {
+            x -= y_step;
+            y += x_step;
+            angle -= step;
+        }
+    }
+    normalize_angle(I32F32::from_bits(angle))
+}
+
+/// Angle of the vector (x, y) counterclockwise from the positive x axis, in radians in
+/// (-pi, pi], with the same conventions as `f64::atan2`. Zero for the zero vector.
+pub fn atan2(y: I32F32, x: I32F32) -> I32F32 {
+    atan2_wide(i128::from(y.to_bits()), i128::from(x.to_bits()))
+}
+
+/// Wraps an angle in radians into (-pi, pi]
+pub fn normalize_angle(angle: I32F32) -> I32F32 {
+    let angle = angle % TAU;
+    if angle > PI {
+        angle - TAU
+    } else if angle <= -PI {
+        angle + TAU
+    } else {
+        angle
+    }
+}
+
+/// Compass bearing from `a` to `b` in radians in [0, 2 pi), measured clockwise from north,
+/// which is the positive y axis: east (positive x) is pi / 2. None if the points are the same.
+pub fn bearing(a: &FixedPoint2D, b: &FixedPoint2D) -> Option<I32F32> {
+    if a == b {
+        return None;
+    }
+    // swapping the axes turns a counterclockwise angle from x into a clockwise angle from y
+    let dx = i128::from(b.x.to_bits()) - i128::from(a.x.to_bits());
+    let dy = i128::from(b.y.to_bits()) - i128::from(a.y.to_bits());
+    let angle = atan2_wide(dx, dy);
+    Some(if angle < 0 { angle + TAU } else { angle })
+}
+
+/// Signed angle to turn `u` onto `v` in radians in (-pi, pi], positive if the turn is
+/// counterclockwise. None if either vector is zero.
+pub fn angle_between(u: &FixedVector2D, v: &FixedVector2D) -> Option<I32F32> {
+    let zero = I32F32::from_num(0);
+    if (u.x == zero && u.y == zero) || (v.x == zero && v.y == zero) {
+        return None;
+    }
+    let (ux, uy) = (i128::from(u.x.to_bits()), i128::from(u.y.to_bits()));
+    let (vx, vy) = (i128::from(v.x.to_bits()), i128::from(v.y.to_bits()));
+    // halved so the sums cannot overflow, which leaves the angle the same
+    let cross = ((ux * vy) >> 1) - ((uy * vx) >> 1);
+    let dot = ((ux * vx) >> 1) + ((uy * vy) >> 1);
+    Some(atan2_wide(cross, dot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: I32F32, b: f64) -> bool {
+        (a - I32F32::from_num(b)).abs() < I32F32::from_num(0.000001)
+    }
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn atan2_matches_float() {
+        for (y, x) in [(1.0, 1.0), (3.0, -4.0), (-0.25, -2.0), (-7.5, 0.5), (0.001, 1000.0)] {
+            assert!(close(atan2(I32F32::from_num(y), I32F32::from_num(x)), f64::atan2(y, x)));
+        }
+        assert_eq!(atan2(I32F32::from_num(0), I32F32::from_num(-3)), PI);
+        assert_eq!(atan2(I32F32::from_num(-2), I32F32::from_num(0)), -FRAC_PI_2);
+    }
+
+    #[test]
+    fn bearings_are_clockwise_from_north() {
+        assert_eq!(bearing(&point(1, 1), &point(1, 5)), Some(I32F32::from_num(0)));
+        assert_eq!(bearing(&point(1, 1), &point(4, 1)), Some(FRAC_PI_2));
+        assert_eq!(bearing(&point(1, 1), &point(1, -2)), Some(PI));
+        assert!(close(bearing(&point(0, 0), &point(-1, 1)).unwrap(), 1.75 * core::f64::consts::PI));
+        assert_eq!(bearing(&point(1, 1), &point(1, 1)), None);
+    }
+
+    #[test]
+    fn angles_are_signed_and_normalized() {
+        let east = point(2, 0).as_vector_2d();
+        let north = point(0, 3).as_vector_2d();
+        assert_eq!(angle_between(&east, &north), Some(FRAC_PI_2));
+        assert_eq!(angle_between(&north, &east), Some(-FRAC_PI_2));
+        assert_eq!(angle_between(&east, &point(0, 0).as_vector_2d()), None);
+
+        assert_eq!(normalize_angle(TAU + FRAC_PI_2), FRAC_PI_2);
+        assert_eq!(normalize_angle(-PI), PI);
+        assert!(close(normalize_angle(I32F32::from_num(-4)), 2.0 * core::f64::consts::PI - 4.0));
+    }
+}
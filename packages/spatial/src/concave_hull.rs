@@ -0,0 +1,154 @@
+use core::cmp::Ordering;
+use alloc::vec::Vec;
+use substrate_fixed::types::I32F32;
+
+use crate::fixed_geom::on_segment;
+use crate::{FixedLineSegment2D, FixedPoint2D, FixedPolygon2D, FixedVector2D};
+
+/// Which part of a turn, counterclockwise from `back`, the direction `v` is in: 0 for (0, 180)
+/// degrees, 1 for 180, 2 for (180, 360) and 3 for 0, i.e. straight back
+pub(crate) fn turn_half(back: &FixedVector2D, v: &FixedVector2D) -> u8 {
+    let turn = back.cross(v);
+    if turn > 0 {
+        0
+    } else if turn < 0 {
+        2
+    } else if back.dot(v) < 0 {
+        1
+    } else {
+        3
+    }
+}
+
+/// Orders candidate points by the counterclockwise angle from `back` to the direction from
+/// `current`, closest first for equal angles
+pub(crate) fn turn_cmp(current: &FixedPoint2D, back: &FixedVector2D, a: &FixedPoint2D, b: &FixedPoint2D) -> Ordering {
+    let va = *a - *current;
+    let vb = *b - *current;
+    turn_half(back, &va)
+        .cmp(&turn_half(back, &vb))
+        .then_with(|| {
+            let turn = va.cross(&vb);
+            if turn > 0 {
+                Ordering::Less
+            } else if turn < 0 {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .then_with(|| va.len_squared().cmp(&vb.len_squared()))
+}
+
+/// true if `point` is inside `polygon` or on its boundary
+fn covers(polygon: &FixedPolygon2D, point: &FixedPoint2D) -> bool {
+    polygon.contains(point)
+        || polygon.vertices().windows(2).any(|edge| on_segment(&edge[0], &edge[1], point))
+}
+
+/// One attempt at the hull with `k` neighbors, or None if it gets stuck or leaves points out
+fn hull_with_k(points: &[FixedPoint2D], k: usize) -> Option<FixedPolygon2D> {
+    let first = *points.iter().min_by(|a, b| (a.y, a.x).cmp(&(b.y, b.x)))?;
+    let mut remaining: Vec<FixedPoint2D> = points.iter().copied().filter(|point| *point != first).collect();
+    let mut hull: Vec<FixedPoint2D> = vec![first];
+    let mut current = first;
+    // the lowest point is approached from the west
+    let mut back = FixedVector2D { x: I32F32::from_num(-1), y: I32F32::from_num(0) };
+
+    while hull.len() == 1 || current != first {
+        if hull.len() == 3 {
+            // the hull may only close once it has 3 vertices
+            remaining.push(first);
+        }
+        if remaining.is_empty() {
+            return None;
+        }
+
+        // the k nearest remaining points, in the order they turn from the previous edge
+        remaining.sort_unstable_by_key(|point| (*point - current).len_squared());
+        let mut candidates: Vec<FixedPoint2D> = remaining.iter().take(k).copied().collect();
+        candidates.sort_unstable_by(|a, b| turn_cmp(&current, &back, a, b));
+
+        let next = candidates.into_iter().find(|candidate| {
+            let edge = FixedLineSegment2D { endpoints: (current, *candidate) };
+            // skip the last edge, which ends at `current`, and the first edge if closing
+            let skip_first = usize::from(*candidate == first);
+            hull.windows(2)
+                .take(hull.len().saturating_sub(2))
+                .skip(skip_first)
+                .all(|other| !edge.meets(&FixedLineSegment2D { endpoints: (other[0], other[1]) }))
+        })?;
+
+        back = current - next;
+        current = next;
+        hull.push(next);
+        remaining.retain(|point| *point != next);
+    }
+
+    let polygon = FixedPolygon2D::try_new(hull).ok()?;
+    if points.iter().all(|point| covers(&polygon, point)) {
+        Some(polygon)
+    } else {
+        None
+    }
+}
+
+/// Builds a concave hull around `points` with the k-nearest neighbors algorithm of Moreira and
+/// Santos (2007), for outlines that follow the points more tightly than the convex hull.
+///
+/// The hull is walked counterclockwise from the lowest point, and each step goes to whichever
+/// of the `k` nearest remaining points turns furthest to the right without crossing the hull
+/// so far. A smaller `k` gives a tighter outline. If no hull can be built with `k`, or it leaves
+/// points outside, `k` is increased until it works; with every point as a neighbor the result
+/// is the convex hull. The returned polygon is closed and does not cross itself.
+///
+/// Takes O(n^2 log n) time per attempt, so keep the number of points small on-chain.
+pub fn concave_hull(points: &[FixedPoint2D], k: usize) -> Result<FixedPolygon2D, &'static str> {
+    let mut distinct: Vec<FixedPoint2D> = points.to_vec();
+    distinct.sort_unstable_by(|a, b| (a.x, a.y).cmp(&(b.x, b.y)));
+    distinct.dedup();
+    if distinct.len() < 3 {
+        return Err("Concave hull needs at least 3 distinct points");
+    }
+    for k in k.max(3).min(distinct.len() - 1)..distinct.len() {
+        if let Some(polygon) = hull_with_k(&distinct, k) {
+            return Ok(polygon);
+        }
+    }
+    Err("Points are colinear")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn hull_follows_a_notch() {
+        // a 6 x 4 grid of points with the top middle points missing, like a U
+        let points: Vec<FixedPoint2D> = (0..6)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .filter(|(x, y)| !(*y >= 2 && (*x == 2 || *x == 3)))
+            .map(|(x, y)| point(x, y))
+            .collect();
+        let notch = FixedPoint2D { x: I32F32::from_num(2.5), y: I32F32::from_num(2.5) };
+
+        let tight = concave_hull(&points, 3).unwrap();
+        for p in &points {
+            assert!(covers(&tight, p));
+        }
+        assert!(!tight.contains(&notch));
+
+        let convex = concave_hull(&points, 100).unwrap();
+        assert!(convex.contains(&notch));
+    }
+
+    #[test]
+    fn colinear_points_have_no_hull() {
+        assert!(concave_hull(&[point(0, 0), point(1, 1), point(2, 2), point(3, 3)], 3).is_err());
+        assert!(concave_hull(&[point(0, 0), point(1, 1), point(0, 0)], 3).is_err());
+    }
+}
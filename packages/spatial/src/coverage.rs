@@ -0,0 +1,72 @@
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedPoint2D, FixedPolygon2D};
+
+/// Samples per axis of the bounding box of the region used by `coverage_fraction`
+pub const DEFAULT_COVERAGE_RESOLUTION: u32 = 64;
+
+/// Estimates the fraction of `region` covered by at least one of the polygons in `covers`, from
+/// 64 x 64 samples at the cell centers of a grid over the region's bounding box. Overlapping
+/// covers are only counted once. See `coverage_fraction_with_resolution` to change the number
+/// of samples.
+pub fn coverage_fraction(region: &FixedPolygon2D, covers: &[FixedPolygon2D]) -> I32F32 {
+    coverage_fraction_with_resolution(region, covers, DEFAULT_COVERAGE_RESOLUTION)
+}
+
+/// Same as `coverage_fraction`, with `resolution` x `resolution` samples. The error of the
+/// estimate shrinks with the cell size, and the work grows with `resolution` squared times the
+/// number of covers. Returns zero if no sample falls inside the region.
+pub fn coverage_fraction_with_resolution(
+    region: &FixedPolygon2D,
+    covers: &[FixedPolygon2D],
+    resolution: u32,
+) -> I32F32 {
+    let lower_left = region.bbox().lower_left();
+    let upper_right = region.bbox().upper_right();
+    let resolution = resolution.max(1);
+    let step_x = (upper_right.x - lower_left.x) / i64::from(resolution);
+    let step_y = (upper_right.y - lower_left.y) / i64::from(resolution);
+
+    let mut in_region: u64 = 0;
+    let mut covered: u64 = 0;
+    for j in 0..resolution {
+        let y = lower_left.y + step_y * I32F32::from_num(j) + step_y / 2;
+        for i in 0..resolution {
+            let sample = FixedPoint2D {
+                x: lower_left.x + step_x * I32F32::from_num(i) + step_x / 2,
+                y,
+            };
+            if !region.contains(&sample) {
+                continue;
+            }
+            in_region += 1;
+            if covers.iter().any(|cover| cover.contains(&sample)) {
+                covered += 1;
+            }
+        }
+    }
+    if in_region == 0 {
+        return I32F32::from_num(0);
+    }
+    I32F32::from_num(covered) / I32F32::from_num(in_region)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x0: i32, y0: i32, x1: i32, y1: i32) -> FixedPolygon2D {
+        let point = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        FixedPolygon2D::try_new(vec![point(x0, y0), point(x1, y0), point(x1, y1), point(x0, y1), point(x0, y0)]).unwrap()
+    }
+
+    #[test]
+    fn coverage_of_overlapping_halves() {
+        let region = square(0, 0, 8, 8);
+        let left = square(-1, -1, 4, 9);
+        let middle = square(2, -1, 4, 9);
+        assert_eq!(coverage_fraction(&region, &[]), I32F32::from_num(0));
+        assert_eq!(coverage_fraction(&region, &[left.clone(), middle]), I32F32::from_num(0.5));
+        assert_eq!(coverage_fraction(&region, &[left, square(4, -1, 9, 9)]), I32F32::from_num(1));
+    }
+}
@@ -0,0 +1,254 @@
+#[cfg(feature = "std")]
+use rand_chacha::ChaChaRng;
+#[cfg(feature = "std")]
+use secret_data_tools_dp::shuffle;
+use substrate_fixed::types::{I32F32, I64F64};
+
+use crate::{FixedBBox2D, FixedPoint2D};
+
+/// Slack allowed when testing whether a point is inside a candidate circle, for the rounding
+/// of circle centers in fixed point
+const INSIDE_TOLERANCE: I32F32 = I32F32::from_bits(1 << 12);
+
+/// A circle with a center and radius
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FixedCircle2D {
+    pub center: FixedPoint2D,
+    pub radius: I32F32,
+}
+
+impl FixedCircle2D {
+    pub const fn new(center: FixedPoint2D, radius: I32F32) -> Self {
+        Self { center, radius }
+    }
+
+    /// true if `point` is inside the circle or on its boundary
+    pub fn contains(&self, point: &FixedPoint2D) -> bool {
+        // in wider numbers, since the squares of large radii and offsets do not fit in I32F32
+        let dx = I64F64::from_num(point.x) - I64F64::from_num(self.center.x);
+        let dy = I64F64::from_num(point.y) - I64F64::from_num(self.center.y);
+        let radius = I64F64::from_num(self.radius);
+        // an offset whose square does not fit in I64F64 is farther than any I32F32 radius
+        dx.checked_mul(dx)
+            .zip(dy.checked_mul(dy))
+            .and_then(|(dx_squared, dy_squared)| dx_squared.checked_add(dy_squared))
+            .map_or(false, |distance_squared| distance_squared <= radius * radius)
+    }
+
+    pub fn bbox(&self) -> FixedBBox2D {
+        FixedBBox2D::new(
+            FixedPoint2D { x: self.center.x - self.radius, y: self.center.y - self.radius },
+            FixedPoint2D { x: self.center.x + self.radius, y: self.center.y + self.radius },
+        )
+    }
+}
+
+/// Candidate circle during the search, kept with its squared radius so no square roots are
+/// taken until the end
+#[derive(Copy, Clone)]
+struct Candidate {
+    center: FixedPoint2D,
+    radius_squared: I32F32,
+}
+
+impl Candidate {
+    fn covers(&self, point: &FixedPoint2D) -> bool {
+        (*point - self.center).len_squared() <= self.radius_squared + INSIDE_TOLERANCE
+    }
+
+    fn point(point: FixedPoint2D) -> Self {
+        Self { center: point, radius_squared: I32F32::from_num(0) }
+    }
+
+    fn diameter(a: FixedPoint2D, b: FixedPoint2D) -> Self {
+        let center = FixedPoint2D { x: (a.x + b.x) / 2, y: (a.y + b.y) / 2 };
+        Self { center, radius_squared: (a - center).len_squared() }
+    }
+
+    /// The circle through a, b and c, or the circle on the farthest pair if they are colinear
+    fn circumcircle(a: FixedPoint2D, b: FixedPoint2D, c: FixedPoint2D) -> Self {
+        // relative to a, in wider numbers since the products grow with the cube of the extent
+        let (bx, by) = (I64F64::from_num(b.x - a.x), I64F64::from_num(b.y - a.y));
+        let (cx, cy) = (I64F64::from_num(c.x - a.x), I64F64::from_num(c.y - a.y));
+        let d = 2 * (bx * cy - by * cx);
+        let center = if d == 0 {
+            None
+        } else {
+            let b_squared = bx * bx + by * by;
+            let c_squared = cx * cx + cy * cy;
+            let ux = (cy * b_squared - by * c_squared) / d;
+            let uy = (bx * c_squared - cx * b_squared) / d;
+            I32F32::checked_from_num(ux)
+                .zip(I32F32::checked_from_num(uy))
+                .and_then(|(ux, uy)| Some(FixedPoint2D { x: a.x.checked_add(ux)?, y: a.y.checked_add(uy)? }))
+        };
+        match center {
+            Some(center) => Self { center, radius_squared: (a - center).len_squared() },
+            None => [Self::diameter(a, b), Self::diameter(a, c), Self::diameter(b, c)]
+                .into_iter()
+                .max_by_key(|candidate| candidate.radius_squared)
+                .unwrap_or(Self::point(a)),
+        }
+    }
+}
+
+/// Smallest fixed-point number whose square is at least `value`
+fn ceil_sqrt(value: I32F32) -> I32F32 {
+    if value <= 0 {
+        return I32F32::from_num(0);
+    }
+    // sqrt(v) * 2^32 = sqrt(v * 2^32 * 2^32), so take the integer square root of bits << 32
+    let target = (value.to_bits() as u128) << 32;
+    let mut root = 1u128 << ((128 - target.leading_zeros()).div_ceil(2));
+    loop {
+        let next = (root + target / root) / 2;
+        if next >= root {
+            break;
+        }
+        root = next;
+    }
+    if root * root < target {
+        root += 1;
+    }
+    I32F32::from_bits(root as i64)
+}
+
+/// Welzl's algorithm, in its iterative form, over points in their search order
+fn welzl(points: &[FixedPoint2D]) -> Candidate {
+    let mut circle = Candidate::point(points[0]);
+    for i in 1..points.len() {
+        if circle.covers(&points[i]) {
+            continue;
+        }
+        // points[i] is on the boundary of the smallest circle around points[..=i]
+        circle = Candidate::point(points[i]);
+        for j in 0..i {
+            if circle.covers(&points[j]) {
+                continue;
+            }
+            circle = Candidate::diameter(points[i], points[j]);
+            for point in &points[..j] {
+                if !circle.covers(point) {
+                    circle = Candidate::circumcircle(points[i], points[j], *point);
+                }
+            }
+        }
+    }
+    circle
+}
+
+/// Finishes the search: the radius is the distance to the farthest point, rounded up, so every
+/// point is inside the returned circle despite rounding
+fn enclosing_circle_in_order(points: &[FixedPoint2D]) -> Result<FixedCircle2D, &'static str> {
+    if points.is_empty() {
+        return Err("Enclosing circle needs at least one point");
+    }
+    let center = welzl(points).center;
+    let radius_squared = points
+        .iter()
+        .map(|point| (*point - center).len_squared())
+        .max()
+        .unwrap_or(I32F32::from_num(0));
+    Ok(FixedCircle2D { center, radius: ceil_sqrt(radius_squared) })
+}
+
+/// Fisher-Yates shuffle with a fixed xorshift sequence from `next`. The modulo is slightly
+/// biased, which does not matter for a fixed order; `min_enclosing_circle_with_rng` uses the
+/// unbiased `shuffle` of the dp crate instead.
+fn xorshift_shuffle(points: &mut [FixedPoint2D], mut next: impl FnMut() -> u64) {
+    for i in (1..points.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        points.swap(i, j);
+    }
+}
+
+/// Smallest circle that contains every point, e.g. the cloaking region around a set of
+/// locations. Uses Welzl's algorithm, which takes expected O(n) time when the points are
+/// visited in random order. This version shuffles with a fixed xorshift sequence, so the
+/// result is deterministic; if the points may be chosen by an adversary to hit the O(n^3)
+/// worst case, use `min_enclosing_circle_with_rng`.
+///
+/// The radius is rounded up, so every point is inside the returned circle. Returns an error
+/// if there are no points.
+pub fn min_enclosing_circle(points: &[FixedPoint2D]) -> Result<FixedCircle2D, &'static str> {
+    let mut shuffled = points.to_vec();
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    xorshift_shuffle(&mut shuffled, || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    });
+    enclosing_circle_in_order(&shuffled)
+}
+
+/// Same as `min_enclosing_circle`, shuffling the points with `rng` instead
+#[cfg(feature = "std")]
+pub fn min_enclosing_circle_with_rng(
+    points: &[FixedPoint2D],
+    rng: &mut ChaChaRng,
+) -> Result<FixedCircle2D, &'static str> {
+    let mut shuffled = points.to_vec();
+    shuffle(rng, &mut shuffled);
+    enclosing_circle_in_order(&shuffled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn circle_is_smallest_and_contains_all_points() {
+        // the two far points are a diameter and the rest are inside
+        let points = [point(-5, 0), point(5, 0), point(0, 3), point(1, -2), point(-3, 3)];
+        let circle = min_enclosing_circle(&points).unwrap();
+        assert_eq!(circle.center, point(0, 0));
+        assert_eq!(circle.radius, I32F32::from_num(5));
+
+        // an acute triangle needs its circumcircle, centered at (2, 1) with radius sqrt(5)
+        let triangle = [point(0, 0), point(4, 0), point(3, 3)];
+        let circle = min_enclosing_circle(&triangle).unwrap();
+        assert_eq!(circle.center, point(2, 1));
+        assert!(triangle.iter().all(|p| circle.contains(p)));
+        assert!((circle.radius * circle.radius - I32F32::from_num(5)).abs() < I32F32::from_num(0.0001));
+
+        let single = min_enclosing_circle(&[point(2, 2)]).unwrap();
+        assert_eq!(single, FixedCircle2D::new(point(2, 2), I32F32::from_num(0)));
+        assert!(min_enclosing_circle(&[]).is_err());
+    }
+
+    #[test]
+    fn large_circles_contain_points_without_overflow() {
+        let circle = FixedCircle2D::new(point(0, 0), I32F32::from_num(50_000));
+        assert!(circle.contains(&point(30_000, 40_000)));
+        assert!(circle.contains(&point(0, -50_000)));
+        assert!(!circle.contains(&point(30_000, 40_001)));
+        let far = FixedPoint2D { x: I32F32::max_value(), y: I32F32::min_value() };
+        assert!(!circle.contains(&far));
+        let huge = FixedCircle2D::new(point(0, 0), I32F32::max_value());
+        assert!(huge.contains(&point(i32::MAX, 0)));
+        assert!(!huge.contains(&far));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn shuffled_search_finds_the_same_circle() {
+        use rand_chacha::rand_core::SeedableRng;
+        let points = [point(-5, 0), point(5, 0), point(0, 3), point(1, -2), point(-3, 3)];
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        let circle = min_enclosing_circle_with_rng(&points, &mut rng).unwrap();
+        assert_eq!(circle, min_enclosing_circle(&points).unwrap());
+    }
+
+    #[test]
+    fn ceil_sqrt_rounds_up() {
+        assert_eq!(ceil_sqrt(I32F32::from_num(25)), I32F32::from_num(5));
+        let root = ceil_sqrt(I32F32::from_num(2));
+        assert!(root * root >= I32F32::from_num(2));
+        assert!(root - I32F32::from_bits(1) < I32F32::from_num(1.4143));
+    }
+}
@@ -1,8 +1,14 @@
-use std::{ops, cmp::Ordering};
+use core::{ops, cmp::Ordering};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use cosmwasm_std::{StdResult, StdError};
+#[cfg(feature = "std")]
 use serde::{Serialize, Deserialize};
+#[cfg(feature = "std")]
 use schemars::JsonSchema;
 use substrate_fixed::{types::I32F32};
+#[cfg(feature = "std")]
+use secret_data_tools_fixed::FixedBytes;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct FixedPoint2D {
@@ -15,10 +21,11 @@ impl FixedPoint2D {
         FixedVector2D { x: self.x, y: self.y }
     }
 
+    #[cfg(feature = "std")]
     pub fn into_stored(&self) -> StoredFixedPoint2D {
         StoredFixedPoint2D { 
-            x: self.x.to_be_bytes().to_vec(), 
-            y: self.y.to_be_bytes().to_vec() 
+            x: self.x.to_stored_bytes(), 
+            y: self.y.to_stored_bytes() 
         }
     }
 }
@@ -33,31 +40,19 @@ impl ops::Sub<FixedPoint2D> for FixedPoint2D {
     }
 }
 
+#[cfg(feature = "std")]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct StoredFixedPoint2D {
     pub x: Vec<u8>,
     pub y: Vec<u8>,
 }
 
+#[cfg(feature = "std")]
 impl StoredFixedPoint2D {
     pub fn into_humanized(&self) -> StdResult<FixedPoint2D> {
         let point = FixedPoint2D {
-            x: I32F32::from_be_bytes(
-                match self.x.as_slice().try_into() {
-                    Ok(x_bytes) => x_bytes,
-                    Err(err) => { 
-                        return Err(StdError::generic_err(format!("{:?}", err))) 
-                    },
-                }
-            ),
-            y: I32F32::from_be_bytes(
-                match self.y.as_slice().try_into() {
-                    Ok(y_bytes) => y_bytes,
-                    Err(err) => { 
-                        return Err(StdError::generic_err(format!("{:?}", err))) 
-                    },
-                }
-            ),
+            x: I32F32::from_stored_bytes(&self.x)?,
+            y: I32F32::from_stored_bytes(&self.y)?,
         };
         Ok(point)
     }
@@ -75,6 +70,11 @@ impl FixedVector2D {
         self.x * other.x + self.y * other.y
     }
 
+    /// cross product, i.e. the z component of the cross product of the vectors in 3D
+    pub fn cross(&self, other: &FixedVector2D) -> I32F32 {
+        self.x * other.y - self.y * other.x
+    }
+
     /// length squared of a vector
     pub fn len_squared(&self) -> I32F32 {
         self.dot(self)
@@ -84,10 +84,11 @@ impl FixedVector2D {
         FixedPoint2D { x: self.x, y: self.y }
     }
 
+    #[cfg(feature = "std")]
     pub fn into_stored(&self) -> StoredFixedVector2D {
         StoredFixedVector2D { 
-            x: self.x.to_be_bytes().to_vec(), 
-            y: self.y.to_be_bytes().to_vec() 
+            x: self.x.to_stored_bytes(), 
+            y: self.y.to_stored_bytes() 
         }
     }
 }
@@ -122,31 +123,19 @@ impl ops::Mul<I32F32> for FixedVector2D {
     }
 }
 
+#[cfg(feature = "std")]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct StoredFixedVector2D {
     pub x: Vec<u8>,
     pub y: Vec<u8>,
 }
 
+#[cfg(feature = "std")]
 impl StoredFixedVector2D {
     pub fn into_humanized(&self) -> StdResult<FixedVector2D> {
         let vector = FixedVector2D {
-            x: I32F32::from_be_bytes(
-                match self.x.as_slice().try_into() {
-                    Ok(x_bytes) => x_bytes,
-                    Err(err) => { 
-                        return Err(StdError::generic_err(format!("{:?}", err))) 
-                    },
-                }
-            ),
-            y: I32F32::from_be_bytes(
-                match self.y.as_slice().try_into() {
-                    Ok(y_bytes) => y_bytes,
-                    Err(err) => { 
-                        return Err(StdError::generic_err(format!("{:?}", err))) 
-                    },
-                }
-            ),
+            x: I32F32::from_stored_bytes(&self.x)?,
+            y: I32F32::from_stored_bytes(&self.y)?,
         };
         Ok(vector)
     }
@@ -170,21 +159,72 @@ pub fn is_counterclockwise(a: &FixedPoint2D, b: &FixedPoint2D, c: &FixedPoint2D)
     else { None }
 }
 
+/// true if `point` lies on the closed segment from `a` to `b`
+pub(crate) fn on_segment(a: &FixedPoint2D, b: &FixedPoint2D, point: &FixedPoint2D) -> bool {
+    is_counterclockwise(a, b, point).is_none()
+        && FixedLineSegment2D { endpoints: (*a, *b) }.bbox().contains(point)
+}
+
+/// Convex hull of the points, counterclockwise from the lowest-leftmost point without repeating
+/// it, and without colinear points (Andrew's monotone chain). Has 1 or 2 points if all of the
+/// points are the same or colinear.
+fn convex_hull(points: &[FixedPoint2D]) -> Vec<FixedPoint2D> {
+    let mut sorted = points.to_vec();
+    sorted.sort_unstable_by(|a, b| (a.x, a.y).cmp(&(b.x, b.y)));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+    let mut hull: Vec<FixedPoint2D> = Vec::with_capacity(2 * sorted.len());
+    // lower chain left to right, then upper chain right to left
+    for pass in [sorted.clone(), sorted.into_iter().rev().collect()] {
+        let chain_start = hull.len();
+        for point in pass {
+            while hull.len() >= chain_start + 2
+                && signed_area(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point starts the next chain
+        hull.pop();
+    }
+    hull
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FixedLineSegment2D {
     pub endpoints: (FixedPoint2D, FixedPoint2D),
 }
 
 impl FixedLineSegment2D {
+    #[cfg(feature = "std")]
     pub fn new(endpoint1: FixedPoint2D, endpoint2: FixedPoint2D) -> StdResult<Self> {
+        Self::try_new(endpoint1, endpoint2).map_err(StdError::generic_err)
+    }
+
+    /// Same as `new`, with a plain error message so that it is available without `std`
+    pub fn try_new(endpoint1: FixedPoint2D, endpoint2: FixedPoint2D) -> Result<Self, &'static str> {
         if endpoint1 == endpoint2 {
-            return Err(StdError::generic_err("Invalid: endpoints cannot be the same"));
+            return Err("Invalid: endpoints cannot be the same");
         }
         Ok(Self {
             endpoints: (endpoint1, endpoint2)
         })
     }
 
+    /// true if each segment has its endpoints on different sides of the line through the other,
+    /// where an endpoint on the line counts as a side of its own. This finds every crossing and
+    /// every endpoint that touches the other segment, but not colinear overlaps. `contains`
+    /// uses it for the ray casting test.
+    pub fn crosses(&self, other: &FixedLineSegment2D) -> bool {
+        (is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.0) != 
+         is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.1)) && 
+        (is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.0) != 
+         is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.1))
+    }
+
     pub fn intersects(&self, other: &FixedLineSegment2D) -> bool {
         (is_counterclockwise(&self.endpoints.0, &other.endpoints.1, &self.endpoints.0) != 
          is_counterclockwise(&self.endpoints.0, &other.endpoints.0, &self.endpoints.1)) && 
@@ -192,6 +232,29 @@ impl FixedLineSegment2D {
          is_counterclockwise(&other.endpoints.0, &self.endpoints.1, &other.endpoints.1))
     }
 
+    /// true if the two segments share at least one point, including an endpoint or, for
+    /// colinear segments, an overlapping stretch
+    pub fn meets(&self, other: &FixedLineSegment2D) -> bool {
+        let other_0 = is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.0);
+        let other_1 = is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.1);
+        if other_0.is_none() && other_1.is_none() {
+            // all four endpoints are on one line, so the segments meet if their extents overlap
+            return self.bbox().overlaps(&other.bbox());
+        }
+        (other_0 != other_1) && 
+        (is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.0) != 
+         is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.1))
+    }
+
+    pub fn bbox(&self) -> FixedBBox2D {
+        let (a, b) = self.endpoints;
+        FixedBBox2D {
+            lower_left: FixedPoint2D { x: a.x.min(b.x), y: a.y.min(b.y) },
+            upper_right: FixedPoint2D { x: a.x.max(b.x), y: a.y.max(b.y) },
+        }
+    }
+
+    #[cfg(feature = "std")]
     pub fn into_stored(&self) -> StoredFixedLineSegment2D {
         StoredFixedLineSegment2D { 
             endpoints: (
@@ -202,11 +265,13 @@ impl FixedLineSegment2D {
     }
 }
 
+#[cfg(feature = "std")]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct StoredFixedLineSegment2D {
     pub endpoints: (StoredFixedPoint2D, StoredFixedPoint2D)
 }
 
+#[cfg(feature = "std")]
 impl StoredFixedLineSegment2D {
     pub fn into_humanized(&self) -> StdResult<FixedLineSegment2D> {
         Ok(FixedLineSegment2D { 
@@ -225,6 +290,75 @@ pub struct FixedBBox2D {
 }
 
 impl FixedBBox2D {
+    /// box with the given corners; `lower_left` must not be above or right of `upper_right`
+    pub const fn new(lower_left: FixedPoint2D, upper_right: FixedPoint2D) -> Self {
+        Self { lower_left, upper_right }
+    }
+
+    /// Smallest box around all of `points`, or None if there are none
+    pub fn from_points(points: &[FixedPoint2D]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(points.iter().fold(Self::new(first, first), |bbox, point| {
+            bbox.union(&Self::new(*point, *point))
+        }))
+    }
+
+    pub fn lower_left(&self) -> FixedPoint2D {
+        self.lower_left
+    }
+
+    pub fn upper_right(&self) -> FixedPoint2D {
+        self.upper_right
+    }
+
+    pub fn width(&self) -> I32F32 {
+        self.upper_right.x - self.lower_left.x
+    }
+
+    pub fn height(&self) -> I32F32 {
+        self.upper_right.y - self.lower_left.y
+    }
+
+    /// Smallest box around both boxes
+    pub fn union(&self, other: &FixedBBox2D) -> FixedBBox2D {
+        FixedBBox2D {
+            lower_left: FixedPoint2D {
+                x: self.lower_left.x.min(other.lower_left.x),
+                y: self.lower_left.y.min(other.lower_left.y),
+            },
+            upper_right: FixedPoint2D {
+                x: self.upper_right.x.max(other.upper_right.x),
+                y: self.upper_right.y.max(other.upper_right.y),
+            },
+        }
+    }
+
+    /// The box shared by both boxes, or None if they do not overlap. Boxes that only touch
+    /// give a box with zero width or height.
+    pub fn intersection(&self, other: &FixedBBox2D) -> Option<FixedBBox2D> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        Some(FixedBBox2D {
+            lower_left: FixedPoint2D {
+                x: self.lower_left.x.max(other.lower_left.x),
+                y: self.lower_left.y.max(other.lower_left.y),
+            },
+            upper_right: FixedPoint2D {
+                x: self.upper_right.x.min(other.upper_right.x),
+                y: self.upper_right.y.min(other.upper_right.y),
+            },
+        })
+    }
+
+    /// true if the two boxes share at least one point
+    pub fn overlaps(&self, other: &FixedBBox2D) -> bool {
+        self.lower_left.x <= other.upper_right.x &&
+        other.lower_left.x <= self.upper_right.x &&
+        self.lower_left.y <= other.upper_right.y &&
+        other.lower_left.y <= self.upper_right.y
+    }
+
     pub fn contains(&self, point: &FixedPoint2D) -> bool {
         point.x >= self.lower_left.x &&
         point.x <= self.upper_right.x &&
@@ -232,6 +366,7 @@ impl FixedBBox2D {
         point.y <= self.upper_right.y
     }
 
+    #[cfg(feature = "std")]
     pub fn into_stored(&self) -> StoredFixedBBox2D {
         StoredFixedBBox2D { 
             lower_left: self.lower_left.into_stored(),
@@ -240,12 +375,14 @@ impl FixedBBox2D {
     }
 }
 
+#[cfg(feature = "std")]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct StoredFixedBBox2D {
     lower_left: StoredFixedPoint2D,
     upper_right: StoredFixedPoint2D,
 }
 
+#[cfg(feature = "std")]
 impl StoredFixedBBox2D {
     pub fn into_humanized(&self) -> StdResult<FixedBBox2D> {
         Ok(FixedBBox2D { 
@@ -263,13 +400,19 @@ pub struct FixedPolygon2D {
 }
 
 impl FixedPolygon2D {
+    #[cfg(feature = "std")]
     pub fn new(points: Vec<FixedPoint2D>) -> StdResult<Self> {
+        Self::try_new(points).map_err(StdError::generic_err)
+    }
+
+    /// Same as `new`, with a plain error message so that it is available without `std`
+    pub fn try_new(points: Vec<FixedPoint2D>) -> Result<Self, &'static str> {
         let length = points.len();
         if length < 3 {
-            return Err(StdError::generic_err("Polygon must have at least 3 vertices"));
+            return Err("Polygon must have at least 3 vertices");
         }
         if points[0] != points[length-1] {
-            return Err(StdError::generic_err("First and last point vector must be the same"))
+            return Err("First and last point vector must be the same")
         }
 
         // calculate bounding box and anchor
@@ -301,6 +444,15 @@ impl FixedPolygon2D {
         self.vertices.len()
     }
 
+    /// Vertices in the order they were given, with the first vertex repeated at the end
+    pub fn vertices(&self) -> &[FixedPoint2D] {
+        &self.vertices
+    }
+
+    pub fn bbox(&self) -> &FixedBBox2D {
+        &self.bbox
+    }
+
     pub fn contains(&self, point: &FixedPoint2D) -> bool {
         if !self.bbox.contains(point) {
             return false;
@@ -335,7 +487,7 @@ impl FixedPolygon2D {
                     continue;
                 }
             }
-            if test_segment.intersects(&edge) {
+            if test_segment.crosses(&edge) {
                 intersections += 1;
             }
         }
@@ -356,12 +508,125 @@ impl FixedPolygon2D {
         }
     }
 
+    /// Twice the signed area, from a fan of triangles around the first vertex
+    fn doubled_signed_area(&self) -> I32F32 {
+        let first = self.vertices[0];
+        self.vertices
+            .windows(2)
+            .fold(I32F32::from_num(0), |area, edge| area + signed_area(first, edge[0], edge[1]))
+    }
+
+    /// Area of the polygon, positive if the vertices are counterclockwise and negative if they
+    /// are clockwise. Areas that a self-intersecting polygon winds around twice count twice.
+    pub fn signed_area(&self) -> I32F32 {
+        self.doubled_signed_area() / 2
+    }
+
+    /// true if the vertices go around the polygon counterclockwise
+    pub fn is_counterclockwise(&self) -> bool {
+        self.doubled_signed_area() > 0
+    }
+
+    /// Number of times the boundary winds counterclockwise around `point`, negative for
+    /// clockwise. Points on the boundary may count either way.
+    pub fn winding_number(&self, point: &FixedPoint2D) -> i32 {
+        if !self.bbox.contains(point) {
+            return 0;
+        }
+        let mut winding: i32 = 0;
+        for edge in self.vertices.windows(2) {
+            if edge[0].y <= point.y {
+                // upward crossing with the point on the left
+                if edge[1].y > point.y && signed_area(edge[0], edge[1], *point) > 0 {
+                    winding += 1;
+                }
+            } else if edge[1].y <= point.y && signed_area(edge[0], edge[1], *point) < 0 {
+                // downward crossing with the point on the right
+                winding -= 1;
+            }
+        }
+        winding
+    }
+
+    /// Same as `contains`, but with the nonzero winding rule instead of the even-odd rule: a
+    /// point is inside if the boundary winds around it at all. The two agree for simple
+    /// polygons. For self-intersecting ones, e.g. a star drawn with one stroke, this also counts
+    /// areas the boundary goes around twice as inside.
+    pub fn contains_winding(&self, point: &FixedPoint2D) -> bool {
+        self.winding_number(point) != 0
+    }
+
+    /// Square of the largest distance between two vertices, i.e. how far the polygon reaches
+    /// across. Found with rotating calipers on the convex hull, in O(n log n) time.
+    pub fn diameter_squared(&self) -> I32F32 {
+        let hull = convex_hull(&self.vertices);
+        let n = hull.len();
+        if n < 3 {
+            return (hull[n - 1] - hull[0]).len_squared();
+        }
+        let mut diameter_squared = I32F32::from_num(0);
+        let mut j = 1;
+        for i in 0..n {
+            let next = (i + 1) % n;
+            // advance j to the vertex furthest from the edge, which is antipodal to both ends
+            while signed_area(hull[i], hull[next], hull[(j + 1) % n])
+                > signed_area(hull[i], hull[next], hull[j])
+            {
+                j = (j + 1) % n;
+            }
+            diameter_squared = diameter_squared
+                .max((hull[j] - hull[i]).len_squared())
+                .max((hull[j] - hull[next]).len_squared());
+        }
+        diameter_squared
+    }
+
+    /// Square of the smallest width of the polygon, i.e. the distance between the closest pair
+    /// of parallel lines that enclose it. Found with rotating calipers on the convex hull, since
+    /// the narrowest direction always has one line along a hull edge. Zero if the vertices are
+    /// colinear.
+    pub fn min_width_squared(&self) -> I32F32 {
+        let hull = convex_hull(&self.vertices);
+        let n = hull.len();
+        if n < 3 {
+            return I32F32::from_num(0);
+        }
+        let mut min_width_squared = I32F32::max_value();
+        let mut j = 1;
+        for i in 0..n {
+            let next = (i + 1) % n;
+            while signed_area(hull[i], hull[next], hull[(j + 1) % n])
+                > signed_area(hull[i], hull[next], hull[j])
+            {
+                j = (j + 1) % n;
+            }
+            // twice the triangle area is the edge length times the height of the furthest vertex
+            let area = signed_area(hull[i], hull[next], hull[j]);
+            let width_squared = area / (hull[next] - hull[i]).len_squared() * area;
+            min_width_squared = min_width_squared.min(width_squared);
+        }
+        min_width_squared
+    }
+
+    /// Returns the same polygon with its vertices in counterclockwise order, reversing them if
+    /// they are clockwise. Unlike `as_counterclockwise_points`, this keeps the shape of
+    /// non-convex polygons, e.g. `FixedPolygon2D::new(points)?.normalize_orientation()`.
+    pub fn normalize_orientation(mut self) -> Self {
+        if self.doubled_signed_area() < I32F32::from_num(0) {
+            self.vertices.reverse();
+        }
+        self
+    }
+
+    /// Sorts the vertices by angle around the lowest vertex. This is only correct for convex
+    /// polygons; use `normalize_orientation` to orient any simple polygon.
     pub fn as_counterclockwise_points(&self) -> Vec<FixedPoint2D> {
         let mut points = self.vertices.clone();
         points.sort_unstable_by(|a, b| FixedPolygon2D::ccw_cmp(&self.anchor, a, b));
         points
     }
 
+    #[cfg(feature = "std")]
     pub fn into_stored(&self) -> StoredFixedPolygon2D {
         StoredFixedPolygon2D { 
             vertices: self.vertices.iter().map(|v| v.into_stored()).collect(),
@@ -371,6 +636,7 @@ impl FixedPolygon2D {
     }
 }
 
+#[cfg(feature = "std")]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct StoredFixedPolygon2D {
     vertices: Vec<StoredFixedPoint2D>,
@@ -378,6 +644,7 @@ pub struct StoredFixedPolygon2D {
     bbox: StoredFixedBBox2D,
 }
 
+#[cfg(feature = "std")]
 impl StoredFixedPolygon2D {
     pub fn into_humanized(&self) -> StdResult<FixedPolygon2D> {
         Ok(FixedPolygon2D { 
@@ -389,4 +656,147 @@ impl StoredFixedPolygon2D {
             bbox: self.bbox.into_humanized()?,
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::{fixed_point, integer_point, integer_polygon, DEFAULT_FIXED_EXTENT, DEFAULT_MAX_VERTICES};
+    use crate::IntegerPoint2D;
+    use proptest::prelude::*;
+
+    fn rotate(point: &FixedPoint2D) -> FixedPoint2D {
+        FixedPoint2D { x: -point.y, y: point.x }
+    }
+
+    fn from_integer(point: &IntegerPoint2D) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(point.x), y: I32F32::from_num(point.y) }
+    }
+
+    /// true if `point` is on an edge, or close enough that rounding in `signed_area` could put
+    /// it on the wrong side
+    fn near_boundary(polygon: &FixedPolygon2D, point: &FixedPoint2D) -> bool {
+        let tolerance = I32F32::from_bits(1 << 16);
+        polygon.vertices.windows(2).any(|edge| {
+            signed_area(edge[0], edge[1], *point).abs() <= tolerance
+                && point.x >= edge[0].x.min(edge[1].x) - tolerance
+                && point.x <= edge[0].x.max(edge[1].x) + tolerance
+                && point.y >= edge[0].y.min(edge[1].y) - tolerance
+                && point.y <= edge[0].y.max(edge[1].y) + tolerance
+        })
+    }
+
+    #[test]
+    fn normalize_orientation_keeps_non_convex_shape() {
+        let point = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        // clockwise L shape
+        let clockwise = FixedPolygon2D::try_new(vec![
+            point(0, 0), point(0, 4), point(2, 4), point(2, 2), point(4, 2), point(4, 0), point(0, 0),
+        ]).unwrap();
+        assert!(!clockwise.is_counterclockwise());
+
+        let normalized = clockwise.clone().normalize_orientation();
+        assert!(normalized.is_counterclockwise());
+        for (x, y) in [(1, 1), (1, 3), (3, 1), (3, 3)] {
+            assert_eq!(normalized.contains(&point(x, y)), clockwise.contains(&point(x, y)));
+        }
+        assert!(!normalized.contains(&point(3, 3)));
+        assert_eq!(normalized.clone().normalize_orientation(), normalized);
+    }
+
+    #[test]
+    fn calipers_measure_diameter_and_width() {
+        let pt = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        // a 4 x 3 rectangle with a notch and a colinear vertex
+        let notched = FixedPolygon2D::try_new(vec![
+            pt(0, 0), pt(2, 0), pt(4, 0), pt(4, 3), pt(2, 1), pt(0, 3), pt(0, 0),
+        ]).unwrap();
+        assert_eq!(notched.diameter_squared(), I32F32::from_num(25));
+        assert_eq!(notched.min_width_squared(), I32F32::from_num(9));
+
+        // the narrowest width of a 3-4-5 triangle is its height over the hypotenuse, 12 / 5
+        let triangle = FixedPolygon2D::try_new(vec![pt(0, 0), pt(4, 0), pt(0, 3), pt(0, 0)]).unwrap();
+        assert_eq!(triangle.diameter_squared(), I32F32::from_num(25));
+        assert!((triangle.min_width_squared() - I32F32::from_num(5.76)).abs() < I32F32::from_num(0.0001));
+
+        let flat = FixedPolygon2D::try_new(vec![pt(0, 0), pt(1, 1), pt(3, 3), pt(0, 0)]).unwrap();
+        assert_eq!(flat.diameter_squared(), I32F32::from_num(18));
+        assert_eq!(flat.min_width_squared(), I32F32::from_num(0));
+    }
+
+    #[test]
+    fn winding_rule_fills_pentagram_center() {
+        let point = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        // five-pointed star drawn with one stroke, so the center is wound around twice
+        let star = FixedPolygon2D::try_new(vec![
+            point(0, 10), point(6, -8), point(-10, 3), point(10, 3), point(-6, -8), point(0, 10),
+        ]).unwrap();
+        let center = point(0, 0);
+        assert!(!star.contains(&center));
+        assert!(star.contains_winding(&center));
+        assert_eq!(star.winding_number(&center).abs(), 2);
+        assert!(star.contains_winding(&point(0, 7)));
+        assert!(!star.contains_winding(&point(8, -6)));
+
+        let square = FixedPolygon2D::try_new(vec![point(0, 0), point(2, 0), point(2, 2), point(0, 2), point(0, 0)]).unwrap();
+        assert_eq!(square.signed_area(), I32F32::from_num(4));
+        assert_eq!(square.clone().normalize_orientation(), square);
+    }
+
+    proptest! {
+        #[test]
+        fn contains_is_invariant_under_rotation(
+            polygon in any::<FixedPolygon2D>(),
+            point in fixed_point(DEFAULT_FIXED_EXTENT),
+        ) {
+            prop_assume!(!near_boundary(&polygon, &point));
+            let rotated = FixedPolygon2D::try_new(polygon.vertices.iter().map(rotate).collect()).unwrap();
+            prop_assert_eq!(polygon.contains(&point), rotated.contains(&rotate(&point)));
+        }
+
+        #[test]
+        fn contains_matches_integer_polygon(
+            polygon in integer_polygon(DEFAULT_MAX_VERTICES, DEFAULT_FIXED_EXTENT),
+            point in integer_point(DEFAULT_FIXED_EXTENT),
+        ) {
+            // integer coordinates are exact in fixed point, so the two must agree everywhere
+            // except on the boundary, where the result depends on the ray direction
+            prop_assume!(!polygon.vertices().windows(2).any(|edge| {
+                crate::integer_geom::signed_area(edge[0], edge[1], point) == 0
+                    && point.x >= edge[0].x.min(edge[1].x)
+                    && point.x <= edge[0].x.max(edge[1].x)
+                    && point.y >= edge[0].y.min(edge[1].y)
+                    && point.y <= edge[0].y.max(edge[1].y)
+            }));
+            let fixed_polygon = FixedPolygon2D::try_new(polygon.vertices().iter().map(from_integer).collect()).unwrap();
+            prop_assert_eq!(polygon.contains(&point), fixed_polygon.contains(&from_integer(&point)));
+        }
+
+        #[cfg(feature = "std")]
+        #[test]
+        fn stored_polygon_round_trips(polygon in any::<FixedPolygon2D>()) {
+            prop_assert_eq!(polygon.into_stored().into_humanized().unwrap(), polygon);
+        }
+    }
+
+    #[test]
+    fn crosses_finds_crossings_and_touches_but_not_overlaps() {
+        let point = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        let segment = |x0, y0, x1, y1| FixedLineSegment2D { endpoints: (point(x0, y0), point(x1, y1)) };
+        assert!(segment(0, 0, 4, 4).crosses(&segment(0, 4, 4, 0)));
+        assert!(segment(0, 0, 4, 0).crosses(&segment(2, 0, 2, 3)));
+        assert!(!segment(0, 0, 4, 0).crosses(&segment(0, 1, 4, 1)));
+        assert!(!segment(0, 0, 4, 0).crosses(&segment(2, 0, 6, 0)));
+    }
+
+    #[test]
+    fn meets_also_finds_colinear_overlaps() {
+        let point = |x: i32, y: i32| FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) };
+        let segment = |x0, y0, x1, y1| FixedLineSegment2D { endpoints: (point(x0, y0), point(x1, y1)) };
+        assert!(segment(0, 0, 4, 4).meets(&segment(0, 4, 4, 0)));
+        assert!(segment(0, 0, 4, 0).meets(&segment(2, 0, 2, 3)));
+        assert!(segment(0, 0, 4, 0).meets(&segment(2, 0, 6, 0)));
+        assert!(segment(0, 0, 4, 0).meets(&segment(4, 0, 6, 0)));
+        assert!(!segment(0, 0, 4, 0).meets(&segment(5, 0, 6, 0)));
+        assert!(!segment(0, 0, 4, 0).meets(&segment(0, 1, 4, 1)));
+    }
+}
@@ -0,0 +1,580 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use rand_chacha::ChaChaRng;
+use secret_data_tools_dp::{laplace, random_unit_interval, PrivacyAccountant, Release};
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedBBox2D, FixedLineSegment2D, FixedPoint2D, FixedPolygon2D};
+
+const NODE_KEY: &[u8] = b"node";
+const POINT_KEY: &[u8] = b"pt";
+
+/// Largest depth of a `GeoIndex`, which has 4^depth leaf cells
+pub const MAX_GEO_INDEX_DEPTH: u8 = 16;
+
+/// How a quadtree cell relates to a query polygon
+enum CellOverlap {
+    Outside,
+    Inside,
+    Partial,
+}
+
+/// Point index in Storage, organized as a quadtree over a square region. Every node keeps the
+/// number of points below it, and the points themselves are kept in the leaf cells, so counting
+/// the points inside a polygon only reads the leaves that the polygon boundary passes through.
+///
+/// Coordinates are I32F32, so the region should span at most a few thousand units for the
+/// cross products used by the polygon tests to stay in range.
+pub struct GeoIndex<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// lower left corner of the indexed region
+    origin: FixedPoint2D,
+    /// side length of the indexed region
+    size: I32F32,
+    /// number of levels below the root
+    depth: u8,
+}
+
+impl<'a> GeoIndex<'a> {
+    /// constructor, for the square region with lower left corner `origin` and side `size`
+    pub const fn new(
+        namespace: &'a [u8],
+        origin: FixedPoint2D,
+        size: I32F32,
+        depth: u8,
+    ) -> Self {
+        assert!(size.to_bits() > 0, "Region size must be greater than zero");
+        assert!(depth <= MAX_GEO_INDEX_DEPTH, "Geo index depth is too large");
+        Self {
+            namespace,
+            prefix: None,
+            origin,
+            size,
+            depth,
+        }
+    }
+
+    /// This is used to produce a new GeoIndex. It can be used when you want to associate
+    /// a GeoIndex to multiple suffixes and you still want to define the GeoIndex
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            origin: self.origin,
+            size: self.size,
+            depth: self.depth,
+        }
+    }
+}
+
+impl<'a> GeoIndex<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn node_key(&self, level: u8, x: u32, y: u32) -> Vec<u8> {
+        [self.as_slice(), NODE_KEY, &[level], &x.to_be_bytes(), &y.to_be_bytes()].concat()
+    }
+
+    fn point_key(&self, x: u32, y: u32, idx: u32) -> Vec<u8> {
+        [self.as_slice(), POINT_KEY, &x.to_be_bytes(), &y.to_be_bytes(), &idx.to_be_bytes()].concat()
+    }
+
+    fn get_node_count(&self, storage: &dyn Storage, level: u8, x: u32, y: u32) -> StdResult<u32> {
+        match storage.get(&self.node_key(level, x, y)) {
+            Some(count_vec) => {
+                let count_bytes = count_vec
+                    .as_slice()
+                    .try_into()
+                    .map_err(|err| StdError::parse_err("u32", err))?;
+                Ok(u32::from_be_bytes(count_bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the indexed region
+    pub fn get_region(&self) -> FixedBBox2D {
+        FixedBBox2D::new(
+            self.origin,
+            FixedPoint2D { x: self.origin.x + self.size, y: self.origin.y + self.size },
+        )
+    }
+
+    /// Returns the total number of indexed points
+    pub fn len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.get_node_count(storage, 0, 0, 0)
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.len(storage)? == 0)
+    }
+
+    /// Bounding box of a cell at `level`
+    fn cell_bbox(&self, level: u8, x: u32, y: u32) -> FixedBBox2D {
+        let cell_size = self.size / (1i64 << level);
+        let lower_left = FixedPoint2D {
+            x: self.origin.x + cell_size * I32F32::from_num(x),
+            y: self.origin.y + cell_size * I32F32::from_num(y),
+        };
+        let upper_right = FixedPoint2D {
+            x: lower_left.x + cell_size,
+            y: lower_left.y + cell_size,
+        };
+        FixedBBox2D::new(lower_left, upper_right)
+    }
+
+    /// Leaf cell of a point in the region. Points on the upper edges go in the last cell.
+    fn leaf_cell(&self, point: &FixedPoint2D) -> (u32, u32) {
+        let cells = 1u32 << self.depth;
+        let cell_size = self.size / i64::from(cells);
+        let index = |offset: I32F32| -> u32 {
+            (offset / cell_size).to_num::<u32>().min(cells - 1)
+        };
+        (index(point.x - self.origin.x), index(point.y - self.origin.y))
+    }
+
+    /// Adds a point, which must lie inside the region
+    pub fn insert(&self, storage: &mut dyn Storage, point: FixedPoint2D) -> StdResult<()> {
+        if !self.get_region().contains(&point) {
+            return Err(StdError::generic_err("Point is outside the indexed region"));
+        }
+        let (x, y) = self.leaf_cell(&point);
+
+        // the leaf count is the index of the new point in its cell
+        let leaf_count = self.get_node_count(storage, self.depth, x, y)?;
+        storage.set(
+            &self.point_key(x, y, leaf_count),
+            &[point.x.to_stored_bytes(), point.y.to_stored_bytes()].concat(),
+        );
+
+        for level in 0..=self.depth {
+            let shift = self.depth - level;
+            let (node_x, node_y) = (x >> shift, y >> shift);
+            let count = self.get_node_count(storage, level, node_x, node_y)?.checked_add(1).ok_or(
+                StdError::generic_err("Count overflow")
+            )?;
+            storage.set(&self.node_key(level, node_x, node_y), &count.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Returns the points in a leaf cell
+    fn leaf_points(&self, storage: &dyn Storage, x: u32, y: u32) -> StdResult<Vec<FixedPoint2D>> {
+        let count = self.get_node_count(storage, self.depth, x, y)?;
+        (0..count)
+            .map(|idx| {
+                let bytes = storage.get(&self.point_key(x, y, idx)).ok_or(
+                    StdError::generic_err("Indexed point not found")
+                )?;
+                let (x_bytes, y_bytes) = bytes.split_at(bytes.len() / 2);
+                Ok(FixedPoint2D {
+                    x: I32F32::from_stored_bytes(x_bytes)?,
+                    y: I32F32::from_stored_bytes(y_bytes)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Exact number of indexed points inside `polygon`. Whole cells inside the polygon are
+    /// counted from their node counts, and only the points of leaf cells that the polygon
+    /// boundary passes through are tested one by one.
+    pub fn count_in_polygon(&self, storage: &dyn Storage, polygon: &FixedPolygon2D) -> StdResult<u32> {
+        let mut total: u32 = 0;
+        let mut stack: Vec<(u8, u32, u32)> = vec![(0, 0, 0)];
+        while let Some((level, x, y)) = stack.pop() {
+            let count = self.get_node_count(storage, level, x, y)?;
+            if count == 0 {
+                continue;
+            }
+            match cell_overlap(polygon, &self.cell_bbox(level, x, y)) {
+                CellOverlap::Outside => {}
+                CellOverlap::Inside => total += count,
+                CellOverlap::Partial if level == self.depth => {
+                    let inside = self.leaf_points(storage, x, y)?
+                        .iter()
+                        .filter(|point| polygon.contains(point))
+                        .count();
+                    // a leaf holds at most u32::MAX points
+                    total += inside as u32;
+                }
+                CellOverlap::Partial => {
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        stack.push((level + 1, 2 * x + dx, 2 * y + dy));
+                    }
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Returns the number of indexed points inside `polygon` with Laplace noise with scale
+    /// 1 / epsilon. Adding or removing one point changes the count by 1, so this is
+    /// epsilon-differentially private. Epsilon is charged to `accountant`, and nothing is
+    /// released if its budget does not cover it.
+    pub fn fuzzy_count_in_polygon(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        accountant: &PrivacyAccountant,
+        polygon: &FixedPolygon2D,
+        epsilon: I32F32,
+    ) -> StdResult<I32F32> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        let count = self.count_in_polygon(storage, polygon)?;
+        accountant.charge(storage, epsilon)?;
+        // sensitivity is 1 for COUNT queries
+        let noise = laplace(rng, I32F32::from_num(1) / epsilon)?;
+        Ok(I32F32::from_num(count) + noise)
+    }
+
+    /// Returns the average position of the indexed points with Laplace noise, at a privacy cost
+    /// of epsilon. The count and the sum of each coordinate are noised separately, with a third
+    /// of epsilon each. Adding or removing a point changes the count by 1 and each coordinate
+    /// sum by at most the region size, since coordinates are summed as offsets from the origin,
+    /// so those are the sensitivities. The result is clamped to the region.
+    ///
+    /// Returns `Release::Refused` if the noisy count is below 1, since the average would be
+    /// mostly noise. Epsilon is charged to `accountant` before any noise is drawn, even if the
+    /// noisy count then falls short, and nothing is released if its budget does not cover it.
+    pub fn fuzzy_centroid(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        accountant: &PrivacyAccountant,
+        epsilon: I32F32,
+    ) -> StdResult<Release<FixedPoint2D>> {
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        let points = self.all_points(storage)?;
+        let mut sum_x = I32F32::from_num(0);
+        let mut sum_y = I32F32::from_num(0);
+        for point in &points {
+            sum_x = sum_x.checked_add(point.x - self.origin.x).ok_or(StdError::generic_err("Coordinate sum overflow"))?;
+            sum_y = sum_y.checked_add(point.y - self.origin.y).ok_or(StdError::generic_err("Coordinate sum overflow"))?;
+        }
+        accountant.charge(storage, epsilon)?;
+
+        let part_epsilon = epsilon / 3;
+        let noisy_count = I32F32::from_num(points.len()) + laplace(rng, I32F32::from_num(1) / part_epsilon)?;
+        let noisy_x = sum_x.saturating_add(laplace(rng, self.size / part_epsilon)?);
+        let noisy_y = sum_y.saturating_add(laplace(rng, self.size / part_epsilon)?);
+        if noisy_count < 1 {
+            return Ok(Release::Refused);
+        }
+        let region = self.get_region();
+        Ok(Release::Released(FixedPoint2D {
+            x: self.origin.x.saturating_add(noisy_x / noisy_count).clamp(region.lower_left().x, region.upper_right().x),
+            y: self.origin.y.saturating_add(noisy_y / noisy_count).clamp(region.lower_left().y, region.upper_right().y),
+        }))
+    }
+
+    /// Returns every indexed point, skipping empty subtrees
+    fn all_points(&self, storage: &dyn Storage) -> StdResult<Vec<FixedPoint2D>> {
+        let mut points: Vec<FixedPoint2D> = vec![];
+        let mut stack: Vec<(u8, u32, u32)> = vec![(0, 0, 0)];
+        while let Some((level, x, y)) = stack.pop() {
+            if self.get_node_count(storage, level, x, y)? == 0 {
+                continue;
+            }
+            if level == self.depth {
+                points.extend(self.leaf_points(storage, x, y)?);
+            } else {
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    stack.push((level + 1, 2 * x + dx, 2 * y + dy));
+                }
+            }
+        }
+        Ok(points)
+    }
+
+    /// Removes every indexed point and node count, so the index no longer takes up any storage
+    pub fn clear(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let mut stack: Vec<(u8, u32, u32)> = vec![(0, 0, 0)];
+        while let Some((level, x, y)) = stack.pop() {
+            let count = self.get_node_count(storage, level, x, y)?;
+            if count == 0 {
+                continue;
+            }
+            if level == self.depth {
+                for idx in 0..count {
+                    storage.remove(&self.point_key(x, y, idx));
+                }
+            } else {
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    stack.push((level + 1, 2 * x + dx, 2 * y + dy));
+                }
+            }
+            storage.remove(&self.node_key(level, x, y));
+        }
+        Ok(())
+    }
+
+    /// Bulk loads every point of `old` into this index and then clears `old`, returning the
+    /// number of points moved. The quadtree cells are fixed by the region and depth, so the
+    /// index never becomes unbalanced and leaves no deleted entries behind; rebuilding is only
+    /// needed to change the region, the depth or the namespace, e.g. when leaf cells have
+    /// filled up and a deeper index would read fewer points per query.
+    ///
+    /// `old` may use the same namespace as this index. Reads and rewrites every point, so the
+    /// gas cost grows with the size of the index.
+    pub fn rebuild_from(&self, storage: &mut dyn Storage, old: &GeoIndex) -> StdResult<u32> {
+        let points = old.all_points(storage)?;
+        let region = self.get_region();
+        if points.iter().any(|point| !region.contains(point)) {
+            return Err(StdError::generic_err("Point is outside the indexed region"));
+        }
+        old.clear(storage)?;
+        for point in &points {
+            self.insert(storage, *point)?;
+        }
+        u32::try_from(points.len()).map_err(|_| StdError::generic_err("Count overflow"))
+    }
+
+    /// Releases `k` cluster centroids of the indexed points, computed with `iterations` rounds
+    /// of differentially private Lloyd's algorithm, at a total privacy cost of epsilon.
+    ///
+    /// The centroids start at random positions in the region. In every round each point is
+    /// assigned to its nearest centroid, and each centroid moves to the noisy sum of its points'
+    /// coordinates divided by their noisy count. Epsilon is split evenly across rounds, and in
+    /// each round across the count and the two coordinate sums. A point changes a coordinate sum
+    /// by at most the region size, so that is the sensitivity of the sums. A cluster whose noisy
+    /// count is below 1 keeps its previous centroid. Sums that would overflow saturate, which
+    /// still changes them by at most the region size per point. Epsilon is charged to
+    /// `accountant` before any noise is drawn, and nothing is released if its budget does not
+    /// cover it.
+    ///
+    /// Every round reads all indexed points, so the gas used grows with the number of points
+    /// times `k` times `iterations`.
+    pub fn dp_kmeans(
+        &self,
+        storage: &mut dyn Storage,
+        rng: &mut ChaChaRng,
+        accountant: &PrivacyAccountant,
+        k: u32,
+        iterations: u32,
+        epsilon: I32F32,
+    ) -> StdResult<Vec<FixedPoint2D>> {
+        if k == 0 || iterations == 0 {
+            return Err(StdError::generic_err("k and iterations must be greater than zero"));
+        }
+        if epsilon <= I32F32::from_num(0) {
+            return Err(StdError::generic_err("Epsilon must be greater than zero"));
+        }
+        let points = self.all_points(storage)?;
+        accountant.charge(storage, epsilon)?;
+
+        // the starting positions do not depend on the data
+        let mut centroids: Vec<FixedPoint2D> = (0..k)
+            .map(|_| FixedPoint2D {
+                x: self.origin.x + self.size * random_unit_interval(rng),
+                y: self.origin.y + self.size * random_unit_interval(rng),
+            })
+            .collect();
+
+        let round_epsilon = epsilon / i64::from(iterations) / 3;
+        let count_scale = I32F32::from_num(1) / round_epsilon;
+        let sum_scale = self.size / round_epsilon;
+        let region = self.get_region();
+        for _ in 0..iterations {
+            // coordinates are summed as offsets from the origin, so each is in [0, size]
+            let mut counts: Vec<u32> = vec![0; centroids.len()];
+            let mut sums: Vec<(I32F32, I32F32)> = vec![(I32F32::from_num(0), I32F32::from_num(0)); centroids.len()];
+            for point in &points {
+                let nearest = centroids
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, centroid)| (*point - **centroid).len_squared())
+                    .map(|(idx, _)| idx)
+                    .unwrap_or(0);
+                counts[nearest] += 1;
+                sums[nearest].0 = sums[nearest].0.saturating_add(point.x - self.origin.x);
+                sums[nearest].1 = sums[nearest].1.saturating_add(point.y - self.origin.y);
+            }
+
+            for (idx, centroid) in centroids.iter_mut().enumerate() {
+                // noise is drawn for every cluster, empty or not
+                let noisy_count = I32F32::from_num(counts[idx]) + laplace(rng, count_scale)?;
+                let noisy_x = sums[idx].0.saturating_add(laplace(rng, sum_scale)?);
+                let noisy_y = sums[idx].1.saturating_add(laplace(rng, sum_scale)?);
+                if noisy_count < 1 {
+                    continue;
+                }
+                *centroid = FixedPoint2D {
+                    x: self.origin.x.saturating_add(noisy_x / noisy_count)
+                        .clamp(region.lower_left().x, region.upper_right().x),
+                    y: self.origin.y.saturating_add(noisy_y / noisy_count)
+                        .clamp(region.lower_left().y, region.upper_right().y),
+                };
+            }
+        }
+        Ok(centroids)
+    }
+}
+
+/// true if the segment from `a` to `b` touches the closed box
+fn segment_touches_box(a: FixedPoint2D, b: FixedPoint2D, bbox: &FixedBBox2D) -> bool {
+    if bbox.contains(&a) || bbox.contains(&b) {
+        return true;
+    }
+    let lower_left = bbox.lower_left();
+    let upper_right = bbox.upper_right();
+    let lower_right = FixedPoint2D { x: upper_right.x, y: lower_left.y };
+    let upper_left = FixedPoint2D { x: lower_left.x, y: upper_right.y };
+    let segment = FixedLineSegment2D { endpoints: (a, b) };
+    [
+        (lower_left, lower_right),
+        (lower_right, upper_right),
+        (upper_right, upper_left),
+        (upper_left, lower_left),
+    ]
+    .iter()
+    .any(|side| segment.meets(&FixedLineSegment2D { endpoints: *side }))
+}
+
+fn cell_overlap(polygon: &FixedPolygon2D, cell: &FixedBBox2D) -> CellOverlap {
+    if !polygon.bbox().overlaps(cell) {
+        return CellOverlap::Outside;
+    }
+    let boundary_touches_cell = polygon
+        .vertices()
+        .windows(2)
+        .any(|edge| segment_touches_box(edge[0], edge[1], cell));
+    if boundary_touches_cell {
+        return CellOverlap::Partial;
+    }
+    // the boundary does not reach the cell, so the cell is entirely on one side of it
+    let center = FixedPoint2D {
+        x: cell.lower_left().x + (cell.upper_right().x - cell.lower_left().x) / 2,
+        y: cell.lower_left().y + (cell.upper_right().y - cell.lower_left().y) / 2,
+    };
+    if polygon.contains(&center) {
+        CellOverlap::Inside
+    } else {
+        CellOverlap::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use rand_chacha::rand_core::SeedableRng;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn rebuild_moves_points_to_deeper_index() {
+        let mut storage = MockStorage::new();
+        let shallow = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(16), 1);
+        let deep = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(16), 3);
+        for (x, y) in [(1, 1), (2, 3), (9, 9), (15, 2)] {
+            shallow.insert(&mut storage, point(x, y)).unwrap();
+        }
+
+        assert_eq!(deep.rebuild_from(&mut storage, &shallow).unwrap(), 4);
+        let square = FixedPolygon2D::try_new(vec![
+            point(0, 0), point(4, 0), point(4, 4), point(0, 4), point(0, 0),
+        ]).unwrap();
+        assert_eq!(deep.count_in_polygon(&storage, &square).unwrap(), 2);
+        assert_eq!(deep.len(&storage).unwrap(), 4);
+
+        deep.clear(&mut storage).unwrap();
+        assert!(deep.is_empty(&storage).unwrap());
+        assert!(storage.get(&deep.point_key(0, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn fuzzy_count_is_charged_to_the_accountant() {
+        let mut storage = MockStorage::new();
+        let index = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(16), 2);
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(1));
+        for (x, y) in [(1, 1), (2, 3), (9, 9)] {
+            index.insert(&mut storage, point(x, y)).unwrap();
+        }
+        let square = FixedPolygon2D::try_new(vec![
+            point(0, 0), point(4, 0), point(4, 4), point(0, 4), point(0, 0),
+        ]).unwrap();
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        let epsilon = I32F32::from_num(0.75);
+        index.fuzzy_count_in_polygon(&mut storage, &mut rng, &accountant, &square, epsilon).unwrap();
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0.25));
+
+        // refused without charging once the budget runs out
+        assert!(index.fuzzy_count_in_polygon(&mut storage, &mut rng, &accountant, &square, epsilon).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0.25));
+    }
+
+    #[test]
+    fn fuzzy_centroid_is_charged_to_the_accountant() {
+        let mut storage = MockStorage::new();
+        let index = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(16), 2);
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(3));
+        for _ in 0..200 {
+            index.insert(&mut storage, point(4, 12)).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        let centroid = index.fuzzy_centroid(&mut storage, &mut rng, &accountant, I32F32::from_num(2)).unwrap().released().unwrap();
+        assert!((centroid.x - I32F32::from_num(4)).abs() < 2);
+        assert!((centroid.y - I32F32::from_num(12)).abs() < 2);
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+
+        assert!(index.fuzzy_centroid(&mut storage, &mut rng, &accountant, I32F32::from_num(2)).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+    }
+
+    #[test]
+    fn empty_centroid_is_refused_but_charged() {
+        let mut storage = MockStorage::new();
+        let index = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(16), 2);
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(40));
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        // the noisy count of an empty index has scale 0.1, so it stays below 1; the refusal is
+        // not an error, so the charge commits
+        let centroid = index.fuzzy_centroid(&mut storage, &mut rng, &accountant, I32F32::from_num(30)).unwrap();
+        assert_eq!(centroid, Release::Refused);
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(10));
+    }
+
+    #[test]
+    fn kmeans_is_charged_and_saturates_large_sums() {
+        let mut storage = MockStorage::new();
+        // offsets of up to a million per point overflow an I32F32 sum after a few thousand points
+        let index = GeoIndex::new(b"geo", point(0, 0), I32F32::from_num(1_000_000), 1);
+        let accountant = PrivacyAccountant::new(b"budget");
+        accountant.set_privacy_budget(&mut storage, I32F32::from_num(2));
+        for _ in 0..3000 {
+            index.insert(&mut storage, point(999_000, 999_000)).unwrap();
+        }
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+        let centroids = index.dp_kmeans(&mut storage, &mut rng, &accountant, 2, 2, I32F32::from_num(1)).unwrap();
+        assert_eq!(centroids.len(), 2);
+        let region = index.get_region();
+        assert!(centroids.iter().all(|centroid| region.contains(centroid)));
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(1));
+
+        index.dp_kmeans(&mut storage, &mut rng, &accountant, 2, 2, I32F32::from_num(1)).unwrap();
+        assert!(index.dp_kmeans(&mut storage, &mut rng, &accountant, 2, 2, I32F32::from_num(1)).is_err());
+        assert_eq!(accountant.get_privacy_budget(&storage).unwrap(), I32F32::from_num(0));
+    }
+}
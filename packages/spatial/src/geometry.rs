@@ -0,0 +1,163 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use cosmwasm_std::StdResult;
+#[cfg(feature = "std")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "std")]
+use schemars::JsonSchema;
+use substrate_fixed::types::I32F32;
+#[cfg(feature = "std")]
+use secret_data_tools_fixed::FixedBytes;
+
+use crate::fixed_geom::on_segment;
+use crate::{FixedBBox2D, FixedLineSegment2D, FixedPoint2D, FixedPolygon2D, MultiPolygon};
+#[cfg(feature = "std")]
+use crate::{StoredFixedBBox2D, StoredFixedLineSegment2D, StoredFixedPoint2D, StoredFixedPolygon2D, StoredMultiPolygon};
+
+/// Any of the fixed-point geometry types, so that stores and messages can hold different kinds
+/// of shapes in one field
+#[derive(Clone, Debug, PartialEq)]
+pub enum FixedGeometry {
+    Point(FixedPoint2D),
+    Segment(FixedLineSegment2D),
+    /// open path through the points, in order
+    Polyline(Vec<FixedPoint2D>),
+    Polygon(FixedPolygon2D),
+    MultiPolygon(MultiPolygon),
+    Circle { center: FixedPoint2D, radius: I32F32 },
+    BBox(FixedBBox2D),
+}
+
+impl FixedGeometry {
+    /// Smallest box around the geometry, or None for an empty polyline or multipolygon
+    pub fn bbox(&self) -> Option<FixedBBox2D> {
+        match self {
+            FixedGeometry::Point(point) => Some(FixedBBox2D::new(*point, *point)),
+            FixedGeometry::Segment(segment) => Some(segment.bbox()),
+            FixedGeometry::Polyline(points) => FixedBBox2D::from_points(points),
+            FixedGeometry::Polygon(polygon) => Some(polygon.bbox().clone()),
+            FixedGeometry::MultiPolygon(multi_polygon) => multi_polygon.bbox(),
+            FixedGeometry::Circle { center, radius } => Some(FixedBBox2D::new(
+                FixedPoint2D { x: center.x - *radius, y: center.y - *radius },
+                FixedPoint2D { x: center.x + *radius, y: center.y + *radius },
+            )),
+            FixedGeometry::BBox(bbox) => Some(bbox.clone()),
+        }
+    }
+
+    /// true if `point` is part of the geometry: equal to a point, on a segment or polyline, or
+    /// inside an area. Points on the boundary of a circle or box are inside; for polygons it
+    /// follows `FixedPolygon2D::contains`.
+    pub fn contains(&self, point: &FixedPoint2D) -> bool {
+        match self {
+            FixedGeometry::Point(geometry_point) => geometry_point == point,
+            FixedGeometry::Segment(segment) => on_segment(&segment.endpoints.0, &segment.endpoints.1, point),
+            FixedGeometry::Polyline(points) => {
+                points.windows(2).any(|edge| on_segment(&edge[0], &edge[1], point))
+                    || (points.len() == 1 && points[0] == *point)
+            }
+            FixedGeometry::Polygon(polygon) => polygon.contains(point),
+            FixedGeometry::MultiPolygon(multi_polygon) => multi_polygon.contains(point),
+            FixedGeometry::Circle { center, radius } => {
+                (*point - *center).len_squared() <= *radius * *radius
+            }
+            FixedGeometry::BBox(bbox) => bbox.contains(point),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn into_stored(&self) -> StoredFixedGeometry {
+        match self {
+            FixedGeometry::Point(point) => StoredFixedGeometry::Point(point.into_stored()),
+            FixedGeometry::Segment(segment) => StoredFixedGeometry::Segment(segment.into_stored()),
+            FixedGeometry::Polyline(points) => {
+                StoredFixedGeometry::Polyline(points.iter().map(|point| point.into_stored()).collect())
+            }
+            FixedGeometry::Polygon(polygon) => StoredFixedGeometry::Polygon(polygon.into_stored()),
+            FixedGeometry::MultiPolygon(multi_polygon) => {
+                StoredFixedGeometry::MultiPolygon(multi_polygon.into_stored())
+            }
+            FixedGeometry::Circle { center, radius } => StoredFixedGeometry::Circle {
+                center: center.into_stored(),
+                radius: radius.to_stored_bytes(),
+            },
+            FixedGeometry::BBox(bbox) => StoredFixedGeometry::BBox(bbox.into_stored()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StoredFixedGeometry {
+    Point(StoredFixedPoint2D),
+    Segment(StoredFixedLineSegment2D),
+    Polyline(Vec<StoredFixedPoint2D>),
+    Polygon(StoredFixedPolygon2D),
+    MultiPolygon(StoredMultiPolygon),
+    Circle { center: StoredFixedPoint2D, radius: Vec<u8> },
+    BBox(StoredFixedBBox2D),
+}
+
+#[cfg(feature = "std")]
+impl StoredFixedGeometry {
+    pub fn into_humanized(&self) -> StdResult<FixedGeometry> {
+        Ok(match self {
+            StoredFixedGeometry::Point(point) => FixedGeometry::Point(point.into_humanized()?),
+            StoredFixedGeometry::Segment(segment) => FixedGeometry::Segment(segment.into_humanized()?),
+            StoredFixedGeometry::Polyline(points) => FixedGeometry::Polyline(
+                points
+                    .iter()
+                    .map(|point| point.into_humanized())
+                    .collect::<StdResult<Vec<FixedPoint2D>>>()?,
+            ),
+            StoredFixedGeometry::Polygon(polygon) => FixedGeometry::Polygon(polygon.into_humanized()?),
+            StoredFixedGeometry::MultiPolygon(multi_polygon) => {
+                FixedGeometry::MultiPolygon(multi_polygon.into_humanized()?)
+            }
+            StoredFixedGeometry::Circle { center, radius } => FixedGeometry::Circle {
+                center: center.into_humanized()?,
+                radius: I32F32::from_stored_bytes(radius)?,
+            },
+            StoredFixedGeometry::BBox(bbox) => FixedGeometry::BBox(bbox.into_humanized()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn bbox_and_contains_per_kind() {
+        let circle = FixedGeometry::Circle { center: point(1, 1), radius: I32F32::from_num(2) };
+        assert_eq!(circle.bbox(), Some(FixedBBox2D::new(point(-1, -1), point(3, 3))));
+        assert!(circle.contains(&point(3, 1)));
+        assert!(!circle.contains(&point(3, 3)));
+
+        let polyline = FixedGeometry::Polyline(vec![point(0, 0), point(2, 0), point(2, 4)]);
+        assert_eq!(polyline.bbox(), Some(FixedBBox2D::new(point(0, 0), point(2, 4))));
+        assert!(polyline.contains(&point(1, 0)));
+        assert!(polyline.contains(&point(2, 3)));
+        assert!(!polyline.contains(&point(1, 1)));
+        assert_eq!(FixedGeometry::Polyline(vec![]).bbox(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stored_geometry_round_trips() {
+        let geometries = [
+            FixedGeometry::Point(point(1, 2)),
+            FixedGeometry::Polyline(vec![point(0, 0), point(2, 0)]),
+            FixedGeometry::Circle { center: point(1, 1), radius: I32F32::from_num(2) },
+            FixedGeometry::BBox(FixedBBox2D::new(point(0, 0), point(1, 1))),
+        ];
+        for geometry in geometries {
+            assert_eq!(geometry.into_stored().into_humanized().unwrap(), geometry);
+        }
+    }
+}
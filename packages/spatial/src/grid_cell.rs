@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedBBox2D, FixedPoint2D};
+
+/// Largest number of cells returned by `cells_overlapping_bbox` and `GridCell::neighbors`
+pub const MAX_GRID_CELLS: u64 = 65_536;
+
+/// A cell of a square grid with its origin at (0, 0): cell (x, y) covers
+/// [x * cell_size, (x + 1) * cell_size) on the x axis, and likewise on the y axis
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GridCell {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl GridCell {
+    pub const fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
+    }
+
+    /// The cell that contains `point`
+    pub fn containing(point: &FixedPoint2D, cell_size: I32F32) -> Result<Self, &'static str> {
+        Ok(Self { x: cell_index(point.x, cell_size)?, y: cell_index(point.y, cell_size)? })
+    }
+
+    /// Bounding box of the cell
+    pub fn bbox(&self, cell_size: I32F32) -> FixedBBox2D {
+        let lower_left = FixedPoint2D {
+            x: cell_size * I32F32::from_num(self.x),
+            y: cell_size * I32F32::from_num(self.y),
+        };
+        let upper_right = FixedPoint2D { x: lower_left.x + cell_size, y: lower_left.y + cell_size };
+        FixedBBox2D::new(lower_left, upper_right)
+    }
+
+    /// The cells at most `radius` cells away in both x and y, not including this cell, ordered
+    /// by row from the bottom and then from left to right. A radius of 1 gives the 8 cells
+    /// around this one, and a radius of 0 gives none. Returns an error if there would be more
+    /// than `MAX_GRID_CELLS` cells, or if they would go past the range of the cell indices.
+    pub fn neighbors(&self, radius: u32) -> Result<Vec<GridCell>, &'static str> {
+        let side = u64::from(radius)
+            .checked_mul(2)
+            .and_then(|diameter| diameter.checked_add(1))
+            .ok_or("Too many neighbor cells")?;
+        let count = side.checked_mul(side).ok_or("Too many neighbor cells")?;
+        if count - 1 > MAX_GRID_CELLS {
+            return Err("Too many neighbor cells");
+        }
+        let radius = i64::from(radius);
+        let (min_x, max_x) = self.x.checked_sub(radius).zip(self.x.checked_add(radius)).ok_or("Cell index overflow")?;
+        let (min_y, max_y) = self.y.checked_sub(radius).zip(self.y.checked_add(radius)).ok_or("Cell index overflow")?;
+
+        let mut cells = Vec::with_capacity((count - 1) as usize);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if (x, y) != (self.x, self.y) {
+                    cells.push(GridCell { x, y });
+                }
+            }
+        }
+        Ok(cells)
+    }
+}
+
+fn cell_index(coordinate: I32F32, cell_size: I32F32) -> Result<i64, &'static str> {
+    if cell_size <= 0 {
+        return Err("Cell size must be greater than zero");
+    }
+    let index = coordinate.checked_div(cell_size).ok_or("Cell index overflow")?;
+    Ok(index.floor().to_num::<i64>())
+}
+
+/// The grid cells with side `cell_size` that overlap the closed box `bbox`, ordered by row from
+/// the bottom and then from left to right. A box edge that lies exactly on a cell boundary
+/// touches the cell on the other side, so that cell is included too. Returns an error if more
+/// than `MAX_GRID_CELLS` cells overlap the box.
+pub fn cells_overlapping_bbox(bbox: &FixedBBox2D, cell_size: I32F32) -> Result<Vec<GridCell>, &'static str> {
+    let lower_left = GridCell::containing(&bbox.lower_left(), cell_size)?;
+    let upper_right = GridCell::containing(&bbox.upper_right(), cell_size)?;
+    let columns = (upper_right.x - lower_left.x + 1) as u64;
+    let rows = (upper_right.y - lower_left.y + 1) as u64;
+    if columns * rows > MAX_GRID_CELLS {
+        return Err("Too many grid cells overlap the box");
+    }
+
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
+    for y in lower_left.y..=upper_right.y {
+        for x in lower_left.x..=upper_right.x {
+            cells.push(GridCell { x, y });
+        }
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn neighbors_surround_the_cell() {
+        let cell = GridCell::new(0, -1);
+        assert!(cell.neighbors(0).unwrap().is_empty());
+        let ring = cell.neighbors(1).unwrap();
+        assert_eq!(ring.len(), 8);
+        assert_eq!(ring[0], GridCell::new(-1, -2));
+        assert_eq!(ring[7], GridCell::new(1, 0));
+        assert!(!ring.contains(&cell));
+        assert_eq!(cell.neighbors(2).unwrap().len(), 24);
+    }
+
+    #[test]
+    fn neighbors_are_capped() {
+        let cell = GridCell::new(0, 0);
+        // a radius of 128 gives 257 * 257 - 1 cells, just over the limit
+        assert_eq!(cell.neighbors(127).unwrap().len(), 255 * 255 - 1);
+        assert!(cell.neighbors(128).is_err());
+        assert!(cell.neighbors(u32::MAX).is_err());
+        assert!(GridCell::new(i64::MAX, 0).neighbors(1).is_err());
+    }
+
+    #[test]
+    fn cells_cover_the_box() {
+        let size = I32F32::from_num(2);
+        assert_eq!(GridCell::containing(&point(-0.5, 3.0), size).unwrap(), GridCell::new(-1, 1));
+
+        let bbox = FixedBBox2D::new(point(-1.0, 0.5), point(2.0, 1.0));
+        let cells = cells_overlapping_bbox(&bbox, size).unwrap();
+        assert_eq!(cells, vec![GridCell::new(-1, 0), GridCell::new(0, 0), GridCell::new(1, 0)]);
+        for cell in &cells {
+            assert!(cell.bbox(size).overlaps(&bbox));
+        }
+
+        assert!(cells_overlapping_bbox(&bbox, I32F32::from_num(0)).is_err());
+        let huge = FixedBBox2D::new(point(0.0, 0.0), point(1000.0, 1000.0));
+        assert!(cells_overlapping_bbox(&huge, I32F32::from_num(1)).is_err());
+    }
+}
@@ -1,4 +1,6 @@
-use std::{ops, cmp::Ordering};
+use core::{ops, cmp::Ordering};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use cosmwasm_std::{StdResult, StdError};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -99,9 +101,15 @@ pub struct IntegerLineSegment2D {
 }
 
 impl IntegerLineSegment2D {
+    #[cfg(feature = "std")]
     pub fn new(endpoint1: IntegerPoint2D, endpoint2: IntegerPoint2D) -> StdResult<IntegerLineSegment2D> {
+        Self::try_new(endpoint1, endpoint2).map_err(StdError::generic_err)
+    }
+
+    /// Same as `new`, with a plain error message so that it is available without `std`
+    pub fn try_new(endpoint1: IntegerPoint2D, endpoint2: IntegerPoint2D) -> Result<IntegerLineSegment2D, &'static str> {
         if endpoint1 == endpoint2 {
-            return Err(StdError::generic_err("Invalid: endpoints cannot be the same"));
+            return Err("Invalid: endpoints cannot be the same");
         }
         Ok(Self {
             endpoints: (endpoint1, endpoint2)
@@ -114,6 +122,17 @@ impl IntegerLineSegment2D {
         (is_counterclockwise(&other.endpoints.0, &self.endpoints.0, &other.endpoints.1) != 
          is_counterclockwise(&other.endpoints.0, &self.endpoints.1, &other.endpoints.1))
     }
+
+    /// true if each segment has its endpoints on different sides of the line through the other,
+    /// where an endpoint on the line counts as a side of its own. This finds every crossing and
+    /// every endpoint that touches the other segment, but not colinear overlaps. `contains`
+    /// uses it for the ray casting test.
+    pub fn crosses(&self, other: &IntegerLineSegment2D) -> bool {
+        (is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.0) != 
+         is_counterclockwise(&self.endpoints.0, &self.endpoints.1, &other.endpoints.1)) && 
+        (is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.0) != 
+         is_counterclockwise(&other.endpoints.0, &other.endpoints.1, &self.endpoints.1))
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -123,6 +142,75 @@ pub struct IntegerBBox {
 }
 
 impl IntegerBBox {
+    /// box with the given corners; `lower_left` must not be above or right of `upper_right`
+    pub const fn new(lower_left: IntegerPoint2D, upper_right: IntegerPoint2D) -> Self {
+        Self { lower_left, upper_right }
+    }
+
+    /// Smallest box around all of `points`, or None if there are none
+    pub fn from_points(points: &[IntegerPoint2D]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(points.iter().fold(Self::new(first, first), |bbox, point| {
+            bbox.union(&Self::new(*point, *point))
+        }))
+    }
+
+    pub fn lower_left(&self) -> IntegerPoint2D {
+        self.lower_left
+    }
+
+    pub fn upper_right(&self) -> IntegerPoint2D {
+        self.upper_right
+    }
+
+    pub fn width(&self) -> i64 {
+        self.upper_right.x - self.lower_left.x
+    }
+
+    pub fn height(&self) -> i64 {
+        self.upper_right.y - self.lower_left.y
+    }
+
+    /// Smallest box around both boxes
+    pub fn union(&self, other: &IntegerBBox) -> IntegerBBox {
+        IntegerBBox {
+            lower_left: IntegerPoint2D {
+                x: self.lower_left.x.min(other.lower_left.x),
+                y: self.lower_left.y.min(other.lower_left.y),
+            },
+            upper_right: IntegerPoint2D {
+                x: self.upper_right.x.max(other.upper_right.x),
+                y: self.upper_right.y.max(other.upper_right.y),
+            },
+        }
+    }
+
+    /// The box shared by both boxes, or None if they do not overlap. Boxes that only touch
+    /// give a box with zero width or height.
+    pub fn intersection(&self, other: &IntegerBBox) -> Option<IntegerBBox> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        Some(IntegerBBox {
+            lower_left: IntegerPoint2D {
+                x: self.lower_left.x.max(other.lower_left.x),
+                y: self.lower_left.y.max(other.lower_left.y),
+            },
+            upper_right: IntegerPoint2D {
+                x: self.upper_right.x.min(other.upper_right.x),
+                y: self.upper_right.y.min(other.upper_right.y),
+            },
+        })
+    }
+
+    /// true if the two boxes share at least one point
+    pub fn overlaps(&self, other: &IntegerBBox) -> bool {
+        self.lower_left.x <= other.upper_right.x &&
+        other.lower_left.x <= self.upper_right.x &&
+        self.lower_left.y <= other.upper_right.y &&
+        other.lower_left.y <= self.upper_right.y
+    }
+
     pub fn contains(&self, point: &IntegerPoint2D) -> bool {
         point.x >= self.lower_left.x &&
         point.x <= self.upper_right.x &&
@@ -139,13 +227,19 @@ pub struct IntegerPolygon2D {
 }
 
 impl IntegerPolygon2D {
+    #[cfg(feature = "std")]
     pub fn new(points: Vec<IntegerPoint2D>) -> StdResult<IntegerPolygon2D> {
+        Self::try_new(points).map_err(StdError::generic_err)
+    }
+
+    /// Same as `new`, with a plain error message so that it is available without `std`
+    pub fn try_new(points: Vec<IntegerPoint2D>) -> Result<IntegerPolygon2D, &'static str> {
         let length = points.len();
         if length < 3 {
-            return Err(StdError::generic_err("Polygon must have at least 3 vertices"));
+            return Err("Polygon must have at least 3 vertices");
         }
         if points[0] != points[length-1] {
-            return Err(StdError::generic_err("First and last point vector must be the same"))
+            return Err("First and last point vector must be the same")
         }
 
         // calculate bounding box and anchor
@@ -177,6 +271,15 @@ impl IntegerPolygon2D {
         self.vertices.len()
     }
 
+    /// Vertices in the order they were given, with the first vertex repeated at the end
+    pub fn vertices(&self) -> &[IntegerPoint2D] {
+        &self.vertices
+    }
+
+    pub fn bbox(&self) -> &IntegerBBox {
+        &self.bbox
+    }
+
     pub fn contains(&self, point: &IntegerPoint2D) -> bool {
         if !self.bbox.contains(point) {
             return false;
@@ -211,7 +314,7 @@ impl IntegerPolygon2D {
                     continue;
                 }
             }
-            if test_segment.intersects(&edge) {
+            if test_segment.crosses(&edge) {
                 intersections += 1;
             }
         }
@@ -232,9 +335,115 @@ impl IntegerPolygon2D {
         }
     }
 
+    /// Twice the signed area, from a fan of triangles around the first vertex
+    fn doubled_signed_area(&self) -> i64 {
+        let first = self.vertices[0];
+        self.vertices
+            .windows(2)
+            .fold(0, |area, edge| area + signed_area(first, edge[0], edge[1]))
+    }
+
+    /// true if the vertices go around the polygon counterclockwise
+    pub fn is_counterclockwise(&self) -> bool {
+        self.doubled_signed_area() > 0
+    }
+
+    /// Returns the same polygon with its vertices in counterclockwise order, reversing them if
+    /// they are clockwise. Unlike `as_counterclockwise_points`, this keeps the shape of
+    /// non-convex polygons, e.g. `IntegerPolygon2D::new(points)?.normalize_orientation()`.
+    pub fn normalize_orientation(mut self) -> Self {
+        if self.doubled_signed_area() < 0 {
+            self.vertices.reverse();
+        }
+        self
+    }
+
+    /// Sorts the vertices by angle around the lowest vertex. This is only correct for convex
+    /// polygons; use `normalize_orientation` to orient any simple polygon.
     pub fn as_counterclockwise_points(&self) -> Vec<IntegerPoint2D> {
         let mut points = self.vertices.clone();
         points.sort_unstable_by(|a, b| IntegerPolygon2D::ccw_cmp(&self.anchor, a, b));
         points
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::{integer_point, DEFAULT_INTEGER_EXTENT};
+    use proptest::prelude::*;
+
+    fn rotate(point: &IntegerPoint2D) -> IntegerPoint2D {
+        IntegerPoint2D { x: -point.y, y: point.x }
+    }
+
+    fn on_boundary(polygon: &IntegerPolygon2D, point: &IntegerPoint2D) -> bool {
+        polygon.vertices.windows(2).any(|edge| {
+            signed_area(edge[0], edge[1], *point) == 0
+                && point.x >= edge[0].x.min(edge[1].x)
+                && point.x <= edge[0].x.max(edge[1].x)
+                && point.y >= edge[0].y.min(edge[1].y)
+                && point.y <= edge[0].y.max(edge[1].y)
+        })
+    }
+
+    #[test]
+    fn bbox_union_and_intersection() {
+        let point = |x: i64, y: i64| IntegerPoint2D { x, y };
+        let a = IntegerBBox::new(point(0, 0), point(4, 2));
+        let b = IntegerBBox::from_points(&[point(3, 1), point(6, 5), point(5, -1)]).unwrap();
+        assert_eq!(b, IntegerBBox::new(point(3, -1), point(6, 5)));
+        assert_eq!(a.union(&b), IntegerBBox::new(point(0, -1), point(6, 5)));
+        assert_eq!(a.intersection(&b), Some(IntegerBBox::new(point(3, 0), point(4, 2))));
+        assert_eq!((a.width(), a.height()), (4, 2));
+        assert_eq!(a.intersection(&IntegerBBox::new(point(5, 3), point(6, 4))), None);
+        assert_eq!(IntegerBBox::from_points(&[]), None);
+    }
+
+    proptest! {
+        #[test]
+        fn contains_is_invariant_under_rotation(
+            polygon in any::<IntegerPolygon2D>(),
+            point in integer_point(DEFAULT_INTEGER_EXTENT),
+        ) {
+            prop_assume!(!on_boundary(&polygon, &point));
+            let rotated = IntegerPolygon2D::try_new(polygon.vertices.iter().map(rotate).collect()).unwrap();
+            prop_assert_eq!(polygon.contains(&point), rotated.contains(&rotate(&point)));
+        }
+
+        #[test]
+        fn contains_is_invariant_under_reversal(
+            polygon in any::<IntegerPolygon2D>(),
+            point in integer_point(DEFAULT_INTEGER_EXTENT),
+        ) {
+            prop_assume!(!on_boundary(&polygon, &point));
+            let reversed = IntegerPolygon2D::try_new(polygon.vertices.iter().rev().copied().collect()).unwrap();
+            prop_assert_eq!(polygon.contains(&point), reversed.contains(&point));
+        }
+
+        #[test]
+        fn point_right_of_leftmost_vertex_is_inside(polygon in any::<IntegerPolygon2D>()) {
+            // the generated polygons start at their leftmost vertex, with the lower chain below
+            // it and the upper chain above it, so the wedge between its two edges is inside
+            let first = polygon.vertices[0];
+            let second = polygon.vertices[1];
+            let before_last = polygon.vertices[polygon.len() - 2];
+            prop_assume!(second.y < first.y && before_last.y > first.y);
+            prop_assume!(second.x > first.x + 1 && before_last.x > first.x + 1);
+            let inside = IntegerPoint2D { x: first.x + 1, y: first.y };
+            prop_assume!(!on_boundary(&polygon, &inside));
+            prop_assert!(polygon.contains(&inside));
+        }
+    }
+
+    #[test]
+    fn crosses_finds_crossings_and_touches_but_not_overlaps() {
+        let segment = |x0, y0, x1, y1| IntegerLineSegment2D {
+            endpoints: (IntegerPoint2D { x: x0, y: y0 }, IntegerPoint2D { x: x1, y: y1 }),
+        };
+        assert!(segment(0, 0, 4, 4).crosses(&segment(0, 4, 4, 0)));
+        assert!(segment(0, 0, 4, 0).crosses(&segment(2, 0, 2, 3)));
+        assert!(!segment(0, 0, 4, 0).crosses(&segment(0, 1, 4, 1)));
+        assert!(!segment(0, 0, 4, 0).crosses(&segment(2, 0, 6, 0)));
+    }
+}
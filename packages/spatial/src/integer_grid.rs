@@ -0,0 +1,287 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+
+use crate::IntegerPoint2D;
+
+const ROW_KEY: &[u8] = b"row";
+
+/// Default limit on the number of cells `astar` expands before giving up
+pub const DEFAULT_MAX_EXPANSIONS: u32 = 10_000;
+
+/// Grid of `width` x `height` cells, with corners (0, 0) and (width - 1, height - 1), and a
+/// bitmap of blocked cells in Storage, one key per row. Movement is between the 4 neighbors of
+/// a cell, at a cost of 1 per step.
+pub struct IntegerGrid<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    width: u32,
+    height: u32,
+    /// number of cells `astar` may expand before returning an error, to bound its gas use
+    max_expansions: u32,
+}
+
+impl<'a> IntegerGrid<'a> {
+    /// constructor
+    pub const fn new(namespace: &'a [u8], width: u32, height: u32) -> Self {
+        assert!(width > 0 && height > 0, "Grid must have at least one cell");
+        Self {
+            namespace,
+            prefix: None,
+            width,
+            height,
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+        }
+    }
+
+    /// Sets how many cells `astar` may expand before it gives up
+    pub const fn with_max_expansions(self, max_expansions: u32) -> Self {
+        Self {
+            namespace: self.namespace,
+            prefix: self.prefix,
+            width: self.width,
+            height: self.height,
+            max_expansions,
+        }
+    }
+
+    /// This is used to produce a new IntegerGrid. It can be used when you want to associate
+    /// an IntegerGrid to multiple suffixes and you still want to define the IntegerGrid
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            width: self.width,
+            height: self.height,
+            max_expansions: self.max_expansions,
+        }
+    }
+}
+
+impl<'a> IntegerGrid<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn row_key(&self, y: u32) -> Vec<u8> {
+        [self.as_slice(), ROW_KEY, &y.to_be_bytes()].concat()
+    }
+
+    fn row_len(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    fn get_row(&self, storage: &dyn Storage, y: u32) -> StdResult<Vec<u8>> {
+        match storage.get(&self.row_key(y)) {
+            Some(row) if row.len() == self.row_len() => Ok(row),
+            Some(_) => Err(StdError::generic_err("Obstacle bitmap row has the wrong length")),
+            None => Ok(vec![0; self.row_len()]),
+        }
+    }
+
+    /// Grid coordinates of `point`, if it is on the grid
+    fn cell(&self, point: &IntegerPoint2D) -> Option<(u32, u32)> {
+        let x = u32::try_from(point.x).ok().filter(|x| *x < self.width)?;
+        let y = u32::try_from(point.y).ok().filter(|y| *y < self.height)?;
+        Some((x, y))
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Marks the cell at `point` as blocked or free
+    pub fn set_obstacle(&self, storage: &mut dyn Storage, point: &IntegerPoint2D, blocked: bool) -> StdResult<()> {
+        let (x, y) = self.cell(point).ok_or(StdError::generic_err("Point is outside the grid"))?;
+        let mut row = self.get_row(storage, y)?;
+        let mask = 1u8 << (x % 8);
+        if blocked {
+            row[x as usize / 8] |= mask;
+        } else {
+            row[x as usize / 8] &= !mask;
+        }
+        storage.set(&self.row_key(y), &row);
+        Ok(())
+    }
+
+    /// true if the cell at `point` is blocked. Points outside the grid count as blocked.
+    pub fn is_obstacle(&self, storage: &dyn Storage, point: &IntegerPoint2D) -> StdResult<bool> {
+        match self.cell(point) {
+            Some((x, y)) => Ok(row_bit(&self.get_row(storage, y)?, x)),
+            None => Ok(true),
+        }
+    }
+
+    /// Checks that `path` moves one step at a time between neighboring free cells, and returns
+    /// its cost, i.e. the number of steps.
+    pub fn path_length(&self, storage: &dyn Storage, path: &[IntegerPoint2D]) -> StdResult<u64> {
+        let mut rows = RowCache::default();
+        for point in path {
+            if self.is_blocked_cached(storage, &mut rows, point)? {
+                return Err(StdError::generic_err("Path crosses a blocked cell"));
+            }
+        }
+        for step in path.windows(2) {
+            let diff = step[1] - step[0];
+            if diff.x.abs() + diff.y.abs() != 1 {
+                return Err(StdError::generic_err("Path steps must be between neighboring cells"));
+            }
+        }
+        Ok(path.len().saturating_sub(1) as u64)
+    }
+
+    /// Finds a shortest path from `start` to `goal` with A*, moving between the 4 neighbors of
+    /// a cell. Returns the path, including both ends, or None if the goal cannot be reached
+    /// in at most `max_cost` steps. Returns an error if the search expands more cells than
+    /// the grid's expansion limit, so that the gas used stays bounded.
+    pub fn astar(
+        &self,
+        storage: &dyn Storage,
+        start: IntegerPoint2D,
+        goal: IntegerPoint2D,
+        max_cost: u64,
+    ) -> StdResult<Option<Vec<IntegerPoint2D>>> {
+        let mut rows = RowCache::default();
+        if self.is_blocked_cached(storage, &mut rows, &start)?
+            || self.is_blocked_cached(storage, &mut rows, &goal)?
+        {
+            return Ok(None);
+        }
+
+        let heuristic = |point: &IntegerPoint2D| -> u64 {
+            point.x.abs_diff(goal.x) + point.y.abs_diff(goal.y)
+        };
+        if heuristic(&start) > max_cost {
+            return Ok(None);
+        }
+
+        // best known cost to reach each cell, and the cell it was reached from
+        let mut best: BTreeMap<(i64, i64), (u64, Option<(i64, i64)>)> = BTreeMap::new();
+        let mut open: BinaryHeap<Reverse<(u64, u64, i64, i64)>> = BinaryHeap::new();
+        best.insert((start.x, start.y), (0, None));
+        open.push(Reverse((heuristic(&start), 0, start.x, start.y)));
+
+        let mut expansions: u32 = 0;
+        while let Some(Reverse((_, cost, x, y))) = open.pop() {
+            if best.get(&(x, y)).is_some_and(|(known, _)| *known < cost) {
+                // stale entry, the cell was reached more cheaply since
+                continue;
+            }
+            if (x, y) == (goal.x, goal.y) {
+                let mut path = vec![goal];
+                let mut current = (x, y);
+                while let Some((_, Some(previous))) = best.get(&current) {
+                    path.push(IntegerPoint2D { x: previous.0, y: previous.1 });
+                    current = *previous;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+
+            expansions += 1;
+            if expansions > self.max_expansions {
+                return Err(StdError::generic_err("Path search expansion limit reached"));
+            }
+
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = IntegerPoint2D { x: x + dx, y: y + dy };
+                let next_cost = cost + 1;
+                if next_cost + heuristic(&next) > max_cost {
+                    continue;
+                }
+                if best.get(&(next.x, next.y)).is_some_and(|(known, _)| *known <= next_cost) {
+                    continue;
+                }
+                if self.is_blocked_cached(storage, &mut rows, &next)? {
+                    continue;
+                }
+                best.insert((next.x, next.y), (next_cost, Some((x, y))));
+                open.push(Reverse((next_cost + heuristic(&next), next_cost, next.x, next.y)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Same as `is_obstacle`, reading each row from storage at most once
+    fn is_blocked_cached(&self, storage: &dyn Storage, rows: &mut RowCache, point: &IntegerPoint2D) -> StdResult<bool> {
+        let (x, y) = match self.cell(point) {
+            Some(cell) => cell,
+            None => return Ok(true),
+        };
+        if !rows.contains_key(&y) {
+            rows.insert(y, self.get_row(storage, y)?);
+        }
+        Ok(row_bit(&rows[&y], x))
+    }
+}
+
+type RowCache = BTreeMap<u32, Vec<u8>>;
+
+fn row_bit(row: &[u8], x: u32) -> bool {
+    row[x as usize / 8] & (1u8 << (x % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    fn point(x: i64, y: i64) -> IntegerPoint2D {
+        IntegerPoint2D { x, y }
+    }
+
+    #[test]
+    fn astar_goes_around_a_wall() {
+        let mut storage = MockStorage::new();
+        let grid = IntegerGrid::new(b"grid", 5, 5);
+        // wall at x = 2, with a gap at the top
+        for y in 0..4 {
+            grid.set_obstacle(&mut storage, &point(2, y), true).unwrap();
+        }
+
+        let path = grid.astar(&storage, point(0, 0), point(4, 0), 100).unwrap().unwrap();
+        assert_eq!(path.first(), Some(&point(0, 0)));
+        assert_eq!(path.last(), Some(&point(4, 0)));
+        assert_eq!(grid.path_length(&storage, &path).unwrap(), 12);
+
+        // too expensive to go around
+        assert_eq!(grid.astar(&storage, point(0, 0), point(4, 0), 11).unwrap(), None);
+
+        grid.set_obstacle(&mut storage, &point(2, 4), true).unwrap();
+        assert_eq!(grid.astar(&storage, point(0, 0), point(4, 0), 100).unwrap(), None);
+    }
+
+    #[test]
+    fn astar_stops_at_expansion_limit() {
+        let storage = MockStorage::new();
+        let grid = IntegerGrid::new(b"grid", 100, 100).with_max_expansions(10);
+        assert!(grid.astar(&storage, point(0, 0), point(99, 99), 1000).is_err());
+    }
+
+    #[test]
+    fn path_length_rejects_invalid_paths() {
+        let mut storage = MockStorage::new();
+        let grid = IntegerGrid::new(b"grid", 3, 3);
+        grid.set_obstacle(&mut storage, &point(1, 1), true).unwrap();
+
+        assert_eq!(grid.path_length(&storage, &[point(0, 0), point(0, 1), point(0, 2)]).unwrap(), 2);
+        assert!(grid.path_length(&storage, &[point(0, 0), point(1, 1)]).is_err());
+        assert!(grid.path_length(&storage, &[point(0, 1), point(1, 1)]).is_err());
+        assert!(grid.path_length(&storage, &[point(0, 0), point(-1, 0)]).is_err());
+    }
+}
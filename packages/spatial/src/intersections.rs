@@ -0,0 +1,140 @@
+use core::cmp::Ordering;
+use alloc::vec::Vec;
+
+use crate::fixed_geom::is_counterclockwise;
+use crate::{FixedLineSegment2D, FixedPoint2D};
+
+fn point_cmp(a: &FixedPoint2D, b: &FixedPoint2D) -> Ordering {
+    (a.x, a.y).cmp(&(b.x, b.y))
+}
+
+/// Endpoints of a segment ordered by x, then y
+fn sweep_endpoints(segment: &FixedLineSegment2D) -> (FixedPoint2D, FixedPoint2D) {
+    let (a, b) = segment.endpoints;
+    if point_cmp(&a, &b) == Ordering::Greater {
+        (b, a)
+    } else {
+        (a, b)
+    }
+}
+
+/// Returns the indices of two segments that intersect, as defined by
+/// `FixedLineSegment2D::meets`, or None if no two segments do. Segments that only share
+/// an endpoint count as intersecting, so leave out adjacent edges when validating a polygon.
+///
+/// This is a Shamos-Hoey sweep: segments are kept ordered from bottom to top along a vertical
+/// sweep line, and each segment is only tested against its neighbors in that order, so it
+/// stops after O(n log n) orientation tests instead of testing all n^2 pairs.
+pub fn any_intersections(segments: &[FixedLineSegment2D]) -> Option<(usize, usize)> {
+    let endpoints: Vec<(FixedPoint2D, FixedPoint2D)> = segments.iter().map(sweep_endpoints).collect();
+
+    // (point, is removal, segment index), so that at the same point segments are added before
+    // any are removed, and segments that touch there are active at the same time
+    let mut events: Vec<(FixedPoint2D, bool, usize)> = Vec::with_capacity(2 * segments.len());
+    for (idx, (left, right)) in endpoints.iter().enumerate() {
+        events.push((*left, false, idx));
+        events.push((*right, true, idx));
+    }
+    events.sort_unstable_by(|a, b| point_cmp(&a.0, &b.0).then(a.1.cmp(&b.1)));
+
+    let found = |a: usize, b: usize| -> Option<(usize, usize)> {
+        if segments[a].meets(&segments[b]) {
+            Some((a.min(b), a.max(b)))
+        } else {
+            None
+        }
+    };
+
+    // active segments, ordered from bottom to top along the sweep line
+    let mut active: Vec<usize> = Vec::new();
+    for (point, is_removal, idx) in events {
+        if is_removal {
+            let position = active.iter().position(|active_idx| *active_idx == idx)?;
+            active.remove(position);
+            if position > 0 && position < active.len() {
+                if let Some(pair) = found(active[position - 1], active[position]) {
+                    return Some(pair);
+                }
+            }
+            continue;
+        }
+
+        // the new segment goes above every active segment that its left endpoint is above
+        let mut on_segment = None;
+        let position = active.partition_point(|active_idx| {
+            let (left, right) = endpoints[*active_idx];
+            match is_counterclockwise(&left, &right, &point) {
+                Some(above) => above,
+                None => {
+                    // the active segment spans the sweep line here, so it passes through
+                    // the point
+                    on_segment = Some(*active_idx);
+                    point.y > right.y
+                }
+            }
+        });
+        if let Some(active_idx) = on_segment {
+            if let Some(pair) = found(active_idx, idx) {
+                return Some(pair);
+            }
+        }
+        active.insert(position, idx);
+        if position > 0 {
+            if let Some(pair) = found(active[position - 1], idx) {
+                return Some(pair);
+            }
+        }
+        if position + 1 < active.len() {
+            if let Some(pair) = found(active[position + 1], idx) {
+                return Some(pair);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use substrate_fixed::types::I32F32;
+
+    fn segment((x0, y0, x1, y1): (i32, i32, i32, i32)) -> FixedLineSegment2D {
+        FixedLineSegment2D {
+            endpoints: (
+                FixedPoint2D { x: I32F32::from_num(x0), y: I32F32::from_num(y0) },
+                FixedPoint2D { x: I32F32::from_num(x1), y: I32F32::from_num(y1) },
+            ),
+        }
+    }
+
+    fn brute_force(segments: &[FixedLineSegment2D]) -> bool {
+        (0..segments.len()).any(|i| (i + 1..segments.len()).any(|j| segments[i].meets(&segments[j])))
+    }
+
+    #[test]
+    fn finds_crossing_pair() {
+        let segments = [segment((0, 0, 4, 0)), segment((0, 2, 4, 2)), segment((1, 3, 3, 1)), segment((5, -1, 5, 5))];
+        assert_eq!(any_intersections(&segments), Some((1, 2)));
+        assert_eq!(any_intersections(&segments[..2]), None);
+        assert_eq!(any_intersections(&[]), None);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_pairwise_test(
+            coords in prop::collection::vec((-8i32..8, -8i32..8, -8i32..8, -8i32..8), 0..12),
+        ) {
+            let segments: Vec<FixedLineSegment2D> = coords
+                .into_iter()
+                .filter(|(x0, y0, x1, y1)| (x0, y0) != (x1, y1))
+                .map(segment)
+                .collect();
+            let result = any_intersections(&segments);
+            prop_assert_eq!(result.is_some(), brute_force(&segments));
+            if let Some((i, j)) = result {
+                prop_assert!(i < j && segments[i].meets(&segments[j]));
+            }
+        }
+    }
+}
@@ -1,7 +1,46 @@
 #![doc = include_str!("../Readme.md")]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+pub mod angles;
+pub mod concave_hull;
+pub mod coverage;
+pub mod enclosing_circle;
 pub mod integer_geom;
+#[cfg(feature = "std")]
+pub mod integer_grid;
+pub mod intersections;
 pub mod fixed_geom;
+#[cfg(feature = "offchain")]
+pub mod offchain;
+pub mod polygon_ops;
+#[cfg(feature = "std")]
+pub mod geo_index;
+pub mod geometry;
+pub mod grid_cell;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
+#[cfg(feature = "std")]
+pub mod spatial_event_store;
+pub mod visibility;
 
+pub use angles::*;
+pub use concave_hull::*;
+pub use coverage::*;
+pub use enclosing_circle::*;
 pub use integer_geom::*;
+#[cfg(feature = "std")]
+pub use integer_grid::*;
+pub use intersections::*;
 pub use fixed_geom::*;
+#[cfg(feature = "offchain")]
+pub use offchain::*;
+pub use polygon_ops::*;
+#[cfg(feature = "std")]
+pub use geo_index::*;
+pub use geometry::*;
+pub use grid_cell::*;
+#[cfg(feature = "std")]
+pub use spatial_event_store::*;
+pub use visibility::*;
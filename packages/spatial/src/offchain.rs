@@ -0,0 +1,137 @@
+use crate::{FixedPoint2D, FixedPolygon2D};
+
+// f64 reference versions of the fixed-point geometry, for validating on-chain results
+// off-chain and measuring how far the fixed-point answers are from exact ones. Each function
+// follows the conventions of the fixed-point function it mirrors.
+
+/// A two-dimensional point with `x` and `y` as f64
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct F64Point2D {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl From<&FixedPoint2D> for F64Point2D {
+    fn from(point: &FixedPoint2D) -> Self {
+        F64Point2D { x: point.x.to_num(), y: point.y.to_num() }
+    }
+}
+
+/// Vertices of a polygon as f64 points, with the first vertex repeated at the end
+pub fn polygon_to_f64(polygon: &FixedPolygon2D) -> Vec<F64Point2D> {
+    polygon.vertices().iter().map(F64Point2D::from).collect()
+}
+
+/// f64 version of `FixedPolygon2D::signed_area`, for a closed ring of vertices
+pub fn signed_area_f64(vertices: &[F64Point2D]) -> f64 {
+    vertices
+        .windows(2)
+        .map(|edge| edge[0].x * edge[1].y - edge[1].x * edge[0].y)
+        .sum::<f64>() / 2.0
+}
+
+/// f64 version of `FixedPolygon2D::winding_number`
+pub fn winding_number_f64(vertices: &[F64Point2D], point: &F64Point2D) -> i32 {
+    let side = |a: &F64Point2D, b: &F64Point2D| (b.x - a.x) * (point.y - a.y) - (point.x - a.x) * (b.y - a.y);
+    let mut winding = 0;
+    for edge in vertices.windows(2) {
+        if edge[0].y <= point.y {
+            if edge[1].y > point.y && side(&edge[0], &edge[1]) > 0.0 {
+                winding += 1;
+            }
+        } else if edge[1].y <= point.y && side(&edge[0], &edge[1]) < 0.0 {
+            winding -= 1;
+        }
+    }
+    winding
+}
+
+/// f64 version of `FixedPolygon2D::contains`, with the even-odd rule. Points on the boundary
+/// may count either way.
+pub fn contains_f64(vertices: &[F64Point2D], point: &F64Point2D) -> bool {
+    let mut inside = false;
+    for edge in vertices.windows(2) {
+        let (a, b) = (edge[0], edge[1]);
+        if (a.y > point.y) != (b.y > point.y) {
+            let crossing_x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < crossing_x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// f64 version of `bearing`: radians in [0, 2 pi), clockwise from north (positive y)
+pub fn bearing_f64(a: &F64Point2D, b: &F64Point2D) -> Option<f64> {
+    if a == b {
+        return None;
+    }
+    let angle = (b.x - a.x).atan2(b.y - a.y);
+    Some(if angle < 0.0 { angle + 2.0 * core::f64::consts::PI } else { angle })
+}
+
+/// f64 version of `angle_between`: the signed counterclockwise turn from `u` to `v`, in
+/// (-pi, pi]
+pub fn angle_between_f64(u: &F64Point2D, v: &F64Point2D) -> Option<f64> {
+    if (u.x == 0.0 && u.y == 0.0) || (v.x == 0.0 && v.y == 0.0) {
+        return None;
+    }
+    Some((u.x * v.y - u.y * v.x).atan2(u.x * v.x + u.y * v.y))
+}
+
+/// f64 version of `FixedPolygon2D::diameter_squared`: the squared distance between the two
+/// farthest vertices, found by comparing every pair
+pub fn diameter_squared_f64(vertices: &[F64Point2D]) -> f64 {
+    let mut diameter_squared: f64 = 0.0;
+    for (i, a) in vertices.iter().enumerate() {
+        for b in &vertices[i + 1..] {
+            diameter_squared = diameter_squared.max((a.x - b.x).powi(2) + (a.y - b.y).powi(2));
+        }
+    }
+    diameter_squared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use substrate_fixed::types::I32F32;
+    use crate::{angle_between, bearing};
+
+    fn point(x: f64, y: f64) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn fixed_polygon_matches_reference() {
+        let polygon = FixedPolygon2D::try_new(vec![
+            point(0.0, 0.0), point(7.25, 1.5), point(9.0, 6.0), point(3.5, 4.75), point(1.0, 8.0), point(0.0, 0.0),
+        ]).unwrap();
+        let vertices = polygon_to_f64(&polygon);
+        assert!((polygon.signed_area().to_num::<f64>() - signed_area_f64(&vertices)).abs() < 1e-6);
+        assert!((polygon.diameter_squared().to_num::<f64>() - diameter_squared_f64(&vertices)).abs() < 1e-6);
+
+        for (x, y) in [(2.0, 2.0), (5.0, 3.1), (3.5, 6.5), (8.0, 1.0), (1.2, 7.0), (-1.0, 3.0)] {
+            let fixed = point(x, y);
+            let reference = F64Point2D::from(&fixed);
+            assert_eq!(polygon.contains(&fixed), contains_f64(&vertices, &reference));
+            assert_eq!(polygon.winding_number(&fixed), winding_number_f64(&vertices, &reference));
+        }
+    }
+
+    #[test]
+    fn fixed_angles_match_reference() {
+        let origin = point(0.5, -1.0);
+        for (x, y) in [(3.0, 4.0), (-2.5, 0.125), (-7.0, -7.0), (0.001, -300.0), (0.5, 9.0)] {
+            let target = point(x, y);
+            let fixed = bearing(&origin, &target).unwrap().to_num::<f64>();
+            let reference = bearing_f64(&F64Point2D::from(&origin), &F64Point2D::from(&target)).unwrap();
+            assert!((fixed - reference).abs() < 1e-6);
+
+            let (u, v) = (origin.as_vector_2d(), target.as_vector_2d());
+            let fixed = angle_between(&u, &v).unwrap().to_num::<f64>();
+            let reference = angle_between_f64(&F64Point2D::from(&origin), &F64Point2D::from(&target)).unwrap();
+            assert!((fixed - reference).abs() < 1e-6);
+        }
+    }
+}
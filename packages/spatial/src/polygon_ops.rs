@@ -0,0 +1,343 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use cosmwasm_std::StdResult;
+#[cfg(feature = "std")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "std")]
+use schemars::JsonSchema;
+
+use crate::concave_hull::{turn_cmp, turn_half};
+use crate::fixed_geom::{is_counterclockwise, on_segment};
+use crate::{FixedBBox2D, FixedLineSegment2D, FixedPoint2D, FixedPolygon2D};
+#[cfg(feature = "std")]
+use crate::StoredFixedPolygon2D;
+
+/// Set of polygons, e.g. the result of a union or difference. Each polygon is one boundary
+/// ring: counterclockwise rings enclose area and clockwise rings are holes in the ring around
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiPolygon {
+    polygons: Vec<FixedPolygon2D>,
+}
+
+impl MultiPolygon {
+    pub fn new(polygons: Vec<FixedPolygon2D>) -> Self {
+        Self { polygons }
+    }
+
+    pub fn polygons(&self) -> &[FixedPolygon2D] {
+        &self.polygons
+    }
+
+    pub fn len(&self) -> usize {
+        self.polygons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    /// true if `point` is inside an odd number of rings, so points in holes are outside
+    pub fn contains(&self, point: &FixedPoint2D) -> bool {
+        self.polygons.iter().filter(|polygon| polygon.contains(point)).count() % 2 == 1
+    }
+
+    /// Bounding box of all the polygons, or None if there are none
+    pub fn bbox(&self) -> Option<FixedBBox2D> {
+        self.polygons.iter().map(|polygon| polygon.bbox().clone()).reduce(|a, b| a.union(&b))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn into_stored(&self) -> StoredMultiPolygon {
+        StoredMultiPolygon {
+            polygons: self.polygons.iter().map(|polygon| polygon.into_stored()).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StoredMultiPolygon {
+    polygons: Vec<StoredFixedPolygon2D>,
+}
+
+#[cfg(feature = "std")]
+impl StoredMultiPolygon {
+    pub fn into_humanized(&self) -> StdResult<MultiPolygon> {
+        Ok(MultiPolygon {
+            polygons: self.polygons
+                .iter()
+                .map(|polygon| polygon.into_humanized())
+                .collect::<StdResult<Vec<FixedPolygon2D>>>()?,
+        })
+    }
+}
+
+/// Where a piece of one polygon's boundary lies relative to the other polygon
+#[derive(Clone, Copy, PartialEq)]
+enum Placement {
+    Inside,
+    Outside,
+    /// on the other boundary, running the same way
+    SharedSame,
+    /// on the other boundary, running the opposite way
+    SharedOpposite,
+}
+
+/// Points at which each edge of `a` and of `b` must be split so that the pieces of the two
+/// boundaries only meet at their ends. Crossing points are computed once, so both boundaries
+/// are split at exactly the same point.
+fn split_points(a: &[FixedPoint2D], b: &[FixedPoint2D]) -> (Vec<Vec<FixedPoint2D>>, Vec<Vec<FixedPoint2D>>) {
+    let mut splits_a: Vec<Vec<FixedPoint2D>> = vec![vec![]; a.len() - 1];
+    let mut splits_b: Vec<Vec<FixedPoint2D>> = vec![vec![]; b.len() - 1];
+    for (i, edge_a) in a.windows(2).enumerate() {
+        for (j, edge_b) in b.windows(2).enumerate() {
+            let (a0, a1, b0, b1) = (edge_a[0], edge_a[1], edge_b[0], edge_b[1]);
+            let segment_a = FixedLineSegment2D { endpoints: (a0, a1) };
+            let segment_b = FixedLineSegment2D { endpoints: (b0, b1) };
+            if !segment_a.meets(&segment_b) {
+                continue;
+            }
+            let b0_side = is_counterclockwise(&a0, &a1, &b0);
+            let b1_side = is_counterclockwise(&a0, &a1, &b1);
+            let a0_side = is_counterclockwise(&b0, &b1, &a0);
+            let a1_side = is_counterclockwise(&b0, &b1, &a1);
+            if b0_side.is_some() && b1_side.is_some() && a0_side.is_some() && a1_side.is_some() {
+                // proper crossing
+                let edge = a1 - a0;
+                let other = b1 - b0;
+                let to_other = b0 - a0;
+                let t = to_other.cross(&other) / edge.cross(&other);
+                let crossing = FixedPoint2D { x: a0.x + edge.x * t, y: a0.y + edge.y * t };
+                splits_a[i].push(crossing);
+                splits_b[j].push(crossing);
+                continue;
+            }
+            // the segments touch at a vertex, or overlap
+            for point in [b0, b1] {
+                if on_segment(&a0, &a1, &point) {
+                    splits_a[i].push(point);
+                }
+            }
+            for point in [a0, a1] {
+                if on_segment(&b0, &b1, &point) {
+                    splits_b[j].push(point);
+                }
+            }
+        }
+    }
+    (splits_a, splits_b)
+}
+
+/// Splits each edge of `ring` at its split points, in order along the edge
+fn fragments(ring: &[FixedPoint2D], splits: Vec<Vec<FixedPoint2D>>) -> Vec<(FixedPoint2D, FixedPoint2D)> {
+    let mut pieces = vec![];
+    for (edge, mut points) in ring.windows(2).zip(splits) {
+        let start = edge[0];
+        points.push(edge[1]);
+        points.sort_unstable_by_key(|point| (*point - start).len_squared());
+        points.dedup();
+        let mut from = start;
+        for to in points {
+            if to != from {
+                pieces.push((from, to));
+                from = to;
+            }
+        }
+    }
+    pieces
+}
+
+fn placement(piece: &(FixedPoint2D, FixedPoint2D), other: &FixedPolygon2D, other_ring: &[FixedPoint2D]) -> Placement {
+    let (from, to) = *piece;
+    if let Some(edge) = other_ring
+        .windows(2)
+        .find(|edge| on_segment(&edge[0], &edge[1], &from) && on_segment(&edge[0], &edge[1], &to))
+    {
+        return if (to - from).dot(&(edge[1] - edge[0])) > 0 {
+            Placement::SharedSame
+        } else {
+            Placement::SharedOpposite
+        };
+    }
+    let middle = FixedPoint2D { x: from.x + (to.x - from.x) / 2, y: from.y + (to.y - from.y) / 2 };
+    if other.contains(&middle) {
+        Placement::Inside
+    } else {
+        Placement::Outside
+    }
+}
+
+/// Joins boundary pieces into closed rings. Where several pieces leave the same point, the
+/// one turning furthest to the left is taken, so rings that touch at a point stay separate.
+fn assemble(mut pieces: Vec<(FixedPoint2D, FixedPoint2D)>) -> Result<MultiPolygon, &'static str> {
+    let mut polygons = vec![];
+    while let Some((start, second)) = pieces.pop() {
+        let mut ring = vec![start, second];
+        let mut current = second;
+        let mut back = start - second;
+        while current != start {
+            let next = pieces
+                .iter()
+                .enumerate()
+                .filter(|(_, piece)| piece.0 == current)
+                .max_by(|(_, a), (_, b)| {
+                    // a piece straight back along the last one is taken last
+                    let a_back = turn_half(&back, &(a.1 - current)) == 3;
+                    let b_back = turn_half(&back, &(b.1 - current)) == 3;
+                    b_back.cmp(&a_back).then_with(|| turn_cmp(&current, &back, &a.1, &b.1))
+                })
+                .map(|(idx, _)| idx)
+                .ok_or("Boundary does not close")?;
+            let (_, to) = pieces.swap_remove(next);
+            back = current - to;
+            current = to;
+            ring.push(to);
+        }
+        let ring = without_colinear_vertices(ring);
+        if ring.len() >= 4 {
+            polygons.push(FixedPolygon2D::try_new(ring)?);
+        }
+    }
+    Ok(MultiPolygon::new(polygons))
+}
+
+/// Drops the vertices of a closed ring that lie on a straight line between their neighbors
+fn without_colinear_vertices(ring: Vec<FixedPoint2D>) -> Vec<FixedPoint2D> {
+    let mut points: Vec<FixedPoint2D> = ring[..ring.len() - 1].to_vec();
+    // removing a vertex can make its neighbors colinear, so repeat until nothing changes
+    let mut changed = true;
+    while changed && points.len() >= 3 {
+        changed = false;
+        let mut idx = 0;
+        while points.len() >= 3 && idx < points.len() {
+            let prev = points[(idx + points.len() - 1) % points.len()];
+            let next = points[(idx + 1) % points.len()];
+            if is_counterclockwise(&prev, &points[idx], &next).is_none() {
+                points.remove(idx);
+                changed = true;
+            } else {
+                idx += 1;
+            }
+        }
+    }
+    if let Some(first) = points.first().copied() {
+        points.push(first);
+    }
+    points
+}
+
+fn boolean_op(
+    a: &FixedPolygon2D,
+    b: &FixedPolygon2D,
+    keep_a: impl Fn(Placement) -> bool,
+    keep_b: impl Fn(Placement) -> bool,
+    reverse_b: bool,
+) -> Result<MultiPolygon, &'static str> {
+    let ring_a = a.clone().normalize_orientation().vertices().to_vec();
+    let ring_b = b.clone().normalize_orientation().vertices().to_vec();
+    let (splits_a, splits_b) = split_points(&ring_a, &ring_b);
+
+    let mut pieces: Vec<(FixedPoint2D, FixedPoint2D)> = fragments(&ring_a, splits_a)
+        .into_iter()
+        .filter(|piece| keep_a(placement(piece, b, &ring_b)))
+        .collect();
+    pieces.extend(
+        fragments(&ring_b, splits_b)
+            .into_iter()
+            .filter(|piece| keep_b(placement(piece, a, &ring_a)))
+            .map(|(from, to)| if reverse_b { (to, from) } else { (from, to) }),
+    );
+    assemble(pieces)
+}
+
+impl FixedPolygon2D {
+    /// Returns the area covered by either polygon. Adjacent polygons that share an edge are
+    /// merged into one. The result may have several polygons if the two do not touch, and
+    /// holes if they enclose an area together.
+    ///
+    /// Both polygons must be simple, i.e. their edges must not cross each other.
+    pub fn union(&self, other: &FixedPolygon2D) -> Result<MultiPolygon, &'static str> {
+        boolean_op(
+            self,
+            other,
+            |placement| matches!(placement, Placement::Outside | Placement::SharedSame),
+            |placement| placement == Placement::Outside,
+            false,
+        )
+    }
+
+    /// Returns the area covered by this polygon and not by `other`, e.g. to carve an
+    /// exclusion zone out of a claim. The result may have several polygons, or none, and a
+    /// hole if `other` lies inside this polygon.
+    ///
+    /// Both polygons must be simple, i.e. their edges must not cross each other.
+    pub fn difference(&self, other: &FixedPolygon2D) -> Result<MultiPolygon, &'static str> {
+        boolean_op(
+            self,
+            other,
+            |placement| matches!(placement, Placement::Outside | Placement::SharedOpposite),
+            |placement| placement == Placement::Inside,
+            true,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use substrate_fixed::types::I32F32;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    fn rectangle(x0: i32, y0: i32, x1: i32, y1: i32) -> FixedPolygon2D {
+        FixedPolygon2D::try_new(vec![point(x0, y0), point(x1, y0), point(x1, y1), point(x0, y1), point(x0, y0)]).unwrap()
+    }
+
+    fn half_point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x) + I32F32::from_num(0.5), y: I32F32::from_num(y) + I32F32::from_num(0.5) }
+    }
+
+    #[test]
+    fn union_merges_adjacent_claims() {
+        let merged = rectangle(0, 0, 2, 2).union(&rectangle(2, 0, 4, 2)).unwrap();
+        assert_eq!(merged.len(), 1);
+        // the shared edge is gone, leaving the 4 corners
+        assert_eq!(merged.polygons()[0].len(), 5);
+        assert!(merged.contains(&half_point(1, 0)));
+        assert!(merged.contains(&half_point(2, 1)));
+        assert!(!merged.contains(&half_point(4, 0)));
+    }
+
+    #[test]
+    fn union_of_overlapping_and_disjoint_polygons() {
+        let overlapping = rectangle(0, 0, 2, 2).union(&rectangle(1, 1, 3, 3)).unwrap();
+        assert_eq!(overlapping.len(), 1);
+        assert_eq!(overlapping.polygons()[0].len(), 9);
+        assert!(overlapping.contains(&half_point(2, 2)));
+        assert!(!overlapping.contains(&half_point(2, 0)));
+
+        let disjoint = rectangle(0, 0, 1, 1).union(&rectangle(5, 5, 6, 6)).unwrap();
+        assert_eq!(disjoint.len(), 2);
+    }
+
+    #[test]
+    fn difference_carves_out_area() {
+        let notched = rectangle(0, 0, 4, 4).difference(&rectangle(2, 2, 6, 6)).unwrap();
+        assert_eq!(notched.len(), 1);
+        assert!(notched.contains(&half_point(0, 0)));
+        assert!(!notched.contains(&half_point(3, 3)));
+
+        let with_hole = rectangle(0, 0, 4, 4).difference(&rectangle(1, 1, 2, 2)).unwrap();
+        assert_eq!(with_hole.len(), 2);
+        assert!(!with_hole.contains(&half_point(1, 1)));
+        assert!(with_hole.contains(&half_point(3, 3)));
+
+        let split = rectangle(0, 0, 4, 1).difference(&rectangle(1, -1, 2, 2)).unwrap();
+        assert_eq!(split.len(), 2);
+
+        assert!(rectangle(1, 1, 2, 2).difference(&rectangle(0, 0, 4, 4)).unwrap().is_empty());
+    }
+}
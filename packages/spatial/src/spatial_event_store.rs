@@ -0,0 +1,358 @@
+use cosmwasm_std::{Storage, StdResult, StdError};
+use cosmwasm_storage::to_length_prefixed;
+use secret_data_tools_fixed::FixedBytes;
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedBBox2D, FixedPoint2D};
+
+const LEN_KEY: &[u8] = b"len";
+const EVENT_KEY: &[u8] = b"event";
+const BUCKET_LEN_KEY: &[u8] = b"bucket-len";
+const BUCKET_ENTRY_KEY: &[u8] = b"bucket";
+
+/// Largest number of cells per side of a `SpatialEventStore` grid, as a power of 2
+pub const MAX_SPATIAL_EVENT_LEVEL: u8 = 16;
+
+/// Largest number of (time bucket, cell) pairs a single query may visit
+pub const MAX_QUERY_BUCKETS: u64 = 4096;
+
+/// Largest number of indexed events that `SpatialEventStore::query` reads, including the ones
+/// skipped for earlier pages or filtered out
+pub const MAX_QUERY_EVENTS: u64 = 4096;
+
+/// One entry of a `SpatialEventStore`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpatialEvent {
+    pub block_time: u64,
+    pub point: FixedPoint2D,
+    pub payload: Vec<u8>,
+}
+
+/// Append-only log of (block time, point, payload) events in Storage, indexed for queries
+/// by area and time window.
+///
+/// The square region is divided into a grid of 2^level x 2^level cells and time into buckets of
+/// `bucket_duration`. Each event is indexed under a composite key of its time bucket and the
+/// Z-order code of its cell, so a query only reads the index entries of the buckets and cells
+/// that overlap its window, and the events in them.
+pub struct SpatialEventStore<'a> {
+    /// prefix of the newly constructed Storage
+    namespace: &'a [u8],
+    /// needed if any suffixes were added to the original namespace.
+    prefix: Option<Vec<u8>>,
+    /// lower left corner of the region
+    origin: FixedPoint2D,
+    /// side length of the region
+    size: I32F32,
+    /// the grid has 2^level cells per side
+    level: u8,
+    /// length of a time bucket, in the same unit as the block times
+    bucket_duration: u64,
+}
+
+impl<'a> SpatialEventStore<'a> {
+    /// constructor
+    pub const fn new(
+        namespace: &'a [u8],
+        origin: FixedPoint2D,
+        size: I32F32,
+        level: u8,
+        bucket_duration: u64,
+    ) -> Self {
+        assert!(size.to_bits() > 0, "Region size must be greater than zero");
+        assert!(level <= MAX_SPATIAL_EVENT_LEVEL, "Grid level is too large");
+        assert!(bucket_duration > 0, "Bucket duration must be greater than zero");
+        Self {
+            namespace,
+            prefix: None,
+            origin,
+            size,
+            level,
+            bucket_duration,
+        }
+    }
+
+    /// This is used to produce a new SpatialEventStore. It can be used when you want to associate
+    /// a SpatialEventStore to multiple suffixes and you still want to define the SpatialEventStore
+    /// as a static constant
+    pub fn add_suffix(&self, suffix: &[u8]) -> Self {
+        let suffix = to_length_prefixed(suffix);
+        let prefix = self.prefix.as_deref().unwrap_or(self.namespace);
+        let prefix = [prefix, suffix.as_slice()].concat();
+        Self {
+            namespace: self.namespace,
+            prefix: Some(prefix),
+            origin: self.origin,
+            size: self.size,
+            level: self.level,
+            bucket_duration: self.bucket_duration,
+        }
+    }
+}
+
+impl<'a> SpatialEventStore<'a> {
+    fn as_slice(&self) -> &[u8] {
+        if let Some(prefix) = &self.prefix {
+            prefix
+        } else {
+            self.namespace
+        }
+    }
+
+    fn event_key(&self, id: u64) -> Vec<u8> {
+        [self.as_slice(), EVENT_KEY, &id.to_be_bytes()].concat()
+    }
+
+    fn bucket_len_key(&self, bucket: u64, z_code: u32) -> Vec<u8> {
+        [self.as_slice(), BUCKET_LEN_KEY, &bucket.to_be_bytes(), &z_code.to_be_bytes()].concat()
+    }
+
+    fn bucket_entry_key(&self, bucket: u64, z_code: u32, idx: u32) -> Vec<u8> {
+        [
+            self.as_slice(),
+            BUCKET_ENTRY_KEY,
+            &bucket.to_be_bytes(),
+            &z_code.to_be_bytes(),
+            &idx.to_be_bytes(),
+        ]
+        .concat()
+    }
+
+    fn get_u64(storage: &dyn Storage, key: &[u8]) -> StdResult<u64> {
+        match storage.get(key) {
+            Some(bytes) => {
+                let bytes = bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|err| StdError::parse_err("u64", err))?;
+                Ok(u64::from_be_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn get_bucket_len(&self, storage: &dyn Storage, bucket: u64, z_code: u32) -> StdResult<u32> {
+        match storage.get(&self.bucket_len_key(bucket, z_code)) {
+            Some(bytes) => {
+                let bytes = bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|err| StdError::parse_err("u32", err))?;
+                Ok(u32::from_be_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the region covered by the store
+    pub fn get_region(&self) -> FixedBBox2D {
+        FixedBBox2D::new(
+            self.origin,
+            FixedPoint2D { x: self.origin.x + self.size, y: self.origin.y + self.size },
+        )
+    }
+
+    /// Returns the total number of events
+    pub fn get_len(&self, storage: &dyn Storage) -> StdResult<u64> {
+        Self::get_u64(storage, &[self.as_slice(), LEN_KEY].concat())
+    }
+
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.get_len(storage)? == 0)
+    }
+
+    /// Grid cell of a coordinate offset from the origin, clamped to the grid
+    fn cell_index(&self, offset: I32F32) -> u32 {
+        let cells = 1u32 << self.level;
+        let cell_size = self.size / i64::from(cells);
+        if offset <= 0 {
+            return 0;
+        }
+        (offset / cell_size).to_num::<u32>().min(cells - 1)
+    }
+
+    /// Appends an event, which must lie inside the region, and returns its id
+    pub fn append(
+        &self,
+        storage: &mut dyn Storage,
+        block_time: u64,
+        point: FixedPoint2D,
+        payload: &[u8],
+    ) -> StdResult<u64> {
+        if !self.get_region().contains(&point) {
+            return Err(StdError::generic_err("Event is outside the region"));
+        }
+        let id = self.get_len(storage)?;
+        let new_len = id.checked_add(1).ok_or(StdError::generic_err("Event log overflow"))?;
+
+        let bucket = block_time / self.bucket_duration;
+        let z_code = z_order(self.cell_index(point.x - self.origin.x), self.cell_index(point.y - self.origin.y));
+        let bucket_len = self.get_bucket_len(storage, bucket, z_code)?;
+        let new_bucket_len = bucket_len.checked_add(1).ok_or(StdError::generic_err("Event bucket overflow"))?;
+
+        storage.set(
+            &self.event_key(id),
+            &[
+                block_time.to_be_bytes().as_slice(),
+                point.x.to_stored_bytes().as_slice(),
+                point.y.to_stored_bytes().as_slice(),
+                payload,
+            ]
+            .concat(),
+        );
+        storage.set(&self.bucket_entry_key(bucket, z_code, bucket_len), &id.to_be_bytes());
+        storage.set(&self.bucket_len_key(bucket, z_code), &new_bucket_len.to_be_bytes());
+        storage.set(&[self.as_slice(), LEN_KEY].concat(), &new_len.to_be_bytes());
+        Ok(id)
+    }
+
+    /// Returns the event with id `id`
+    pub fn get(&self, storage: &dyn Storage, id: u64) -> StdResult<SpatialEvent> {
+        let bytes = storage.get(&self.event_key(id)).ok_or(StdError::generic_err("Event not found"))?;
+        let coord_len = I32F32::from_num(0).to_stored_bytes().len();
+        if bytes.len() < 8 + 2 * coord_len {
+            return Err(StdError::generic_err("Stored event is too short"));
+        }
+        let (time_bytes, rest) = bytes.split_at(8);
+        let (x_bytes, rest) = rest.split_at(coord_len);
+        let (y_bytes, payload) = rest.split_at(coord_len);
+        Ok(SpatialEvent {
+            block_time: u64::from_be_bytes(time_bytes.try_into().map_err(|err| StdError::parse_err("u64", err))?),
+            point: FixedPoint2D {
+                x: I32F32::from_stored_bytes(x_bytes)?,
+                y: I32F32::from_stored_bytes(y_bytes)?,
+            },
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Returns one page of the events inside `bbox` with a block time in
+    /// `[time_range.0, time_range.1]`, with pages numbered from 0. Events are ordered by time
+    /// bucket, then by the Z-order of their cell, then by insertion.
+    ///
+    /// Returns an error if the window spans more than `MAX_QUERY_BUCKETS` (time bucket, cell)
+    /// pairs, or if the page cannot be filled without reading more than `MAX_QUERY_EVENTS`
+    /// events, to keep the gas used by a query bounded; split such queries into smaller windows.
+    pub fn query(
+        &self,
+        storage: &dyn Storage,
+        bbox: &FixedBBox2D,
+        time_range: (u64, u64),
+        start_page: u32,
+        size: u32,
+    ) -> StdResult<Vec<SpatialEvent>> {
+        let (start_time, end_time) = time_range;
+        if size == 0 || start_time > end_time || !self.get_region().overlaps(bbox) {
+            return Ok(vec![]);
+        }
+        let first_bucket = start_time / self.bucket_duration;
+        let last_bucket = end_time / self.bucket_duration;
+        let min_x = self.cell_index(bbox.lower_left().x - self.origin.x);
+        let max_x = self.cell_index(bbox.upper_right().x - self.origin.x);
+        let min_y = self.cell_index(bbox.lower_left().y - self.origin.y);
+        let max_y = self.cell_index(bbox.upper_right().y - self.origin.y);
+
+        let cells = u64::from(max_x - min_x + 1) * u64::from(max_y - min_y + 1);
+        let buckets = (last_bucket - first_bucket).saturating_add(1);
+        if buckets.saturating_mul(cells) > MAX_QUERY_BUCKETS {
+            return Err(StdError::generic_err("Query window is too large"));
+        }
+        let mut z_codes: Vec<u32> = (min_x..=max_x)
+            .flat_map(|x| (min_y..=max_y).map(move |y| z_order(x, y)))
+            .collect();
+        z_codes.sort_unstable();
+
+        let mut to_skip = u64::from(start_page) * u64::from(size);
+        let mut page: Vec<SpatialEvent> = vec![];
+        let mut reads: u64 = 0;
+        for bucket in first_bucket..=last_bucket {
+            for z_code in &z_codes {
+                for idx in 0..self.get_bucket_len(storage, bucket, *z_code)? {
+                    reads += 1;
+                    if reads > MAX_QUERY_EVENTS {
+                        return Err(StdError::generic_err("Query reads too many events"));
+                    }
+                    let id = Self::get_u64(storage, &self.bucket_entry_key(bucket, *z_code, idx))?;
+                    let event = self.get(storage, id)?;
+                    if event.block_time < start_time
+                        || event.block_time > end_time
+                        || !bbox.contains(&event.point)
+                    {
+                        continue;
+                    }
+                    if to_skip > 0 {
+                        to_skip -= 1;
+                    } else {
+                        page.push(event);
+                        if page.len() as u64 == u64::from(size) {
+                            return Ok(page);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(page)
+    }
+}
+
+/// Interleaves the bits of the cell coordinates, x in the even bits and y in the odd bits
+fn z_order(x: u32, y: u32) -> u32 {
+    (0..16).fold(0, |code, bit| {
+        code | (((x >> bit) & 1) << (2 * bit)) | (((y >> bit) & 1) << (2 * bit + 1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn query_filters_by_area_and_time() {
+        let mut storage = MockStorage::new();
+        let store = SpatialEventStore::new(b"events", point(0, 0), I32F32::from_num(64), 3, 100);
+        store.append(&mut storage, 10, point(1, 1), b"a").unwrap();
+        store.append(&mut storage, 20, point(60, 60), b"b").unwrap();
+        store.append(&mut storage, 150, point(2, 2), b"c").unwrap();
+        store.append(&mut storage, 30, point(3, 3), b"d").unwrap();
+        assert!(store.append(&mut storage, 30, point(65, 3), b"e").is_err());
+        assert_eq!(store.get_len(&storage).unwrap(), 4);
+
+        let near_origin = FixedBBox2D::new(point(0, 0), point(10, 10));
+        let payloads = |events: Vec<SpatialEvent>| -> Vec<Vec<u8>> {
+            events.into_iter().map(|event| event.payload).collect()
+        };
+        assert_eq!(
+            payloads(store.query(&storage, &near_origin, (0, 99), 0, 10).unwrap()),
+            vec![b"a".to_vec(), b"d".to_vec()],
+        );
+        assert_eq!(
+            payloads(store.query(&storage, &near_origin, (0, 199), 1, 1).unwrap()),
+            vec![b"d".to_vec()],
+        );
+        assert_eq!(
+            payloads(store.query(&storage, &near_origin, (100, 199), 0, 10).unwrap()),
+            vec![b"c".to_vec()],
+        );
+        assert!(store.query(&storage, &store.get_region(), (0, 100_000), 0, 10).is_err());
+    }
+
+    #[test]
+    fn query_reads_a_bounded_number_of_events() {
+        let mut storage = MockStorage::new();
+        let store = SpatialEventStore::new(b"events", point(0, 0), I32F32::from_num(64), 1, 100);
+        for _ in 0..MAX_QUERY_EVENTS {
+            store.append(&mut storage, 10, point(1, 1), b"x").unwrap();
+        }
+        store.append(&mut storage, 10, point(2, 2), b"y").unwrap();
+        let cell = FixedBBox2D::new(point(0, 0), point(10, 10));
+        assert_eq!(store.query(&storage, &cell, (0, 99), 0, 10).unwrap().len(), 10);
+        // the last event is only reached after reading every earlier one
+        let skip_all = u32::try_from(MAX_QUERY_EVENTS).unwrap();
+        assert!(store.query(&storage, &cell, (0, 99), skip_all, 1).is_err());
+    }
+}
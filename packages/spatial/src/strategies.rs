@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+use proptest::arbitrary::Arbitrary;
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedPoint2D, FixedPolygon2D, IntegerPoint2D, IntegerPolygon2D};
+
+// Proptest strategies for the geometry types, so that contract logic built on them can be
+// fuzzed with valid geometry. Coordinates are kept within an extent so that the cross products
+// used by `contains`, `intersects` and `meets` cannot overflow.
+
+/// Coordinate extent used by the `Arbitrary` impls of the integer types
+pub const DEFAULT_INTEGER_EXTENT: i64 = 1 << 20;
+/// Coordinate extent used by the `Arbitrary` impls of the fixed-point types. Products of
+/// coordinate differences must fit in an I32F32, so this is much smaller than the integer one.
+pub const DEFAULT_FIXED_EXTENT: i64 = 1 << 12;
+/// Largest number of distinct vertices generated by the `Arbitrary` polygon impls
+pub const DEFAULT_MAX_VERTICES: usize = 16;
+
+/// Points with both coordinates in [-extent, extent]
+pub fn integer_point(extent: i64) -> impl Strategy<Value = IntegerPoint2D> {
+    (-extent..=extent, -extent..=extent).prop_map(|(x, y)| IntegerPoint2D { x, y })
+}
+
+/// Points with both coordinates in [-extent, extent], using every fractional bit
+pub fn fixed_point(extent: i64) -> impl Strategy<Value = FixedPoint2D> {
+    let bits = extent << 32;
+    (-bits..=bits, -bits..=bits).prop_map(|(x, y)| FixedPoint2D {
+        x: I32F32::from_bits(x),
+        y: I32F32::from_bits(y),
+    })
+}
+
+/// Closed vertex list of a simple x-monotone polygon. The distinct `xs` are sorted. The first
+/// and last x are joined at y = 0 by a lower chain below the x axis and an upper chain above it,
+/// and `sides` decides which chain each of the other xs goes to, so the chains never cross.
+fn monotone_vertices<T: Copy>(
+    xs: Vec<T>,
+    sides: Vec<(bool, T)>,
+    zero: T,
+    negate: impl Fn(T) -> T,
+) -> Vec<(T, T)> {
+    let last = xs.len() - 1;
+    let mut lower = Vec::new();
+    let mut upper = Vec::new();
+    for (x, (is_upper, height)) in xs[1..last].iter().zip(sides) {
+        if is_upper {
+            upper.push((*x, height));
+        } else {
+            lower.push((*x, negate(height)));
+        }
+    }
+    let mut vertices = Vec::with_capacity(xs.len() + 1);
+    vertices.push((xs[0], zero));
+    vertices.extend(lower);
+    vertices.push((xs[last], zero));
+    vertices.extend(upper.into_iter().rev());
+    vertices.push((xs[0], zero));
+    vertices
+}
+
+/// Simple polygons with 3 to `max_vertices` distinct vertices and coordinates in
+/// [-extent, extent]. The polygons are x-monotone, which covers both convex and concave shapes.
+pub fn integer_polygon(max_vertices: usize, extent: i64) -> impl Strategy<Value = IntegerPolygon2D> {
+    (
+        btree_set(-extent..=extent, 3..=max_vertices.max(3)),
+        vec((any::<bool>(), 1..=extent / 2), max_vertices.max(3)),
+        -extent / 2..=extent / 2,
+    )
+        .prop_map(|(xs, sides, offset)| {
+            let points = monotone_vertices(xs.into_iter().collect(), sides, 0, |y| -y)
+                .into_iter()
+                .map(|(x, y)| IntegerPoint2D { x, y: y + offset })
+                .collect();
+            IntegerPolygon2D::try_new(points).unwrap()
+        })
+}
+
+/// Fixed-point version of `integer_polygon`, using every fractional bit
+pub fn fixed_polygon(max_vertices: usize, extent: i64) -> impl Strategy<Value = FixedPolygon2D> {
+    let bits = extent << 32;
+    (
+        btree_set(-bits..=bits, 3..=max_vertices.max(3)),
+        vec((any::<bool>(), 1..=bits / 2), max_vertices.max(3)),
+        -bits / 2..=bits / 2,
+    )
+        .prop_map(|(xs, sides, offset)| {
+            let points = monotone_vertices(xs.into_iter().collect(), sides, 0, |y| -y)
+                .into_iter()
+                .map(|(x, y)| FixedPoint2D {
+                    x: I32F32::from_bits(x),
+                    y: I32F32::from_bits(y + offset),
+                })
+                .collect();
+            FixedPolygon2D::try_new(points).unwrap()
+        })
+}
+
+impl Arbitrary for IntegerPoint2D {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        integer_point(DEFAULT_INTEGER_EXTENT).boxed()
+    }
+}
+
+impl Arbitrary for FixedPoint2D {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        fixed_point(DEFAULT_FIXED_EXTENT).boxed()
+    }
+}
+
+impl Arbitrary for IntegerPolygon2D {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        integer_polygon(DEFAULT_MAX_VERTICES, DEFAULT_INTEGER_EXTENT).boxed()
+    }
+}
+
+impl Arbitrary for FixedPolygon2D {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        fixed_polygon(DEFAULT_MAX_VERTICES, DEFAULT_FIXED_EXTENT).boxed()
+    }
+}
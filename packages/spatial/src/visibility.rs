@@ -0,0 +1,160 @@
+use core::cmp::Ordering;
+use alloc::vec::Vec;
+use substrate_fixed::types::I32F32;
+
+use crate::{FixedLineSegment2D, FixedPoint2D, FixedPolygon2D, FixedVector2D};
+
+/// How far, relative to the length of a ray, the rays just past a segment endpoint are turned
+const RAY_OFFSET: I32F32 = I32F32::from_bits(1 << 16);
+
+/// true if `d` points into the half plane y > 0, or along the positive x axis
+fn in_upper_half(d: &FixedVector2D) -> bool {
+    d.y > 0 || (d.y == 0 && d.x > 0)
+}
+
+/// Orders directions counterclockwise, starting from the positive x axis
+fn angle_cmp(a: &FixedVector2D, b: &FixedVector2D) -> Ordering {
+    match (in_upper_half(a), in_upper_half(b)) {
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        _ => {
+            let turn = a.cross(b);
+            if turn > 0 {
+                Ordering::Less
+            } else if turn < 0 {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        }
+    }
+}
+
+/// Parameter t of the first point `origin + t * direction`, with t > 0, where the ray hits
+/// `segment`, if it does
+fn ray_hit(origin: &FixedPoint2D, direction: &FixedVector2D, segment: &FixedLineSegment2D) -> Option<I32F32> {
+    let edge = segment.endpoints.1 - segment.endpoints.0;
+    let denom = direction.cross(&edge);
+    if denom == 0 {
+        // parallel, the ray can only graze the segment
+        return None;
+    }
+    let to_start = segment.endpoints.0 - *origin;
+    let t = to_start.cross(&edge) / denom;
+    let u = to_start.cross(direction) / denom;
+    if t > 0 && (I32F32::from_num(0)..=I32F32::from_num(1)).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Returns the region visible from `origin` when the line segments in `occluders` block the
+/// view, e.g. for fog of war. The view is limited to the bounding box of `origin` and the
+/// occluders, grown by 1 on every side, so the result is always a closed polygon, listed
+/// counterclockwise around `origin`.
+///
+/// Rays are cast towards every segment endpoint and just to either side of it, so this takes
+/// O(n^2) time in the number of segments. `origin` should not lie on an occluder. As with
+/// `FixedPolygon2D::contains`, coordinates should stay within a few thousand units so that
+/// the cross products cannot overflow.
+pub fn visibility_polygon(
+    origin: FixedPoint2D,
+    occluders: &[FixedLineSegment2D],
+) -> Result<FixedPolygon2D, &'static str> {
+    let mut min_x = origin.x;
+    let mut min_y = origin.y;
+    let mut max_x = origin.x;
+    let mut max_y = origin.y;
+    for point in occluders.iter().flat_map(|segment| [segment.endpoints.0, segment.endpoints.1]) {
+        min_x = min_x.min(point.x);
+        min_y = min_y.min(point.y);
+        max_x = max_x.max(point.x);
+        max_y = max_y.max(point.y);
+    }
+    let one = I32F32::from_num(1);
+    let corners = [
+        FixedPoint2D { x: min_x - one, y: min_y - one },
+        FixedPoint2D { x: max_x + one, y: min_y - one },
+        FixedPoint2D { x: max_x + one, y: max_y + one },
+        FixedPoint2D { x: min_x - one, y: max_y + one },
+    ];
+
+    let mut segments: Vec<FixedLineSegment2D> = occluders.to_vec();
+    for i in 0..corners.len() {
+        segments.push(FixedLineSegment2D {
+            endpoints: (corners[i], corners[(i + 1) % corners.len()]),
+        });
+    }
+
+    // a ray at each endpoint, and one just to either side to see past it
+    let mut directions: Vec<FixedVector2D> = Vec::with_capacity(segments.len() * 6);
+    for point in segments.iter().flat_map(|segment| [segment.endpoints.0, segment.endpoints.1]) {
+        let direction = point - origin;
+        if direction.x == 0 && direction.y == 0 {
+            continue;
+        }
+        let normal = FixedVector2D { x: -direction.y, y: direction.x } * RAY_OFFSET;
+        directions.push(direction.clone());
+        directions.push(direction.clone() + normal.clone());
+        directions.push(direction - normal);
+    }
+    directions.sort_unstable_by(angle_cmp);
+
+    let mut vertices: Vec<FixedPoint2D> = Vec::with_capacity(directions.len() + 1);
+    for direction in directions {
+        // the box around everything always stops the ray
+        let nearest = segments
+            .iter()
+            .filter_map(|segment| ray_hit(&origin, &direction, segment))
+            .min();
+        if let Some(t) = nearest {
+            let hit = FixedPoint2D {
+                x: origin.x + direction.x * t,
+                y: origin.y + direction.y * t,
+            };
+            if vertices.last() != Some(&hit) {
+                vertices.push(hit);
+            }
+        }
+    }
+    if vertices.len() > 1 && vertices.first() == vertices.last() {
+        vertices.pop();
+    }
+    if let Some(first) = vertices.first().copied() {
+        vertices.push(first);
+    }
+    FixedPolygon2D::try_new(vertices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: i32, y: i32) -> FixedPoint2D {
+        FixedPoint2D { x: I32F32::from_num(x), y: I32F32::from_num(y) }
+    }
+
+    #[test]
+    fn wall_hides_points_behind_it() {
+        let wall = FixedLineSegment2D::try_new(point(2, -1), point(2, 1)).unwrap();
+        // two small segments out of the way, to make the view larger
+        let far = FixedLineSegment2D::try_new(point(10, 9), point(10, 10)).unwrap();
+        let near = FixedLineSegment2D::try_new(point(-10, -10), point(-9, -10)).unwrap();
+        let visible = visibility_polygon(point(0, 0), &[wall, far, near]).unwrap();
+
+        assert!(visible.contains(&point(1, 0)));
+        assert!(visible.contains(&point(-5, 0)));
+        assert!(visible.contains(&point(8, 6)));
+        assert!(!visible.contains(&point(6, 0)));
+        assert!(!visible.contains(&point(8, 2)));
+    }
+
+    #[test]
+    fn without_occluders_everything_is_visible() {
+        let visible = visibility_polygon(point(0, 0), &[]).unwrap();
+        assert!(visible.contains(&point(0, 0)));
+        assert_eq!(visible.bbox().lower_left(), point(-1, -1));
+        assert_eq!(visible.bbox().upper_right(), point(1, 1));
+    }
+}